@@ -58,6 +58,17 @@ impl Attributes {
             network_magic,
         }
     }
+
+    /// The digest an address with these attributes has for `xpub`, as in
+    /// `ExtendedAddr::addr`, or `None` if `xpub` is not a valid xpub
+    ///
+    /// Checking many addresses against the same keys only needs the
+    /// digests of the keys once for each distinct attributes.
+    pub fn spending_digest_raw(&self, xpub: &[u8]) -> Option<[u8; 28]> {
+        XPub::from_slice(xpub)
+            .ok()
+            .map(|xpub| hash_spending_data(&xpub, self))
+    }
 }
 
 const ATTRIBUTE_NAME_TAG_DERIVATION: u64 = 1;
@@ -19,4 +19,4 @@ mod base58;
 mod cbor;
 mod crc32;
 
-pub use address::{Addr, ExtendedAddr, ParseExtendedAddrError};
+pub use address::{Addr, Attributes, ExtendedAddr, ParseExtendedAddrError};
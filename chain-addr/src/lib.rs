@@ -74,6 +74,44 @@ pub enum Kind {
     Multisig([u8; 32]),
 }
 
+impl Kind {
+    /// the spending key of a single address, `None` for any other kind
+    pub fn single_spending_key(&self) -> Option<&PublicKey<Ed25519>> {
+        match self {
+            Kind::Single(spending_key) => Some(spending_key),
+            _ => None,
+        }
+    }
+
+    /// the spending key and the group (stake) key of a group address,
+    /// `None` for any other kind
+    pub fn group_keys(&self) -> Option<(&PublicKey<Ed25519>, &PublicKey<Ed25519>)> {
+        match self {
+            Kind::Group(spending_key, group_key) => Some((spending_key, group_key)),
+            _ => None,
+        }
+    }
+
+    /// the key identifying the account the address is linked to: the
+    /// account key of an account address or the group key of a group
+    /// address. `None` for single and multisig addresses.
+    pub fn account_identifier(&self) -> Option<&PublicKey<Ed25519>> {
+        match self {
+            Kind::Account(account_key) => Some(account_key),
+            Kind::Group(_, group_key) => Some(group_key),
+            _ => None,
+        }
+    }
+
+    /// the hash identifying the multisig account, `None` for any other kind
+    pub fn multisig_hash(&self) -> Option<[u8; 32]> {
+        match self {
+            Kind::Multisig(hash) => Some(*hash),
+            _ => None,
+        }
+    }
+}
+
 /// Kind Type of an address
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum KindType {
@@ -125,6 +163,26 @@ impl Address {
     pub fn kind(&self) -> &Kind {
         &self.1
     }
+
+    /// see `Kind::single_spending_key`
+    pub fn single_spending_key(&self) -> Option<&PublicKey<Ed25519>> {
+        self.1.single_spending_key()
+    }
+
+    /// see `Kind::group_keys`
+    pub fn group_keys(&self) -> Option<(&PublicKey<Ed25519>, &PublicKey<Ed25519>)> {
+        self.1.group_keys()
+    }
+
+    /// see `Kind::account_identifier`
+    pub fn account_identifier(&self) -> Option<&PublicKey<Ed25519>> {
+        self.1.account_identifier()
+    }
+
+    /// see `Kind::multisig_hash`
+    pub fn multisig_hash(&self) -> Option<[u8; 32]> {
+        self.1.multisig_hash()
+    }
 }
 
 #[derive(Debug)]
@@ -653,4 +711,53 @@ mod test {
             );
         }
     }
+
+    fn fake_key(start: u8) -> PublicKey<Ed25519> {
+        let bytes: Vec<u8> = (start..start + 32).collect();
+        PublicKey::from_binary(&bytes).unwrap()
+    }
+
+    #[test]
+    fn payload_of_single() {
+        let spending_key = fake_key(1);
+        let addr = Address(Discrimination::Test, Kind::Single(spending_key.clone()));
+        assert_eq!(addr.single_spending_key(), Some(&spending_key));
+        assert_eq!(addr.group_keys(), None);
+        assert_eq!(addr.account_identifier(), None);
+        assert_eq!(addr.multisig_hash(), None);
+    }
+
+    #[test]
+    fn payload_of_group() {
+        let spending_key = fake_key(1);
+        let group_key = fake_key(41);
+        let addr = Address(
+            Discrimination::Test,
+            Kind::Group(spending_key.clone(), group_key.clone()),
+        );
+        assert_eq!(addr.single_spending_key(), None);
+        assert_eq!(addr.group_keys(), Some((&spending_key, &group_key)));
+        assert_eq!(addr.account_identifier(), Some(&group_key));
+        assert_eq!(addr.multisig_hash(), None);
+    }
+
+    #[test]
+    fn payload_of_account() {
+        let account_key = fake_key(41);
+        let addr = Address(Discrimination::Test, Kind::Account(account_key.clone()));
+        assert_eq!(addr.single_spending_key(), None);
+        assert_eq!(addr.group_keys(), None);
+        assert_eq!(addr.account_identifier(), Some(&account_key));
+        assert_eq!(addr.multisig_hash(), None);
+    }
+
+    #[test]
+    fn payload_of_multisig() {
+        let hash = [7u8; 32];
+        let addr = Address(Discrimination::Test, Kind::Multisig(hash));
+        assert_eq!(addr.single_spending_key(), None);
+        assert_eq!(addr.group_keys(), None);
+        assert_eq!(addr.account_identifier(), None);
+        assert_eq!(addr.multisig_hash(), Some(hash));
+    }
 }
@@ -252,6 +252,17 @@ pub fn read_mut_slice<'a, T: Readable>(
     Ok(())
 }
 
+/// Read a `T` that spans all of `bytes`
+///
+/// Fails with `ReadError::UnconsumedData` if bytes are left after the
+/// `T`, so that no trailing data is silently dropped.
+pub fn read_exactly<T: Readable>(bytes: &[u8]) -> Result<T, ReadError> {
+    let mut buf = ReadBuf::from(bytes);
+    let t = T::read(&mut buf)?;
+    buf.expect_end()?;
+    Ok(t)
+}
+
 /// Transform a raw buffer into a Header
 pub fn read_from_raw<T: Readable>(raw: &[u8]) -> Result<T, std::io::Error> {
     let mut rbuf = ReadBuf::from(raw);
@@ -1,5 +1,6 @@
 use crate::key::{
-    AsymmetricKey, AsymmetricPublicKey, PublicKeyError, SecretKeyError, SecretKeySizeStatic,
+    zero_bytes, AsymmetricKey, AsymmetricPublicKey, PublicKeyError, SecretKeyError,
+    SecretKeySizeStatic,
 };
 use crate::sign::{SignatureError, SigningAlgorithm, Verification, VerificationAlgorithm};
 use cryptoxide::ed25519;
@@ -27,6 +28,12 @@ impl Pub {
     }
 }
 
+impl Drop for Priv {
+    fn drop(&mut self) {
+        zero_bytes(&mut self.0)
+    }
+}
+
 impl AsRef<[u8]> for Priv {
     fn as_ref(&self) -> &[u8] {
         &self.0[..]
@@ -1,4 +1,6 @@
-use crate::key::{AsymmetricKey, AsymmetricPublicKey, SecretKeyError, SecretKeySizeStatic};
+use crate::key::{
+    zero_bytes, AsymmetricKey, AsymmetricPublicKey, SecretKeyError, SecretKeySizeStatic,
+};
 use crate::sign::SigningAlgorithm;
 
 use super::ed25519 as ei;
@@ -14,6 +16,12 @@ pub struct Ed25519Extended;
 #[derive(Clone)]
 pub struct ExtendedPriv([u8; ed25519::PRIVATE_KEY_LENGTH]);
 
+impl Drop for ExtendedPriv {
+    fn drop(&mut self) {
+        zero_bytes(&mut self.0)
+    }
+}
+
 impl AsRef<[u8]> for ExtendedPriv {
     fn as_ref(&self) -> &[u8] {
         &self.0[..]
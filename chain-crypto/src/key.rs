@@ -161,6 +161,12 @@ impl<A: AsymmetricKey> SecretKey<A> {
     pub fn generate<T: RngCore + CryptoRng>(rng: T) -> Self {
         SecretKey(A::generate(rng))
     }
+    /// The secret key material, only meant for exporting the key.
+    ///
+    /// Anything copied from it is not wiped when the key is dropped.
+    pub fn leak_secret(&self) -> &A::Secret {
+        &self.0
+    }
     pub fn to_public(&self) -> PublicKey<A::PubAlg> {
         PublicKey(<A as AsymmetricKey>::compute_public(&self.0))
     }
@@ -248,6 +254,19 @@ impl<A: AsymmetricKey> Bech32 for SecretKey<A> {
     }
 }
 
+/// Overwrite `bytes` with zeros, in a way the compiler cannot drop as
+/// a dead store, to wipe secret key material before its memory is freed.
+///
+/// This does not reach the copies left behind when a key is moved.
+pub(crate) fn zero_bytes(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // a volatile write is never optimized away, unlike a plain store to
+        // memory that is not read anymore
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -261,4 +280,11 @@ mod test {
             write!(f, "SecretKey ({:?})", self.0.as_ref())
         }
     }
+
+    #[test]
+    fn zero_bytes_wipes_everything() {
+        let mut bytes = [0xa5; 64];
+        zero_bytes(&mut bytes[..]);
+        assert!(bytes.iter().all(|byte| *byte == 0));
+    }
 }
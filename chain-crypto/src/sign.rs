@@ -142,6 +142,14 @@ impl<A: VerificationAlgorithm, T: AsRef<[u8]>> Signature<T, A> {
     }
 }
 
+impl<A: VerificationAlgorithm, T> Signature<T, A> {
+    /// verify the signature against the already serialized bytes of the signed object
+    #[must_use]
+    pub fn verify_slice(&self, publickey: &key::PublicKey<A>, slice: &[u8]) -> Verification {
+        <A as VerificationAlgorithm>::verify_bytes(&publickey.0, &self.signdata, slice)
+    }
+}
+
 /*
 impl<A: SigningAlgorithm, T: AsRef<[u8]>> Signature<T, A::Public>
     where <A as key::AsymmetricKey>::Public: VerificationAlgorithm,
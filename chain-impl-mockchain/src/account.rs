@@ -1,17 +1,19 @@
 use crate::accounting::account;
-use crate::key::{deserialize_public_key, serialize_public_key};
+use crate::key::{
+    deserialize_public_key, serialize_public_key, AccountPublicKey, AccountSignature,
+};
 use crate::transaction::WitnessAccountData;
 use chain_core::{
     mempack::{ReadBuf, ReadError, Readable},
     property,
 };
-use chain_crypto::{Ed25519, PublicKey, Signature};
+use chain_crypto::{Ed25519, PublicKey};
 
 pub use account::{LedgerError, SpendingCounter};
 
 pub type AccountAlg = Ed25519;
 
-pub type Witness = Signature<WitnessAccountData, AccountAlg>;
+pub type Witness = AccountSignature<WitnessAccountData>;
 
 /// Account Identifier (also used as Public Key)
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -29,6 +31,18 @@ impl From<Identifier> for PublicKey<AccountAlg> {
     }
 }
 
+impl From<AccountPublicKey> for Identifier {
+    fn from(pk: AccountPublicKey) -> Self {
+        Identifier(pk.into_raw())
+    }
+}
+
+impl From<Identifier> for AccountPublicKey {
+    fn from(i: Identifier) -> Self {
+        AccountPublicKey::from_raw(i.0)
+    }
+}
+
 impl AsRef<PublicKey<AccountAlg>> for Identifier {
     fn as_ref(&self) -> &PublicKey<AccountAlg> {
         &self.0
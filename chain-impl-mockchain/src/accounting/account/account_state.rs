@@ -63,14 +63,14 @@ impl<Extra: Clone> AccountState<Extra> {
     pub fn sub(&self, v: Value) -> Result<Option<Self>, LedgerError> {
         let new_value = (self.value - v)?;
         match self.counter.increment() {
-            None => {
+            Err(CounterOverflow {}) => {
                 if new_value == Value::zero() {
                     Ok(None)
                 } else {
                     Err(LedgerError::NeedTotalWithdrawal)
                 }
             }
-            Some(new_counter) => Ok(Some(Self {
+            Ok(new_counter) => Ok(Some(Self {
                 counter: new_counter,
                 delegation: self.delegation.clone(),
                 value: new_value,
@@ -93,21 +93,42 @@ impl<Extra: Clone> AccountState<Extra> {
 /// the counter is incremented. A matching counter
 /// needs to be used in the spending phase to make
 /// sure we have non-replayability of a transaction.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SpendingCounter(u32);
 
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub CounterOverflow{} = "Spending counter reached its maximum",
+}
+
 impl SpendingCounter {
     pub fn zero() -> Self {
         SpendingCounter(0)
     }
 
-    fn increment(&self) -> Option<Self> {
-        self.0.checked_add(1).map(SpendingCounter)
+    /// The counter of the next spending, erroring out rather than
+    /// wrapping around once the counter reached `u32::MAX`
+    pub fn increment(&self) -> Result<Self, CounterOverflow> {
+        self.0
+            .checked_add(1)
+            .map(SpendingCounter)
+            .ok_or(CounterOverflow {})
     }
 
+    /// The counter as signed in account witnesses, in little endian
     pub fn to_bytes(&self) -> [u8; 4] {
         self.0.to_le_bytes()
     }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        SpendingCounter(u32::from_le_bytes(bytes))
+    }
+
+    /// This counter followed by the counters of the next spendings from
+    /// the same account, up to `u32::MAX`
+    pub fn iter_from(self) -> impl Iterator<Item = SpendingCounter> {
+        std::iter::successors(Some(self), |counter| counter.increment().ok())
+    }
 }
 
 impl From<u32> for SpendingCounter {
@@ -134,7 +155,7 @@ impl<'a, ID, Extra> Iterator for Iter<'a, ID, Extra> {
 
 #[cfg(test)]
 mod tests {
-    use super::{AccountState, SpendingCounter};
+    use super::{AccountState, CounterOverflow, SpendingCounter};
     use crate::{certificate::PoolId, value::Value};
     use quickcheck::{Arbitrary, Gen, TestResult};
     use quickcheck_macros::quickcheck;
@@ -348,4 +369,29 @@ mod tests {
                 && account_state.get_value() != value)
     }
 
+    #[test]
+    pub fn counter_does_not_wrap_around() {
+        let last = SpendingCounter::from(std::u32::MAX);
+        assert_eq!(last.increment(), Err(CounterOverflow {}));
+        assert_eq!(
+            SpendingCounter::from(std::u32::MAX - 1).increment(),
+            Ok(last)
+        );
+        assert_eq!(
+            SpendingCounter::from(std::u32::MAX - 2)
+                .iter_from()
+                .collect::<Vec<_>>(),
+            vec![
+                SpendingCounter::from(std::u32::MAX - 2),
+                SpendingCounter::from(std::u32::MAX - 1),
+                last
+            ]
+        );
+    }
+
+    #[quickcheck]
+    pub fn counter_bytes_roundtrip(counter: SpendingCounter) -> bool {
+        SpendingCounter::from_bytes(counter.to_bytes()) == counter
+            && counter.to_bytes() == u32::from(counter).to_le_bytes()
+    }
 }
@@ -6,8 +6,9 @@ use crate::block::{
     ChainLength, Common, Fragment, GenesisPraosProof, Header, KESSignature, Proof,
 };
 use crate::certificate::PoolId;
-use crate::key::make_signature;
+use crate::key::try_make_signature;
 use crate::leadership;
+use crate::leadership::signer::{LeaderSigner, SignError};
 use crate::transaction::{AuthenticatedTransaction, NoExtra};
 use chain_addr::Address;
 use chain_crypto::{
@@ -121,14 +122,17 @@ impl BlockBuilder {
     }
 
     /// create a BFT Block. this block will be signed with the given private key
-    pub fn make_bft_block(mut self, bft_signing_key: &SecretKey<Ed25519>) -> Block {
+    pub fn make_bft_block(
+        mut self,
+        bft_signing_key: &SecretKey<Ed25519>,
+    ) -> Result<Block, std::io::Error> {
         assert_ne!(self.common.chain_length, ChainLength(0));
         self.finalize_common(BlockVersion::Ed25519Signed);
         let bft_proof = BftProof {
             leader_id: leadership::bft::LeaderId(bft_signing_key.to_public()),
-            signature: super::BftSignature(make_signature(bft_signing_key, &self.common)),
+            signature: super::BftSignature(try_make_signature(bft_signing_key, &self.common)?),
         };
-        self.make_block(Proof::Bft(bft_proof))
+        Ok(self.make_block(Proof::Bft(bft_proof)))
     }
 
     /// create a Praos/Genesis block, this block will be signed with the
@@ -138,7 +142,7 @@ impl BlockBuilder {
         node_id: &PoolId,
         kes_signing_key: &SecretKey<SumEd25519_12>,
         vrf_proof: <Curve25519_2HashDH as VerifiableRandomFunction>::VerifiedRandomOutput,
-    ) -> Block {
+    ) -> Result<Block, std::io::Error> {
         assert_ne!(self.common.chain_length, ChainLength(0));
         self.finalize_common(BlockVersion::KesVrfproof);
 
@@ -146,9 +150,45 @@ impl BlockBuilder {
             node_id: node_id.clone(),
             vrf_proof: vrf_proof,
             // ! SECURITY FIXME ! : also include id and vrf proof.
-            kes_proof: KESSignature(make_signature(kes_signing_key, &self.common)),
+            kes_proof: KESSignature(try_make_signature(kes_signing_key, &self.common)?),
+        };
+        Ok(self.make_block(Proof::GenesisPraos(genesis_praos_proof)))
+    }
+
+    /// create a BFT Block signed by the given signer, fails if the signer
+    /// does not hold a BFT key
+    pub fn make_bft_block_with_signer(mut self, signer: &LeaderSigner) -> Result<Block, SignError> {
+        assert_ne!(self.common.chain_length, ChainLength(0));
+        let leader_id = signer.bft_leader_id().ok_or(SignError::NoBftKey)?;
+        self.finalize_common(BlockVersion::Ed25519Signed);
+        let bft_proof = BftProof {
+            leader_id,
+            signature: signer.sign_bft_header(&self.common)?,
         };
-        self.make_block(Proof::GenesisPraos(genesis_praos_proof))
+        Ok(self.make_block(Proof::Bft(bft_proof)))
+    }
+
+    /// create a Praos/Genesis block signed by the given signer, fails if
+    /// the signer does not hold Genesis Praos keys
+    pub fn make_genesis_praos_block_with_signer(
+        mut self,
+        signer: &LeaderSigner,
+        vrf_proof: <Curve25519_2HashDH as VerifiableRandomFunction>::VerifiedRandomOutput,
+    ) -> Result<Block, SignError> {
+        assert_ne!(self.common.chain_length, ChainLength(0));
+        let node_id = signer
+            .node_id()
+            .ok_or(SignError::NoGenesisPraosKeys)?
+            .clone();
+        self.finalize_common(BlockVersion::KesVrfproof);
+
+        let genesis_praos_proof = GenesisPraosProof {
+            node_id,
+            vrf_proof,
+            // ! SECURITY FIXME ! : also include id and vrf proof.
+            kes_proof: signer.sign_praos_header(&self.common)?,
+        };
+        Ok(self.make_block(Proof::GenesisPraos(genesis_praos_proof)))
     }
 }
 
@@ -161,6 +201,7 @@ mod tests {
         version::BlockVersion::{Ed25519Signed, KesVrfproof},
         AnyBlockVersion, Block,
     };
+    use crate::leadership::signer::LeaderSigner;
     use crate::testing::arbitrary::utils::Verify;
     use chain_core::property::BlockId as BlockIdProperty;
     use chain_crypto::{testing::TestCryptoGen, Ed25519, SumEd25519_12};
@@ -230,11 +271,13 @@ mod tests {
         builder.date(expected_common.block_date);
         builder.chain_length(expected_common.chain_length);
         builder.parent(parent_header.hash());
-        let block = builder.make_genesis_praos_block(
-            &genesis_praos_proof.node_id,
-            &kes_signing_key,
-            genesis_praos_proof.vrf_proof,
-        );
+        let block = builder
+            .make_genesis_praos_block(
+                &genesis_praos_proof.node_id,
+                &kes_signing_key,
+                genesis_praos_proof.vrf_proof,
+            )
+            .unwrap();
 
         verify_block(block, expected_common, block_content)
     }
@@ -262,8 +305,57 @@ mod tests {
         builder.date(expected_common.block_date);
         builder.chain_length(expected_common.chain_length);
         builder.parent(parent_header.hash());
-        let block = builder.make_bft_block(&bft_signing_key);
+        let block = builder.make_bft_block(&bft_signing_key).unwrap();
 
         verify_block(block, expected_common, block_content)
     }
+
+    #[quickcheck]
+    pub fn make_bft_block_with_signer(
+        key_gen: TestCryptoGen,
+        parent_header: Header,
+        block_content: BlockContents,
+    ) -> TestResult {
+        let make_builder = || {
+            let mut builder = BlockBuilder::new();
+            builder.messages(block_content.iter().cloned());
+            builder.date(*parent_header.block_date());
+            builder.chain_length(ChainLength(parent_header.chain_length().0 + 1));
+            builder.parent(parent_header.hash());
+            builder
+        };
+
+        let signer = LeaderSigner::new().with_bft(key_gen.secret_key::<Ed25519>(0));
+        let block = match make_builder().make_bft_block_with_signer(&signer) {
+            Err(error) => {
+                return TestResult::error(format!("signer with a BFT key failed: {}", error))
+            }
+            Ok(block) => block,
+        };
+        let expected = make_builder()
+            .make_bft_block(&key_gen.secret_key::<Ed25519>(0))
+            .unwrap();
+
+        let mut verify = Verify::new();
+        verify.verify_eq(expected.header, block.header, "block header");
+        verify.get_result()
+    }
+
+    #[quickcheck]
+    pub fn make_block_with_signer_without_keys(
+        parent_header: Header,
+        genesis_praos_proof: GenesisPraosProof,
+    ) -> bool {
+        let make_builder = || {
+            let mut builder = BlockBuilder::new();
+            builder.chain_length(ChainLength(parent_header.chain_length().0 + 1));
+            builder.parent(parent_header.hash());
+            builder
+        };
+        let signer = LeaderSigner::new();
+        make_builder().make_bft_block_with_signer(&signer).is_err()
+            && make_builder()
+                .make_genesis_praos_block_with_signer(&signer, genesis_praos_proof.vrf_proof)
+                .is_err()
+    }
 }
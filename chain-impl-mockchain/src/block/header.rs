@@ -5,7 +5,8 @@ use crate::block::{
 use crate::certificate::PoolId;
 use crate::date::BlockDate;
 use crate::key::{
-    deserialize_public_key, deserialize_signature, serialize_public_key, serialize_signature, Hash,
+    ct_eq, deserialize_public_key, deserialize_signature, serialize_public_key,
+    serialize_signature, BlockIdTag, Hash, HashScheme,
 };
 use crate::leadership::{bft, genesis};
 use chain_core::{
@@ -46,6 +47,73 @@ impl From<ChainLength> for u32 {
     }
 }
 
+impl ChainLength {
+    /// The chain length of the block following a block of this chain
+    /// length.
+    ///
+    /// # Panics
+    ///
+    /// If the chain length is already `u32::MAX`.
+    pub fn next(self) -> Self {
+        self.checked_add(1).expect("chain length overflow")
+    }
+
+    /// `None` if the result would not fit in a chain length.
+    pub fn checked_add(self, n: u32) -> Option<Self> {
+        self.0.checked_add(n).map(ChainLength)
+    }
+
+    pub fn checked_sub(self, n: u32) -> Option<Self> {
+        self.0.checked_sub(n).map(ChainLength)
+    }
+
+    pub fn saturating_add(self, n: u32) -> Self {
+        ChainLength(self.0.saturating_add(n))
+    }
+
+    pub fn saturating_sub(self, n: u32) -> Self {
+        ChainLength(self.0.saturating_sub(n))
+    }
+
+    /// The number of blocks between the two chain lengths, whichever
+    /// is the greatest.
+    pub fn distance(self, other: Self) -> u32 {
+        if self >= other {
+            self.0 - other.0
+        } else {
+            other.0 - self.0
+        }
+    }
+
+    /// The chain lengths from `from` included up to `to` excluded, in
+    /// increasing order. Empty if `to` is not greater than `from`.
+    pub fn range(from: Self, to: Self) -> impl DoubleEndedIterator<Item = Self> {
+        (from.0..to.0).map(ChainLength)
+    }
+}
+
+/// # Panics
+///
+/// On overflow, use `ChainLength::checked_add` to handle it.
+impl std::ops::Add<u32> for ChainLength {
+    type Output = Self;
+
+    fn add(self, n: u32) -> Self {
+        self.checked_add(n).expect("chain length overflow")
+    }
+}
+
+/// # Panics
+///
+/// On underflow, use `ChainLength::checked_sub` to handle it.
+impl std::ops::Sub<u32> for ChainLength {
+    type Output = Self;
+
+    fn sub(self, n: u32) -> Self {
+        self.checked_sub(n).expect("chain length underflow")
+    }
+}
+
 /// FIXME SECURITY : we want to sign Common + everything in proof except the signature
 pub type HeaderToSign = Common;
 
@@ -96,14 +164,14 @@ pub struct HeaderContentEvalContext {
 
 impl PartialEq<Self> for BftSignature {
     fn eq(&self, other: &Self) -> bool {
-        self.0.as_ref() == other.0.as_ref()
+        ct_eq(self.0.as_ref(), other.0.as_ref())
     }
 }
 impl Eq for BftSignature {}
 
 impl PartialEq<Self> for KESSignature {
     fn eq(&self, other: &Self) -> bool {
-        self.0.as_ref() == other.0.as_ref()
+        ct_eq(self.0.as_ref(), other.0.as_ref())
     }
 }
 impl Eq for KESSignature {}
@@ -146,12 +214,20 @@ impl Header {
 
     /// function to compute the Header Hash as per the spec. It is the hash
     /// of the serialized header (except the first 2bytes: the size)
+    ///
+    /// This is the legacy scheme, use `hash_with` to follow the hash scheme
+    /// of the chain.
     #[inline]
     pub fn hash(&self) -> HeaderHash {
+        self.hash_with(HashScheme::Legacy)
+    }
+
+    /// the Header Hash as computed by `scheme`
+    pub fn hash_with(&self, scheme: HashScheme) -> HeaderHash {
         // TODO: this is not the optimal way to compute the hash
         use chain_core::property::Serialize;
         let bytes = self.serialize_as_vec().unwrap();
-        HeaderHash::hash_bytes(&bytes[..])
+        scheme.hash::<BlockIdTag>(&bytes[..])
     }
 
     #[inline]
@@ -182,7 +258,7 @@ impl Header {
 
 impl property::ChainLength for ChainLength {
     fn next(&self) -> Self {
-        ChainLength(self.0.checked_add(1).unwrap())
+        ChainLength::next(*self)
     }
 }
 
@@ -332,6 +408,58 @@ mod test {
         fn header_serialization_bijection(b: Header) -> TestResult {
             property::testing::serialization_bijection_r(b)
         }
+
+        fn chain_length_distance_is_symmetric(a: u32, b: u32) -> bool {
+            let (a, b) = (ChainLength(a), ChainLength(b));
+            a.distance(b) == b.distance(a)
+                && a.saturating_add(a.distance(b)) >= b
+                && a.saturating_sub(a.distance(b)) <= b
+        }
+    }
+
+    #[test]
+    fn chain_length_arithmetic_at_the_bounds() {
+        let max = ChainLength(std::u32::MAX);
+        assert_eq!(max.checked_add(1), None);
+        assert_eq!(max.saturating_add(1), max);
+        assert_eq!(max.checked_add(0), Some(max));
+        assert_eq!(ChainLength(std::u32::MAX - 1).next(), max);
+        assert_eq!(ChainLength(0).checked_sub(1), None);
+        assert_eq!(ChainLength(0).saturating_sub(1), ChainLength(0));
+        assert_eq!(ChainLength(5) - 5, ChainLength(0));
+        assert_eq!(ChainLength(0) + std::u32::MAX, max);
+        assert_eq!(ChainLength(0).distance(max), std::u32::MAX);
+        assert_eq!(max.distance(ChainLength(0)), std::u32::MAX);
+    }
+
+    #[test]
+    #[should_panic]
+    fn chain_length_next_panics_at_max() {
+        ChainLength(std::u32::MAX).next();
+    }
+
+    #[test]
+    #[should_panic]
+    fn chain_length_add_panics_on_overflow() {
+        let _ = ChainLength(std::u32::MAX) + 1;
+    }
+
+    #[test]
+    fn chain_length_range_is_half_open() {
+        let lengths: Vec<_> = ChainLength::range(ChainLength(3), ChainLength(6)).collect();
+        assert_eq!(
+            lengths,
+            vec![ChainLength(3), ChainLength(4), ChainLength(5)]
+        );
+        assert_eq!(
+            ChainLength::range(ChainLength(6), ChainLength(3)).count(),
+            0
+        );
+        let max = ChainLength(std::u32::MAX);
+        assert_eq!(
+            ChainLength::range(ChainLength(std::u32::MAX - 1), max).collect::<Vec<_>>(),
+            vec![ChainLength(std::u32::MAX - 1)]
+        );
     }
 
     impl Arbitrary for AnyBlockVersion {
@@ -76,7 +76,7 @@ impl Block {
     pub fn is_consistent(&self) -> bool {
         let (content_hash, content_size) = self.contents.compute_hash_size();
 
-        &content_hash == self.header.block_content_hash()
+        content_hash.ct_eq(self.header.block_content_hash())
             && content_size == self.header.common.block_content_size as usize
     }
 
@@ -7,6 +7,7 @@ mod test;
 pub use delegation::{OwnerStakeDelegation, StakeDelegation};
 pub use pool::{
     PoolId, PoolManagement, PoolOwnersSigned, PoolRegistration, PoolRetirement, PoolUpdate,
+    RewardDestination,
 };
 
 #[derive(Debug, Clone)]
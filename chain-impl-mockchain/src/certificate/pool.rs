@@ -1,5 +1,7 @@
+use crate::account;
 use crate::key::{deserialize_public_key, deserialize_signature};
 use crate::leadership::genesis::GenesisPraosLeader;
+use crate::value::Value;
 use chain_core::{
     mempack::{ReadBuf, ReadError, Readable},
     property,
@@ -29,8 +31,24 @@ pub struct PoolRegistration {
     pub owners: Vec<PublicKey<Ed25519>>,
     /// Genesis Praos keys
     pub keys: GenesisPraosLeader,
+    /// Where the rewards of the pool are credited
+    pub reward_destination: RewardDestination,
 }
 
+/// Destination of the rewards of a pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RewardDestination {
+    /// All the rewards are credited to a single account
+    Account(account::Identifier),
+    /// The rewards are split between the owners of the pool, proportionally
+    /// to their weight. There is one weight per owner, in the same order as
+    /// the owners.
+    OwnersSplit(Vec<u32>),
+}
+
+const REWARD_DESTINATION_ACCOUNT: u8 = 1;
+const REWARD_DESTINATION_OWNERS_SPLIT: u8 = 2;
+
 /// Updating info for a pool
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PoolUpdate {
@@ -60,14 +78,96 @@ pub enum PoolManagement {
     Retirement(PoolOwnersSigned<PoolRetirement>),
 }
 
+impl RewardDestination {
+    /// split the rewards equally between the `nb_owners` owners of the pool
+    pub fn owners_equal_split(nb_owners: usize) -> Self {
+        RewardDestination::OwnersSplit(vec![1; nb_owners])
+    }
+
+    /// check the destination can be used by a pool with the given owners:
+    /// a split needs exactly one weight per owner and a non zero total weight
+    pub fn is_valid_for(&self, owners: &[PublicKey<Ed25519>]) -> bool {
+        match self {
+            RewardDestination::Account(_) => true,
+            RewardDestination::OwnersSplit(weights) => {
+                weights.len() == owners.len() && weights.iter().any(|weight| *weight > 0)
+            }
+        }
+    }
+
+    /// split `reward` between the accounts to credit.
+    ///
+    /// For an owners split, every owner receives its share rounded down
+    /// and the remainder goes to the first owner. Returns `None` if the
+    /// destination is not valid for the given owners.
+    pub fn split(
+        &self,
+        owners: &[PublicKey<Ed25519>],
+        reward: Value,
+    ) -> Option<Vec<(account::Identifier, Value)>> {
+        if !self.is_valid_for(owners) {
+            return None;
+        }
+        match self {
+            RewardDestination::Account(identifier) => Some(vec![(identifier.clone(), reward)]),
+            RewardDestination::OwnersSplit(weights) => {
+                let total: u128 = weights.iter().map(|weight| *weight as u128).sum();
+                let mut shares: Vec<(account::Identifier, Value)> = owners
+                    .iter()
+                    .zip(weights.iter())
+                    .map(|(owner, weight)| {
+                        let share = reward.0 as u128 * *weight as u128 / total;
+                        (owner.clone().into(), Value(share as u64))
+                    })
+                    .collect();
+                let distributed: u64 = shares.iter().map(|(_, share)| share.0).sum();
+                (shares[0].1).0 += reward.0 - distributed;
+                Some(shares)
+            }
+        }
+    }
+
+    pub fn serialize_in<T>(&self, bb: ByteBuilder<T>) -> ByteBuilder<T> {
+        match self {
+            RewardDestination::Account(identifier) => bb
+                .u8(REWARD_DESTINATION_ACCOUNT)
+                .bytes(identifier.as_ref().as_ref()),
+            RewardDestination::OwnersSplit(weights) => bb
+                .u8(REWARD_DESTINATION_OWNERS_SPLIT)
+                .iter8(&mut weights.iter(), |bb, weight| bb.u32(*weight)),
+        }
+    }
+}
+
+impl Readable for RewardDestination {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        match buf.get_u8()? {
+            REWARD_DESTINATION_ACCOUNT => {
+                account::Identifier::read(buf).map(RewardDestination::Account)
+            }
+            REWARD_DESTINATION_OWNERS_SPLIT => {
+                let weights_nb = buf.get_u8()?;
+                let mut weights = Vec::with_capacity(weights_nb as usize);
+                for _ in 0..weights_nb {
+                    weights.push(buf.get_u32()?);
+                }
+                Ok(RewardDestination::OwnersSplit(weights))
+            }
+            tag => Err(ReadError::UnknownTag(tag as u32)),
+        }
+    }
+}
+
 impl PoolRegistration {
     pub fn serialize_in(&self, bb: ByteBuilder<Self>) -> ByteBuilder<Self> {
-        bb.u128(self.serial)
+        let bb = bb
+            .u128(self.serial)
             .u64(self.start_validity.into())
             .u8(self.management_threshold)
             .iter8(&mut self.owners.iter(), |bb, o| bb.bytes(o.as_ref()))
             .bytes(self.keys.vrf_public_key.as_ref())
-            .bytes(self.keys.kes_public_key.as_ref())
+            .bytes(self.keys.kes_public_key.as_ref());
+        self.reward_destination.serialize_in(bb)
     }
     pub fn serialize(&self) -> ByteArray<Self> {
         self.serialize_in(ByteBuilder::new()).finalize()
@@ -182,6 +282,7 @@ impl Readable for PoolRegistration {
         }
 
         let keys = GenesisPraosLeader::read(buf)?;
+        let reward_destination = RewardDestination::read(buf)?;
 
         let info = Self {
             serial,
@@ -189,6 +290,7 @@ impl Readable for PoolRegistration {
             management_threshold,
             owners,
             keys,
+            reward_destination,
         };
         Ok(info)
     }
@@ -244,3 +346,76 @@ impl<T: Readable> Readable for PoolOwnersSigned<T> {
         Ok(PoolOwnersSigned { inner, signatures })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_crypto::testing::TestCryptoGen;
+
+    fn owners(nb: u32) -> Vec<PublicKey<Ed25519>> {
+        let key_gen = TestCryptoGen(0);
+        (0..nb)
+            .map(|idx| key_gen.secret_key::<Ed25519>(idx).to_public())
+            .collect()
+    }
+
+    #[test]
+    pub fn owners_split_gives_remainder_to_first_owner() {
+        let owners = owners(3);
+        let shares = RewardDestination::owners_equal_split(3)
+            .split(&owners, Value(100))
+            .unwrap();
+        let values: Vec<Value> = shares.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, vec![Value(34), Value(33), Value(33)]);
+        for ((identifier, _), owner) in shares.iter().zip(owners.iter()) {
+            assert_eq!(identifier.as_ref(), owner);
+        }
+    }
+
+    #[test]
+    pub fn owners_split_follows_weights() {
+        let owners = owners(2);
+        let shares = RewardDestination::OwnersSplit(vec![3, 1])
+            .split(&owners, Value(10))
+            .unwrap();
+        let values: Vec<Value> = shares.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, vec![Value(8), Value(2)]);
+    }
+
+    #[test]
+    pub fn account_destination_takes_everything() {
+        let owners = owners(3);
+        let account: account::Identifier = owners[1].clone().into();
+        let shares = RewardDestination::Account(account.clone())
+            .split(&owners, Value(100))
+            .unwrap();
+        assert_eq!(shares, vec![(account, Value(100))]);
+    }
+
+    #[test]
+    pub fn invalid_owners_split_is_rejected() {
+        let owners = owners(2);
+        assert!(RewardDestination::OwnersSplit(vec![1])
+            .split(&owners, Value(10))
+            .is_none());
+        assert!(RewardDestination::OwnersSplit(vec![0, 0])
+            .split(&owners, Value(10))
+            .is_none());
+    }
+
+    #[test]
+    pub fn reward_destination_serialization_bijection() {
+        let owners = owners(1);
+        let destinations = vec![
+            RewardDestination::Account(owners[0].clone().into()),
+            RewardDestination::OwnersSplit(vec![1, 2, 3]),
+        ];
+        for destination in destinations {
+            let bytes = destination
+                .serialize_in(ByteBuilder::<RewardDestination>::new())
+                .finalize();
+            let mut buf = ReadBuf::from(bytes.as_ref());
+            assert_eq!(RewardDestination::read(&mut buf).unwrap(), destination);
+        }
+    }
+}
@@ -69,12 +69,18 @@ impl Arbitrary for PoolRegistration {
         let keys = Arbitrary::arbitrary(g);
 
         let pk = testing::arbitrary_public_key::<Ed25519, G>(g);
+        let reward_destination = if bool::arbitrary(g) {
+            RewardDestination::Account(Arbitrary::arbitrary(g))
+        } else {
+            RewardDestination::owners_equal_split(1)
+        };
         PoolRegistration {
             serial: Arbitrary::arbitrary(g),
             management_threshold: 1,
             start_validity: start_validity.into(),
             owners: vec![pk],
             keys,
+            reward_destination,
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::key::HashScheme;
 use crate::leadership::bft::LeaderId;
 use crate::milli::Milli;
 use crate::{block::ConsensusVersion, fee::LinearFee};
@@ -56,6 +57,7 @@ pub enum ConfigParam {
     LinearFee(LinearFee),
     ProposalExpiration(u32),
     KESUpdateSpeed(u32),
+    HashScheme(HashScheme),
 }
 
 // Discriminants can NEVER be 1024 or higher
@@ -89,6 +91,8 @@ pub enum Tag {
     ProposalExpiration = 15,
     #[strum(to_string = "kes-update-speed")]
     KESUpdateSpeed = 16,
+    #[strum(to_string = "hash-scheme")]
+    HashScheme = 17,
 }
 
 impl Tag {
@@ -108,6 +112,7 @@ impl Tag {
             14 => Some(Tag::LinearFee),
             15 => Some(Tag::ProposalExpiration),
             16 => Some(Tag::KESUpdateSpeed),
+            17 => Some(Tag::HashScheme),
             _ => None,
         }
     }
@@ -132,6 +137,7 @@ impl<'a> From<&'a ConfigParam> for Tag {
             ConfigParam::LinearFee(_) => Tag::LinearFee,
             ConfigParam::ProposalExpiration(_) => Tag::ProposalExpiration,
             ConfigParam::KESUpdateSpeed(_) => Tag::KESUpdateSpeed,
+            ConfigParam::HashScheme(_) => Tag::HashScheme,
         }
     }
 }
@@ -177,6 +183,7 @@ impl Readable for ConfigParam {
             Tag::KESUpdateSpeed => {
                 ConfigParamVariant::from_payload(bytes).map(ConfigParam::KESUpdateSpeed)
             }
+            Tag::HashScheme => ConfigParamVariant::from_payload(bytes).map(ConfigParam::HashScheme),
         }
         .map_err(Into::into)
     }
@@ -202,6 +209,7 @@ impl property::Serialize for ConfigParam {
             ConfigParam::LinearFee(data) => data.to_payload(),
             ConfigParam::ProposalExpiration(data) => data.to_payload(),
             ConfigParam::KESUpdateSpeed(data) => data.to_payload(),
+            ConfigParam::HashScheme(data) => data.to_payload(),
         };
         let taglen = TagLen::new(tag, bytes.len()).ok_or_else(|| {
             io::Error::new(
@@ -257,6 +265,29 @@ impl ConfigParamVariant for Discrimination {
     }
 }
 
+const VAL_HASH_LEGACY: u8 = 1;
+const VAL_HASH_TAGGED: u8 = 2;
+
+impl ConfigParamVariant for HashScheme {
+    fn to_payload(&self) -> Vec<u8> {
+        match self {
+            HashScheme::Legacy => vec![VAL_HASH_LEGACY],
+            HashScheme::Tagged => vec![VAL_HASH_TAGGED],
+        }
+    }
+
+    fn from_payload(payload: &[u8]) -> Result<Self, Error> {
+        if payload.len() != 1 {
+            return Err(Error::SizeInvalid);
+        };
+        match payload[0] {
+            VAL_HASH_LEGACY => Ok(HashScheme::Legacy),
+            VAL_HASH_TAGGED => Ok(HashScheme::Tagged),
+            _ => Err(Error::StructureInvalid),
+        }
+    }
+}
+
 impl ConfigParamVariant for ConsensusVersion {
     fn to_payload(&self) -> Vec<u8> {
         (*self as u16).to_be_bytes().to_vec()
@@ -428,9 +459,19 @@ mod test {
         }
     }
 
+    impl Arbitrary for HashScheme {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            if bool::arbitrary(g) {
+                HashScheme::Legacy
+            } else {
+                HashScheme::Tagged
+            }
+        }
+    }
+
     impl Arbitrary for ConfigParam {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
-            match u8::arbitrary(g) % 12 {
+            match u8::arbitrary(g) % 13 {
                 0 => ConfigParam::Block0Date(Arbitrary::arbitrary(g)),
                 1 => ConfigParam::Discrimination(Arbitrary::arbitrary(g)),
                 2 => ConfigParam::ConsensusVersion(Arbitrary::arbitrary(g)),
@@ -443,6 +484,7 @@ mod test {
                 9 => ConfigParam::RemoveBftLeader(Arbitrary::arbitrary(g)),
                 10 => ConfigParam::LinearFee(Arbitrary::arbitrary(g)),
                 11 => ConfigParam::ProposalExpiration(Arbitrary::arbitrary(g)),
+                12 => ConfigParam::HashScheme(Arbitrary::arbitrary(g)),
                 _ => unreachable!(),
             }
         }
@@ -3,7 +3,7 @@ mod raw;
 
 use crate::legacy;
 use chain_addr::Address;
-use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::mempack::{read_exactly, ReadBuf, ReadError, Readable};
 use chain_core::property;
 
 pub use config::ConfigParams;
@@ -11,6 +11,7 @@ pub use raw::{FragmentId, FragmentRaw};
 
 use crate::{
     certificate,
+    key::HashScheme,
     transaction::{AuthenticatedTransaction, NoExtra},
     update::{SignedUpdateProposal, SignedUpdateVote},
 };
@@ -111,14 +112,18 @@ impl Fragment {
     }
 
     pub fn from_raw(raw: &FragmentRaw) -> Result<Self, ReadError> {
-        let mut buf = ReadBuf::from(raw.as_ref());
-        Fragment::read(&mut buf)
+        read_exactly(raw.as_ref())
     }
 
     /// The ID of a message is a hash of its serialization *without* the size.
     pub fn hash(&self) -> FragmentId {
         self.to_raw().id()
     }
+
+    /// The ID of the message as computed by `scheme`
+    pub fn hash_with(&self, scheme: HashScheme) -> FragmentId {
+        self.to_raw().id_with(scheme)
+    }
 }
 
 impl Readable for Fragment {
@@ -204,5 +209,16 @@ mod test {
             let b_got = Fragment::from_raw(&b.to_raw()).unwrap();
             TestResult::from_bool(b == b_got)
         }
+
+        fn fragment_with_trailing_bytes_is_rejected(b: Fragment, junk: Vec<u8>) -> TestResult {
+            if junk.is_empty() {
+                return TestResult::discard();
+            }
+            let mut raw = b.to_raw();
+            raw.0.extend_from_slice(&junk);
+            TestResult::from_bool(
+                Fragment::from_raw(&raw) == Err(ReadError::UnconsumedData(junk.len()))
+            )
+        }
     }
 }
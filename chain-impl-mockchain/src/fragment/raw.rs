@@ -1,4 +1,4 @@
-use crate::key::Hash;
+use crate::key::{FragmentIdTag, Hash, HashScheme};
 use chain_core::property;
 
 pub type FragmentId = Hash;
@@ -12,8 +12,15 @@ impl FragmentRaw {
         2 + self.0.len()
     }
 
+    /// the ID of the fragment with the legacy scheme, use `id_with` to
+    /// follow the hash scheme of the chain
     pub fn id(&self) -> FragmentId {
-        FragmentId::hash_bytes(self.0.as_ref())
+        self.id_with(HashScheme::Legacy)
+    }
+
+    /// the ID of the fragment as computed by `scheme`
+    pub fn id_with(&self, scheme: HashScheme) -> FragmentId {
+        scheme.hash::<FragmentIdTag>(self.0.as_ref())
     }
 }
 
@@ -1,6 +1,7 @@
 //! Module provides cryptographic utilities and types related to
 //! the user keys.
 //!
+use crate::multisig;
 use chain_core::mempack::{read_mut_slice, ReadBuf, ReadError, Readable};
 use chain_core::property;
 use chain_crypto as crypto;
@@ -9,8 +10,14 @@ use chain_crypto::{
 };
 use rand_core::{CryptoRng, RngCore};
 
+use std::convert::TryFrom;
 use std::str::FromStr;
 
+/// An Ed25519 secret key, in either of its forms
+///
+/// The key material is wiped from memory when the key is dropped, and
+/// its `Debug` output only shows a fingerprint of the public key. Use
+/// `leak_secret_bytes` to get the key material itself.
 #[derive(Clone)]
 pub enum EitherEd25519SecretKey {
     Extended(crypto::SecretKey<crypto::Ed25519Extended>),
@@ -35,16 +42,355 @@ impl EitherEd25519SecretKey {
             EitherEd25519SecretKey::Normal(sk) => sk.sign(dat),
         }
     }
+
+    /// The secret key material, for tools exporting the key. This is
+    /// the only way to get it out of the key.
+    ///
+    /// The returned bytes are not wiped with the key: copies of them have
+    /// to be wiped by the caller.
+    pub fn leak_secret_bytes(&self) -> &[u8] {
+        match self {
+            EitherEd25519SecretKey::Extended(sk) => sk.leak_secret().as_ref(),
+            EitherEd25519SecretKey::Normal(sk) => sk.leak_secret().as_ref(),
+        }
+    }
+
+    /// A short identification of the key, made from its public key only
+    pub fn fingerprint(&self) -> String {
+        let hash = Hash::hash_bytes(self.to_public().as_ref());
+        hash.as_ref()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Whether `a` and `b` are the same bytes, in a time that only depends on
+/// their lengths
+///
+/// Signatures and hashes compared while verifying are compared with this
+/// instead of `==`, which stops at the first differing byte. The lengths
+/// are not hidden: inputs of different lengths are told apart at once.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    ct_eq_bytes(a.iter(), b.iter())
+}
+
+fn ct_eq_bytes<'a, A, B>(a: A, b: B) -> bool
+where
+    A: ExactSizeIterator<Item = &'a u8>,
+    B: ExactSizeIterator<Item = &'a u8>,
+{
+    if a.len() != b.len() {
+        return false;
+    }
+    a.zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The prefix of the passphrases hashed into keys by `from_passphrase`
+#[cfg(any(test, feature = "property-test-api"))]
+const PASSPHRASE_KEY_DOMAIN: &[u8] = b"chain-impl-mockchain passphrase key\0";
+
+#[cfg(any(test, feature = "property-test-api"))]
+impl EitherEd25519SecretKey {
+    /// A key for test fixtures, derived from a human-readable passphrase
+    ///
+    /// The key is the normal Ed25519 key of seed
+    /// `Blake2b256("chain-impl-mockchain passphrase key\0" ++ passphrase)`.
+    /// Fixtures of other projects rely on the keys this gives, so the
+    /// derivation must never change.
+    ///
+    /// Anyone knowing the passphrase has the key: this is not meant for
+    /// keys protecting funds.
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut input = PASSPHRASE_KEY_DOMAIN.to_vec();
+        input.extend_from_slice(passphrase.as_bytes());
+        let seed = crypto::Blake2b256::new(&input);
+        let key = SecretKey::from_binary(seed.as_ref()).expect("a hash is a valid Ed25519 seed");
+        EitherEd25519SecretKey::Normal(key)
+    }
+}
+
+impl std::fmt::Debug for EitherEd25519SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let kind = match self {
+            EitherEd25519SecretKey::Extended(_) => "Extended",
+            EitherEd25519SecretKey::Normal(_) => "Normal",
+        };
+        write!(
+            f,
+            "EitherEd25519SecretKey::{}(<secret key of {}>)",
+            kind,
+            self.fingerprint()
+        )
+    }
+}
+
+// Ed25519 public keys and signatures dedicated to one use, so that a
+// signature can only be verified with a key of the same use without an
+// explicit `into_raw`/`from_raw` conversion.
+macro_rules! ed25519_key_use {
+    ($(#[$pk_doc:meta])* $public_key:ident, $(#[$sig_doc:meta])* $signature:ident) => {
+        $(#[$pk_doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $public_key(crypto::PublicKey<crypto::Ed25519>);
+
+        impl $public_key {
+            pub fn from_secret(secret_key: &EitherEd25519SecretKey) -> Self {
+                $public_key(secret_key.to_public())
+            }
+
+            pub fn from_raw(public_key: crypto::PublicKey<crypto::Ed25519>) -> Self {
+                $public_key(public_key)
+            }
+
+            pub fn as_raw(&self) -> &crypto::PublicKey<crypto::Ed25519> {
+                &self.0
+            }
+
+            pub fn into_raw(self) -> crypto::PublicKey<crypto::Ed25519> {
+                self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $public_key {
+            fn as_ref(&self) -> &[u8] {
+                self.0.as_ref()
+            }
+        }
+
+        impl std::fmt::Display for $public_key {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl property::Serialize for $public_key {
+            type Error = std::io::Error;
+            fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+                serialize_public_key(&self.0, writer)
+            }
+        }
+
+        impl Readable for $public_key {
+            fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+                deserialize_public_key(buf).map($public_key)
+            }
+        }
+
+        /// bech32 in human readable formats, raw bytes otherwise
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $public_key {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use chain_crypto::bech32::Bech32 as _;
+                if serializer.is_human_readable() {
+                    serializer.serialize_str(&self.0.to_bech32_str())
+                } else {
+                    serializer.serialize_bytes(self.0.as_ref())
+                }
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $public_key {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_public_key_serde(deserializer).map($public_key)
+            }
+        }
+
+        $(#[$sig_doc])*
+        pub struct $signature<T>(crypto::Signature<T, crypto::Ed25519>);
+
+        impl<T: AsRef<[u8]>> $signature<T> {
+            pub fn sign(secret_key: &EitherEd25519SecretKey, data: &T) -> Self {
+                $signature(secret_key.sign(data))
+            }
+
+            #[must_use]
+            pub fn verify(&self, public_key: &$public_key, data: &T) -> crypto::Verification {
+                self.0.verify(&public_key.0, data)
+            }
+        }
+
+        impl<T> $signature<T> {
+            pub fn from_raw(signature: crypto::Signature<T, crypto::Ed25519>) -> Self {
+                $signature(signature)
+            }
+
+            pub fn as_raw(&self) -> &crypto::Signature<T, crypto::Ed25519> {
+                &self.0
+            }
+
+            pub fn into_raw(self) -> crypto::Signature<T, crypto::Ed25519> {
+                self.0
+            }
+        }
+
+        impl<T> Clone for $signature<T> {
+            fn clone(&self) -> Self {
+                $signature(self.0.clone())
+            }
+        }
+
+        impl<T> std::fmt::Debug for $signature<T> {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}({:?})", stringify!($signature), self.0)
+            }
+        }
+
+        impl<T> AsRef<[u8]> for $signature<T> {
+            fn as_ref(&self) -> &[u8] {
+                self.0.as_ref()
+            }
+        }
+
+        impl<T> property::Serialize for $signature<T> {
+            type Error = std::io::Error;
+            fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+                serialize_signature(&self.0, writer)
+            }
+        }
+
+        impl<T> Readable for $signature<T> {
+            fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+                deserialize_signature(buf).map($signature)
+            }
+        }
+    };
 }
 
-pub type SpendingPublicKey = crypto::PublicKey<crypto::Ed25519>;
-pub type SpendingSignature<T> = crypto::Signature<T, crypto::Ed25519>;
+ed25519_key_use!(
+    /// Public key of a utxo address
+    SpendingPublicKey,
+    /// Signature made with the key of a utxo address, only verified with a
+    /// `SpendingPublicKey`
+    ///
+    /// ```compile_fail
+    /// use chain_impl_mockchain::key::{AccountPublicKey, SpendingSignature};
+    ///
+    /// fn verify(signature: &SpendingSignature<Vec<u8>>, key: &AccountPublicKey) {
+    ///     let _ = signature.verify(key, &vec![]);
+    /// }
+    /// ```
+    SpendingSignature
+);
 
-pub type AccountPublicKey = crypto::PublicKey<crypto::Ed25519>;
-pub type AccountSignature<T> = crypto::Signature<T, crypto::Ed25519>;
+ed25519_key_use!(
+    /// Public key of a single account
+    AccountPublicKey,
+    /// Signature made with the key of a single account, only verified with
+    /// an `AccountPublicKey`
+    ///
+    /// ```compile_fail
+    /// use chain_impl_mockchain::key::{AccountSignature, SpendingPublicKey};
+    ///
+    /// fn verify(signature: &AccountSignature<Vec<u8>>, key: &SpendingPublicKey) {
+    ///     let _ = signature.verify(key, &vec![]);
+    /// }
+    /// ```
+    AccountSignature
+);
 
 pub type Ed25519Signature<T> = crypto::Signature<T, crypto::Ed25519>;
 
+// a fixed number of bytes, from hex in human readable formats or from
+// raw bytes otherwise
+#[cfg(feature = "serde")]
+struct FixedBytesVisitor {
+    what: &'static str,
+    size: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for FixedBytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} of {} bytes", self.what, self.size)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let bytes =
+            hex::decode(v).map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))?;
+        self.visit_byte_buf(bytes)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        self.visit_byte_buf(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        if v.len() == self.size {
+            Ok(v)
+        } else {
+            Err(E::invalid_length(v.len(), &self))
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_hex_or_bytes<S: serde::Serializer>(
+    bytes: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&hex::encode(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_hex_or_bytes<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+    what: &'static str,
+    size: usize,
+) -> Result<Vec<u8>, D::Error> {
+    let visitor = FixedBytesVisitor { what, size };
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(visitor)
+    } else {
+        deserializer.deserialize_bytes(visitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct Bech32PublicKeyVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for Bech32PublicKeyVisitor {
+    type Value = crypto::PublicKey<crypto::Ed25519>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "a bech32 Ed25519 public key with the prefix {}",
+            <crypto::Ed25519 as AsymmetricPublicKey>::PUBLIC_BECH32_HRP
+        )
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        use chain_crypto::bech32::Bech32 as _;
+        crypto::PublicKey::try_from_bech32_str(v).map_err(E::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_public_key_serde<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<crypto::PublicKey<crypto::Ed25519>, D::Error> {
+    use serde::de::Error as _;
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(Bech32PublicKeyVisitor)
+    } else {
+        let bytes = deserialize_hex_or_bytes(
+            deserializer,
+            "an Ed25519 public key",
+            <crypto::Ed25519 as AsymmetricPublicKey>::PUBLIC_KEY_SIZE,
+        )?;
+        crypto::PublicKey::from_binary(&bytes).map_err(D::Error::custom)
+    }
+}
+
 fn chain_crypto_pub_err(e: crypto::PublicKeyError) -> ReadError {
     match e {
         crypto::PublicKeyError::SizeInvalid => {
@@ -103,6 +449,159 @@ where
     crypto::Signature::from_binary(&bytes).map_err(chain_crypto_sig_err)
 }
 
+const ANY_PUBLIC_KEY_ED25519_TAG: u8 = 1;
+const ANY_PUBLIC_KEY_ED25519_BIP32_TAG: u8 = 2;
+const ANY_PUBLIC_KEY_SUM_ED25519_12_TAG: u8 = 3;
+
+/// A public key of any of the algorithms used in the block payloads,
+/// serialized as a tag byte identifying the algorithm followed by the key
+///
+/// Keys of different algorithms never compare equal, even if their bytes
+/// are the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AnyPublicKey {
+    Ed25519(crypto::PublicKey<crypto::Ed25519>),
+    Ed25519Bip32(crypto::PublicKey<crypto::Ed25519Bip32>),
+    SumEd25519(crypto::PublicKey<crypto::SumEd25519_12>),
+}
+
+impl AnyPublicKey {
+    pub fn algorithm_name(&self) -> &'static str {
+        match self {
+            AnyPublicKey::Ed25519(_) => "ed25519",
+            AnyPublicKey::Ed25519Bip32(_) => "ed25519-bip32",
+            AnyPublicKey::SumEd25519(_) => "sum-ed25519-12",
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            AnyPublicKey::Ed25519(_) => ANY_PUBLIC_KEY_ED25519_TAG,
+            AnyPublicKey::Ed25519Bip32(_) => ANY_PUBLIC_KEY_ED25519_BIP32_TAG,
+            AnyPublicKey::SumEd25519(_) => ANY_PUBLIC_KEY_SUM_ED25519_12_TAG,
+        }
+    }
+
+    /// the Ed25519 key, or the key itself if it is of another algorithm
+    pub fn into_ed25519(self) -> Result<crypto::PublicKey<crypto::Ed25519>, Self> {
+        match self {
+            AnyPublicKey::Ed25519(key) => Ok(key),
+            other => Err(other),
+        }
+    }
+
+    /// the Ed25519Bip32 key, or the key itself if it is of another algorithm
+    pub fn into_ed25519_bip32(self) -> Result<crypto::PublicKey<crypto::Ed25519Bip32>, Self> {
+        match self {
+            AnyPublicKey::Ed25519Bip32(key) => Ok(key),
+            other => Err(other),
+        }
+    }
+
+    /// the SumEd25519_12 key, or the key itself if it is of another algorithm
+    pub fn into_sum_ed25519(self) -> Result<crypto::PublicKey<crypto::SumEd25519_12>, Self> {
+        match self {
+            AnyPublicKey::SumEd25519(key) => Ok(key),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<crypto::PublicKey<crypto::Ed25519>> for AnyPublicKey {
+    fn from(key: crypto::PublicKey<crypto::Ed25519>) -> Self {
+        AnyPublicKey::Ed25519(key)
+    }
+}
+
+impl From<crypto::PublicKey<crypto::Ed25519Bip32>> for AnyPublicKey {
+    fn from(key: crypto::PublicKey<crypto::Ed25519Bip32>) -> Self {
+        AnyPublicKey::Ed25519Bip32(key)
+    }
+}
+
+impl From<crypto::PublicKey<crypto::SumEd25519_12>> for AnyPublicKey {
+    fn from(key: crypto::PublicKey<crypto::SumEd25519_12>) -> Self {
+        AnyPublicKey::SumEd25519(key)
+    }
+}
+
+impl property::Serialize for AnyPublicKey {
+    type Error = std::io::Error;
+
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        writer.write_all(&[self.tag()])?;
+        match self {
+            AnyPublicKey::Ed25519(key) => serialize_public_key(key, writer),
+            AnyPublicKey::Ed25519Bip32(key) => serialize_public_key(key, writer),
+            AnyPublicKey::SumEd25519(key) => serialize_public_key(key, writer),
+        }
+    }
+}
+
+impl Readable for AnyPublicKey {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        match buf.get_u8()? {
+            ANY_PUBLIC_KEY_ED25519_TAG => deserialize_public_key(buf).map(AnyPublicKey::Ed25519),
+            ANY_PUBLIC_KEY_ED25519_BIP32_TAG => {
+                deserialize_public_key(buf).map(AnyPublicKey::Ed25519Bip32)
+            }
+            ANY_PUBLIC_KEY_SUM_ED25519_12_TAG => {
+                deserialize_public_key(buf).map(AnyPublicKey::SumEd25519)
+            }
+            tag => Err(ReadError::UnknownTag(tag as u32)),
+        }
+    }
+}
+
+/// The serialized bytes of some data, to sign them or verify signatures
+/// of them several times without serializing the data again
+pub struct SignedMessage<'a, T> {
+    bytes: Vec<u8>,
+    data: std::marker::PhantomData<&'a T>,
+}
+
+impl<'a, T: property::Serialize> SignedMessage<'a, T> {
+    /// serialize `data`, fails if it cannot be serialized
+    pub fn new(data: &'a T) -> Result<Self, T::Error> {
+        Ok(SignedMessage {
+            bytes: data.serialize_as_vec()?,
+            data: std::marker::PhantomData,
+        })
+    }
+}
+
+impl<'a, T> SignedMessage<'a, T> {
+    /// the serialized bytes of the data, e.g. to hash them
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn sign<A>(&self, secret_key: &crypto::SecretKey<A>) -> crypto::Signature<T, A::PubAlg>
+    where
+        A: SigningAlgorithm,
+        <A as AsymmetricKey>::PubAlg: VerificationAlgorithm,
+    {
+        secret_key.sign(&self.bytes).coerce()
+    }
+
+    pub fn verify<A>(
+        &self,
+        signature: &crypto::Signature<T, A>,
+        public_key: &crypto::PublicKey<A>,
+    ) -> crypto::Verification
+    where
+        A: VerificationAlgorithm,
+    {
+        verify_signature_raw(signature, public_key, &self.bytes)
+    }
+}
+
+/// sign the serialized bytes of `data` with the given secret key
+///
+/// # Panics
+///
+/// if `data` cannot be serialized, use `try_make_signature` to handle
+/// the error instead.
 pub fn make_signature<T, A>(
     spending_key: &crypto::SecretKey<A>,
     data: &T,
@@ -112,10 +611,29 @@ where
     <A as AsymmetricKey>::PubAlg: VerificationAlgorithm,
     T: property::Serialize,
 {
-    let bytes = data.serialize_as_vec().unwrap();
-    spending_key.sign(&bytes).coerce()
+    try_make_signature(spending_key, data).unwrap()
+}
+
+/// sign the serialized bytes of `data` with the given secret key,
+/// fails if `data` cannot be serialized
+pub fn try_make_signature<T, A>(
+    spending_key: &crypto::SecretKey<A>,
+    data: &T,
+) -> Result<crypto::Signature<T, A::PubAlg>, T::Error>
+where
+    A: SigningAlgorithm,
+    <A as AsymmetricKey>::PubAlg: VerificationAlgorithm,
+    T: property::Serialize,
+{
+    Ok(SignedMessage::new(data)?.sign(spending_key))
 }
 
+/// verify the signature against the serialized bytes of `data`
+///
+/// # Panics
+///
+/// if `data` cannot be serialized, use `try_verify_signature` to handle
+/// the error instead.
 pub fn verify_signature<T, A>(
     signature: &crypto::Signature<T, A>,
     public_key: &crypto::PublicKey<A>,
@@ -125,10 +643,72 @@ where
     A: VerificationAlgorithm,
     T: property::Serialize,
 {
-    let bytes = data.serialize_as_vec().unwrap();
-    signature.clone().coerce().verify(public_key, &bytes)
+    try_verify_signature(signature, public_key, data).unwrap()
+}
+
+/// verify the signature against the serialized bytes of `data`,
+/// fails if `data` cannot be serialized
+pub fn try_verify_signature<T, A>(
+    signature: &crypto::Signature<T, A>,
+    public_key: &crypto::PublicKey<A>,
+    data: &T,
+) -> Result<crypto::Verification, T::Error>
+where
+    A: VerificationAlgorithm,
+    T: property::Serialize,
+{
+    Ok(SignedMessage::new(data)?.verify(signature, public_key))
+}
+
+/// verify the signature against the already serialized bytes of the
+/// signed data
+pub fn verify_signature_raw<T, A>(
+    signature: &crypto::Signature<T, A>,
+    public_key: &crypto::PublicKey<A>,
+    bytes: &[u8],
+) -> crypto::Verification
+where
+    A: VerificationAlgorithm,
+{
+    signature.verify_slice(public_key, bytes)
+}
+
+/// An Ed25519 signature to verify in a batch: the signed bytes, the
+/// public key and the signature
+pub type SignatureBatchItem<'a, T> = (
+    &'a [u8],
+    &'a crypto::PublicKey<crypto::Ed25519>,
+    &'a crypto::Signature<T, crypto::Ed25519>,
+);
+
+/// verify all the signatures of `items`, succeeding only if every one of
+/// them verifies; an empty batch verifies
+///
+/// chain-crypto does not have a batch verification primitive yet, so the
+/// signatures are verified one after the other until one fails. Callers
+/// get the speedup of batch verification once it is added there.
+pub fn verify_signatures_batch<T>(items: &[SignatureBatchItem<T>]) -> crypto::Verification {
+    match verify_batch_locating(items) {
+        Ok(()) => crypto::Verification::Success,
+        Err(_) => crypto::Verification::Failed,
+    }
+}
+
+/// same as `verify_signatures_batch`, failing with the index of the first
+/// item that does not verify, e.g. to report the input of its witness
+pub fn verify_batch_locating<T>(items: &[SignatureBatchItem<T>]) -> Result<(), usize> {
+    match items.iter().position(|(bytes, public_key, signature)| {
+        verify_signature_raw(signature, public_key, bytes) == crypto::Verification::Failed
+    }) {
+        Some(index) => Err(index),
+        None => Ok(()),
+    }
 }
 
+/// # Panics
+///
+/// if `public_key` is empty or if `data` cannot be serialized, use
+/// `try_verify_multi_signature` to handle the serialization error instead.
 pub fn verify_multi_signature<T, A>(
     signature: &crypto::Signature<T, A>,
     public_key: &[crypto::PublicKey<A>],
@@ -139,8 +719,26 @@ where
     T: property::Serialize,
 {
     assert!(public_key.len() > 0);
-    let bytes = data.serialize_as_vec().unwrap();
-    signature.clone().coerce().verify(&public_key[0], &bytes)
+    try_verify_multi_signature(signature, public_key, data).unwrap()
+}
+
+/// verify the signature against the serialized bytes of `data` with the
+/// first of the public keys, fails if `data` cannot be serialized. The
+/// signature does not verify against an empty list of keys.
+pub fn try_verify_multi_signature<T, A>(
+    signature: &crypto::Signature<T, A>,
+    public_key: &[crypto::PublicKey<A>],
+    data: &T,
+) -> Result<crypto::Verification, T::Error>
+where
+    A: VerificationAlgorithm,
+    T: property::Serialize,
+{
+    let message = SignedMessage::new(data)?;
+    match public_key.first() {
+        None => Ok(crypto::Verification::Failed),
+        Some(public_key) => Ok(message.verify(signature, public_key)),
+    }
 }
 
 /// A serializable type T with a signature.
@@ -149,6 +747,10 @@ pub struct Signed<T, A: VerificationAlgorithm> {
     pub sig: crypto::Signature<T, A>,
 }
 
+/// # Panics
+///
+/// if `data` cannot be serialized, use `try_signed_new` to handle
+/// the error instead.
 pub fn signed_new<T: property::Serialize, A: SigningAlgorithm>(
     secret_key: &crypto::SecretKey<A>,
     data: T,
@@ -156,12 +758,21 @@ pub fn signed_new<T: property::Serialize, A: SigningAlgorithm>(
 where
     A::PubAlg: VerificationAlgorithm,
 {
-    let bytes = data.serialize_as_vec().unwrap();
-    let signature = secret_key.sign(&bytes).coerce();
-    Signed {
+    try_signed_new(secret_key, data).unwrap()
+}
+
+pub fn try_signed_new<T: property::Serialize, A: SigningAlgorithm>(
+    secret_key: &crypto::SecretKey<A>,
+    data: T,
+) -> Result<Signed<T, A::PubAlg>, T::Error>
+where
+    A::PubAlg: VerificationAlgorithm,
+{
+    let signature = try_make_signature(secret_key, &data)?;
+    Ok(Signed {
         data: data,
         sig: signature,
-    }
+    })
 }
 
 impl<T: property::Serialize, A: VerificationAlgorithm> property::Serialize for Signed<T, A>
@@ -187,7 +798,7 @@ impl<T: Readable, A: VerificationAlgorithm> Readable for Signed<T, A> {
 
 impl<T: PartialEq, A: VerificationAlgorithm> PartialEq<Self> for Signed<T, A> {
     fn eq(&self, other: &Self) -> bool {
-        self.data.eq(&other.data) && self.sig.as_ref() == other.sig.as_ref()
+        self.data.eq(&other.data) && ct_eq(self.sig.as_ref(), other.sig.as_ref())
     }
 }
 impl<T: PartialEq, A: VerificationAlgorithm> Eq for Signed<T, A> {}
@@ -210,66 +821,490 @@ impl<T: Clone, A: VerificationAlgorithm> Clone for Signed<T, A> {
     }
 }
 
-/// Hash that is used as an address of the various components.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Hash(crypto::Blake2b256);
-impl Hash {
-    pub fn hash_bytes(bytes: &[u8]) -> Self {
-        Hash(crypto::Blake2b256::new(bytes))
-    }
-    pub fn from_bytes(bytes: [u8; 32]) -> Self {
-        Hash(crypto::Blake2b256::from(bytes))
-    }
-}
+/// The signature of a `Signed`, on its own, serialized with serde as hex
+/// in human readable formats and as raw bytes otherwise, e.g. to show
+/// the signature alongside a summary of the signed data
+#[cfg(feature = "serde")]
+pub struct SignedHex<T, A: VerificationAlgorithm>(pub crypto::Signature<T, A>);
 
-impl From<[u8; 32]> for Hash {
-    fn from(a: [u8; 32]) -> Self {
-        Hash::from_bytes(a)
+#[cfg(feature = "serde")]
+impl<'a, T, A: VerificationAlgorithm> From<&'a Signed<T, A>> for SignedHex<T, A> {
+    fn from(signed: &'a Signed<T, A>) -> Self {
+        SignedHex(signed.sig.clone())
     }
 }
 
-impl From<Hash> for [u8; 32] {
-    fn from(h: Hash) -> Self {
-        h.0.into()
+#[cfg(feature = "serde")]
+impl<T, A: VerificationAlgorithm> PartialEq<Self> for SignedHex<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(self.0.as_ref(), other.0.as_ref())
     }
 }
 
-impl property::Serialize for Hash {
-    type Error = std::io::Error;
-    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
-        writer.write_all(self.0.as_hash_bytes())?;
-        Ok(())
+#[cfg(feature = "serde")]
+impl<T, A: VerificationAlgorithm> std::fmt::Debug for SignedHex<T, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SignedHex({})", hex::encode(self.0.as_ref()))
     }
 }
 
-impl property::Deserialize for Hash {
-    type Error = std::io::Error;
-    fn deserialize<R: std::io::BufRead>(mut reader: R) -> Result<Self, Self::Error> {
-        let mut buffer = [0; crypto::Blake2b256::HASH_SIZE];
-        reader.read_exact(&mut buffer)?;
-        Ok(Hash(crypto::Blake2b256::from(buffer)))
+#[cfg(feature = "serde")]
+impl<T, A: VerificationAlgorithm> serde::Serialize for SignedHex<T, A> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_hex_or_bytes(self.0.as_ref(), serializer)
     }
 }
 
-impl Readable for Hash {
-    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
-        let bytes = <[u8; crypto::Blake2b256::HASH_SIZE]>::read(buf)?;
-        Ok(Hash(crypto::Blake2b256::from(bytes)))
+#[cfg(feature = "serde")]
+impl<'de, T, A: VerificationAlgorithm> serde::Deserialize<'de> for SignedHex<T, A> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+        let bytes = deserialize_hex_or_bytes(deserializer, "a signature", A::SIGNATURE_SIZE)?;
+        crypto::Signature::from_binary(&bytes)
+            .map(SignedHex)
+            .map_err(D::Error::custom)
     }
 }
 
-impl property::BlockId for Hash {
-    fn zero() -> Hash {
-        Hash(crypto::Blake2b256::from([0; crypto::Blake2b256::HASH_SIZE]))
-    }
-}
+/// leading byte of a `Signed` inside an `AnySigned`
+const SIGNED_TAG: u8 = 0;
+/// leading byte of a `SignedByHash`
+const SIGNED_BY_HASH_TAG: u8 = 1;
 
-impl property::FragmentId for Hash {}
+/// A serializable type T with a signature of the hash of its serialized
+/// bytes, instead of the bytes themselves like `Signed`. The tag of the
+/// mode is signed along with the hash.
+///
+/// The signature can then be verified with `verify_by_hash` from a hash
+/// computed earlier, without having the serialized data in memory.
+pub struct SignedByHash<T, A: VerificationAlgorithm> {
+    pub data: T,
+    pub sig: crypto::Signature<Hash, A>,
+}
 
-impl AsRef<[u8]> for Hash {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_ref()
-    }
+/// # Panics
+///
+/// if `data` cannot be serialized, use `try_signed_by_hash_new` to handle
+/// the error instead.
+pub fn signed_by_hash_new<T: property::Serialize, A: SigningAlgorithm>(
+    secret_key: &crypto::SecretKey<A>,
+    data: T,
+) -> SignedByHash<T, A::PubAlg>
+where
+    A::PubAlg: VerificationAlgorithm,
+{
+    try_signed_by_hash_new(secret_key, data).unwrap()
+}
+
+pub fn try_signed_by_hash_new<T: property::Serialize, A: SigningAlgorithm>(
+    secret_key: &crypto::SecretKey<A>,
+    data: T,
+) -> Result<SignedByHash<T, A::PubAlg>, T::Error>
+where
+    A::PubAlg: VerificationAlgorithm,
+{
+    let hash = Hash::hash_serializable(&data)?;
+    Ok(SignedByHash {
+        data: data,
+        sig: secret_key.sign(&by_hash_message(&hash)).coerce(),
+    })
+}
+
+// the bytes signed for a `SignedByHash`: the hash after the tag of the
+// mode, so that the signature of a `Signed` never verifies as one
+fn by_hash_message(hash: &Hash) -> Vec<u8> {
+    let mut message = Vec::with_capacity(1 + hash.as_ref().len());
+    message.push(SIGNED_BY_HASH_TAG);
+    message.extend_from_slice(hash.as_ref());
+    message
+}
+
+/// verify the signature of a `SignedByHash` against the hash of the
+/// serialized bytes of its data
+pub fn verify_by_hash<A>(
+    signature: &crypto::Signature<Hash, A>,
+    public_key: &crypto::PublicKey<A>,
+    hash: &Hash,
+) -> crypto::Verification
+where
+    A: VerificationAlgorithm,
+{
+    signature.verify_slice(public_key, &by_hash_message(hash))
+}
+
+impl<T: property::Serialize, A: VerificationAlgorithm> SignedByHash<T, A> {
+    /// verify the signature against the data, fails if the data cannot
+    /// be serialized
+    pub fn verify(
+        &self,
+        public_key: &crypto::PublicKey<A>,
+    ) -> Result<crypto::Verification, T::Error> {
+        let hash = Hash::hash_serializable(&self.data)?;
+        Ok(verify_by_hash(&self.sig, public_key, &hash))
+    }
+}
+
+impl<T: property::Serialize, A: VerificationAlgorithm> property::Serialize for SignedByHash<T, A>
+where
+    std::io::Error: From<T::Error>,
+{
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        writer.write_all(&[SIGNED_BY_HASH_TAG])?;
+        self.data.serialize(&mut writer)?;
+        serialize_signature(&self.sig, &mut writer)?;
+        Ok(())
+    }
+}
+
+impl<T: Readable, A: VerificationAlgorithm> SignedByHash<T, A> {
+    fn read_untagged<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        Ok(SignedByHash {
+            data: T::read(buf)?,
+            sig: deserialize_signature(buf)?,
+        })
+    }
+}
+
+impl<T: Readable, A: VerificationAlgorithm> Readable for SignedByHash<T, A> {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        match buf.get_u8()? {
+            SIGNED_BY_HASH_TAG => Self::read_untagged(buf),
+            tag => Err(ReadError::UnknownTag(tag as u32)),
+        }
+    }
+}
+
+impl<T: PartialEq, A: VerificationAlgorithm> PartialEq<Self> for SignedByHash<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.data.eq(&other.data) && ct_eq(self.sig.as_ref(), other.sig.as_ref())
+    }
+}
+impl<T: PartialEq, A: VerificationAlgorithm> Eq for SignedByHash<T, A> {}
+impl<T: std::fmt::Debug, A: VerificationAlgorithm> std::fmt::Debug for SignedByHash<T, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "SignedByHash ( data: {:?}, signature: {:?} )",
+            self.data,
+            self.sig.as_ref()
+        )
+    }
+}
+impl<T: Clone, A: VerificationAlgorithm> Clone for SignedByHash<T, A> {
+    fn clone(&self) -> Self {
+        SignedByHash {
+            data: self.data.clone(),
+            sig: self.sig.clone(),
+        }
+    }
+}
+
+/// Data signed either way, serialized with a leading tag byte so that
+/// readers can tell which one it is.
+pub enum AnySigned<T, A: VerificationAlgorithm> {
+    Full(Signed<T, A>),
+    ByHash(SignedByHash<T, A>),
+}
+
+impl<T: property::Serialize, A: VerificationAlgorithm> AnySigned<T, A> {
+    pub fn data(&self) -> &T {
+        match self {
+            AnySigned::Full(signed) => &signed.data,
+            AnySigned::ByHash(signed) => &signed.data,
+        }
+    }
+
+    /// verify the signature the way it was made, fails if the data cannot
+    /// be serialized
+    pub fn verify(
+        &self,
+        public_key: &crypto::PublicKey<A>,
+    ) -> Result<crypto::Verification, T::Error> {
+        match self {
+            AnySigned::Full(signed) => try_verify_signature(&signed.sig, public_key, &signed.data),
+            AnySigned::ByHash(signed) => signed.verify(public_key),
+        }
+    }
+}
+
+impl<T: property::Serialize, A: VerificationAlgorithm> property::Serialize for AnySigned<T, A>
+where
+    std::io::Error: From<T::Error>,
+{
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        match self {
+            AnySigned::Full(signed) => {
+                writer.write_all(&[SIGNED_TAG])?;
+                signed.serialize(&mut writer)
+            }
+            AnySigned::ByHash(signed) => signed.serialize(&mut writer),
+        }
+    }
+}
+
+impl<T: Readable, A: VerificationAlgorithm> Readable for AnySigned<T, A> {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        match buf.get_u8()? {
+            SIGNED_TAG => Signed::read(buf).map(AnySigned::Full),
+            SIGNED_BY_HASH_TAG => SignedByHash::read_untagged(buf).map(AnySigned::ByHash),
+            tag => Err(ReadError::UnknownTag(tag as u32)),
+        }
+    }
+}
+
+impl<T: PartialEq, A: VerificationAlgorithm> PartialEq<Self> for AnySigned<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AnySigned::Full(a), AnySigned::Full(b)) => a == b,
+            (AnySigned::ByHash(a), AnySigned::ByHash(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+impl<T: PartialEq, A: VerificationAlgorithm> Eq for AnySigned<T, A> {}
+impl<T: std::fmt::Debug, A: VerificationAlgorithm> std::fmt::Debug for AnySigned<T, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AnySigned::Full(signed) => std::fmt::Debug::fmt(signed, f),
+            AnySigned::ByHash(signed) => std::fmt::Debug::fmt(signed, f),
+        }
+    }
+}
+impl<T: Clone, A: VerificationAlgorithm> Clone for AnySigned<T, A> {
+    fn clone(&self) -> Self {
+        match self {
+            AnySigned::Full(signed) => AnySigned::Full(signed.clone()),
+            AnySigned::ByHash(signed) => AnySigned::ByHash(signed.clone()),
+        }
+    }
+}
+
+/// Hash that is used as an address of the various components.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash(crypto::Blake2b256);
+impl Hash {
+    pub fn hash_bytes(bytes: &[u8]) -> Self {
+        Hash(crypto::Blake2b256::new(bytes))
+    }
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Hash(crypto::Blake2b256::from(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_hash_bytes()
+    }
+
+    /// same as `==`, in constant time, see `ct_eq`
+    pub fn ct_eq(&self, other: &Hash) -> bool {
+        ct_eq(self.as_bytes(), other.as_bytes())
+    }
+
+    /// the hash made only of zeros, same as `BlockId::zero`
+    pub fn zero() -> Self {
+        Hash::from_bytes([0; crypto::Blake2b256::HASH_SIZE])
+    }
+
+    /// hash of the serialized bytes of `t`
+    ///
+    /// # Panics
+    ///
+    /// if `t` cannot be serialized, use `hash_serializable` to handle
+    /// the error instead.
+    pub fn digest<T: property::Serialize>(t: &T) -> Self {
+        Hash::hash_serializable(t).unwrap()
+    }
+
+    /// hash everything `reader` yields until the end of the stream, without
+    /// holding more than one chunk in memory
+    pub fn hash_reader<R: std::io::Read>(mut reader: R) -> std::io::Result<Self> {
+        let mut context = HashContext::new();
+        let mut chunk = [0; 4096];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => return Ok(context.finalize()),
+                Ok(read) => context.append(&chunk[..read]),
+                Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// hash the serialized form of `t`, without building it in memory first
+    pub fn hash_serializable<T: property::Serialize>(t: &T) -> Result<Self, T::Error> {
+        let mut context = HashContext::new();
+        t.serialize(&mut context)?;
+        Ok(context.finalize())
+    }
+
+    /// hash `bytes` in the domain `tag`: the same bytes hashed with two
+    /// different tags give unrelated hashes
+    ///
+    /// The hashed input is the length of the tag (4 bytes, big endian),
+    /// the tag then `bytes`, so no tag is a prefix of another.
+    pub fn hash_with_tag(tag: &'static [u8], bytes: &[u8]) -> Self {
+        let mut context = HashContext::new();
+        context.append(&(tag.len() as u32).to_be_bytes());
+        context.append(tag);
+        context.append(bytes);
+        context.finalize()
+    }
+
+    /// hash `bytes` in the domain of `T`, see `hash_with_tag`
+    pub fn of<T: HashTag>(bytes: &[u8]) -> Self {
+        Hash::hash_with_tag(T::TAG, bytes)
+    }
+}
+
+/// The domain of the hashes of one kind of object, for `Hash::of`
+pub trait HashTag {
+    const TAG: &'static [u8];
+}
+
+/// The domain of the block ids
+pub struct BlockIdTag;
+
+impl HashTag for BlockIdTag {
+    const TAG: &'static [u8] = b"block-id";
+}
+
+/// The domain of the fragment ids
+pub struct FragmentIdTag;
+
+impl HashTag for FragmentIdTag {
+    const TAG: &'static [u8] = b"fragment-id";
+}
+
+/// How the ids of the blocks and fragments are computed
+///
+/// Switching to the tagged scheme changes every id, so the legacy
+/// untagged hashing stays the default. The scheme of a chain is the
+/// `hash-scheme` config parameter, see `Ledger::hash_scheme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashScheme {
+    /// plain hash of the bytes, the same for every kind of object
+    Legacy,
+    /// hash of the bytes in the domain of the kind of object
+    Tagged,
+}
+
+impl HashScheme {
+    pub fn hash<T: HashTag>(self, bytes: &[u8]) -> Hash {
+        match self {
+            HashScheme::Legacy => Hash::hash_bytes(bytes),
+            HashScheme::Tagged => Hash::of::<T>(bytes),
+        }
+    }
+}
+
+impl Default for HashScheme {
+    fn default() -> Self {
+        HashScheme::Legacy
+    }
+}
+
+/// Incremental construction of a `Hash`
+///
+/// Appending the bytes in any number of pieces gives the same hash as
+/// `Hash::hash_bytes` on their concatenation.
+#[derive(Clone)]
+pub struct HashContext(crypto::digest::Context<crypto::Blake2b256>);
+
+impl HashContext {
+    pub fn new() -> Self {
+        HashContext(crypto::digest::Context::new())
+    }
+
+    pub fn append(&mut self, bytes: &[u8]) {
+        self.0.append_data(bytes)
+    }
+
+    pub fn finalize(self) -> Hash {
+        let bytes: [u8; 32] = self.0.finalize().into();
+        Hash::from_bytes(bytes)
+    }
+}
+
+impl Default for HashContext {
+    fn default() -> Self {
+        HashContext::new()
+    }
+}
+
+impl std::io::Write for HashContext {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.append(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl From<[u8; 32]> for Hash {
+    fn from(a: [u8; 32]) -> Self {
+        Hash::from_bytes(a)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Hash {
+    type Error = crypto::hash::Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        crypto::Blake2b256::try_from_slice(bytes).map(Hash)
+    }
+}
+
+impl<T> From<crypto::digest::DigestOf<crypto::Blake2b256, T>> for Hash {
+    fn from(digest: crypto::digest::DigestOf<crypto::Blake2b256, T>) -> Self {
+        Hash::from_bytes(digest.into())
+    }
+}
+
+impl From<Hash> for [u8; 32] {
+    fn from(h: Hash) -> Self {
+        h.0.into()
+    }
+}
+
+impl property::Serialize for Hash {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        writer.write_all(self.0.as_hash_bytes())?;
+        Ok(())
+    }
+}
+
+impl property::Deserialize for Hash {
+    type Error = std::io::Error;
+    fn deserialize<R: std::io::BufRead>(mut reader: R) -> Result<Self, Self::Error> {
+        let mut buffer = [0; crypto::Blake2b256::HASH_SIZE];
+        reader.read_exact(&mut buffer)?;
+        Ok(Hash(crypto::Blake2b256::from(buffer)))
+    }
+}
+
+impl Readable for Hash {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let bytes = <[u8; crypto::Blake2b256::HASH_SIZE]>::read(buf)?;
+        Ok(Hash(crypto::Blake2b256::from(bytes)))
+    }
+}
+
+impl property::BlockId for Hash {
+    fn zero() -> Hash {
+        Hash::zero()
+    }
+}
+
+impl property::FragmentId for Hash {}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
 }
 
 impl From<crypto::Blake2b256> for Hash {
@@ -291,15 +1326,1039 @@ impl FromStr for Hash {
     }
 }
 
+/// hex in human readable formats, raw bytes otherwise
+#[cfg(feature = "serde")]
+impl serde::Serialize for Hash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_hex_or_bytes(self.as_bytes(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Hash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes =
+            deserialize_hex_or_bytes(deserializer, "a hash", crypto::Blake2b256::HASH_SIZE)?;
+        let mut hash = [0; crypto::Blake2b256::HASH_SIZE];
+        hash.copy_from_slice(&bytes);
+        Ok(Hash::from_bytes(hash))
+    }
+}
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub HashPrefixError
+        Empty = "Hash prefix is empty",
+        TooLong { length: usize } = "Hash prefix of {length} bytes is longer than a hash",
+        InvalidHex = "Hash prefix is not made of hexadecimal byte pairs",
+}
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub PrefixResolutionError
+        NotFound { prefix: HashPrefix } = "No hash starts with {prefix}",
+        AmbiguousPrefix { prefix: HashPrefix } = "More than one hash starts with {prefix}",
+}
+
+/// The first bytes of a hash, to refer to a hash in a shorter form as
+/// long as no other hash starts with the same bytes
+///
+/// The text form is the bytes in hexadecimal, like the text form of `Hash`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HashPrefix(Vec<u8>);
+
+impl HashPrefix {
+    /// a prefix of 1 to 32 bytes
+    pub fn new(bytes: &[u8]) -> Result<Self, HashPrefixError> {
+        if bytes.is_empty() {
+            Err(HashPrefixError::Empty)
+        } else if bytes.len() > crypto::Blake2b256::HASH_SIZE {
+            Err(HashPrefixError::TooLong {
+                length: bytes.len(),
+            })
+        } else {
+            Ok(HashPrefix(bytes.to_vec()))
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn matches(&self, hash: &Hash) -> bool {
+        hash.as_bytes().starts_with(&self.0)
+    }
+
+    /// the only hash of `hashes` starting with this prefix
+    ///
+    /// Stops at the second matching hash, so a prefix shared by a lot
+    /// of the hashes does not take a full scan to be found ambiguous.
+    pub fn resolve<'a, I>(&self, hashes: I) -> Result<&'a Hash, PrefixResolutionError>
+    where
+        I: IntoIterator<Item = &'a Hash>,
+    {
+        let mut matching = hashes.into_iter().filter(|hash| self.matches(hash));
+        match (matching.next(), matching.next()) {
+            (Some(hash), None) => Ok(hash),
+            (None, _) => Err(PrefixResolutionError::NotFound {
+                prefix: self.clone(),
+            }),
+            (Some(_), Some(_)) => Err(PrefixResolutionError::AmbiguousPrefix {
+                prefix: self.clone(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for HashPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HashPrefix {
+    type Err = HashPrefixError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s).map_err(|_| HashPrefixError::InvalidHex)?;
+        HashPrefix::new(&bytes)
+    }
+}
+
+/// The fewest keys of a `MultisigSpec`, as for the owners of a
+/// `multisig::Declaration`
+pub const MULTISIG_MIN_KEYS: usize = 2;
+
+/// The most keys of a `MultisigSpec`, as for the owners of a
+/// `multisig::Declaration`
+pub const MULTISIG_MAX_KEYS: usize = 8;
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub MultisigSpecError
+        ThresholdInvalid { threshold: u8, keys: usize } = "Threshold {threshold} is not between 1 and the number of keys, {keys}",
+        NotEnoughKeys { keys: usize } = "{keys} keys given, a multisig has at least 2",
+        TooManyKeys { keys: usize } = "{keys} keys given, a multisig has at most 8",
+        DuplicateKey { key: AccountPublicKey } = "Key {key} is given twice",
+}
+
+/// The keys of the participants of a multisig account, any `threshold`
+/// of which can spend from it
+///
+/// The keys are kept sorted by their bytes, so that the same set of keys
+/// given in any order makes the same spec, with the same identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigSpec {
+    threshold: u8,
+    keys: Vec<AccountPublicKey>,
+}
+
+impl MultisigSpec {
+    /// Check that `MULTISIG_MIN_KEYS <= keys.len() <= MULTISIG_MAX_KEYS`,
+    /// that `1 <= threshold <= keys.len()` and that no key is given twice,
+    /// the bounds of `multisig::Declaration::is_valid`
+    pub fn new(threshold: u8, mut keys: Vec<AccountPublicKey>) -> Result<Self, MultisigSpecError> {
+        if keys.len() < MULTISIG_MIN_KEYS {
+            return Err(MultisigSpecError::NotEnoughKeys { keys: keys.len() });
+        }
+        if keys.len() > MULTISIG_MAX_KEYS {
+            return Err(MultisigSpecError::TooManyKeys { keys: keys.len() });
+        }
+        if threshold < 1 || threshold as usize > keys.len() {
+            return Err(MultisigSpecError::ThresholdInvalid {
+                threshold,
+                keys: keys.len(),
+            });
+        }
+        keys.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        if let Some(pair) = keys.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MultisigSpecError::DuplicateKey {
+                key: pair[0].clone(),
+            });
+        }
+        Ok(MultisigSpec { threshold, keys })
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// the keys, sorted by their bytes
+    pub fn keys(&self) -> &[AccountPublicKey] {
+        &self.keys
+    }
+
+    /// the declaration of the multisig account, with an owner for each
+    /// key in the order of `keys`
+    pub fn declaration(&self) -> multisig::Declaration {
+        let declaration = multisig::Declaration {
+            threshold: self.threshold,
+            owners: self
+                .keys
+                .iter()
+                .map(|key| multisig::DeclElement::from_publickey(key.as_raw()))
+                .collect(),
+        };
+        debug_assert!(declaration.is_valid().is_ok());
+        declaration
+    }
+
+    /// the identifier of the multisig account, that of its `declaration`
+    pub fn identifier(&self) -> multisig::Identifier {
+        self.declaration().to_identifier()
+    }
+}
+
+/// The identifier of the multisig account of `keys` with `threshold`,
+/// whatever the order of the keys, see `MultisigSpec::identifier`
+///
+/// Panics if the keys and threshold do not make a valid `MultisigSpec`.
+pub fn multisig_identifier(threshold: u8, keys: &[AccountPublicKey]) -> multisig::Identifier {
+    match MultisigSpec::new(threshold, keys.to_vec()) {
+        Ok(spec) => spec.identifier(),
+        Err(error) => panic!("invalid multisig spec: {}", error),
+    }
+}
+
+impl property::Serialize for MultisigSpec {
+    type Error = std::io::Error;
+
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        writer.write_all(&[self.threshold, self.keys.len() as u8])?;
+        for key in &self.keys {
+            property::Serialize::serialize(key, &mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl Readable for MultisigSpec {
+    /// Only the canonical form is accepted: a valid spec with its keys
+    /// sorted.
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let threshold = buf.get_u8()?;
+        let count = buf.get_u8()? as usize;
+        if count > MULTISIG_MAX_KEYS {
+            return Err(ReadError::StructureInvalid(
+                MultisigSpecError::TooManyKeys { keys: count }.to_string(),
+            ));
+        }
+        let mut keys = Vec::with_capacity(count);
+        for _ in 0..count {
+            keys.push(AccountPublicKey::read(buf)?);
+        }
+        let spec = MultisigSpec::new(threshold, keys.clone())
+            .map_err(|error| ReadError::StructureInvalid(error.to_string()))?;
+        if spec.keys != keys {
+            return Err(ReadError::StructureInvalid(
+                "multisig keys are not sorted".to_string(),
+            ));
+        }
+        Ok(spec)
+    }
+}
+
 #[cfg(any(test, feature = "property-test-api"))]
 pub mod test {
     use super::*;
     use quickcheck::{Arbitrary, Gen};
 
+    /// first byte of the long prefix shared by the near-colliding hashes
+    const SHARED_PREFIX_BYTE: u8 = 0x5a;
+
     impl Arbitrary for Hash {
+        /// mostly uniformly random hashes, but also the all-zero and
+        /// all-ones hashes, and hashes sharing a long prefix with each other
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            match g.next_u32() % 10 {
+                0 => Hash::zero(),
+                1 => Hash::from_bytes([0xff; 32]),
+                2 | 3 => {
+                    let mut bytes = [SHARED_PREFIX_BYTE; 32];
+                    // keep at least 24 bytes of shared prefix
+                    let tail_start = 24 + (g.next_u32() % 8) as usize;
+                    for byte in bytes[tail_start..].iter_mut() {
+                        *byte = (g.next_u32() % 256) as u8;
+                    }
+                    Hash::from_bytes(bytes)
+                }
+                _ => Hash(Arbitrary::arbitrary(g)),
+            }
+        }
+
+        /// shrink to the zero hash, then to the hash with one of its
+        /// non-zero bytes cleared
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            if *self == Hash::zero() {
+                return Box::new(std::iter::empty());
+            }
+            let bytes = *self.as_bytes();
+            let cleared = (0..bytes.len())
+                .filter(move |i| bytes[*i] != 0)
+                .map(move |i| {
+                    let mut bytes = bytes;
+                    bytes[i] = 0;
+                    Hash::from_bytes(bytes)
+                });
+            Box::new(std::iter::once(Hash::zero()).chain(cleared))
+        }
+    }
+
+    impl<T, A> Arbitrary for Signed<T, A>
+    where
+        T: Arbitrary,
+        A: VerificationAlgorithm + 'static,
+        A::Signature: Send,
+    {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
-            Hash(Arbitrary::arbitrary(g))
+            let bytes: Vec<u8> = std::iter::repeat_with(|| u8::arbitrary(g))
+                .take(A::SIGNATURE_SIZE)
+                .collect();
+            Signed {
+                data: T::arbitrary(g),
+                sig: crypto::Signature::from_binary(&bytes).unwrap(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serialization;
+    use chain_core::mempack::read_exactly;
+    use quickcheck_macros::quickcheck;
+
+    /// A payload that can never be serialized
+    struct Unserializable;
+
+    impl property::Serialize for Unserializable {
+        type Error = std::io::Error;
+        fn serialize<W: std::io::Write>(&self, _: W) -> Result<(), Self::Error> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "cannot serialize",
+            ))
         }
     }
 
+    fn secret_key() -> SecretKey<crypto::Ed25519> {
+        crypto::testing::TestCryptoGen(0).secret_key(0)
+    }
+
+    #[test]
+    fn hash_codec() {
+        serialization::assert_codec_roundtrip::<Hash>();
+        serialization::assert_rejects_truncation::<Hash>();
+        serialization::assert_rejects_trailing_bytes::<Hash>();
+    }
+
+    #[test]
+    fn signed_codec() {
+        type SignedHash = Signed<Hash, crypto::Ed25519>;
+        serialization::assert_codec_roundtrip::<SignedHash>();
+        serialization::assert_rejects_truncation::<SignedHash>();
+        serialization::assert_rejects_trailing_bytes::<SignedHash>();
+    }
+
+    #[test]
+    fn try_make_signature_fails_on_unserializable_data() {
+        assert!(try_make_signature(&secret_key(), &Unserializable).is_err());
+        assert!(try_signed_new(&secret_key(), Unserializable).is_err());
+    }
+
+    #[test]
+    fn try_verify_signature_fails_on_unserializable_data() {
+        let key = secret_key();
+        let signature = key.sign(b"").coerce::<Unserializable>();
+        assert!(try_verify_signature(&signature, &key.to_public(), &Unserializable).is_err());
+    }
+
+    #[test]
+    fn try_verify_multi_signature_fails_on_unserializable_data() {
+        let key = secret_key();
+        let signature = key.sign(b"").coerce::<Unserializable>();
+        assert!(
+            try_verify_multi_signature(&signature, &[key.to_public()], &Unserializable).is_err()
+        );
+        let data = Hash::hash_bytes(b"some header");
+        let signature = make_signature(&key, &data);
+        assert_eq!(
+            try_verify_multi_signature(&signature, &[key.to_public()], &data).unwrap(),
+            crypto::Verification::Success
+        );
+        assert_eq!(
+            try_verify_multi_signature(&signature, &[], &data).unwrap(),
+            crypto::Verification::Failed
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn make_signature_panics_on_unserializable_data() {
+        make_signature(&secret_key(), &Unserializable);
+    }
+
+    #[test]
+    fn signed_message_agrees_with_the_signature_functions() {
+        let keys: Vec<SecretKey<crypto::Ed25519>> = (0..3)
+            .map(|i| crypto::testing::TestCryptoGen(0).secret_key(i))
+            .collect();
+        let data = Hash::hash_bytes(b"some header");
+        let message = SignedMessage::new(&data).unwrap();
+        assert_eq!(
+            message.as_bytes(),
+            &property::Serialize::serialize_as_vec(&data).unwrap()[..]
+        );
+
+        let signature = message.sign(&keys[0]);
+        assert!(signature.as_ref() == make_signature(&keys[0], &data).as_ref());
+        for (i, key) in keys.iter().enumerate() {
+            let expected = if i == 0 {
+                crypto::Verification::Success
+            } else {
+                crypto::Verification::Failed
+            };
+            let public_key = key.to_public();
+            assert_eq!(message.verify(&signature, &public_key), expected);
+            assert_eq!(verify_signature(&signature, &public_key, &data), expected);
+        }
+        assert!(SignedMessage::new(&Unserializable).is_err());
+    }
+
+    #[test]
+    fn hash_prefixes_are_parsed_from_hex() {
+        let hash = Hash::hash_bytes(b"fragment");
+        let hex = hash.to_string();
+        for length in &[1, 4, 32] {
+            let prefix: HashPrefix = hex[..length * 2].parse().unwrap();
+            assert_eq!(prefix.as_bytes(), &hash.as_bytes()[..*length]);
+            assert_eq!(prefix.to_string(), hex[..length * 2]);
+            assert!(prefix.matches(&hash));
+        }
+        assert!(!"00"
+            .parse::<HashPrefix>()
+            .unwrap()
+            .matches(&Hash::from_bytes([1; 32])));
+
+        assert_eq!("".parse::<HashPrefix>(), Err(HashPrefixError::Empty));
+        assert_eq!(HashPrefix::new(&[]), Err(HashPrefixError::Empty));
+        assert_eq!(
+            format!("{}00", hex).parse::<HashPrefix>(),
+            Err(HashPrefixError::TooLong { length: 33 })
+        );
+        assert_eq!(
+            "abc".parse::<HashPrefix>(),
+            Err(HashPrefixError::InvalidHex)
+        );
+        assert_eq!("zz".parse::<HashPrefix>(), Err(HashPrefixError::InvalidHex));
+    }
+
+    #[test]
+    fn ambiguous_hash_prefixes_are_reported() {
+        let hashes = vec![
+            Hash::from_bytes([0xab; 32]),
+            Hash::from_bytes({
+                let mut bytes = [0xab; 32];
+                bytes[1] = 0xcd;
+                bytes
+            }),
+            Hash::from_bytes([0x12; 32]),
+        ];
+        let prefix = |s: &str| s.parse::<HashPrefix>().unwrap();
+
+        assert_eq!(prefix("abab").resolve(&hashes), Ok(&hashes[0]));
+        assert_eq!(prefix("abcd").resolve(&hashes), Ok(&hashes[1]));
+        assert_eq!(
+            prefix("ab").resolve(&hashes),
+            Err(PrefixResolutionError::AmbiguousPrefix {
+                prefix: prefix("ab")
+            })
+        );
+        assert_eq!(
+            prefix("34").resolve(&hashes),
+            Err(PrefixResolutionError::NotFound {
+                prefix: prefix("34")
+            })
+        );
+
+        // the resolution stops at the second match
+        let mut looked_at = 0;
+        let endless = std::iter::repeat(&hashes[0]).inspect(|_| looked_at += 1);
+        assert!(prefix("ab").resolve(endless).is_err());
+        assert_eq!(looked_at, 2);
+    }
+
+    #[test]
+    fn verify_signature_raw_matches_verify_signature() {
+        let key = secret_key();
+        let data = Hash::hash_bytes(b"some data");
+        let signature = make_signature(&key, &data);
+        let bytes = property::Serialize::serialize_as_vec(&data).unwrap();
+        assert_eq!(
+            verify_signature_raw(&signature, &key.to_public(), &bytes),
+            crypto::Verification::Success
+        );
+        assert_eq!(
+            verify_signature(&signature, &key.to_public(), &data),
+            crypto::Verification::Success
+        );
+        assert_eq!(
+            verify_signature_raw(&signature, &key.to_public(), b"other data"),
+            crypto::Verification::Failed
+        );
+    }
+
+    #[test]
+    fn hash_verification_agrees_with_full_payload() {
+        let key = secret_key();
+        let data = Hash::hash_bytes(b"some data");
+        let full = signed_new(&key, data);
+        let by_hash = signed_by_hash_new(&key, data);
+
+        assert_eq!(
+            verify_signature(&full.sig, &key.to_public(), &data),
+            crypto::Verification::Success
+        );
+        assert_eq!(
+            by_hash.verify(&key.to_public()).unwrap(),
+            crypto::Verification::Success
+        );
+        let hash = Hash::hash_serializable(&data).unwrap();
+        assert_eq!(
+            verify_by_hash(&by_hash.sig, &key.to_public(), &hash),
+            crypto::Verification::Success
+        );
+        assert_eq!(
+            verify_by_hash(&by_hash.sig, &key.to_public(), &data),
+            crypto::Verification::Failed
+        );
+    }
+
+    #[test]
+    fn cross_mode_signatures_fail_verification() {
+        let key = secret_key();
+        let data = Hash::hash_bytes(b"some data");
+        let full = signed_new(&key, data);
+        let by_hash = signed_by_hash_new(&key, data);
+        let hash = Hash::hash_serializable(&data).unwrap();
+
+        assert_eq!(
+            verify_by_hash(&full.sig.clone().coerce(), &key.to_public(), &hash),
+            crypto::Verification::Failed
+        );
+        assert_eq!(
+            verify_signature(&by_hash.sig.clone().coerce(), &key.to_public(), &data),
+            crypto::Verification::Failed
+        );
+        let swapped: AnySigned<Hash, crypto::Ed25519> = AnySigned::ByHash(SignedByHash {
+            data,
+            sig: full.sig.coerce(),
+        });
+        assert_eq!(
+            swapped.verify(&key.to_public()).unwrap(),
+            crypto::Verification::Failed
+        );
+
+        // the full signature of a hash is not the signature by hash of
+        // the data it is the hash of
+        let full_of_hash = signed_new(&key, hash);
+        assert_eq!(
+            verify_by_hash(&full_of_hash.sig.coerce(), &key.to_public(), &hash),
+            crypto::Verification::Failed
+        );
+    }
+
+    #[test]
+    fn signing_mode_is_tagged() {
+        let key = secret_key();
+        let data = Hash::hash_bytes(b"some data");
+        let full = AnySigned::Full(signed_new(&key, data));
+        let by_hash = AnySigned::ByHash(signed_by_hash_new(&key, data));
+
+        for signed in vec![full, by_hash] {
+            let bytes = property::Serialize::serialize_as_vec(&signed).unwrap();
+            let read = AnySigned::read(&mut ReadBuf::from(&bytes)).unwrap();
+            assert_eq!(read, signed);
+            assert_eq!(
+                read.verify(&key.to_public()).unwrap(),
+                crypto::Verification::Success
+            );
+        }
+
+        let full = AnySigned::Full(signed_new(&key, data));
+        let bytes = property::Serialize::serialize_as_vec(&full).unwrap();
+        match SignedByHash::<Hash, crypto::Ed25519>::read(&mut ReadBuf::from(&bytes)) {
+            Err(ReadError::UnknownTag(0)) => (),
+            _ => panic!("a full signature should not read as a signature by hash"),
+        }
+    }
+
+    #[test]
+    fn signed_with_trailing_bytes_is_rejected() {
+        let signed = signed_new(&secret_key(), Hash::hash_bytes(b"some data"));
+        let mut bytes = property::Serialize::serialize_as_vec(&signed).unwrap();
+        assert_eq!(
+            read_exactly::<Signed<Hash, crypto::Ed25519>>(&bytes),
+            Ok(signed)
+        );
+
+        bytes.extend_from_slice(&[0, 1, 2]);
+        assert_eq!(
+            read_exactly::<Signed<Hash, crypto::Ed25519>>(&bytes),
+            Err(ReadError::UnconsumedData(3))
+        );
+    }
+
+    /// the hash of `b"golden signed data"` signed with the passphrase key
+    /// of "alice"
+    const GOLDEN_SIGNED: &str = "\
+        d18d61bfb1d015f5f6c000accc60233cd54e44173ccaa4e586532dc57066edbba746200b7a3d40e54627816c\
+        3f8b84e330f30c20235901eff02f0121b1c7bf02243cec16939645916edaf4e84ddca1a7e5b6dfdadedd22b1\
+        776e04a15c4d4501";
+
+    #[test]
+    fn golden_signed_serialization_is_stable() {
+        let bytes = hex::decode(GOLDEN_SIGNED).unwrap();
+        serialization::assert_serialization_stable::<Signed<Hash, crypto::Ed25519>>(&bytes);
+
+        let key = match EitherEd25519SecretKey::from_passphrase("alice") {
+            EitherEd25519SecretKey::Normal(key) => key,
+            EitherEd25519SecretKey::Extended(_) => unreachable!(),
+        };
+        let signed = signed_new(&key, Hash::hash_bytes(b"golden signed data"));
+        assert_eq!(
+            property::Serialize::serialize_as_vec(&signed).unwrap(),
+            bytes
+        );
+    }
+
+    fn signed_messages(n: usize) -> Vec<(Vec<u8>, SecretKey<crypto::Ed25519>)> {
+        (0..n)
+            .map(|i| {
+                let key = crypto::testing::TestCryptoGen(0).secret_key(i as u32);
+                (format!("message {}", i).into_bytes(), key)
+            })
+            .collect()
+    }
+
+    fn sequential(items: &[SignatureBatchItem<()>]) -> Result<(), usize> {
+        for (index, (bytes, public_key, signature)) in items.iter().enumerate() {
+            if signature.verify_slice(public_key, bytes) == crypto::Verification::Failed {
+                return Err(index);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn batch_verification_locates_the_invalid_signature() {
+        let messages = signed_messages(5);
+        let public_keys: Vec<_> = messages.iter().map(|(_, key)| key.to_public()).collect();
+        let signatures: Vec<crypto::Signature<(), crypto::Ed25519>> = messages
+            .iter()
+            .map(|(bytes, key)| key.sign(bytes).coerce())
+            .collect();
+        let items = |signatures: &[crypto::Signature<(), crypto::Ed25519>]| -> Vec<_> {
+            messages
+                .iter()
+                .zip(public_keys.iter())
+                .zip(signatures.iter())
+                .map(|(((bytes, _), public_key), signature)| {
+                    (bytes.as_slice(), public_key, signature)
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            verify_signatures_batch::<()>(&[]),
+            crypto::Verification::Success
+        );
+        assert_eq!(verify_batch_locating::<()>(&[]), Ok(()));
+
+        let valid = items(&signatures);
+        assert_eq!(
+            verify_signatures_batch(&valid),
+            crypto::Verification::Success
+        );
+        assert_eq!(verify_batch_locating(&valid), Ok(()));
+        assert_eq!(verify_batch_locating(&valid), sequential(&valid));
+
+        for position in 0..signatures.len() {
+            // the signature of the next message in place of the one of
+            // `position`
+            let mut invalid = signatures.clone();
+            invalid[position] = signatures[(position + 1) % signatures.len()].clone();
+            let invalid = items(&invalid);
+            assert_eq!(
+                verify_signatures_batch(&invalid),
+                crypto::Verification::Failed
+            );
+            assert_eq!(verify_batch_locating(&invalid), Err(position));
+            assert_eq!(verify_batch_locating(&invalid), sequential(&invalid));
+        }
+    }
+
+    fn either_secret_keys() -> Vec<EitherEd25519SecretKey> {
+        vec![
+            EitherEd25519SecretKey::Normal(secret_key()),
+            EitherEd25519SecretKey::Extended(crypto::testing::TestCryptoGen(0).secret_key(1)),
+        ]
+    }
+
+    #[test]
+    fn secret_key_debug_shows_no_secret_bytes() {
+        for key in either_secret_keys() {
+            let debug = format!("{:?}", key);
+            let secret = key.leak_secret_bytes();
+            let secret_hex: String = secret.iter().map(|byte| format!("{:02x}", byte)).collect();
+            assert!(debug.contains(&key.fingerprint()));
+            assert!(!debug.contains(&secret_hex[..16]));
+            assert!(!debug.contains(&format!("{:?}", secret)));
+        }
+    }
+
+    #[test]
+    fn signing_works_after_a_copy_of_the_key_is_dropped() {
+        let data = Hash::hash_bytes(b"some data");
+        for key in either_secret_keys() {
+            let public_key = key.to_public();
+            let copy = key.clone();
+            drop(key);
+            let signature = copy.sign(&data);
+            assert_eq!(
+                signature.verify(&public_key, &data),
+                crypto::Verification::Success
+            );
+            assert_eq!(copy.to_public(), public_key);
+        }
+    }
+
+    #[test]
+    fn hash_from_slice_checks_the_size() {
+        let bytes = [7; 33];
+        assert_eq!(
+            Hash::try_from(&bytes[..32]).unwrap(),
+            Hash::from_bytes([7; 32])
+        );
+        assert_eq!(
+            Hash::try_from(&bytes[..31]),
+            Err(crypto::hash::Error::InvalidHashSize(31, 32))
+        );
+        assert_eq!(
+            Hash::try_from(&bytes[..]),
+            Err(crypto::hash::Error::InvalidHashSize(33, 32))
+        );
+    }
+
+    #[test]
+    fn hash_conversions_agree() {
+        let hash = Hash::hash_bytes(b"some data");
+        assert_eq!(Hash::try_from(&hash.as_bytes()[..]).unwrap(), hash);
+        assert_eq!(hash.as_bytes(), &<[u8; 32]>::from(hash));
+        assert_eq!(Hash::zero(), <Hash as property::BlockId>::zero());
+        assert_eq!(Hash::zero().as_bytes(), &[0; 32]);
+
+        let bytes = property::Serialize::serialize_as_vec(&hash).unwrap();
+        assert_eq!(Hash::digest(&hash), Hash::hash_bytes(&bytes));
+
+        let digest: crypto::digest::DigestOf<crypto::Blake2b256, ()> =
+            crypto::digest::DigestOf::from(crypto::Blake2b256::new(b"some data"));
+        assert_eq!(Hash::from(digest), hash);
+    }
+
+    #[quickcheck]
+    fn chunked_hashing_matches_hash_bytes(data: Vec<u8>, cuts: Vec<usize>) -> bool {
+        let expected = Hash::hash_bytes(&data);
+
+        let mut cuts: Vec<usize> = cuts.into_iter().map(|cut| cut % (data.len() + 1)).collect();
+        cuts.push(0);
+        cuts.push(data.len());
+        cuts.sort();
+        let mut context = HashContext::new();
+        for window in cuts.windows(2) {
+            context.append(&data[window[0]..window[1]]);
+        }
+
+        context.finalize() == expected && Hash::hash_reader(&data[..]).unwrap() == expected
+    }
+
+    #[test]
+    fn legacy_hashing_matches_known_vectors() {
+        assert_eq!(
+            Hash::hash_bytes(b"").to_string(),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+        assert_eq!(
+            Hash::hash_bytes(b"abc").to_string(),
+            "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"
+        );
+        assert_eq!(
+            HashScheme::Legacy.hash::<FragmentIdTag>(b"abc"),
+            Hash::hash_bytes(b"abc")
+        );
+        assert_eq!(HashScheme::default(), HashScheme::Legacy);
+    }
+
+    #[quickcheck]
+    fn tags_separate_the_domains(data: Vec<u8>) -> bool {
+        let block = Hash::of::<BlockIdTag>(&data);
+        let fragment = Hash::of::<FragmentIdTag>(&data);
+        block != fragment
+            && block != Hash::hash_bytes(&data)
+            && fragment == HashScheme::Tagged.hash::<FragmentIdTag>(&data)
+    }
+
+    #[test]
+    fn tag_is_length_prefixed() {
+        // moving bytes between the tag and the payload changes the hash
+        assert_ne!(
+            Hash::hash_with_tag(b"ab", b"c"),
+            Hash::hash_with_tag(b"a", b"bc")
+        );
+    }
+
+    #[test]
+    fn hash_serializable_matches_serialized_bytes() {
+        let data = Hash::hash_bytes(b"some data");
+        let bytes = property::Serialize::serialize_as_vec(&data).unwrap();
+        assert_eq!(
+            Hash::hash_serializable(&data).unwrap(),
+            Hash::hash_bytes(&bytes)
+        );
+        assert!(Hash::hash_serializable(&Unserializable).is_err());
+    }
+
+    fn any_public_keys() -> Vec<AnyPublicKey> {
+        let gen = crypto::testing::TestCryptoGen(0);
+        vec![
+            gen.secret_key::<crypto::Ed25519>(1).to_public().into(),
+            gen.secret_key::<crypto::Ed25519Bip32>(2).to_public().into(),
+            gen.secret_key::<crypto::SumEd25519_12>(3)
+                .to_public()
+                .into(),
+        ]
+    }
+
+    #[test]
+    fn any_public_key_round_trips() {
+        use chain_core::property::Serialize as _;
+
+        for key in any_public_keys() {
+            let bytes = key.serialize_as_vec().unwrap();
+            assert_eq!(bytes[0], key.tag());
+            let decoded = AnyPublicKey::read(&mut ReadBuf::from(&bytes)).unwrap();
+            assert_eq!(decoded, key);
+            assert_eq!(decoded.algorithm_name(), key.algorithm_name());
+        }
+    }
+
+    #[test]
+    fn any_public_key_rejects_unknown_tags() {
+        for tag in &[0u8, 4, 0xff] {
+            let mut bytes = vec![*tag];
+            bytes.extend_from_slice(&[0; 64]);
+            match AnyPublicKey::read(&mut ReadBuf::from(&bytes)) {
+                Err(ReadError::UnknownTag(t)) => assert_eq!(t, u32::from(*tag)),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn any_public_key_algorithms_are_distinct() {
+        let ed25519 = crypto::testing::TestCryptoGen(0)
+            .secret_key::<crypto::Ed25519>(1)
+            .to_public();
+        let sum =
+            crypto::PublicKey::<crypto::SumEd25519_12>::from_binary(ed25519.as_ref()).unwrap();
+        let ed25519 = AnyPublicKey::from(ed25519);
+        let sum = AnyPublicKey::from(sum);
+        assert_ne!(ed25519, sum);
+
+        assert!(ed25519.clone().into_ed25519().is_ok());
+        assert_eq!(ed25519.clone().into_sum_ed25519(), Err(ed25519.clone()));
+        assert_eq!(sum.clone().into_ed25519_bip32(), Err(sum.clone()));
+        assert!(sum.into_sum_ed25519().is_ok());
+    }
+
+    // these keys are used as fixtures elsewhere: if this fails, the
+    // derivation changed and has to be put back, not the test updated
+    #[test]
+    fn passphrase_keys_are_stable() {
+        let public_hex = |passphrase| {
+            hex::encode(EitherEd25519SecretKey::from_passphrase(passphrase).to_public())
+        };
+        assert_eq!(
+            public_hex("alice"),
+            "b3a805b7ae0847bf314611c25dd13f5ee81bdaf1e6d78a26d5b3d57534e95e06"
+        );
+        assert_eq!(
+            public_hex("bob"),
+            "551c2eddbf10c03ba1636a0f1f3df9ffd60380a8be5ca59265e5f98f85c3662c"
+        );
+    }
+
+    fn participants() -> Vec<AccountPublicKey> {
+        ["alice", "bob", "carol"]
+            .iter()
+            .map(|name| {
+                AccountPublicKey::from_secret(&EitherEd25519SecretKey::from_passphrase(name))
+            })
+            .collect()
+    }
+
+    // pins the identifier format: if this fails, the identifiers of the
+    // existing accounts changed
+    #[test]
+    fn multisig_identifiers_are_stable() {
+        let keys = participants();
+        assert_eq!(
+            multisig_identifier(2, &keys).to_string(),
+            "607d812b41ba235fc42bf2e625b297b8e223300dead6f6bf5bdad988795395f1"
+        );
+        assert_eq!(
+            multisig_identifier(1, &keys[..2]).to_string(),
+            "b3a1d92401f04642761371703a3009f3293cb8841e4167761bad42b2befcef7d"
+        );
+
+        let reversed: Vec<_> = keys.iter().rev().cloned().collect();
+        assert_eq!(
+            multisig_identifier(2, &reversed),
+            multisig_identifier(2, &keys)
+        );
+        assert_ne!(multisig_identifier(3, &keys), multisig_identifier(2, &keys));
+    }
+
+    #[test]
+    fn multisig_specs_are_validated() {
+        let keys = participants();
+        assert_eq!(
+            MultisigSpec::new(0, keys.clone()),
+            Err(MultisigSpecError::ThresholdInvalid {
+                threshold: 0,
+                keys: 3
+            })
+        );
+        assert_eq!(
+            MultisigSpec::new(4, keys.clone()),
+            Err(MultisigSpecError::ThresholdInvalid {
+                threshold: 4,
+                keys: 3
+            })
+        );
+        assert_eq!(
+            MultisigSpec::new(1, vec![]),
+            Err(MultisigSpecError::NotEnoughKeys { keys: 0 })
+        );
+        assert_eq!(
+            MultisigSpec::new(1, keys[..1].to_vec()),
+            Err(MultisigSpecError::NotEnoughKeys { keys: 1 })
+        );
+        let many: Vec<_> = (0..9).map(|_| keys[0].clone()).collect();
+        assert_eq!(
+            MultisigSpec::new(1, many),
+            Err(MultisigSpecError::TooManyKeys { keys: 9 })
+        );
+        let twice = vec![keys[1].clone(), keys[0].clone(), keys[1].clone()];
+        assert_eq!(
+            MultisigSpec::new(1, twice),
+            Err(MultisigSpecError::DuplicateKey {
+                key: keys[1].clone()
+            })
+        );
+    }
+
+    #[test]
+    fn multisig_spec_reads_only_the_canonical_form() {
+        let spec = MultisigSpec::new(2, participants()).unwrap();
+        let bytes = property::Serialize::serialize_as_vec(&spec).unwrap();
+        assert_eq!(bytes.len(), 2 + 3 * 32);
+        assert_eq!(read_exactly::<MultisigSpec>(&bytes), Ok(spec.clone()));
+
+        // the last two keys swapped
+        let mut unsorted = bytes.clone();
+        unsorted[2 + 32..].rotate_left(32);
+        assert!(read_exactly::<MultisigSpec>(&unsorted).is_err());
+
+        let mut threshold_too_high = bytes.clone();
+        threshold_too_high[0] = 4;
+        assert!(read_exactly::<MultisigSpec>(&threshold_too_high).is_err());
+
+        let mut too_many = bytes;
+        too_many[1] = 9;
+        assert!(read_exactly::<MultisigSpec>(&too_many).is_err());
+    }
+
+    #[test]
+    fn ct_eq_compares_bytes() {
+        assert!(ct_eq(&[], &[]));
+        assert!(ct_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!ct_eq(&[0, 2, 3], &[1, 2, 3]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2]));
+        assert!(!ct_eq(&[], &[0]));
+
+        let hash = Hash::hash_bytes(b"a");
+        assert!(hash.ct_eq(&hash.clone()));
+        assert!(!hash.ct_eq(&Hash::hash_bytes(b"b")));
+    }
+
+    #[quickcheck]
+    fn ct_eq_reads_every_byte(a: Vec<u8>, flip: usize) -> bool {
+        let mut b = a.clone();
+        if !b.is_empty() {
+            let at = flip % b.len();
+            b[at] ^= 0xff;
+        }
+        let count = |bytes: &Vec<u8>| {
+            let read = std::cell::Cell::new(0);
+            let result = ct_eq_bytes(a.iter().inspect(|_| read.set(read.get() + 1)), bytes.iter());
+            (result, read.get())
+        };
+        count(&a) == (true, a.len()) && count(&b) == (a.is_empty(), a.len())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_round_trips() {
+        let hash = Hash::hash_bytes(b"serde");
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", hash));
+        assert_eq!(serde_json::from_str::<Hash>(&json).unwrap(), hash);
+
+        let alice = EitherEd25519SecretKey::from_passphrase("alice");
+        let public_key = AccountPublicKey::from_secret(&alice);
+        let json = serde_json::to_string(&public_key).unwrap();
+        assert!(json.starts_with("\"ed25519_pk1"));
+        assert_eq!(
+            serde_json::from_str::<AccountPublicKey>(&json).unwrap(),
+            public_key
+        );
+        let spending_key = serde_json::from_str::<SpendingPublicKey>(&json).unwrap();
+        assert_eq!(spending_key.as_raw(), public_key.as_raw());
+
+        let signed = signed_new(&secret_key(), hash);
+        let signature = SignedHex::from(&signed);
+        let json = serde_json::to_string(&signature).unwrap();
+        assert_eq!(json, format!("\"{}\"", hex::encode(signed.sig.as_ref())));
+        assert_eq!(
+            serde_json::from_str::<SignedHex<Hash, crypto::Ed25519>>(&json).unwrap(),
+            signature
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_wrong_lengths() {
+        let error = serde_json::from_str::<Hash>("\"abcdef\"").unwrap_err();
+        assert!(error.to_string().contains("32 bytes"), "{}", error);
+        let error =
+            serde_json::from_str::<SignedHex<Hash, crypto::Ed25519>>("\"abcdef\"").unwrap_err();
+        assert!(error.to_string().contains("64 bytes"), "{}", error);
+        assert!(serde_json::from_str::<Hash>("\"not hex\"").is_err());
+        assert!(serde_json::from_str::<AccountPublicKey>("\"ed25519_pk1\"").is_err());
+    }
 }
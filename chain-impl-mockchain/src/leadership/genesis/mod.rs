@@ -4,7 +4,7 @@ use crate::{
     block::{BlockDate, Header, Proof},
     certificate::PoolId,
     date::Epoch,
-    key::{deserialize_public_key, verify_signature},
+    key::{deserialize_public_key, try_verify_signature},
     leadership::{Error, ErrorKind, Verification},
     ledger::Ledger,
     stake::{self, StakeDistribution},
@@ -160,16 +160,21 @@ impl LeadershipData {
                             &genesis_praos_proof.vrf_proof,
                         );
 
-                        let valid = verify_signature(
+                        let valid = try_verify_signature(
                             &genesis_praos_proof.kes_proof.0,
                             &pool_info.keys.kes_public_key,
                             &block_header.common,
                         );
 
-                        if valid == SigningVerification::Failed {
-                            Verification::Failure(Error::new(ErrorKind::InvalidLeaderSignature))
-                        } else {
-                            Verification::Success
+                        match valid {
+                            Err(error) => Verification::Failure(Error::new_(
+                                ErrorKind::InvalidLeaderSignature,
+                                error,
+                            )),
+                            Ok(SigningVerification::Failed) => {
+                                Verification::Failure(Error::new(ErrorKind::InvalidLeaderSignature))
+                            }
+                            Ok(SigningVerification::Success) => Verification::Success,
                         }
                     }
                     (_, _) => Verification::Failure(Error::new(ErrorKind::InvalidBlockMessage)),
@@ -194,7 +199,7 @@ impl Readable for GenesisPraosLeader {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::certificate::{PoolId, PoolRegistration};
+    use crate::certificate::{PoolId, PoolRegistration, RewardDestination};
     use crate::ledger::Ledger;
     use crate::milli::Milli;
     use crate::stake::PoolStakeDistribution;
@@ -214,6 +219,7 @@ mod tests {
         let pool_vrf_private_key = SecretKey::generate(&mut rng);
         let pool_kes: KeyPair<SumEd25519_12> = KeyPair::generate(&mut rng);
         let (_, pool_kes_public_key) = pool_kes.into_keys();
+        let reward_account: KeyPair<Ed25519> = KeyPair::generate(&mut rng);
 
         let pool_info = PoolRegistration {
             serial: 1234,
@@ -224,6 +230,7 @@ mod tests {
                 vrf_public_key: pool_vrf_private_key.to_public(),
                 kes_public_key: pool_kes_public_key,
             },
+            reward_destination: RewardDestination::Account(reward_account.into_keys().1.into()),
         };
 
         let pool_id = pool_info.to_id();
@@ -30,6 +30,18 @@ impl Nonce {
     }
 }
 
+impl AsRef<[u8]> for Nonce {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for Nonce {
+    fn from(bytes: [u8; 32]) -> Self {
+        Nonce(bytes)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ActiveSlotsCoeffError {
     InvalidValue(Milli),
@@ -9,6 +9,7 @@ use chain_time::era::TimeEra;
 
 pub mod bft;
 pub mod genesis;
+pub mod signer;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ErrorKind {
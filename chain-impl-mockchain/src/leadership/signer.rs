@@ -0,0 +1,274 @@
+//! Container for the secret keys of a leader
+//!
+//! The `LeaderSigner` owns the BFT and the Genesis Praos secret keys of a
+//! leader and never hands them out: the only thing that leaves the container
+//! is the signature of a block header (or the VRF witness of a slot). It is
+//! not `Clone` and its `Debug` implementation only shows public material so
+//! the secrets cannot be copied around or end up in the logs by accident.
+
+use super::{bft, genesis, Error, Leader};
+use crate::block::{BftSignature, BlockDate, HeaderToSign, KESSignature};
+use crate::certificate::PoolId;
+use crate::key::try_make_signature;
+use chain_crypto::{
+    Curve25519_2HashDH, Ed25519, EvolvingStatus, PublicKey, SecretKey, SumEd25519_12,
+};
+use std::fmt;
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub EvolveError
+        NoKesKey = "The signer does not hold a KES secret key",
+        PeriodInThePast { current: u32, requested: u32 } = "Cannot evolve the KES key back from period {current} to period {requested}",
+        KeyExhausted { period: u32 } = "The KES key cannot be evolved past period {period}",
+}
+
+custom_error! {
+    pub SignError
+        NoBftKey = "The signer does not hold a BFT secret key",
+        NoGenesisPraosKeys = "The signer does not hold Genesis Praos secret keys",
+        HeaderNotSerializable { source: std::io::Error } = "Cannot serialize the header to sign",
+}
+
+struct GenesisPraosSecrets {
+    node_id: PoolId,
+    kes_key: SecretKey<SumEd25519_12>,
+    vrf_key: SecretKey<Curve25519_2HashDH>,
+}
+
+/// The secret keys of a leader, usable only through signing operations
+pub struct LeaderSigner {
+    bft: Option<SecretKey<Ed25519>>,
+    genesis_praos: Option<GenesisPraosSecrets>,
+}
+
+impl LeaderSigner {
+    /// create a signer with no key, it will refuse to sign anything
+    pub fn new() -> Self {
+        LeaderSigner {
+            bft: None,
+            genesis_praos: None,
+        }
+    }
+
+    /// add (or replace) the BFT signing key of the signer
+    pub fn with_bft(mut self, sig_key: SecretKey<Ed25519>) -> Self {
+        self.bft = Some(sig_key);
+        self
+    }
+
+    /// add (or replace) the Genesis Praos KES and VRF keys of the signer
+    pub fn with_genesis_praos(
+        mut self,
+        node_id: PoolId,
+        kes_key: SecretKey<SumEd25519_12>,
+        vrf_key: SecretKey<Curve25519_2HashDH>,
+    ) -> Self {
+        self.genesis_praos = Some(GenesisPraosSecrets {
+            node_id,
+            kes_key,
+            vrf_key,
+        });
+        self
+    }
+
+    /// the public key of the BFT leader, if the signer holds a BFT key
+    pub fn bft_leader_id(&self) -> Option<bft::LeaderId> {
+        self.bft.as_ref().map(|key| bft::LeaderId(key.to_public()))
+    }
+
+    /// the stake pool identifier, if the signer holds Genesis Praos keys
+    pub fn node_id(&self) -> Option<&PoolId> {
+        self.genesis_praos.as_ref().map(|praos| &praos.node_id)
+    }
+
+    /// the public KES key, if the signer holds Genesis Praos keys
+    pub fn kes_public_key(&self) -> Option<PublicKey<SumEd25519_12>> {
+        self.genesis_praos
+            .as_ref()
+            .map(|praos| praos.kes_key.to_public())
+    }
+
+    /// the current period of the KES key, if the signer holds Genesis Praos keys
+    pub fn kes_period(&self) -> Option<u32> {
+        self.genesis_praos
+            .as_ref()
+            .map(|praos| SecretKey::get_period(&praos.kes_key))
+    }
+
+    /// sign the header with the BFT key, fails if the signer does not
+    /// hold a BFT key
+    pub fn sign_bft_header(&self, header: &HeaderToSign) -> Result<BftSignature, SignError> {
+        let key = self.bft.as_ref().ok_or(SignError::NoBftKey)?;
+        Ok(BftSignature(try_make_signature(key, header)?))
+    }
+
+    /// sign the header with the KES key, fails if the signer does not
+    /// hold Genesis Praos keys
+    pub fn sign_praos_header(&self, header: &HeaderToSign) -> Result<KESSignature, SignError> {
+        let praos = self
+            .genesis_praos
+            .as_ref()
+            .ok_or(SignError::NoGenesisPraosKeys)?;
+        Ok(KESSignature(try_make_signature(&praos.kes_key, header)?))
+    }
+
+    /// evaluate the VRF for the given date, returns `Ok(None)` if the
+    /// signer does not hold Genesis Praos keys or is not a leader for
+    /// this date
+    pub fn praos_leader_witness(
+        &self,
+        leadership: &genesis::LeadershipData,
+        date: BlockDate,
+    ) -> Result<Option<genesis::Witness>, Error> {
+        match &self.genesis_praos {
+            None => Ok(None),
+            Some(praos) => leadership.leader(&praos.node_id, &praos.vrf_key, date),
+        }
+    }
+
+    /// evolve the KES key until it reaches the period `to_period`
+    ///
+    /// The key cannot go back in time, and evolving to the current
+    /// period is a no-op.
+    pub fn evolve_kes(&mut self, to_period: u32) -> Result<(), EvolveError> {
+        let praos = self.genesis_praos.as_mut().ok_or(EvolveError::NoKesKey)?;
+        let current = SecretKey::get_period(&praos.kes_key);
+        if to_period < current {
+            return Err(EvolveError::PeriodInThePast {
+                current,
+                requested: to_period,
+            });
+        }
+        while SecretKey::get_period(&praos.kes_key) < to_period {
+            if SecretKey::evolve(&mut praos.kes_key) == EvolvingStatus::Failed {
+                return Err(EvolveError::KeyExhausted {
+                    period: SecretKey::get_period(&praos.kes_key),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Leader> for LeaderSigner {
+    fn from(leader: Leader) -> Self {
+        LeaderSigner {
+            bft: leader.bft_leader.map(|bft_leader| bft_leader.sig_key),
+            genesis_praos: leader
+                .genesis_leader
+                .map(|genesis_leader| GenesisPraosSecrets {
+                    node_id: genesis_leader.node_id,
+                    kes_key: genesis_leader.sig_key,
+                    vrf_key: genesis_leader.vrf_key,
+                }),
+        }
+    }
+}
+
+impl fmt::Debug for LeaderSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LeaderSigner")
+            .field("bft", &self.bft_leader_id())
+            .field("node_id", &self.node_id())
+            .field("kes_period", &self.kes_period())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::BlockBuilder;
+    use crate::key::verify_signature;
+    use chain_crypto::{bech32, testing::TestCryptoGen, Bech32, Verification};
+
+    fn make_signer(key_gen: &TestCryptoGen) -> LeaderSigner {
+        LeaderSigner::new()
+            .with_bft(key_gen.secret_key(0))
+            .with_genesis_praos(
+                PoolId::from([1; 32]),
+                key_gen.secret_key(1),
+                key_gen.secret_key(2),
+            )
+    }
+
+    fn assert_not_leaked<B: Bech32>(debug: &str, secret: &B) {
+        let encoded = secret.to_bech32_str();
+        let bytes = bech32::try_from_bech32_to_bytes::<B>(&encoded).unwrap();
+        // skip the human readable prefix, only the key material matters
+        let data = encoded.rsplit('1').next().unwrap();
+        assert!(!debug.contains(data), "secret key found in {}", debug);
+        assert!(
+            !debug.contains(&format!("{:?}", bytes)),
+            "secret key found in {}",
+            debug
+        );
+    }
+
+    #[test]
+    pub fn debug_does_not_leak_secrets() {
+        let key_gen = TestCryptoGen(0);
+        let debug = format!("{:?}", make_signer(&key_gen));
+
+        assert_not_leaked::<SecretKey<Ed25519>>(&debug, &key_gen.secret_key(0));
+        assert_not_leaked::<SecretKey<SumEd25519_12>>(&debug, &key_gen.secret_key(1));
+        assert_not_leaked::<SecretKey<Curve25519_2HashDH>>(&debug, &key_gen.secret_key(2));
+    }
+
+    #[test]
+    pub fn bft_signature_verifies() {
+        let signer = make_signer(&TestCryptoGen(0));
+        let header = BlockBuilder::new().common;
+        let signature = signer.sign_bft_header(&header).unwrap();
+        let leader_id = signer.bft_leader_id().unwrap();
+        assert_eq!(
+            verify_signature(&signature.0, &leader_id.0, &header),
+            Verification::Success
+        );
+    }
+
+    #[test]
+    pub fn praos_signature_verifies() {
+        let signer = make_signer(&TestCryptoGen(0));
+        let header = BlockBuilder::new().common;
+        let signature = signer.sign_praos_header(&header).unwrap();
+        let public_key = signer.kes_public_key().unwrap();
+        assert_eq!(
+            verify_signature(&signature.0, &public_key, &header),
+            Verification::Success
+        );
+    }
+
+    #[test]
+    pub fn empty_signer_refuses_to_sign() {
+        let mut signer = LeaderSigner::new();
+        let header = BlockBuilder::new().common;
+        match signer.sign_bft_header(&header) {
+            Err(SignError::NoBftKey) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match signer.sign_praos_header(&header) {
+            Err(SignError::NoGenesisPraosKeys) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(signer.evolve_kes(1), Err(EvolveError::NoKesKey));
+    }
+
+    #[test]
+    pub fn evolve_kes_forward_only() {
+        let mut signer = make_signer(&TestCryptoGen(0));
+        assert_eq!(signer.kes_period(), Some(0));
+        signer.evolve_kes(3).unwrap();
+        assert_eq!(signer.kes_period(), Some(3));
+        signer.evolve_kes(3).unwrap();
+        assert_eq!(signer.kes_period(), Some(3));
+        assert_eq!(
+            signer.evolve_kes(2),
+            Err(EvolveError::PeriodInThePast {
+                current: 3,
+                requested: 2
+            })
+        );
+    }
+}
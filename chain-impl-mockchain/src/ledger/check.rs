@@ -83,6 +83,10 @@ pub(super) fn valid_pool_registration_certificate(
         auth_cert.owners.len() >= 256,
         Error::PoolRegistrationInvalid
     )?;
+    if_cond_fail_with!(
+        !auth_cert.reward_destination.is_valid_for(&auth_cert.owners),
+        Error::PoolRegistrationInvalid
+    )?;
     Ok(())
 }
 
@@ -0,0 +1,196 @@
+//! Validation of the funds declared in the initial block: the outputs of
+//! its transactions and the legacy UTxO declarations.
+
+use crate::legacy::{DeclarationError, OldAddress, UtxoDeclaration, MAX_DECLARATION_ENTRIES};
+use crate::transaction::Output;
+use crate::value::Value;
+use chain_addr::{Address, AddressReadable};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The position of an initial fund among those given to
+/// `check_initial_funds`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitialFund {
+    /// the output `index` of the modern initial funds
+    Modern { index: usize },
+    /// the entry `index` of the legacy declaration `declaration`
+    Legacy { declaration: usize, index: usize },
+}
+
+impl fmt::Display for InitialFund {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitialFund::Modern { index } => write!(f, "initial fund {}", index),
+            InitialFund::Legacy { declaration, index } => {
+                write!(f, "entry {} of legacy declaration {}", index, declaration)
+            }
+        }
+    }
+}
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub InitialFundsError
+        ZeroValue { fund: InitialFund, address: String } = "Zero value in {fund}, for the address {address}",
+        DuplicateAddress { fund: InitialFund, address: String } = "Address {address} declared again in {fund}",
+        TooManyEntries { declaration: usize, entries: usize, max: usize } = "Legacy declaration {declaration} has {entries} entries, at most {max} are allowed",
+        TotalValueTooBig { fund: InitialFund, address: String } = "Total initial value too big, overflowing at {fund} for the address {address}",
+}
+
+// what `check_initial_funds_in` does with the modern outputs of zero value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ZeroOutputs {
+    /// fail with `InitialFundsError::ZeroValue`
+    Reject,
+    /// leave them to the caller
+    Ignore,
+}
+
+/// The initial funds accepted by `check_initial_funds`, by kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitialFundsSummary {
+    pub modern_entries: usize,
+    pub modern_value: Value,
+    pub legacy_declarations: usize,
+    pub legacy_entries: usize,
+    pub legacy_value: Value,
+}
+
+impl InitialFundsSummary {
+    pub fn total_value(&self) -> Value {
+        // checked not to overflow by `check_initial_funds`
+        self.modern_value + self.legacy_value
+    }
+}
+
+// the addresses held by the errors, only built for the offending entry.
+// The bech32 prefix is only for display, the discrimination being part
+// of the address itself.
+fn display_modern(address: &Address) -> String {
+    AddressReadable::from_address("ca", address).to_string()
+}
+
+fn display_legacy(address: &OldAddress) -> String {
+    address.to_string()
+}
+
+/// Check the funds of an initial block, `modern` being the outputs of
+/// its transactions and `legacy` its legacy UTxO declarations, in the
+/// order of the block.
+///
+/// No entry can have a zero value, no legacy address can be declared
+/// twice, even in different declarations, no legacy declaration can have
+/// more than `legacy::MAX_DECLARATION_ENTRIES` entries, and the total
+/// value of all the funds must fit in a `Value`. The modern outputs are utxos, so an address can be given several of them,
+/// and a legacy address is never equal to a modern one. The first
+/// offending entry, modern ones first, is reported, each legacy
+/// declaration being checked with `UtxoDeclaration::validate` before its
+/// entries are compared to those of the previous declarations.
+pub fn check_initial_funds(
+    modern: &[Output<Address>],
+    legacy: &[UtxoDeclaration],
+) -> Result<InitialFundsSummary, InitialFundsError> {
+    check_initial_funds_in(modern, legacy, ZeroOutputs::Reject)
+}
+
+/// Same as `check_initial_funds`, on the funds as they are found in the
+/// fragments of the block, without collecting them first.
+pub(crate) fn check_initial_funds_in<'a, M, L>(
+    modern: M,
+    legacy: L,
+    zero_outputs: ZeroOutputs,
+) -> Result<InitialFundsSummary, InitialFundsError>
+where
+    M: IntoIterator<Item = &'a Output<Address>>,
+    L: IntoIterator<Item = &'a UtxoDeclaration>,
+{
+    let mut total = Value::zero();
+
+    let mut modern_entries = 0;
+    for (index, output) in modern.into_iter().enumerate() {
+        let fund = InitialFund::Modern { index };
+        modern_entries += 1;
+        if output.value == Value::zero() {
+            match zero_outputs {
+                ZeroOutputs::Reject => {
+                    return Err(InitialFundsError::ZeroValue {
+                        fund,
+                        address: display_modern(&output.address),
+                    })
+                }
+                ZeroOutputs::Ignore => continue,
+            }
+        }
+        add_fund(&mut total, output.value, fund, || {
+            display_modern(&output.address)
+        })?;
+    }
+    let modern_value = total;
+
+    let mut legacy_addresses = BTreeSet::new();
+    let mut legacy_declarations = 0;
+    let mut legacy_entries = 0;
+    for (declaration, decl) in legacy.into_iter().enumerate() {
+        if decl.addrs.len() > MAX_DECLARATION_ENTRIES {
+            return Err(InitialFundsError::TooManyEntries {
+                declaration,
+                entries: decl.addrs.len(),
+                max: MAX_DECLARATION_ENTRIES,
+            });
+        }
+        decl.validate().map_err(|error| match error {
+            DeclarationError::ZeroValue { index } => InitialFundsError::ZeroValue {
+                fund: InitialFund::Legacy { declaration, index },
+                address: display_legacy(&decl.addrs[index].0),
+            },
+            DeclarationError::DuplicateAddress { index, address } => {
+                InitialFundsError::DuplicateAddress {
+                    fund: InitialFund::Legacy { declaration, index },
+                    address: display_legacy(&address),
+                }
+            }
+        })?;
+        for (index, (address, value)) in decl.addrs.iter().enumerate() {
+            let fund = InitialFund::Legacy { declaration, index };
+            // only declared once in `decl`, see `validate`
+            if !legacy_addresses.insert(address) {
+                return Err(InitialFundsError::DuplicateAddress {
+                    fund,
+                    address: display_legacy(address),
+                });
+            }
+            add_fund(&mut total, *value, fund, || display_legacy(address))?;
+        }
+        legacy_declarations += 1;
+        legacy_entries += decl.addrs.len();
+    }
+
+    Ok(InitialFundsSummary {
+        modern_entries,
+        modern_value,
+        legacy_declarations,
+        legacy_entries,
+        legacy_value: total - modern_value,
+    })
+}
+
+// add the value of a fund, not zero, to the total of the funds so far,
+// `address` giving the address to report
+fn add_fund<F>(
+    total: &mut Value,
+    value: Value,
+    fund: InitialFund,
+    address: F,
+) -> Result<(), InitialFundsError>
+where
+    F: Fn() -> String,
+{
+    *total = total
+        .checked_add(value)
+        .map_err(|_| InitialFundsError::TotalValueTooBig {
+            fund,
+            address: address(),
+        })?;
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use super::ledger::{Error, Ledger, LedgerStaticParameters};
+use super::pots::{self, PotsBuilder};
 use crate::block::{BlockDate, ChainLength};
 use crate::config::ConfigParam;
-use crate::stake::DelegationState;
-use crate::value::*;
+use crate::stake::{DelegationState, StakeTracker};
 use crate::{account, legacy, multisig, setting, update, utxo};
 use chain_addr::Address;
 use chain_time::TimeEra;
@@ -43,6 +43,7 @@ pub enum Entry<'a> {
             &'a crate::certificate::PoolRegistration,
         ),
     ),
+    Pot(pots::Entry),
 }
 
 pub struct Globals {
@@ -72,6 +73,7 @@ enum IterState<'a> {
     StakePools(
         imhamt::HamtIter<'a, crate::certificate::PoolId, crate::certificate::PoolRegistration>,
     ),
+    Pots(pots::Entries<'a>),
     Done,
 }
 
@@ -147,11 +149,18 @@ impl<'a> Iterator for LedgerIterator<'a> {
             },
             IterState::StakePools(iter) => match iter.next() {
                 None => {
-                    self.state = IterState::Done;
+                    self.state = IterState::Pots(self.ledger.pots.entries());
                     self.next()
                 }
                 Some(x) => Some(Entry::StakePool(x)),
             },
+            IterState::Pots(iter) => match iter.next() {
+                None => {
+                    self.state = IterState::Done;
+                    self.next()
+                }
+                Some(x) => Some(Entry::Pot(x)),
+            },
             IterState::Done => None,
         }
     }
@@ -176,6 +185,7 @@ impl<'a> std::iter::FromIterator<Entry<'a>> for Result<Ledger, Error> {
         let mut multisig_accounts = vec![];
         let mut multisig_declarations = vec![];
         let delegation = DelegationState::new();
+        let mut pots = PotsBuilder::new();
         let mut globals = None;
 
         for entry in iter {
@@ -219,24 +229,32 @@ impl<'a> std::iter::FromIterator<Entry<'a>> for Result<Ledger, Error> {
                         .insert(pool_id.clone(), pool_state.clone())
                         .unwrap();
                 }
+                Entry::Pot(entry) => {
+                    pots.add_entry(entry)?;
+                }
             }
         }
 
         let globals = globals.ok_or(Error::IncompleteLedger)?;
 
+        let utxos = utxos.into_iter().collect();
+        let accounts = accounts.into_iter().collect();
+        let stake = StakeTracker::from_state(&accounts, &utxos)?;
+
         Ok(Ledger {
-            utxos: utxos.into_iter().collect(),
+            utxos,
             oldutxos: oldutxos.into_iter().collect(),
-            accounts: accounts.into_iter().collect(),
+            accounts,
             settings: setting::Settings::new().apply(&config_params)?,
             updates,
             multisig: multisig::Ledger::restore(multisig_accounts, multisig_declarations),
             delegation,
+            stake,
             static_params: Arc::new(globals.static_params),
             date: globals.date,
             chain_length: globals.chain_length,
             era: globals.era,
-            pot: Value::zero(),
+            pots: pots.finish(),
         })
     }
 }
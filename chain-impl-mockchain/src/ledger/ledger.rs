@@ -2,18 +2,21 @@
 //! current state and verify transactions.
 
 use super::check::{self, TxVerifyError, TxVerifyLimits};
+use super::initial_funds::{check_initial_funds_in, InitialFundsError, ZeroOutputs};
+use super::pots::{self, Pots, PotsDelta};
 use crate::block::{
     BlockDate, ChainLength, ConsensusVersion, HeaderContentEvalContext, HeaderHash,
 };
 use crate::config::{self, ConfigParam};
 use crate::fee::{FeeAlgorithm, LinearFee};
 use crate::fragment::{Fragment, FragmentId};
+use crate::key::{self, AccountPublicKey, HashScheme, SpendingPublicKey};
 use crate::leadership::genesis::ActiveSlotsCoeffError;
-use crate::stake::{DelegationError, DelegationState, StakeDistribution};
+use crate::stake::{DelegationError, DelegationState, StakeDistribution, StakeError, StakeTracker};
 use crate::transaction::*;
 use crate::value::*;
 use crate::{account, certificate, legacy, multisig, setting, stake, update, utxo};
-use chain_addr::{Address, Discrimination, Kind};
+use chain_addr::{Address, Discrimination};
 use chain_core::property::{self, ChainLength as _};
 use chain_crypto::Verification;
 use chain_time::{Epoch, SlotDuration, TimeEra, TimeFrame, Timeline};
@@ -33,6 +36,77 @@ pub struct LedgerStaticParameters {
 #[derive(Clone)]
 pub struct LedgerParameters {
     pub fees: LinearFee,
+    /// how far ahead of the wall clock a block can be dated, `None`
+    /// disables the check (e.g. when replaying or bootstrapping)
+    pub date_acceptance_window: Option<DateAcceptanceWindow>,
+    /// how the signatures of the transaction witnesses are verified
+    pub witness_verification: WitnessVerification,
+}
+
+/// How the ledger verifies the signatures of the witnesses of a
+/// transaction.
+///
+/// Both accept and reject the same transactions with the same errors,
+/// `Batch` only changes how much work it takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessVerification {
+    /// verify each witness as its input is applied
+    Sequential,
+    /// verify the signatures of the utxo and account witnesses together
+    /// before applying the inputs, see `key::verify_signatures_batch`.
+    /// If the batch fails, the witnesses are verified one by one to find
+    /// the error to report.
+    Batch,
+}
+
+impl Default for WitnessVerification {
+    fn default() -> Self {
+        WitnessVerification::Sequential
+    }
+}
+
+/// Bound on how many slots ahead of the current (wall clock) slot a
+/// block can be dated.
+#[derive(Debug, Clone)]
+pub struct DateAcceptanceWindow {
+    pub time_frame: TimeFrame,
+    pub max_slots_ahead: u32,
+}
+
+impl DateAcceptanceWindow {
+    /// check the block date is not further than `max_slots_ahead` slots
+    /// after the slot of `now`
+    pub fn check(
+        &self,
+        era: &TimeEra,
+        block_date: BlockDate,
+        now: &SystemTime,
+    ) -> Result<(), Error> {
+        let ahead = self.time_frame.slot_duration().to_duration() * self.max_slots_ahead;
+        let horizon = match now.checked_add(ahead) {
+            // no representable time is far enough in the future
+            None => return Ok(()),
+            Some(horizon) => horizon,
+        };
+        // a wall clock before the beginning of the era only accepts the first date
+        let max_date = self
+            .time_frame
+            .slot_at(&horizon)
+            .and_then(|slot| era.from_slot_to_era(slot))
+            .map(|position| BlockDate {
+                epoch: position.epoch.0,
+                slot_id: position.slot.0,
+            })
+            .unwrap_or_else(BlockDate::first);
+        if block_date > max_date {
+            Err(Error::BlockDateTooFarInFuture {
+                block_date,
+                max_date,
+            })
+        } else {
+            Ok(())
+        }
+    }
 }
 
 //Limits for input/output transactions and witnesses
@@ -58,11 +132,12 @@ pub struct Ledger {
     pub(crate) updates: update::UpdateState,
     pub(crate) multisig: multisig::Ledger,
     pub(crate) delegation: DelegationState,
+    pub(crate) stake: StakeTracker,
     pub(crate) static_params: Arc<LedgerStaticParameters>,
     pub(crate) date: BlockDate,
     pub(crate) chain_length: ChainLength,
     pub(crate) era: TimeEra,
-    pub(crate) pot: Value,
+    pub(crate) pots: Pots,
 }
 
 custom_error! {
@@ -89,6 +164,7 @@ custom_error! {
         InitialMessageNoPraosActiveSlotsCoeff = "Missing praos active slot coefficient in the initial fragment",
         InitialMessageNoKesUpdateSpeed = "Missing KES Update speed in the initial fragment",
         UtxoTotalValueTooBig = "Total initial value is too big",
+        InitialFundsInvalid { source: InitialFundsError } = "Invalid initial funds: {source}",
         HasOwnerStakeDelegation = "Owner stake delegation are not valid in the block0",
         HasUpdateProposal = "Update proposal fragments are not valid in the block0",
         HasUpdateVote = "Update vote fragments are not valid in the block0",
@@ -103,7 +179,7 @@ custom_error! {
     pub Error
         Config { source: config::Error } = "Invalid settings",
         UtxoValueNotMatching { expected: Value, value: Value } = "The UTxO value ({expected}) in the transaction does not match the actually state value: {value}",
-        UtxoError { source: utxo::Error } = "Invalid UTxO",
+        UtxoError { source: utxo::Error } = "Invalid UTxO: {source}",
         UtxoInvalidSignature { utxo: UtxoPointer, output: OutputAddress, witness: Witness } = "Transaction with invalid signature",
         OldUtxoInvalidSignature { utxo: UtxoPointer, output: OutputOldAddress, witness: Witness } = "Old Transaction with invalid signature",
         OldUtxoInvalidPublicKey { utxo: UtxoPointer, output: OutputOldAddress, witness: Witness } = "Old Transaction with invalid public key",
@@ -120,6 +196,7 @@ custom_error! {
         ZeroOutput { output: Output<Address> } = "Empty output",
         OutputGroupInvalid { output: Output<Address> } = "Output group invalid",
         Delegation { source: DelegationError } = "Error or Invalid delegation",
+        Stake { source: StakeError } = "Stake distribution inconsistent with the ledger: {source}",
         AccountIdentifierInvalid = "Invalid account identifier",
         InvalidDiscrimination = "Invalid discrimination",
         ExpectingAccountWitness = "Expected an account witness",
@@ -131,9 +208,11 @@ custom_error! {
         WrongChainLength { actual: ChainLength, expected: ChainLength } = "Wrong chain length, expected {expected} but received {actual}",
         NonMonotonicDate { block_date: BlockDate, chain_date: BlockDate } = "Non Monotonic date, chain date is at {chain_date} but the block is at {block_date}",
         IncompleteLedger = "Ledger cannot be reconstructed from serialized state because of missing entries",
-        PotValueInvalid { error: ValueError } = "Ledger pot value invalid: {error}",
+        Pots { source: pots::Error } = "Ledger pots are invalid",
+        PotsRestore { source: pots::RestoreError } = "Ledger pots cannot be reconstructed from serialized state: {source}",
         PoolRegistrationInvalid = "Pool Registration certificate invalid",
         PoolUpdateNotAllowedYet = "Pool Update not allowed yet",
+        BlockDateTooFarInFuture { block_date: BlockDate, max_date: BlockDate } = "Block date {block_date} is too far in the future, the latest accepted date is {max_date}",
 }
 
 impl Ledger {
@@ -150,11 +229,12 @@ impl Ledger {
             updates: update::UpdateState::new(),
             multisig: multisig::Ledger::new(),
             delegation: DelegationState::new(),
+            stake: StakeTracker::new(),
             static_params: Arc::new(static_params),
             date: BlockDate::first(),
             chain_length: ChainLength(0),
             era,
-            pot: Value::zero(),
+            pots: Pots::zero(),
         }
     }
 
@@ -162,7 +242,8 @@ impl Ledger {
     where
         I: IntoIterator<Item = &'a Fragment>,
     {
-        let mut content_iter = contents.into_iter();
+        let contents: Vec<&Fragment> = contents.into_iter().collect();
+        let mut content_iter = contents.iter().cloned();
 
         let init_ents = match content_iter.next() {
             Some(Fragment::Initial(ref init_ents)) => Ok(init_ents),
@@ -189,6 +270,8 @@ impl Ledger {
                 }
                 ConfigParam::SlotDuration(d) => {
                     slot_duration = Some(*d);
+                    // also kept in the settings, where later config changes update it
+                    regular_ents.push(param.clone());
                 }
                 ConfigParam::SlotsPerEpoch(n) => {
                     slots_per_epoch = Some(*n);
@@ -239,10 +322,27 @@ impl Ledger {
             });
         }
 
+        // check all the initial funds together before applying any of them.
+        // The outputs of zero value are rejected with `Error::ZeroOutput`
+        // when applied, as they always were.
+        let modern_funds = content_iter.clone().flat_map(|content| match content {
+            Fragment::Transaction(tx) => &tx.transaction.outputs[..],
+            _ => &[][..],
+        });
+        let legacy_funds = content_iter.clone().filter_map(|content| match content {
+            Fragment::OldUtxoDeclaration(old) => Some(old),
+            _ => None,
+        });
+        check_initial_funds_in(modern_funds, legacy_funds, ZeroOutputs::Ignore).map_err(
+            |source| Error::Block0 {
+                source: Block0Error::InitialFundsInvalid { source },
+            },
+        )?;
+
         let mut ledger = Ledger::empty(settings, static_params, era);
 
         for content in content_iter {
-            let fragment_id = content.hash();
+            let fragment_id = content.hash_with(ledger.settings.hash_scheme);
             match content {
                 Fragment::Initial(_) => {
                     return Err(Error::Block0 {
@@ -322,6 +422,10 @@ impl Ledger {
             });
         }
 
+        if let Some(window) = &ledger_params.date_acceptance_window {
+            window.check(&self.era, metadata.block_date, &SystemTime::now())?;
+        }
+
         let (updates, settings) = new_ledger.updates.process_proposals(
             new_ledger.settings,
             new_ledger.date,
@@ -330,9 +434,17 @@ impl Ledger {
         new_ledger.updates = updates;
         new_ledger.settings = settings;
 
+        // the pots are only touched once all the fragments are valid
+        let mut pots_delta = PotsDelta::zero();
         for content in contents {
-            new_ledger = new_ledger.apply_fragment(ledger_params, content, metadata)?;
+            new_ledger = new_ledger.apply_fragment_in_block(
+                ledger_params,
+                content,
+                metadata,
+                &mut pots_delta,
+            )?;
         }
+        new_ledger.pots = new_ledger.pots.commit(&pots_delta)?;
 
         new_ledger.date = metadata.block_date;
         metadata
@@ -352,10 +464,26 @@ impl Ledger {
         ledger_params: &LedgerParameters,
         content: &Fragment,
         metadata: &HeaderContentEvalContext,
+    ) -> Result<Self, Error> {
+        let mut pots_delta = PotsDelta::zero();
+        let mut new_ledger =
+            self.apply_fragment_in_block(ledger_params, content, metadata, &mut pots_delta)?;
+        new_ledger.pots = new_ledger.pots.commit(&pots_delta)?;
+        Ok(new_ledger)
+    }
+
+    // Same as `apply_fragment`, adding what goes to the pots to `pots_delta`
+    // instead of the pots of the ledger.
+    fn apply_fragment_in_block(
+        &self,
+        ledger_params: &LedgerParameters,
+        content: &Fragment,
+        metadata: &HeaderContentEvalContext,
+        pots_delta: &mut PotsDelta,
     ) -> Result<Self, Error> {
         let mut new_ledger = self.clone();
 
-        let fragment_id = content.hash();
+        let fragment_id = content.hash_with(new_ledger.settings.hash_scheme);
         match content {
             Fragment::Initial(_) => {
                 return Err(Error::Block0 {
@@ -368,10 +496,11 @@ impl Ledger {
                 });
             }
             Fragment::Transaction(authenticated_tx) => {
-                let (new_ledger_, _fee) = new_ledger.apply_transaction(
+                let (new_ledger_, _fee) = new_ledger.apply_transaction_in_block(
                     &fragment_id,
                     &authenticated_tx,
                     &ledger_params,
+                    pots_delta,
                 )?;
                 new_ledger = new_ledger_;
             }
@@ -381,28 +510,31 @@ impl Ledger {
                 new_ledger = new_ledger_;
             }
             Fragment::StakeDelegation(authenticated_tx) => {
-                let (new_ledger_, _fee) = new_ledger.apply_transaction(
+                let (new_ledger_, _fee) = new_ledger.apply_transaction_in_block(
                     &fragment_id,
                     &authenticated_tx,
                     &ledger_params,
+                    pots_delta,
                 )?;
                 new_ledger =
                     new_ledger_.apply_stake_delegation(&authenticated_tx.transaction.extra)?;
             }
             Fragment::PoolRegistration(authenticated_tx) => {
-                let (new_ledger_, _fee) = new_ledger.apply_transaction(
+                let (new_ledger_, _fee) = new_ledger.apply_transaction_in_block(
                     &fragment_id,
                     &authenticated_tx,
                     &ledger_params,
+                    pots_delta,
                 )?;
                 new_ledger =
                     new_ledger_.apply_pool_registration(&authenticated_tx.transaction.extra)?;
             }
             Fragment::PoolManagement(authenticated_tx) => {
-                let (new_ledger_, _fee) = new_ledger.apply_transaction(
+                let (new_ledger_, _fee) = new_ledger.apply_transaction_in_block(
                     &fragment_id,
                     &authenticated_tx,
                     &ledger_params,
+                    pots_delta,
                 )?;
                 new_ledger =
                     new_ledger_.apply_pool_management(&authenticated_tx.transaction.extra)?;
@@ -428,6 +560,25 @@ impl Ledger {
         signed_tx: &AuthenticatedTransaction<Address, Extra>,
         dyn_params: &LedgerParameters,
     ) -> Result<(Self, Value), Error>
+    where
+        Extra: property::Serialize,
+        LinearFee: FeeAlgorithm<Transaction<Address, Extra>>,
+    {
+        let mut pots_delta = PotsDelta::zero();
+        let (mut new_ledger, fee) =
+            self.apply_transaction_in_block(fragment_id, signed_tx, dyn_params, &mut pots_delta)?;
+        new_ledger.pots = new_ledger.pots.commit(&pots_delta)?;
+        Ok((new_ledger, fee))
+    }
+
+    // Same as `apply_transaction`, adding the fee to `pots_delta`.
+    fn apply_transaction_in_block<Extra>(
+        mut self,
+        fragment_id: &FragmentId,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+        dyn_params: &LedgerParameters,
+        pots_delta: &mut PotsDelta,
+    ) -> Result<(Self, Value), Error>
     where
         Extra: property::Serialize,
         LinearFee: FeeAlgorithm<Transaction<Address, Extra>>,
@@ -435,9 +586,9 @@ impl Ledger {
         signed_tx.verify_well_formed(&TX_VERIFY_LIMITS)?;
         let fee = calculate_fee(signed_tx, dyn_params)?;
         signed_tx.transaction.verify_strictly_balanced(fee)?;
-        self = self.apply_tx_inputs(signed_tx)?;
+        self = self.apply_tx_inputs(signed_tx, dyn_params.witness_verification)?;
         self = self.apply_tx_outputs(*fragment_id, signed_tx)?;
-        self = self.apply_tx_fee(fee)?;
+        pots_delta.add_fees(fee);
         Ok((self, fee))
     }
 
@@ -502,6 +653,37 @@ impl Ledger {
         }
     }
 
+    /// credit the reward of a pool to its reward destination, creating
+    /// the accounts that do not exist yet.
+    ///
+    /// The caller is responsible for taking `reward` out of the pots.
+    pub fn distribute_pool_reward(
+        mut self,
+        pool_id: &certificate::PoolId,
+        reward: Value,
+    ) -> Result<Self, Error> {
+        let reg = self.delegation.stake_pool_get(pool_id)?;
+        let shares = reg
+            .reward_destination
+            .split(&reg.owners, reward)
+            .ok_or(Error::PoolRegistrationInvalid)?;
+        for (account, share) in shares {
+            if share == Value::zero() {
+                continue;
+            }
+            let delegation = self.account_delegation(&account);
+            self.accounts = match self.accounts.add_value(&account, share) {
+                Ok(accounts) => accounts,
+                Err(account::LedgerError::NonExistent) => {
+                    self.accounts.add_account(&account, share, ())?
+                }
+                Err(error) => return Err(error.into()),
+            };
+            self.stake.add_account_value(delegation.as_ref(), share)?;
+        }
+        Ok(self)
+    }
+
     pub fn apply_stake_delegation(
         mut self,
         auth_cert: &certificate::StakeDelegation,
@@ -513,9 +695,17 @@ impl Ledger {
         }
 
         if let Some(account_key) = auth_cert.account_id.to_single_account() {
+            let previous = self.account_delegation(&account_key);
             self.accounts = self
                 .accounts
                 .set_delegation(&account_key, Some(pool_id.clone()))?;
+            let account_value = self.accounts.get_state(&account_key)?.value();
+            self.stake.change_delegation(
+                &account_key,
+                account_value,
+                previous.as_ref(),
+                Some(pool_id),
+            )?;
         } else {
             return Err(DelegationError::StakeDelegationAccountIsInvalid(
                 auth_cert.account_id.clone(),
@@ -559,6 +749,7 @@ impl Ledger {
 
         match match_identifier_witness(&account_id, witness)? {
             MatchingIdentifierWitness::Single(account_id, witness) => {
+                let previous = self.account_delegation(&account_id);
                 let single = input_single_account_verify(
                     self.accounts,
                     &self.static_params.block0_initial_hash,
@@ -566,10 +757,17 @@ impl Ledger {
                     &account_id,
                     witness,
                     value,
+                    false,
                 )?;
-                self.accounts = single.set_delegation(
+                let pool_id = &auth_cert.transaction.extra.pool_id;
+                self.accounts = single.set_delegation(&account_id, Some(pool_id.clone()))?;
+                self.stake.remove_account_value(previous.as_ref(), value)?;
+                let account_value = self.accounts.get_state(&account_id)?.value();
+                self.stake.change_delegation(
                     &account_id,
-                    Some(auth_cert.transaction.extra.pool_id.clone()),
+                    account_value,
+                    previous.as_ref(),
+                    Some(pool_id),
                 )?;
             }
             MatchingIdentifierWitness::Multi(account_id, witness) => {
@@ -591,7 +789,18 @@ impl Ledger {
         Ok((self, value))
     }
 
+    /// the stake distribution of the current state, from the running totals
+    /// maintained as the ledger is updated
     pub fn get_stake_distribution(&self) -> StakeDistribution {
+        let distribution = self.stake.distribution(&self.delegation);
+        #[cfg(feature = "stake-distribution-check")]
+        debug_assert_eq!(distribution, self.recompute_stake_distribution());
+        distribution
+    }
+
+    /// compute the stake distribution from scratch, walking all the
+    /// accounts and utxos of the ledger
+    pub fn recompute_stake_distribution(&self) -> StakeDistribution {
         stake::get_distribution(&self.accounts, &self.delegation, &self.utxos)
     }
 
@@ -604,18 +813,56 @@ impl Ledger {
         &self.accounts
     }
 
+    pub fn multisig(&self) -> &multisig::Ledger {
+        &self.multisig
+    }
+
     pub fn get_ledger_parameters(&self) -> LedgerParameters {
         LedgerParameters {
             fees: *self.settings.linear_fees,
+            date_acceptance_window: None,
+            witness_verification: WitnessVerification::default(),
         }
     }
 
+    /// the time frame of the ledger, starting at the block0 date and
+    /// ticking at the slot duration of the current settings, which
+    /// follows the config changes adopted since block0
+    pub fn time_frame(&self) -> TimeFrame {
+        let system_time =
+            SystemTime::UNIX_EPOCH + Duration::from_secs(self.static_params.block0_start_time.0);
+        TimeFrame::new(
+            Timeline::new(system_time),
+            SlotDuration::from_secs(self.settings.slot_duration as u32),
+        )
+    }
+
     pub fn consensus_version(&self) -> ConsensusVersion {
         self.settings.consensus_version
     }
 
-    pub fn utxos<'a>(&'a self) -> utxo::Iter<'a, Address> {
-        self.utxos.iter()
+    /// how the ids of the fragments and blocks of the chain are computed,
+    /// to pass to `Fragment::hash_with` and `Header::hash_with`
+    pub fn hash_scheme(&self) -> HashScheme {
+        self.settings.hash_scheme
+    }
+
+    pub fn utxos(&self) -> &utxo::Ledger<Address> {
+        &self.utxos
+    }
+
+    pub fn utxos_legacy(&self) -> &utxo::Ledger<legacy::OldAddress> {
+        &self.oldutxos
+    }
+
+    pub fn pots(&self) -> &Pots {
+        &self.pots
+    }
+
+    /// a copy of the current settings, which stays as it is when the
+    /// ledger is updated afterwards
+    pub fn settings_snapshot(&self) -> setting::Settings {
+        self.settings.clone()
     }
 
     pub fn chain_length(&self) -> ChainLength {
@@ -642,6 +889,27 @@ impl Ledger {
         &self.era
     }
 
+    /// A rough estimate of the memory held by the ledger, in bytes
+    ///
+    /// Only the entries of the utxo, legacy utxo, account and multisig
+    /// ledgers are counted, at the size of their key and value. Memory
+    /// shared with other ledgers is counted for each of them.
+    pub fn estimate_size(&self) -> usize {
+        use std::mem::size_of;
+        let utxo_key = size_of::<FragmentId>() + size_of::<TransactionIndex>();
+        let account_state = size_of::<crate::accounting::account::AccountState<()>>();
+        let account_entry = size_of::<account::Identifier>() + account_state;
+        let multisig_entry = size_of::<multisig::Identifier>() + account_state;
+
+        size_of::<Ledger>()
+            + self.utxos.iter().count() * (utxo_key + size_of::<Output<Address>>())
+            + self.oldutxos.iter().count() * (utxo_key + size_of::<Output<legacy::OldAddress>>())
+            + self.accounts.iter().count() * account_entry
+            + self.multisig.iter_accounts().count() * multisig_entry
+            + self.multisig.iter_declarations().count()
+                * (size_of::<multisig::Identifier>() + size_of::<multisig::Declaration>())
+    }
+
     fn validate_utxo_total_value(&self) -> Result<(), Error> {
         let old_utxo_values = self.oldutxos.iter().map(|entry| entry.output.value);
         let new_utxo_values = self.utxos.iter().map(|entry| entry.output.value);
@@ -655,7 +923,7 @@ impl Ledger {
             .chain(new_utxo_values)
             .chain(Some(account_value))
             .chain(Some(multisig_value))
-            .chain(Some(self.pot));
+            .chain(self.pots.values());
         Value::sum(all_utxo_values).map_err(|_| Error::Block0 {
             source: Block0Error::UtxoTotalValueTooBig,
         })?;
@@ -665,8 +933,13 @@ impl Ledger {
     fn apply_tx_inputs<Extra: property::Serialize>(
         mut self,
         signed_tx: &AuthenticatedTransaction<Address, Extra>,
+        witness_verification: WitnessVerification,
     ) -> Result<Self, Error> {
         let sign_data_hash = signed_tx.transaction.hash();
+        let verified_in_batch = match witness_verification {
+            WitnessVerification::Sequential => false,
+            WitnessVerification::Batch => self.verify_witnesses_batch(&sign_data_hash, signed_tx),
+        };
         for (input, witness) in signed_tx
             .transaction
             .inputs
@@ -675,11 +948,17 @@ impl Ledger {
         {
             match input.to_enum() {
                 InputEnum::UtxoInput(utxo) => {
-                    self = self.apply_input_to_utxo(&sign_data_hash, &utxo, witness)?
+                    self = self.apply_input_to_utxo(
+                        &sign_data_hash,
+                        &utxo,
+                        witness,
+                        verified_in_batch,
+                    )?
                 }
                 InputEnum::AccountInput(account_id, value) => {
                     match match_identifier_witness(&account_id, witness)? {
                         MatchingIdentifierWitness::Single(account_id, witness) => {
+                            let delegation = self.account_delegation(&account_id);
                             self.accounts = input_single_account_verify(
                                 self.accounts,
                                 &self.static_params.block0_initial_hash,
@@ -687,7 +966,10 @@ impl Ledger {
                                 &account_id,
                                 witness,
                                 value,
-                            )?
+                                verified_in_batch,
+                            )?;
+                            self.stake
+                                .remove_account_value(delegation.as_ref(), value)?;
                         }
                         MatchingIdentifierWitness::Multi(account_id, witness) => {
                             self.multisig = input_multi_account_verify(
@@ -706,62 +988,154 @@ impl Ledger {
         Ok(self)
     }
 
+    /// Verify together the signatures of the utxo and single account
+    /// witnesses of `signed_tx`, against the current state of the ledger.
+    ///
+    /// False if one of them does not verify, if the output or the account
+    /// of one of the inputs cannot be found, or if an account is spent
+    /// more than once, its witnesses signing the counters of successive
+    /// spendings; the inputs then have to verify their witnesses one by
+    /// one. The other witnesses are always verified by their input.
+    fn verify_witnesses_batch<Extra>(
+        &self,
+        sign_data_hash: &TransactionSignDataHash,
+        signed_tx: &AuthenticatedTransaction<Address, Extra>,
+    ) -> bool {
+        let block0_hash = &self.static_params.block0_initial_hash;
+        let mut signed = Vec::with_capacity(signed_tx.witnesses.len());
+        let mut accounts = Vec::new();
+        for (input, witness) in signed_tx
+            .transaction
+            .inputs
+            .iter()
+            .zip(signed_tx.witnesses.iter())
+        {
+            match (input.to_enum(), witness) {
+                (InputEnum::UtxoInput(utxo), Witness::Utxo(signature)) => {
+                    let entry = match self.utxos.get(&utxo.transaction_id, &utxo.output_index) {
+                        Some(entry) => entry,
+                        None => return false,
+                    };
+                    let public_key = match entry.output.address.public_key() {
+                        Some(public_key) => public_key.clone(),
+                        None => return false,
+                    };
+                    let data = WitnessUtxoData::new(block0_hash, sign_data_hash);
+                    signed.push((
+                        data.as_ref().to_vec(),
+                        public_key,
+                        signature.as_raw().clone().coerce::<()>(),
+                    ));
+                }
+                (InputEnum::AccountInput(account_id, _), Witness::Account(signature)) => {
+                    let account_id = match account_id.to_single_account() {
+                        Some(account_id) => account_id,
+                        None => return false,
+                    };
+                    // all the witnesses would be checked against the
+                    // counter of the first spending
+                    if accounts.contains(&account_id) {
+                        return false;
+                    }
+                    accounts.push(account_id.clone());
+                    let counter = match self.accounts.get_state(&account_id) {
+                        Ok(state) => state.counter,
+                        Err(_) => return false,
+                    };
+                    let data = WitnessAccountData::new(block0_hash, sign_data_hash, &counter);
+                    signed.push((
+                        data.as_ref().to_vec(),
+                        AccountPublicKey::from(account_id).into_raw(),
+                        signature.as_raw().clone().coerce::<()>(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        let items: Vec<key::SignatureBatchItem<()>> = signed
+            .iter()
+            .map(|(data, public_key, signature)| (data.as_slice(), public_key, signature))
+            .collect();
+        key::verify_signatures_batch(&items) == Verification::Success
+    }
+
     fn apply_tx_outputs<Extra>(
         mut self,
         fragment_id: FragmentId,
         signed_tx: &AuthenticatedTransaction<Address, Extra>,
     ) -> Result<Self, Error> {
         let mut new_utxos = Vec::new();
-        for (index, output) in signed_tx.transaction.outputs.iter().enumerate() {
+        let outputs = &signed_tx.transaction.outputs;
+        for (index, output) in TransactionIndex::iter_to(outputs.len()).zip(outputs.iter()) {
             check::valid_output_value(&output)?;
 
             if output.address.discrimination() != self.static_params.discrimination {
                 return Err(Error::InvalidDiscrimination);
             }
-            match output.address.kind() {
-                Kind::Single(_) => {
-                    new_utxos.push((index as u8, output.clone()));
+            let address = &output.address;
+            if let Some(identifier) = address.multisig_hash() {
+                let identifier = multisig::Identifier::from(identifier);
+                self.multisig = self.multisig.add_value(&identifier, output.value)?;
+            } else if let Some((_, group_key)) = address.group_keys() {
+                let account_id = group_key.clone().into();
+                // TODO: probably faster to just call add_account and check for already exists error
+                if !self.accounts.exists(&account_id) {
+                    self.accounts = self.accounts.add_account(&account_id, Value::zero(), ())?;
                 }
-                Kind::Group(_, account_id) => {
-                    let account_id = account_id.clone().into();
-                    // TODO: probably faster to just call add_account and check for already exists error
-                    if !self.accounts.exists(&account_id) {
-                        self.accounts =
-                            self.accounts.add_account(&account_id, Value::zero(), ())?;
+                let delegation = self.account_delegation(&account_id);
+                self.stake
+                    .add_group_utxo(&account_id, delegation.as_ref(), output.value)?;
+                new_utxos.push((index, output.clone()));
+            } else if let Some(account_key) = address.account_identifier() {
+                // don't have a way to make a newtype ref from the ref so .clone()
+                let account = account_key.clone().into();
+                let delegation = self.account_delegation(&account);
+                self.accounts = match self.accounts.add_value(&account, output.value) {
+                    Ok(accounts) => accounts,
+                    Err(account::LedgerError::NonExistent) => {
+                        self.accounts.add_account(&account, output.value, ())?
                     }
-                    new_utxos.push((index as u8, output.clone()));
-                }
-                Kind::Account(identifier) => {
-                    // don't have a way to make a newtype ref from the ref so .clone()
-                    let account = identifier.clone().into();
-                    self.accounts = match self.accounts.add_value(&account, output.value) {
-                        Ok(accounts) => accounts,
-                        Err(account::LedgerError::NonExistent) => {
-                            self.accounts.add_account(&account, output.value, ())?
-                        }
-                        Err(error) => return Err(error.into()),
-                    };
-                }
-                Kind::Multisig(identifier) => {
-                    let identifier = multisig::Identifier::from(identifier.clone());
-                    self.multisig = self.multisig.add_value(&identifier, output.value)?;
-                }
+                    Err(error) => return Err(error.into()),
+                };
+                self.stake
+                    .add_account_value(delegation.as_ref(), output.value)?;
+            } else {
+                // single address
+                self.stake.add_single_utxo(output.value)?;
+                new_utxos.push((index, output.clone()));
             }
         }
         self.utxos = self.utxos.add(&fragment_id, &new_utxos)?;
         Ok(self)
     }
 
-    fn apply_tx_fee(mut self, fee: Value) -> Result<Self, Error> {
-        self.pot = (self.pot + fee).map_err(|error| Error::PotValueInvalid { error })?;
-        Ok(self)
+    /// the current delegation of an account, `None` if the account doesn't exist
+    fn account_delegation(&self, account: &account::Identifier) -> Option<certificate::PoolId> {
+        self.accounts
+            .get_state(account)
+            .ok()
+            .and_then(|state| state.delegation().clone())
     }
 
+    fn remove_utxo_stake(&mut self, output: &OutputAddress) -> Result<(), StakeError> {
+        if let Some((_, group_key)) = output.address.group_keys() {
+            let account_id = group_key.clone().into();
+            let delegation = self.account_delegation(&account_id);
+            self.stake
+                .remove_group_utxo(&account_id, delegation.as_ref(), output.value)
+        } else {
+            self.stake.remove_single_utxo(output.value)
+        }
+    }
+
+    /// `verified_in_batch` tells that the signature of a utxo witness was
+    /// verified by `verify_witnesses_batch` already.
     fn apply_input_to_utxo(
         mut self,
         sign_data_hash: &TransactionSignDataHash,
         utxo: &UtxoPointer,
         witness: &Witness,
+        verified_in_batch: bool,
     ) -> Result<Self, Error> {
         match witness {
             Witness::Account(_) => Err(Error::ExpectingUtxoWitness),
@@ -779,7 +1153,7 @@ impl Ledger {
                     });
                 };
 
-                if legacy::oldaddress_from_xpub(&associated_output.address, xpub) {
+                if !legacy::oldaddress_from_xpub(&associated_output.address, xpub) {
                     return Err(Error::OldUtxoInvalidPublicKey {
                         utxo: utxo.clone(),
                         output: associated_output.clone(),
@@ -804,6 +1178,7 @@ impl Ledger {
                 let (new_utxos, associated_output) =
                     self.utxos.remove(&utxo.transaction_id, utxo.output_index)?;
                 self.utxos = new_utxos;
+                self.remove_utxo_stake(&associated_output)?;
                 if utxo.value != associated_output.value {
                     return Err(Error::UtxoValueNotMatching {
                         expected: utxo.value,
@@ -811,12 +1186,15 @@ impl Ledger {
                     });
                 }
 
+                if verified_in_batch {
+                    return Ok(self);
+                }
                 let data_to_verify =
                     WitnessUtxoData::new(&self.static_params.block0_initial_hash, sign_data_hash);
-                let verified = signature.verify(
-                    &associated_output.address.public_key().unwrap(),
-                    &data_to_verify,
+                let public_key = SpendingPublicKey::from_raw(
+                    associated_output.address.public_key().unwrap().clone(),
                 );
+                let verified = signature.verify(&public_key, &data_to_verify);
                 if verified == chain_crypto::Verification::Failed {
                     return Err(Error::UtxoInvalidSignature {
                         utxo: utxo.clone(),
@@ -837,12 +1215,12 @@ fn apply_old_declaration(
 ) -> Result<utxo::Ledger<legacy::OldAddress>, Error> {
     assert!(decl.addrs.len() < 255);
     let mut outputs = Vec::with_capacity(decl.addrs.len());
-    for (i, d) in decl.addrs.iter().enumerate() {
+    for (i, d) in TransactionIndex::iter_to(decl.addrs.len()).zip(decl.addrs.iter()) {
         let output = Output {
             address: d.0.clone(),
             value: d.1,
         };
-        outputs.push((i as u8, output))
+        outputs.push((i, output))
     }
     utxos = utxos.add(&fragment_id, &outputs)?;
     Ok(utxos)
@@ -895,13 +1273,17 @@ fn input_single_account_verify<'a>(
     account: &account::Identifier,
     witness: &'a account::Witness,
     value: Value,
+    verified_in_batch: bool,
 ) -> Result<account::Ledger, Error> {
     // .remove_value() check if there's enough value and if not, returns a Err.
     let (new_ledger, spending_counter) = ledger.remove_value(&account, value)?;
     ledger = new_ledger;
+    if verified_in_batch {
+        return Ok(ledger);
+    }
 
     let tidsc = WitnessAccountData::new(block0_hash, sign_data_hash, &spending_counter);
-    let verified = witness.verify(&account.clone().into(), &tidsc);
+    let verified = witness.verify(&AccountPublicKey::from(account.clone()), &tidsc);
     if verified == chain_crypto::Verification::Failed {
         return Err(Error::AccountInvalidSignature {
             account: account.clone(),
@@ -1,7 +1,11 @@
 pub mod check;
+pub mod initial_funds;
 pub mod iter;
 pub mod ledger;
+pub mod pots;
+pub mod serialization;
 
+pub use initial_funds::*;
 pub use iter::*;
 pub use ledger::*;
 
@@ -0,0 +1,540 @@
+//! Binary snapshot of a ledger state
+//!
+//! The snapshot starts with a format version byte, followed by every part
+//! of the state in a fixed order. The stake tracker is not part of the
+//! snapshot, it is rebuilt from the accounts and utxos when reading it back.
+
+use super::ledger::{Ledger, LedgerStaticParameters};
+use super::pots::{self, Pots};
+use crate::accounting::account::{AccountState, SpendingCounter};
+use crate::block::{BlockDate, ChainLength};
+use crate::certificate::{PoolId, PoolRegistration};
+use crate::config::Block0Date;
+use crate::fragment::{ConfigParams, FragmentId};
+use crate::leadership::{bft, genesis::Nonce};
+use crate::multisig::{DeclElement, Declaration};
+use crate::stake::{DelegationState, StakeTracker};
+use crate::transaction::{Output, TransactionIndex};
+use crate::update::{UpdateProposal, UpdateProposalState, UpdateState};
+use crate::value::Value;
+use crate::{account, key, legacy, multisig, setting, utxo};
+use chain_addr::{Address, Discrimination};
+use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::packer::Codec;
+use chain_core::property::{self, Serialize as _};
+use chain_time::{Epoch, TimeEra};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::sync::Arc;
+
+/// version of the snapshot format, to bump on every incompatible change
+pub const FORMAT_VERSION: u8 = 4;
+
+const DISCRIMINATION_PRODUCTION: u8 = 1;
+const DISCRIMINATION_TEST: u8 = 2;
+
+const DECL_ELEMENT_OWNER: u8 = 1;
+const DECL_ELEMENT_SUB: u8 = 2;
+
+fn put_len<W: Write>(codec: &mut Codec<W>, len: usize) -> std::io::Result<()> {
+    assert!(len <= std::u32::MAX as usize);
+    codec.put_u32(len as u32)
+}
+
+fn serialize_account_state<W: Write>(
+    codec: &mut Codec<W>,
+    state: &AccountState<()>,
+) -> std::io::Result<()> {
+    codec.put_u32(state.counter.into())?;
+    match &state.delegation {
+        None => codec.put_u8(0)?,
+        Some(pool_id) => {
+            codec.put_u8(1)?;
+            codec.write_all(pool_id.as_ref())?;
+        }
+    }
+    state.value.serialize(codec)
+}
+
+fn read_account_state<'a>(buf: &mut ReadBuf<'a>) -> Result<AccountState<()>, ReadError> {
+    let counter = SpendingCounter::from(buf.get_u32()?);
+    let delegation = match buf.get_u8()? {
+        0 => None,
+        1 => Some(PoolId::from(<[u8; 32]>::read(buf)?)),
+        t => return Err(ReadError::UnknownTag(t as u32)),
+    };
+    let value = Value::read(buf)?;
+    Ok(AccountState {
+        counter,
+        delegation,
+        value,
+        extra: (),
+    })
+}
+
+fn serialize_declaration<W: Write>(
+    codec: &mut Codec<W>,
+    declaration: &Declaration,
+) -> std::io::Result<()> {
+    assert!(declaration.owners.len() < 256);
+    codec.put_u8(declaration.threshold)?;
+    codec.put_u8(declaration.owners.len() as u8)?;
+    for owner in &declaration.owners {
+        match owner {
+            DeclElement::Owner(hash) => {
+                codec.put_u8(DECL_ELEMENT_OWNER)?;
+                hash.serialize(&mut *codec)?;
+            }
+            DeclElement::Sub(sub) => {
+                codec.put_u8(DECL_ELEMENT_SUB)?;
+                serialize_declaration(codec, sub)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_declaration<'a>(buf: &mut ReadBuf<'a>) -> Result<Declaration, ReadError> {
+    let threshold = buf.get_u8()?;
+    let nb_owners = buf.get_u8()? as usize;
+    let mut owners = Vec::with_capacity(nb_owners);
+    for _ in 0..nb_owners {
+        let owner = match buf.get_u8()? {
+            DECL_ELEMENT_OWNER => DeclElement::Owner(key::Hash::read(buf)?),
+            DECL_ELEMENT_SUB => DeclElement::Sub(read_declaration(buf)?),
+            t => return Err(ReadError::UnknownTag(t as u32)),
+        };
+        owners.push(owner);
+    }
+    Ok(Declaration { threshold, owners })
+}
+
+fn serialize_utxos<W: Write, OutAddress, F>(
+    codec: &mut Codec<W>,
+    utxos: &utxo::Ledger<OutAddress>,
+    serialize_address: F,
+) -> std::io::Result<()>
+where
+    F: Fn(&mut Codec<W>, &OutAddress) -> std::io::Result<()>,
+{
+    put_len(codec, utxos.iter().count())?;
+    for entry in utxos.iter() {
+        entry.fragment_id.serialize(&mut *codec)?;
+        entry.output_index.serialize(&mut *codec)?;
+        serialize_address(codec, &entry.output.address)?;
+        entry.output.value.serialize(&mut *codec)?;
+    }
+    Ok(())
+}
+
+fn read_utxos<'a, OutAddress, F>(
+    buf: &mut ReadBuf<'a>,
+    read_address: F,
+) -> Result<utxo::Ledger<OutAddress>, ReadError>
+where
+    OutAddress: Clone,
+    F: Fn(&mut ReadBuf<'a>) -> Result<OutAddress, ReadError>,
+{
+    let nb_entries = buf.get_u32()?;
+    let mut outputs: HashMap<FragmentId, Vec<(TransactionIndex, Output<OutAddress>)>> =
+        HashMap::new();
+    for _ in 0..nb_entries {
+        let fragment_id = FragmentId::read(buf)?;
+        let output_index = TransactionIndex::read(buf)?;
+        let address = read_address(buf)?;
+        let value = Value::read(buf)?;
+        outputs
+            .entry(fragment_id)
+            .or_insert_with(Vec::new)
+            .push((output_index, Output { address, value }));
+    }
+    Ok(outputs.into_iter().collect())
+}
+
+fn serialize_old_address<W: Write>(
+    codec: &mut Codec<W>,
+    address: &legacy::OldAddress,
+) -> std::io::Result<()> {
+    let bytes = address.as_ref();
+    codec.put_u16(bytes.len() as u16)?;
+    codec.write_all(bytes)
+}
+
+fn read_old_address<'a>(buf: &mut ReadBuf<'a>) -> Result<legacy::OldAddress, ReadError> {
+    let size = buf.get_u16()? as usize;
+    legacy::OldAddress::try_from(buf.get_slice(size)?)
+        .map_err(|error| ReadError::StructureInvalid(format!("invalid legacy address: {}", error)))
+}
+
+// since version 2 of the format, the pots are tagged entries in place of
+// the single value of the fees in version 1, which is the `Fees` entry.
+// version 3 adds the `Rewards` entry and version 4 the fees of the open
+// epoch after the entries, when an epoch is open.
+fn serialize_pots<W: Write>(codec: &mut Codec<W>, pots: &Pots) -> std::io::Result<()> {
+    let entries: Vec<pots::Entry> = pots.entries().collect();
+    codec.put_u8(entries.len() as u8)?;
+    for entry in entries {
+        codec.put_u8(entry.entry_type().tag())?;
+        entry.value().serialize(&mut *codec)?;
+    }
+    match pots.open_epoch_fees() {
+        None => codec.put_u8(0),
+        Some((epoch, fees)) => {
+            codec.put_u8(1)?;
+            codec.put_u32(epoch.0)?;
+            fees.serialize(codec)
+        }
+    }
+}
+
+fn read_pots<'a>(buf: &mut ReadBuf<'a>) -> Result<Pots, ReadError> {
+    let mut pots = pots::PotsBuilder::new();
+    let nb_entries = buf.get_u8()?;
+    for _ in 0..nb_entries {
+        let tag = buf.get_u8()?;
+        let entry_type =
+            pots::EntryType::from_tag(tag).ok_or(ReadError::UnknownTag(u32::from(tag)))?;
+        pots.add_entry(pots::Entry::new(entry_type, Value::read(buf)?))
+            .map_err(|error| ReadError::StructureInvalid(error.to_string()))?;
+    }
+    let mut pots = pots.finish();
+    match buf.get_u8()? {
+        0 => {}
+        1 => {
+            let epoch = Epoch(buf.get_u32()?);
+            let fees = Value::read(buf)?;
+            pots.restore_epoch_fees(epoch, fees)
+                .map_err(|error| ReadError::StructureInvalid(error.to_string()))?;
+        }
+        t => return Err(ReadError::UnknownTag(u32::from(t))),
+    }
+    Ok(pots)
+}
+
+fn serialize_static_params<W: Write>(
+    codec: &mut Codec<W>,
+    static_params: &LedgerStaticParameters,
+) -> std::io::Result<()> {
+    static_params.block0_initial_hash.serialize(&mut *codec)?;
+    codec.put_u64(static_params.block0_start_time.0)?;
+    codec.put_u8(match static_params.discrimination {
+        Discrimination::Production => DISCRIMINATION_PRODUCTION,
+        Discrimination::Test => DISCRIMINATION_TEST,
+    })?;
+    codec.put_u32(static_params.kes_update_speed)
+}
+
+fn read_static_params<'a>(buf: &mut ReadBuf<'a>) -> Result<LedgerStaticParameters, ReadError> {
+    let block0_initial_hash = key::Hash::read(buf)?;
+    let block0_start_time = Block0Date(buf.get_u64()?);
+    let discrimination = match buf.get_u8()? {
+        DISCRIMINATION_PRODUCTION => Discrimination::Production,
+        DISCRIMINATION_TEST => Discrimination::Test,
+        t => return Err(ReadError::UnknownTag(t as u32)),
+    };
+    let kes_update_speed = buf.get_u32()?;
+    Ok(LedgerStaticParameters {
+        block0_initial_hash,
+        block0_start_time,
+        discrimination,
+        kes_update_speed,
+    })
+}
+
+fn serialize_updates<W: Write>(codec: &mut Codec<W>, updates: &UpdateState) -> std::io::Result<()> {
+    put_len(codec, updates.proposals.len())?;
+    for (proposal_id, state) in updates.proposals.iter() {
+        proposal_id.serialize(&mut *codec)?;
+        state.proposal.serialize(&mut *codec)?;
+        codec.put_u32(state.proposal_date.epoch)?;
+        codec.put_u32(state.proposal_date.slot_id)?;
+        put_len(codec, state.votes.len())?;
+        for voter in state.votes.iter() {
+            voter.serialize(&mut *codec)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_updates<'a>(buf: &mut ReadBuf<'a>) -> Result<UpdateState, ReadError> {
+    let mut updates = UpdateState::new();
+    let nb_proposals = buf.get_u32()?;
+    for _ in 0..nb_proposals {
+        let proposal_id = FragmentId::read(buf)?;
+        let proposal = UpdateProposal::read(buf)?;
+        let epoch = buf.get_u32()?;
+        let slot_id = buf.get_u32()?;
+        let nb_votes = buf.get_u32()?;
+        let mut votes = HashSet::new();
+        for _ in 0..nb_votes {
+            votes.insert(bft::LeaderId::read(buf)?);
+        }
+        updates.proposals.insert(
+            proposal_id,
+            UpdateProposalState {
+                proposal,
+                proposal_date: BlockDate { epoch, slot_id },
+                votes,
+            },
+        );
+    }
+    Ok(updates)
+}
+
+impl property::Serialize for Ledger {
+    type Error = std::io::Error;
+
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        let mut codec = Codec::new(writer);
+        codec.put_u8(FORMAT_VERSION)?;
+
+        serialize_static_params(&mut codec, &self.static_params)?;
+        self.settings.to_config_params().serialize(&mut codec)?;
+        codec.write_all(self.settings.consensus_nonce.as_ref())?;
+
+        codec.put_u64(self.era.slot_start().into())?;
+        codec.put_u32(self.era.epoch_start().0)?;
+        codec.put_u32(self.era.slots_per_epoch())?;
+        codec.put_u32(self.date.epoch)?;
+        codec.put_u32(self.date.slot_id)?;
+        codec.put_u32(self.chain_length.0)?;
+        serialize_pots(&mut codec, &self.pots)?;
+
+        serialize_utxos(&mut codec, &self.utxos, |codec, address| {
+            address.serialize(codec)
+        })?;
+        serialize_utxos(&mut codec, &self.oldutxos, serialize_old_address)?;
+
+        put_len(&mut codec, self.accounts.iter().count())?;
+        for (identifier, state) in self.accounts.iter() {
+            identifier.serialize(&mut codec)?;
+            serialize_account_state(&mut codec, state)?;
+        }
+
+        put_len(&mut codec, self.multisig.iter_accounts().count())?;
+        for (identifier, state) in self.multisig.iter_accounts() {
+            codec.write_all(identifier.as_ref())?;
+            serialize_account_state(&mut codec, state)?;
+        }
+        put_len(&mut codec, self.multisig.iter_declarations().count())?;
+        for (_, declaration) in self.multisig.iter_declarations() {
+            serialize_declaration(&mut codec, declaration)?;
+        }
+
+        put_len(&mut codec, self.delegation.stake_pools.iter().count())?;
+        for (_, registration) in self.delegation.stake_pools.iter() {
+            registration.serialize(&mut codec)?;
+        }
+
+        serialize_updates(&mut codec, &self.updates)
+    }
+}
+
+impl Readable for Ledger {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        match buf.get_u8()? {
+            FORMAT_VERSION => {}
+            2 => {
+                return Err(ReadError::StructureInvalid(
+                    "ledger format version 2 predates the rewards pot and is no longer supported"
+                        .to_string(),
+                ))
+            }
+            3 => {
+                return Err(ReadError::StructureInvalid(
+                    "ledger format version 3 lacks the fees of the open epoch and is no longer supported"
+                        .to_string(),
+                ))
+            }
+            version => {
+                return Err(ReadError::StructureInvalid(format!(
+                    "unsupported ledger format version {}",
+                    version
+                )))
+            }
+        }
+
+        let static_params = read_static_params(buf)?;
+        let config_params = ConfigParams::read(buf)?;
+        let mut settings = setting::Settings::new()
+            .apply(&config_params)
+            .map_err(|error| ReadError::StructureInvalid(format!("invalid settings: {}", error)))?;
+        settings.consensus_nonce = Nonce::from(<[u8; 32]>::read(buf)?);
+
+        let slot_start = buf.get_u64()?;
+        let epoch_start = buf.get_u32()?;
+        let slots_per_epoch = buf.get_u32()?;
+        let era = TimeEra::new(slot_start.into(), Epoch(epoch_start), slots_per_epoch);
+        let epoch = buf.get_u32()?;
+        let slot_id = buf.get_u32()?;
+        let chain_length = ChainLength(buf.get_u32()?);
+        let pots = read_pots(buf)?;
+
+        let utxos = read_utxos(buf, Address::read)?;
+        let oldutxos = read_utxos(buf, read_old_address)?;
+
+        let nb_accounts = buf.get_u32()?;
+        let mut accounts = Vec::new();
+        for _ in 0..nb_accounts {
+            let identifier = account::Identifier::read(buf)?;
+            accounts.push((identifier, read_account_state(buf)?));
+        }
+        let accounts: account::Ledger = accounts.into_iter().collect();
+
+        let nb_multisig_accounts = buf.get_u32()?;
+        let mut multisig_accounts = Vec::new();
+        for _ in 0..nb_multisig_accounts {
+            let identifier = multisig::Identifier::from(<[u8; 32]>::read(buf)?);
+            multisig_accounts.push((identifier, read_account_state(buf)?));
+        }
+        let nb_declarations = buf.get_u32()?;
+        let mut declarations = Vec::new();
+        for _ in 0..nb_declarations {
+            let declaration = read_declaration(buf)?;
+            declarations.push((declaration.to_identifier(), declaration));
+        }
+
+        let nb_pools = buf.get_u32()?;
+        let mut delegation = DelegationState::new();
+        for _ in 0..nb_pools {
+            delegation = delegation
+                .register_stake_pool(PoolRegistration::read(buf)?)
+                .map_err(|error| {
+                    ReadError::StructureInvalid(format!("invalid stake pool: {}", error))
+                })?;
+        }
+
+        let updates = read_updates(buf)?;
+
+        let stake = StakeTracker::from_state(&accounts, &utxos).map_err(|error| {
+            ReadError::StructureInvalid(format!("inconsistent stake: {}", error))
+        })?;
+        Ok(Ledger {
+            utxos,
+            oldutxos,
+            accounts,
+            settings,
+            updates,
+            multisig: multisig::Ledger::restore(multisig_accounts, declarations),
+            delegation,
+            stake,
+            static_params: Arc::new(static_params),
+            date: BlockDate { epoch, slot_id },
+            chain_length,
+            era,
+            pots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::data::AddressData;
+    use crate::testing::ledger::{self, ConfigBuilder};
+    use chain_core::mempack::read_from_raw;
+
+    #[test]
+    pub fn ledger_serialization_roundtrip() {
+        let actors = [
+            AddressData::utxo(Discrimination::Test),
+            AddressData::delegation(Discrimination::Test),
+            AddressData::account(Discrimination::Test),
+        ];
+        let outputs = actors
+            .iter()
+            .map(|actor| actor.make_output(Value(100)))
+            .collect();
+        let fragments = [ledger::create_initial_transactions(&outputs)];
+        let (_, mut ledger) =
+            ledger::create_initial_fake_ledger(&fragments, ConfigBuilder::new().build()).unwrap();
+        ledger.pots.append_fees(Value(42)).unwrap();
+        ledger.pots.treasury_add(Value(7)).unwrap();
+        ledger.pots.append_rewards(Value(3)).unwrap();
+        ledger.settings.consensus_nonce = Nonce::from([7; 32]);
+
+        let bytes = ledger.serialize_as_vec().unwrap();
+        let decoded: Ledger = read_from_raw(&bytes).unwrap();
+        assert!(decoded == ledger);
+        assert_eq!(
+            decoded.get_stake_distribution(),
+            ledger.get_stake_distribution()
+        );
+    }
+
+    #[test]
+    pub fn unknown_version_is_rejected() {
+        let (_, ledger) =
+            ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+        let mut bytes = ledger.serialize_as_vec().unwrap();
+        bytes[0] = FORMAT_VERSION + 1;
+        assert!(Ledger::read(&mut ReadBuf::from(&bytes)).is_err());
+    }
+
+    #[test]
+    pub fn older_versions_are_rejected() {
+        let (_, ledger) =
+            ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+        let mut bytes = ledger.serialize_as_vec().unwrap();
+        for version in 2..FORMAT_VERSION {
+            bytes[0] = version;
+            match Ledger::read(&mut ReadBuf::from(&bytes)) {
+                Err(ReadError::StructureInvalid(message)) => {
+                    assert!(message.contains(&format!("version {}", version)))
+                }
+                _ => panic!("a version {} snapshot must be rejected", version),
+            }
+        }
+    }
+
+    #[test]
+    pub fn open_epoch_fees_roundtrip() {
+        let (_, mut ledger) =
+            ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+        ledger.pots.append_fees(Value(10)).unwrap();
+        ledger
+            .pots
+            .append_fees_in_epoch(Epoch(3), Value(32))
+            .unwrap();
+
+        let bytes = ledger.serialize_as_vec().unwrap();
+        let decoded: Ledger = read_from_raw(&bytes).unwrap();
+        assert!(decoded == ledger);
+        assert_eq!(decoded.pots.fees_in_epoch(Epoch(3)), Value(32));
+        assert_eq!(decoded.pots.carried_over_fees(), Ok(Value(10)));
+    }
+
+    fn pots_bytes(entries: &[pots::Entry]) -> Vec<u8> {
+        let mut codec = Codec::new(Vec::new());
+        codec.put_u8(entries.len() as u8).unwrap();
+        for entry in entries {
+            codec.put_u8(entry.entry_type().tag()).unwrap();
+            entry.value().serialize(&mut codec).unwrap();
+        }
+        // no open epoch
+        codec.put_u8(0).unwrap();
+        codec.into_inner()
+    }
+
+    #[test]
+    pub fn pots_are_read_from_shuffled_entries() {
+        let mut pots = Pots::zero();
+        pots.append_fees(Value(42)).unwrap();
+        pots.treasury_add(Value(7)).unwrap();
+        pots.append_rewards(Value(3)).unwrap();
+        let mut entries: Vec<_> = pots.entries().collect();
+        entries.reverse();
+        entries.swap(0, 1);
+
+        let bytes = pots_bytes(&entries);
+        assert_eq!(read_pots(&mut ReadBuf::from(&bytes)).unwrap(), pots);
+
+        entries.push(pots::Entry::Fees(Value(1)));
+        let bytes = pots_bytes(&entries);
+        match read_pots(&mut ReadBuf::from(&bytes)) {
+            Err(ReadError::StructureInvalid(_)) => (),
+            other => panic!("duplicated fees entry read as {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg(test)]
+
+use crate::{
+    block::{BlockDate, HeaderContentEvalContext},
+    config::{Block0Date, ConfigParam},
+    fragment::{ConfigParams, Fragment},
+    ledger::{DateAcceptanceWindow, Error, Ledger},
+    testing::ledger::{self, ConfigBuilder},
+};
+use chain_core::property::ChainLength as _;
+use std::time::{Duration, SystemTime};
+
+const MAX_SLOTS_AHEAD: u32 = 1;
+
+fn ledger_started_now() -> Ledger {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let config = ConfigBuilder::new()
+        .with_block0_date(Block0Date(now))
+        .with_slot_duration(20)
+        .build();
+    let (_, ledger) = ledger::create_initial_fake_ledger(&[], config).unwrap();
+    ledger
+}
+
+fn apply_empty_block(
+    ledger: &Ledger,
+    date_acceptance_window: Option<DateAcceptanceWindow>,
+    block_date: BlockDate,
+) -> Result<Ledger, Error> {
+    let mut ledger_params = ledger.get_ledger_parameters();
+    ledger_params.date_acceptance_window = date_acceptance_window;
+    let metadata = HeaderContentEvalContext {
+        block_date,
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+    ledger.apply_block(&ledger_params, std::iter::empty::<&Fragment>(), &metadata)
+}
+
+fn window(ledger: &Ledger) -> Option<DateAcceptanceWindow> {
+    Some(DateAcceptanceWindow {
+        time_frame: ledger.time_frame(),
+        max_slots_ahead: MAX_SLOTS_AHEAD,
+    })
+}
+
+fn next_slot() -> BlockDate {
+    BlockDate {
+        epoch: 0,
+        slot_id: 1,
+    }
+}
+
+fn far_future() -> BlockDate {
+    BlockDate {
+        epoch: 1000,
+        slot_id: 0,
+    }
+}
+
+#[test]
+pub fn block_one_slot_ahead_is_accepted() {
+    let ledger = ledger_started_now();
+    assert!(apply_empty_block(&ledger, window(&ledger), next_slot()).is_ok());
+}
+
+#[test]
+pub fn far_future_block_is_rejected() {
+    let ledger = ledger_started_now();
+    match apply_empty_block(&ledger, window(&ledger), far_future()) {
+        Err(Error::BlockDateTooFarInFuture { block_date, .. }) => {
+            assert_eq!(block_date, far_future())
+        }
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("far future block accepted"),
+    }
+}
+
+#[test]
+pub fn disabled_window_accepts_any_date() {
+    let ledger = ledger_started_now();
+    assert!(apply_empty_block(&ledger, None, next_slot()).is_ok());
+    assert!(apply_empty_block(&ledger, None, far_future()).is_ok());
+}
+
+#[test]
+pub fn time_frame_follows_the_slot_duration_of_the_settings() {
+    let mut ledger = ledger_started_now();
+    assert_eq!(ledger.settings.slot_duration, 20);
+    assert_eq!(
+        ledger.time_frame().slot_duration().to_duration(),
+        Duration::from_secs(20)
+    );
+
+    let mut changes = ConfigParams::new();
+    changes.push(ConfigParam::SlotDuration(5));
+    ledger.settings = ledger.settings.apply(&changes).unwrap();
+    assert_eq!(
+        ledger.time_frame().slot_duration().to_duration(),
+        Duration::from_secs(5)
+    );
+}
@@ -72,7 +72,7 @@ pub fn ledger_verifies_transaction_discrimination(
         .with_discrimination(arbitrary_input_disc)
         .build();
     let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&[message], config).unwrap();
-    let mut utxos = ledger.utxos();
+    let mut utxos = ledger.utxos().iter();
     let signed_tx = TransactionBuilder::new()
         .with_input(faucet.make_input(value, utxos.next()))
         .with_output(Output::from_address(receiver.address.clone(), value))
@@ -1,11 +1,17 @@
 #![cfg(test)]
 
+use crate::ledger::{
+    check_initial_funds, Block0Error, Error, InitialFund, InitialFundsError, InitialFundsSummary,
+};
+use crate::legacy::{UtxoDeclaration, MAX_DECLARATION_ENTRIES};
 use crate::testing::{
     arbitrary::OutputsWithoutMultisig,
+    keys::KeyFactory,
     ledger::{self, ConfigBuilder},
+    LegacyWallet,
 };
 use crate::{transaction::Output, value::Value};
-use chain_addr::{Address, Discrimination};
+use chain_addr::{Address, AddressReadable, Discrimination};
 use quickcheck::TestResult;
 use quickcheck_macros::quickcheck;
 
@@ -41,3 +47,213 @@ fn should_ledger_fail(outputs: &Vec<Output<Address>>) -> bool {
     }
     outputs.iter().any(|x| x.value == Value::zero())
 }
+
+fn declaration(wallets: &[(&LegacyWallet, Value)]) -> UtxoDeclaration {
+    UtxoDeclaration {
+        addrs: wallets
+            .iter()
+            .map(|(wallet, value)| (wallet.address().clone(), *value))
+            .collect(),
+    }
+}
+
+#[test]
+pub fn mixed_initial_funds_are_summarized_by_kind() {
+    let mut keys = KeyFactory::from_seed([1; 32]);
+    let alice = keys.utxo_address(Discrimination::Test);
+    let bob = keys.account_address(Discrimination::Test);
+    let carol = keys.legacy_wallet(&[0]);
+    let dave = keys.legacy_wallet(&[1]);
+    let erin = keys.legacy_wallet(&[2]);
+
+    // an address can be given several utxos
+    let modern = vec![
+        alice.make_output(Value(10)),
+        alice.make_output(Value(20)),
+        bob.make_output(Value(30)),
+    ];
+    let legacy = vec![
+        declaration(&[(&carol, Value(1)), (&dave, Value(2))]),
+        declaration(&[(&erin, Value(3))]),
+    ];
+    let summary = check_initial_funds(&modern, &legacy).unwrap();
+    assert_eq!(
+        summary,
+        InitialFundsSummary {
+            modern_entries: 3,
+            modern_value: Value(60),
+            legacy_declarations: 2,
+            legacy_entries: 3,
+            legacy_value: Value(6),
+        }
+    );
+    assert_eq!(summary.total_value(), Value(66));
+}
+
+#[test]
+pub fn zero_initial_funds_are_pinpointed() {
+    let mut keys = KeyFactory::from_seed([2; 32]);
+    let alice = keys.utxo_address(Discrimination::Test);
+    let carol = keys.legacy_wallet(&[0]);
+    let dave = keys.legacy_wallet(&[1]);
+
+    let modern = vec![alice.make_output(Value(10)), alice.make_output(Value(0))];
+    assert_eq!(
+        check_initial_funds(&modern, &[]),
+        Err(InitialFundsError::ZeroValue {
+            fund: InitialFund::Modern { index: 1 },
+            address: AddressReadable::from_address("ca", &alice.address).to_string(),
+        })
+    );
+
+    let legacy = vec![
+        declaration(&[(&carol, Value(1))]),
+        declaration(&[(&dave, Value(0))]),
+    ];
+    assert_eq!(
+        check_initial_funds(&[], &legacy),
+        Err(InitialFundsError::ZeroValue {
+            fund: InitialFund::Legacy {
+                declaration: 1,
+                index: 0
+            },
+            address: dave.address().to_string(),
+        })
+    );
+}
+
+#[test]
+pub fn legacy_addresses_are_declared_once() {
+    let mut keys = KeyFactory::from_seed([3; 32]);
+    let carol = keys.legacy_wallet(&[0]);
+    let dave = keys.legacy_wallet(&[1]);
+
+    let legacy = vec![
+        declaration(&[(&carol, Value(1)), (&dave, Value(2))]),
+        declaration(&[(&carol, Value(3))]),
+    ];
+    let error = check_initial_funds(&[], &legacy).unwrap_err();
+    assert_eq!(
+        error,
+        InitialFundsError::DuplicateAddress {
+            fund: InitialFund::Legacy {
+                declaration: 1,
+                index: 0
+            },
+            address: carol.address().to_string(),
+        }
+    );
+    assert!(error
+        .to_string()
+        .contains("entry 0 of legacy declaration 1"));
+
+    let legacy = vec![declaration(&[(&carol, Value(1)), (&carol, Value(2))])];
+    assert_eq!(
+        check_initial_funds(&[], &legacy),
+        Err(InitialFundsError::DuplicateAddress {
+            fund: InitialFund::Legacy {
+                declaration: 0,
+                index: 1
+            },
+            address: carol.address().to_string(),
+        })
+    );
+}
+
+#[test]
+pub fn legacy_declarations_are_limited_in_entries() {
+    let mut keys = KeyFactory::from_seed([4; 32]);
+    let wallets: Vec<_> = (0..=MAX_DECLARATION_ENTRIES as u32)
+        .map(|index| keys.legacy_wallet(&[index]))
+        .collect();
+    let values: Vec<_> = wallets.iter().map(|wallet| (wallet, Value(1))).collect();
+
+    let full = declaration(&values[..MAX_DECLARATION_ENTRIES]);
+    assert!(check_initial_funds(&[], &[full]).is_ok());
+
+    let legacy = vec![declaration(&values[..1]), declaration(&values[1..])];
+    assert!(check_initial_funds(&[], &legacy).is_ok());
+
+    let too_many = declaration(&values);
+    assert_eq!(
+        check_initial_funds(&[], &[declaration(&values[..1]), too_many]),
+        Err(InitialFundsError::TooManyEntries {
+            declaration: 1,
+            entries: MAX_DECLARATION_ENTRIES + 1,
+            max: MAX_DECLARATION_ENTRIES,
+        })
+    );
+}
+
+#[test]
+pub fn total_initial_funds_are_bounded_across_kinds() {
+    let mut keys = KeyFactory::from_seed([5; 32]);
+    let alice = keys.utxo_address(Discrimination::Test);
+    let carol = keys.legacy_wallet(&[0]);
+    let dave = keys.legacy_wallet(&[1]);
+
+    let modern = vec![alice.make_output(Value(u64::max_value() - 1))];
+    let legacy = vec![declaration(&[(&carol, Value(1)), (&dave, Value(1))])];
+    assert_eq!(
+        check_initial_funds(&modern, &legacy),
+        Err(InitialFundsError::TotalValueTooBig {
+            fund: InitialFund::Legacy {
+                declaration: 0,
+                index: 1
+            },
+            address: dave.address().to_string(),
+        })
+    );
+    assert!(check_initial_funds(&modern, &legacy[..0]).is_ok());
+}
+
+#[test]
+pub fn ledger_rejects_invalid_initial_funds_up_front() {
+    let mut keys = KeyFactory::from_seed([6; 32]);
+    let alice = keys.utxo_address(Discrimination::Test);
+    let carol = keys.legacy_wallet(&[0]);
+
+    let fragments = [
+        ledger::create_initial_transaction(alice.make_output(Value(10))),
+        LegacyWallet::declaration(&[(&carol, Value(1))]),
+        LegacyWallet::declaration(&[(&carol, Value(2))]),
+    ];
+    let result = ledger::create_initial_fake_ledger(&fragments, ConfigBuilder::new().build());
+    match result {
+        Err(Error::Block0 {
+            source: Block0Error::InitialFundsInvalid { source },
+        }) => assert_eq!(
+            source,
+            InitialFundsError::DuplicateAddress {
+                fund: InitialFund::Legacy {
+                    declaration: 1,
+                    index: 0
+                },
+                address: carol.address().to_string(),
+            }
+        ),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("the duplicate legacy address was accepted"),
+    }
+    assert!(
+        ledger::create_initial_fake_ledger(&fragments[..2], ConfigBuilder::new().build()).is_ok()
+    );
+}
+
+#[test]
+pub fn ledger_rejects_zero_initial_outputs_as_it_applies_them() {
+    let mut keys = KeyFactory::from_seed([7; 32]);
+    let alice = keys.utxo_address(Discrimination::Test);
+
+    // the error of block0s that were already rejected for it
+    let zero = alice.make_output(Value::zero());
+    let fragments = [ledger::create_initial_transactions(&vec![
+        alice.make_output(Value(10)),
+        zero.clone(),
+    ])];
+    match ledger::create_initial_fake_ledger(&fragments, ConfigBuilder::new().build()) {
+        Err(Error::ZeroOutput { output }) => assert_eq!(output, zero),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("the zero output was accepted"),
+    }
+}
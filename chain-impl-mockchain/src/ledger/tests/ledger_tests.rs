@@ -1,25 +1,34 @@
 #![cfg(test)]
 
 use crate::{
+    account::{self, SpendingCounter},
+    fee::LinearFee,
     fragment::{Fragment, FragmentId},
+    key::{AccountPublicKey, HashScheme},
     ledger::{
         check::TxVerifyError,
         Entry,
-        Error::TransactionMalformed,
-        Ledger,
+        Error::{self, TransactionMalformed},
+        Ledger, WitnessVerification,
     },
     testing::{
         arbitrary::{
             AccountStatesVerifier, ArbitraryValidTransactionData, NonZeroValue, UtxoVerifier,
         },
+        assert_value_conserved,
         data::AddressData,
+        keys::KeyFactory,
         ledger::{self, ConfigBuilder},
-        tx_builder::TransactionBuilder,
+        tx_builder::{SealError, TransactionBuilder, WitnessSigner},
+        witness_builder::FaultyWitnessBuilder,
     },
     transaction::*,
+    utxo::SelectionStrategy,
     value::*,
 };
 use chain_addr::Discrimination;
+use chain_core::mempack::read_from_raw;
+use chain_core::property::Serialize as _;
 use quickcheck::TestResult;
 use quickcheck_macros::quickcheck;
 
@@ -58,7 +67,7 @@ pub fn ledger_accepts_correct_transaction(
 
     let (block0_hash, ledger) =
         ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
-    let mut utxos = ledger.utxos();
+    let mut utxos = ledger.utxos().iter();
     let signed_tx = TransactionBuilder::new()
         .with_input(faucet.make_input(value.into(), utxos.next()))
         .with_output(Output::from_address(receiver.address.clone(), value.into()))
@@ -87,8 +96,56 @@ pub fn ledger_accepts_correct_transaction(
     }
 }
 
+#[quickcheck]
+pub fn partial_spend_sends_the_change_back(
+    faucet: AddressData,
+    receiver: AddressData,
+    value: NonZeroValue,
+    amount: u64,
+) -> TestResult {
+    let value: Value = value.into();
+    let amount = Value(amount % value.0 + 1);
+    let message =
+        ledger::create_initial_transaction(Output::from_address(faucet.address.clone(), value));
+    let (block0_hash, ledger) =
+        ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
+    let signed_tx = TransactionBuilder::new()
+        .with_payment_from(
+            ledger.utxos().iter().next().unwrap(),
+            &faucet,
+            &receiver.address,
+            amount,
+        )
+        .unwrap()
+        .authenticate()
+        .with_witness(&block0_hash, &faucet)
+        .seal();
+    let fragment_id = Fragment::Transaction(signed_tx.clone()).hash();
+
+    let fees = ledger.get_ledger_parameters();
+    match ledger.apply_transaction(&fragment_id, &signed_tx, &fees) {
+        Err(err) => TestResult::error(format!("Error from ledger: {}", err)),
+        Ok((after, _)) => {
+            let value_of = |address_data: &AddressData| {
+                after
+                    .utxos()
+                    .iter()
+                    .filter(|entry| entry.output.address == address_data.address)
+                    .map(|entry| entry.output.value.0)
+                    .sum::<u64>()
+            };
+            TestResult::from_bool(
+                calculate_total_funds_in_ledger(&after) == value.0
+                    && (faucet.address == receiver.address
+                        || (value_of(&receiver) == amount.0
+                            && value_of(&faucet) == value.0 - amount.0)),
+            )
+        }
+    }
+}
+
 fn calculate_total_funds_in_ledger(ledger: &Ledger) -> u64 {
-    ledger.utxos().map(|x| x.output.value.0).sum::<u64>()
+    ledger.utxos().iter().map(|x| x.output.value.0).sum::<u64>()
         + ledger.accounts().get_total_value().unwrap().0
 }
 
@@ -125,7 +182,9 @@ pub fn total_funds_are_total_in_ledger(
     match result {
         Err(err) => TestResult::error(format!("Error from ledger: {:?}", err)),
 
-        Ok((ledger, _)) => {
+        Ok((ledger_after, _)) => {
+            assert_value_conserved(&ledger, &ledger_after);
+            let ledger = ledger_after;
             let total_funds_after = calculate_total_funds_in_ledger(&ledger);
             if total_funds_before != total_funds_after {
                 return TestResult::error(format!(
@@ -165,7 +224,7 @@ pub fn utxo_no_enough_signatures() {
     ));
     let (_, ledger) =
         ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
-    let mut utxos = ledger.utxos();
+    let mut utxos = ledger.utxos().iter();
     let signed_tx = TransactionBuilder::new()
         .with_input(Input::from_utxo_entry(utxos.next().unwrap()))
         .with_output(Output::from_address(receiver.address.clone(), Value(1)))
@@ -201,7 +260,7 @@ pub fn transaction_with_more_than_253_outputs() {
 
     let (block0_hash, ledger) =
         ledger::create_initial_fake_ledger(&[message], ConfigBuilder::new().build()).unwrap();
-    let mut utxos = ledger.utxos();
+    let mut utxos = ledger.utxos().iter();
     let signed_tx = TransactionBuilder::new()
         .with_input(Input::from_utxo_entry(utxos.next().unwrap()))
         .with_outputs(outputs)
@@ -307,6 +366,9 @@ pub fn iterate() {
                     id, info.serial, info.owners, info.keys,
                 );
             }
+            Entry::Pot(entry) => {
+                println!("Pot {} {}", entry.entry_type(), entry.value());
+            }
         }
     }
 
@@ -315,3 +377,443 @@ pub fn iterate() {
 
     assert!(ledger == ledger2);
 }
+
+#[test]
+pub fn selected_inputs_pay_for_outputs_and_fees() {
+    let mut keys = KeyFactory::from_seed([3; 32]);
+    let alice = keys.utxo_address(Discrimination::Test);
+    let bob = keys.utxo_address(Discrimination::Test);
+    let outputs = [40, 50, 60]
+        .iter()
+        .map(|value| alice.make_output(Value(*value)))
+        .collect();
+    let fees = LinearFee::new(2, 1, 0);
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(
+        &[ledger::create_initial_transactions(&outputs)],
+        ConfigBuilder::new().with_fee(fees).build(),
+    )
+    .unwrap();
+
+    for strategy in &[
+        SelectionStrategy::LargestFirst,
+        SelectionStrategy::FirstMatch,
+    ] {
+        let mut builder = TransactionBuilder::new();
+        builder
+            .with_output(bob.make_output(Value(100)))
+            .with_selected_inputs(ledger.utxos(), &alice, *strategy, &fees)
+            .unwrap();
+        let signers = vec![alice.clone(); builder.inputs().len()];
+        let signed_tx = builder
+            .authenticate()
+            .with_witnesses(&block0_hash, &signers)
+            .seal();
+        let size = signed_tx.serialize_as_vec().unwrap().len();
+        assert!(size <= builder.estimated_size());
+        let fragment_id = Fragment::Transaction(signed_tx.clone()).hash();
+
+        let (after, _) = ledger
+            .apply_transaction(&fragment_id, &signed_tx, &ledger.get_ledger_parameters())
+            .unwrap();
+        let value_of = |address_data: &AddressData| {
+            after
+                .utxos()
+                .iter()
+                .filter(|entry| entry.output.address == address_data.address)
+                .map(|entry| entry.output.value.0)
+                .sum::<u64>()
+        };
+        let nb_parts = signed_tx.transaction.inputs.len() + signed_tx.transaction.outputs.len();
+        assert_eq!(value_of(&bob), 100);
+        assert_eq!(value_of(&alice), 150 - 100 - (2 + nb_parts as u64));
+    }
+
+    let too_much = TransactionBuilder::new()
+        .with_output(bob.make_output(Value(150)))
+        .with_selected_inputs(
+            ledger.utxos(),
+            &alice,
+            SelectionStrategy::LargestFirst,
+            &fees,
+        )
+        .map(|_| ());
+    assert!(too_much.is_err());
+}
+
+#[test]
+pub fn consecutive_account_spendings_sign_the_next_counters() {
+    let mut keys = KeyFactory::from_seed([4; 32]);
+    let mut alice = keys.account_address(Discrimination::Test);
+    let bob = keys.utxo_address(Discrimination::Test);
+    let (block0_hash, mut ledger) = ledger::create_initial_fake_ledger(
+        &[ledger::create_initial_transactions(&vec![
+            alice.make_output(Value(100))
+        ])],
+        ConfigBuilder::new().build(),
+    )
+    .unwrap();
+
+    let spend = |ledger: &Ledger, alice: &AddressData| {
+        let signed_tx = TransactionBuilder::new()
+            .with_input(alice.make_input(Value(10), None))
+            .with_output(bob.make_output(Value(10)))
+            .authenticate()
+            .with_witness(&block0_hash, alice)
+            .seal();
+        let fragment_id = Fragment::Transaction(signed_tx.clone()).hash();
+        ledger
+            .apply_transaction(&fragment_id, &signed_tx, &ledger.get_ledger_parameters())
+            .map(|(ledger, _)| ledger)
+    };
+
+    for counter in SpendingCounter::zero().iter_from().take(3) {
+        alice.spending_counter = Some(counter);
+        ledger = spend(&ledger, &alice).unwrap();
+    }
+    assert_eq!(ledger.accounts().get_total_value().unwrap(), Value(70));
+
+    // replaying a spending with an already used counter is rejected
+    alice.spending_counter = Some(SpendingCounter::zero());
+    assert!(spend(&ledger, &alice).is_err());
+}
+
+fn account_value(ledger: &Ledger, owner: &AddressData) -> Value {
+    let id = account::Identifier::from(AccountPublicKey::from_raw(owner.public_key()));
+    ledger.accounts().get_state(&id).unwrap().value()
+}
+
+fn apply_sealed(
+    ledger: &Ledger,
+    signed_tx: &AuthenticatedTransaction<chain_addr::Address, NoExtra>,
+) -> Result<Ledger, crate::ledger::Error> {
+    let fragment_id = Fragment::Transaction(signed_tx.clone()).hash();
+    ledger
+        .apply_transaction(&fragment_id, signed_tx, &ledger.get_ledger_parameters())
+        .map(|(ledger, _)| ledger)
+}
+
+#[test]
+pub fn transactions_between_accounts_and_utxos_are_sealed_by_the_builder() {
+    let mut keys = KeyFactory::from_seed([5; 32]);
+    let alice = keys.account_address(Discrimination::Test);
+    let bob = keys.account_address(Discrimination::Test);
+    let carol = keys.utxo_address(Discrimination::Test);
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(
+        &[ledger::create_initial_transactions(&vec![
+            alice.make_output(Value(100)),
+            bob.make_output(Value(1)),
+            carol.make_output(Value(100)),
+        ])],
+        ConfigBuilder::new().build(),
+    )
+    .unwrap();
+
+    // account to account
+    let signed_tx = TransactionBuilder::new()
+        .with_account_input(&alice.public_key(), Value(40), SpendingCounter::zero())
+        .with_account_output(&bob.public_key(), Value(40))
+        .seal_with(&block0_hash, &[WitnessSigner::from(&alice)])
+        .unwrap();
+    let ledger = apply_sealed(&ledger, &signed_tx).unwrap();
+    assert_eq!(account_value(&ledger, &alice), Value(60));
+    assert_eq!(account_value(&ledger, &bob), Value(41));
+
+    // utxo to account
+    let entry = ledger
+        .utxos()
+        .iter()
+        .find(|entry| entry.output.address == carol.address)
+        .unwrap();
+    let signed_tx = TransactionBuilder::new()
+        .with_input(carol.make_input(Value(100), Some(entry)))
+        .with_output(carol.make_output(Value(30)))
+        .with_account_output(&alice.public_key(), Value(70))
+        .seal_with(&block0_hash, &[WitnessSigner::from(&carol)])
+        .unwrap();
+    let ledger = apply_sealed(&ledger, &signed_tx).unwrap();
+    assert_eq!(account_value(&ledger, &alice), Value(130));
+
+    // account to utxo, along with a utxo input
+    let entry = ledger
+        .utxos()
+        .iter()
+        .find(|entry| entry.output.address == carol.address)
+        .unwrap();
+    let signed_tx = TransactionBuilder::new()
+        .with_account_input(&bob.public_key(), Value(41), SpendingCounter::zero())
+        .with_input(carol.make_input(Value(30), Some(entry)))
+        .with_output(carol.make_output(Value(71)))
+        .seal_with(
+            &block0_hash,
+            &[WitnessSigner::from(&bob), WitnessSigner::from(&carol)],
+        )
+        .unwrap();
+    let ledger = apply_sealed(&ledger, &signed_tx).unwrap();
+    assert_eq!(account_value(&ledger, &bob), Value::zero());
+    assert_eq!(
+        ledger
+            .utxos()
+            .iter()
+            .map(|entry| entry.output.value)
+            .collect::<Vec<_>>(),
+        vec![Value(71)]
+    );
+
+    // the second spending of alice signs the next counter
+    let signed_tx = TransactionBuilder::new()
+        .with_account_input(&alice.public_key(), Value(30), SpendingCounter::from(1))
+        .with_account_output(&bob.public_key(), Value(30))
+        .seal_with(&block0_hash, &[WitnessSigner::from(&alice)])
+        .unwrap();
+    let ledger = apply_sealed(&ledger, &signed_tx).unwrap();
+    assert_eq!(account_value(&ledger, &alice), Value(100));
+}
+
+#[test]
+pub fn account_input_above_the_balance_is_rejected() {
+    let mut keys = KeyFactory::from_seed([6; 32]);
+    let alice = keys.account_address(Discrimination::Test);
+    let bob = keys.account_address(Discrimination::Test);
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(
+        &[ledger::create_initial_transactions(&vec![
+            alice.make_output(Value(100))
+        ])],
+        ConfigBuilder::new().build(),
+    )
+    .unwrap();
+
+    let signed_tx = TransactionBuilder::new()
+        .with_account_input(&alice.public_key(), Value(150), SpendingCounter::zero())
+        .with_account_output(&bob.public_key(), Value(150))
+        .seal_with(&block0_hash, &[WitnessSigner::from(&alice)])
+        .unwrap();
+    assert!(apply_sealed(&ledger, &signed_tx).is_err());
+    assert_eq!(account_value(&ledger, &alice), Value(100));
+
+    // the builder refuses to sign an account input with a utxo witness
+    let mut builder = TransactionBuilder::new();
+    builder
+        .with_account_input(&alice.public_key(), Value(10), SpendingCounter::zero())
+        .with_account_output(&bob.public_key(), Value(10));
+    let utxo_signer = WitnessSigner::Utxo(alice.private_key());
+    assert_eq!(
+        builder.seal_with(&block0_hash, &[utxo_signer]).unwrap_err(),
+        SealError::WrongSigner { index: 0 }
+    );
+    assert_eq!(
+        builder.seal_with(&block0_hash, &[]).unwrap_err(),
+        SealError::SignerCount {
+            inputs: 1,
+            signers: 0
+        }
+    );
+}
+
+/// the cause of the rejection of a witness by the ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WitnessRejection {
+    Utxo,
+    Account,
+}
+
+fn witness_rejection(error: &Error) -> Option<WitnessRejection> {
+    match error {
+        Error::UtxoInvalidSignature { .. } => Some(WitnessRejection::Utxo),
+        Error::AccountInvalidSignature { .. } => Some(WitnessRejection::Account),
+        _ => None,
+    }
+}
+
+#[test]
+pub fn faulty_witnesses_are_rejected_for_their_defect() {
+    let mut keys = KeyFactory::from_seed([7; 32]);
+    let alice = keys.account_address(Discrimination::Test);
+    let bob = keys.utxo_address(Discrimination::Test);
+    let mallory = keys.account_address(Discrimination::Test);
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(
+        &[ledger::create_initial_transactions(&vec![
+            alice.make_output(Value(100)),
+            bob.make_output(Value(100)),
+        ])],
+        ConfigBuilder::new().build(),
+    )
+    .unwrap();
+    let entry = ledger
+        .utxos()
+        .iter()
+        .find(|entry| entry.output.address == bob.address)
+        .unwrap();
+
+    let cases = vec![
+        (
+            &alice,
+            alice.make_input(Value(10), None),
+            WitnessRejection::Account,
+        ),
+        (
+            &bob,
+            bob.make_input(Value(100), Some(entry)),
+            WitnessRejection::Utxo,
+        ),
+    ];
+    for (owner, input, rejection) in cases {
+        let mut builder = TransactionBuilder::new();
+        builder
+            .with_output(mallory.make_output(input.value))
+            .with_input(input);
+        let transaction_hash = builder.authenticate().transaction_hash();
+        let faulty = FaultyWitnessBuilder::new(&block0_hash, owner, transaction_hash);
+
+        let mut cases = vec![
+            ("wrong block0", faulty.with_wrong_block0()),
+            ("first bit flipped", faulty.with_flipped_signature_bit(0)),
+            ("middle bit flipped", faulty.with_flipped_signature_bit(255)),
+            ("last bit flipped", faulty.with_flipped_signature_bit(511)),
+            (
+                "mismatched key",
+                faulty.with_mismatched_key(&mallory.private_key()),
+            ),
+        ];
+        if rejection == WitnessRejection::Account {
+            cases.push(("next counter", faulty.with_wrong_counter(1)));
+            cases.push(("far counter", faulty.with_wrong_counter(1000)));
+        }
+
+        let with_witness =
+            |witness: Witness| builder.authenticate().with_raw_witness(witness).seal();
+        assert!(apply_sealed(&ledger, &with_witness(faulty.valid())).is_ok());
+        for (defect, witness) in cases {
+            let error = apply_sealed(&ledger, &with_witness(witness))
+                .map(|_| ())
+                .expect_err(defect);
+            assert_eq!(
+                witness_rejection(&error),
+                Some(rejection),
+                "{}: unexpected error {:?}",
+                defect,
+                error
+            );
+        }
+    }
+}
+
+fn apply_verifying_with(
+    ledger: &Ledger,
+    signed_tx: &AuthenticatedTransaction<chain_addr::Address, NoExtra>,
+    witness_verification: WitnessVerification,
+) -> Result<(), Error> {
+    let fragment_id = Fragment::Transaction(signed_tx.clone()).hash();
+    let mut params = ledger.get_ledger_parameters();
+    params.witness_verification = witness_verification;
+    ledger
+        .apply_transaction(&fragment_id, signed_tx, &params)
+        .map(|_| ())
+}
+
+#[test]
+pub fn batch_witness_verification_agrees_with_sequential() {
+    let mut keys = KeyFactory::from_seed([8; 32]);
+    let alice = keys.account_address(Discrimination::Test);
+    let bob = keys.utxo_address(Discrimination::Test);
+    let mallory = keys.account_address(Discrimination::Test);
+    let (block0_hash, ledger) = ledger::create_initial_fake_ledger(
+        &[ledger::create_initial_transactions(&vec![
+            alice.make_output(Value(100)),
+            bob.make_output(Value(100)),
+        ])],
+        ConfigBuilder::new().build(),
+    )
+    .unwrap();
+    let entry = ledger
+        .utxos()
+        .iter()
+        .find(|entry| entry.output.address == bob.address)
+        .unwrap();
+
+    let mut builder = TransactionBuilder::new();
+    builder
+        .with_input(alice.make_input(Value(10), None))
+        .with_input(bob.make_input(Value(100), Some(entry)))
+        .with_output(mallory.make_output(Value(110)));
+    let transaction_hash = builder.authenticate().transaction_hash();
+    let alice_witness = FaultyWitnessBuilder::new(&block0_hash, &alice, transaction_hash);
+    let bob_witness = FaultyWitnessBuilder::new(&block0_hash, &bob, transaction_hash);
+
+    let mut transactions = vec![(alice_witness.valid(), bob_witness.valid())];
+    transactions.push((alice_witness.with_wrong_counter(1), bob_witness.valid()));
+    transactions.push((alice_witness.valid(), bob_witness.with_wrong_block0()));
+    transactions.push((
+        alice_witness.with_flipped_signature_bit(7),
+        bob_witness.with_mismatched_key(&mallory.private_key()),
+    ));
+    for (alice_witness, bob_witness) in transactions {
+        let signed_tx = builder
+            .authenticate()
+            .with_raw_witness(alice_witness)
+            .with_raw_witness(bob_witness)
+            .seal();
+        assert_eq!(
+            apply_verifying_with(&ledger, &signed_tx, WitnessVerification::Batch),
+            apply_verifying_with(&ledger, &signed_tx, WitnessVerification::Sequential)
+        );
+    }
+    let signed_tx = builder
+        .authenticate()
+        .with_raw_witness(alice_witness.valid())
+        .with_raw_witness(bob_witness.valid())
+        .seal();
+    assert!(apply_verifying_with(&ledger, &signed_tx, WitnessVerification::Batch).is_ok());
+
+    // an account spent twice signs the counters of both spendings, the
+    // same counter twice is a replay
+    let mut builder = TransactionBuilder::new();
+    builder
+        .with_input(alice.make_input(Value(10), None))
+        .with_input(alice.make_input(Value(10), None))
+        .with_output(mallory.make_output(Value(20)));
+    let witness = FaultyWitnessBuilder::new(
+        &block0_hash,
+        &alice,
+        builder.authenticate().transaction_hash(),
+    );
+    for second_witness in vec![witness.with_wrong_counter(1), witness.valid()] {
+        let signed_tx = builder
+            .authenticate()
+            .with_raw_witness(witness.valid())
+            .with_raw_witness(second_witness)
+            .seal();
+        assert_eq!(
+            apply_verifying_with(&ledger, &signed_tx, WitnessVerification::Batch),
+            apply_verifying_with(&ledger, &signed_tx, WitnessVerification::Sequential)
+        );
+    }
+}
+
+#[test]
+pub fn fragment_ids_follow_the_hash_scheme_of_the_chain() {
+    let faucet = AddressData::utxo(Discrimination::Test);
+    let message = ledger::create_initial_transaction(faucet.make_output(Value(100)));
+    let legacy_id = message.hash_with(HashScheme::Legacy);
+    let tagged_id = message.hash_with(HashScheme::Tagged);
+    assert_ne!(legacy_id, tagged_id);
+    assert_eq!(legacy_id, message.hash());
+
+    for (scheme, fragment_id) in &[
+        (HashScheme::Legacy, legacy_id),
+        (HashScheme::Tagged, tagged_id),
+    ] {
+        let (_, ledger) = ledger::create_initial_fake_ledger(
+            &[message.clone()],
+            ConfigBuilder::new().with_hash_scheme(*scheme).build(),
+        )
+        .unwrap();
+        assert_eq!(ledger.hash_scheme(), *scheme);
+        let utxos: Vec<_> = ledger.utxos().iter().collect();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].fragment_id, *fragment_id);
+
+        // the scheme is kept by the snapshots of the ledger
+        let decoded: Ledger = read_from_raw(&ledger.serialize_as_vec().unwrap()).unwrap();
+        assert_eq!(decoded.hash_scheme(), *scheme);
+    }
+}
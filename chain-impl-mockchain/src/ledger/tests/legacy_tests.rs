@@ -0,0 +1,68 @@
+#![cfg(test)]
+
+use crate::ledger::{Error, Ledger};
+use crate::testing::{
+    keys::KeyFactory,
+    ledger::{self, ConfigBuilder},
+    scenario,
+    tx_builder::TransactionBuilder,
+    LegacyWallet,
+};
+use crate::{block::HeaderHash, fragment::Fragment, transaction::TransactionIndex, value::Value};
+use chain_addr::Discrimination;
+
+fn ledger_with_declaration(declaration: &Fragment) -> (HeaderHash, Ledger) {
+    ledger::create_initial_fake_ledger(&[declaration.clone()], ConfigBuilder::new().build())
+        .unwrap()
+}
+
+#[test]
+pub fn legacy_funds_can_be_spent() {
+    let mut keys = KeyFactory::from_seed([1; 32]);
+    let wallet = keys.legacy_wallet(&[0x8000_0000, 0]);
+    let receiver = keys.utxo_address(Discrimination::Test);
+    let declaration = LegacyWallet::declaration(&[(&wallet, Value(100))]);
+    let (block0_hash, ledger) = ledger_with_declaration(&declaration);
+
+    let fragment = TransactionBuilder::new()
+        .with_input(LegacyWallet::make_input(
+            declaration.hash(),
+            TransactionIndex::new(0).unwrap(),
+            Value(100),
+        ))
+        .with_output(receiver.make_output(Value(100)))
+        .authenticate()
+        .with_legacy_witness(&block0_hash, &wallet)
+        .as_message();
+    let ledger = scenario::apply(&ledger, &fragment).unwrap();
+
+    assert_eq!(ledger.utxos_legacy().iter().count(), 0);
+    let outputs: Vec<_> = ledger.utxos().values().cloned().collect();
+    assert_eq!(outputs, vec![receiver.make_output(Value(100))]);
+}
+
+#[test]
+pub fn witness_from_another_xpub_is_rejected() {
+    let mut keys = KeyFactory::from_seed([2; 32]);
+    let wallet = keys.legacy_wallet(&[0]);
+    let thief = keys.legacy_wallet(&[0]);
+    let receiver = keys.utxo_address(Discrimination::Test);
+    let declaration = LegacyWallet::declaration(&[(&wallet, Value(100))]);
+    let (block0_hash, ledger) = ledger_with_declaration(&declaration);
+
+    let fragment = TransactionBuilder::new()
+        .with_input(LegacyWallet::make_input(
+            declaration.hash(),
+            TransactionIndex::new(0).unwrap(),
+            Value(100),
+        ))
+        .with_output(receiver.make_output(Value(100)))
+        .authenticate()
+        .with_legacy_witness(&block0_hash, &thief)
+        .as_message();
+    match scenario::apply(&ledger, &fragment) {
+        Err(Error::OldUtxoInvalidPublicKey { .. }) => {}
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("legacy funds spent with another xpub"),
+    }
+}
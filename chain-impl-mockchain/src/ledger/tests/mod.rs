@@ -1,3 +1,9 @@
+pub mod block_date_tests;
 pub mod discrimination_tests;
 pub mod initial_funds_tests;
 pub mod ledger_tests;
+pub mod legacy_tests;
+pub mod pool_reward_tests;
+pub mod stake_distribution_tests;
+pub mod scenario_tests;
+pub mod update_tests;
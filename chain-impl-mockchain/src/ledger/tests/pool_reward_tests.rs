@@ -0,0 +1,105 @@
+#![cfg(test)]
+
+use crate::{
+    account,
+    certificate::{PoolRegistration, RewardDestination},
+    leadership::genesis::GenesisPraosLeader,
+    ledger::Ledger,
+    testing::ledger::{self, ConfigBuilder},
+    value::Value,
+};
+use chain_crypto::{testing::TestCryptoGen, Curve25519_2HashDH, Ed25519, PublicKey, SumEd25519_12};
+use chain_time::DurationSeconds;
+
+fn owners(key_gen: &TestCryptoGen, nb: u32) -> Vec<PublicKey<Ed25519>> {
+    (0..nb)
+        .map(|idx| key_gen.secret_key::<Ed25519>(idx).to_public())
+        .collect()
+}
+
+fn register_pool(
+    key_gen: &TestCryptoGen,
+    owners: Vec<PublicKey<Ed25519>>,
+    reward_destination: RewardDestination,
+) -> (Ledger, PoolRegistration) {
+    let (_, ledger) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let registration = PoolRegistration {
+        serial: 1,
+        start_validity: DurationSeconds::from(0).into(),
+        management_threshold: 1,
+        owners,
+        keys: GenesisPraosLeader {
+            vrf_public_key: key_gen.secret_key::<Curve25519_2HashDH>(100).to_public(),
+            kes_public_key: key_gen.secret_key::<SumEd25519_12>(101).to_public(),
+        },
+        reward_destination,
+    };
+    let ledger = ledger.apply_pool_registration(&registration).unwrap();
+    (ledger, registration)
+}
+
+fn account_value(ledger: &Ledger, public_key: &PublicKey<Ed25519>) -> Value {
+    let identifier: account::Identifier = public_key.clone().into();
+    ledger.accounts().get_state(&identifier).unwrap().value()
+}
+
+#[test]
+pub fn reward_is_split_between_owners() {
+    let key_gen = TestCryptoGen(0);
+    let owners = owners(&key_gen, 3);
+    let (ledger, registration) = register_pool(
+        &key_gen,
+        owners.clone(),
+        RewardDestination::owners_equal_split(3),
+    );
+
+    let ledger = ledger
+        .distribute_pool_reward(&registration.to_id(), Value(100))
+        .unwrap();
+
+    assert_eq!(account_value(&ledger, &owners[0]), Value(34));
+    assert_eq!(account_value(&ledger, &owners[1]), Value(33));
+    assert_eq!(account_value(&ledger, &owners[2]), Value(33));
+}
+
+#[test]
+pub fn reward_goes_to_single_account() {
+    let key_gen = TestCryptoGen(0);
+    let owners = owners(&key_gen, 3);
+    let reward_account = key_gen.secret_key::<Ed25519>(10).to_public();
+    let (ledger, registration) = register_pool(
+        &key_gen,
+        owners.clone(),
+        RewardDestination::Account(reward_account.clone().into()),
+    );
+
+    let ledger = ledger
+        .distribute_pool_reward(&registration.to_id(), Value(100))
+        .unwrap();
+
+    assert_eq!(account_value(&ledger, &reward_account), Value(100));
+    for owner in owners.iter() {
+        let identifier: account::Identifier = owner.clone().into();
+        assert!(!ledger.accounts().exists(&identifier));
+    }
+}
+
+#[test]
+pub fn invalid_owners_split_is_rejected_at_registration() {
+    let key_gen = TestCryptoGen(0);
+    let (ledger, _) =
+        ledger::create_initial_fake_ledger(&[], ConfigBuilder::new().build()).unwrap();
+    let registration = PoolRegistration {
+        serial: 1,
+        start_validity: DurationSeconds::from(0).into(),
+        management_threshold: 1,
+        owners: owners(&key_gen, 2),
+        keys: GenesisPraosLeader {
+            vrf_public_key: key_gen.secret_key::<Curve25519_2HashDH>(100).to_public(),
+            kes_public_key: key_gen.secret_key::<SumEd25519_12>(101).to_public(),
+        },
+        reward_destination: RewardDestination::owners_equal_split(3),
+    };
+    assert!(ledger.apply_pool_registration(&registration).is_err());
+}
@@ -0,0 +1,149 @@
+#![cfg(test)]
+
+use crate::{
+    block::HeaderContentEvalContext,
+    date::BlockDate,
+    fee::LinearFee,
+    ledger::{
+        pots::{self, EntryType},
+        Error, Ledger,
+    },
+    testing::{
+        ledger::ConfigBuilder,
+        scenario::{prepare_scenario, wallet},
+    },
+    value::Value,
+};
+
+#[test]
+pub fn transfer_between_wallets() {
+    let (mut ledger, mut controller) = prepare_scenario()
+        .with_initials(vec![wallet("alice", 1_000), wallet("bob", 500)])
+        .build()
+        .unwrap();
+
+    // successive transfers from the same wallet need the spending counter to move on
+    controller
+        .transfer_funds("alice", "bob", &mut ledger, 100)
+        .unwrap();
+    controller
+        .transfer_funds("alice", "bob", &mut ledger, 200)
+        .unwrap();
+    controller
+        .transfer_funds("bob", "alice", &mut ledger, 50)
+        .unwrap();
+
+    assert_eq!(controller.balance("alice", &ledger), Value(750));
+    assert_eq!(controller.balance("bob", &ledger), Value(750));
+}
+
+#[test]
+pub fn transfer_above_balance_is_rejected() {
+    let (mut ledger, mut controller) = prepare_scenario()
+        .with_initials(vec![wallet("alice", 1_000), wallet("bob", 500)])
+        .build()
+        .unwrap();
+
+    assert!(controller
+        .transfer_funds("bob", "alice", &mut ledger, 501)
+        .is_err());
+    assert_eq!(controller.balance("alice", &ledger), Value(1_000));
+    assert_eq!(controller.balance("bob", &ledger), Value(500));
+
+    // the rejected transfer didn't consume the spending counter
+    controller
+        .transfer_funds("bob", "alice", &mut ledger, 500)
+        .unwrap();
+    assert_eq!(controller.balance("bob", &ledger), Value::zero());
+}
+
+#[test]
+pub fn fees_go_to_the_pot() {
+    let (mut ledger, mut controller) = prepare_scenario()
+        .with_initials(vec![wallet("alice", 1_000), wallet("bob", 500)])
+        .with_config(ConfigBuilder::new().with_fee(LinearFee::new(10, 1, 0)))
+        .build()
+        .unwrap();
+
+    controller
+        .transfer_funds("alice", "bob", &mut ledger, 100)
+        .unwrap();
+    controller
+        .transfer_funds("bob", "alice", &mut ledger, 100)
+        .unwrap();
+
+    // each transfer has one input and one output
+    assert_eq!(ledger.pots().fees(), Value(24));
+    assert_eq!(controller.balance("alice", &ledger), Value(988));
+    assert_eq!(controller.balance("bob", &ledger), Value(488));
+}
+
+fn next_block(ledger: &Ledger) -> HeaderContentEvalContext {
+    HeaderContentEvalContext {
+        block_date: BlockDate {
+            epoch: ledger.date().epoch,
+            slot_id: ledger.date().slot_id + 1,
+        },
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    }
+}
+
+#[test]
+pub fn block_fees_are_committed_only_if_the_block_is_valid() {
+    let (mut ledger, controller) = prepare_scenario()
+        .with_initials(vec![wallet("alice", 1_000), wallet("bob", 500)])
+        .with_config(ConfigBuilder::new().with_fee(LinearFee::new(10, 1, 0)))
+        .build()
+        .unwrap();
+    let params = ledger.get_ledger_parameters();
+    let alice_to_bob = controller.transfer("alice", "bob", &ledger, 100);
+    let bob_to_alice = controller.transfer("bob", "alice", &ledger, 100);
+    let above_balance = controller.transfer("bob", "alice", &ledger, 600);
+
+    // the fees of all the fragments of a valid block are committed
+    let valid = ledger
+        .apply_block(
+            &params,
+            vec![&alice_to_bob, &bob_to_alice],
+            &next_block(&ledger),
+        )
+        .unwrap();
+    assert_eq!(valid.pots().fees(), Value(24));
+    assert_eq!(controller.balance("alice", &valid), Value(988));
+
+    // a valid fragment followed by an invalid one makes no ledger at all
+    assert!(ledger
+        .apply_block(
+            &params,
+            vec![&alice_to_bob, &above_balance],
+            &next_block(&ledger),
+        )
+        .is_err());
+
+    // each block alone fits in the fees pot, but not the fees of both
+    ledger
+        .pots
+        .set_entry(EntryType::Fees, Value(u64::max_value() - 20));
+    let fits = ledger
+        .apply_block(&params, vec![&alice_to_bob], &next_block(&ledger))
+        .unwrap();
+    assert_eq!(fits.pots().fees(), Value(u64::max_value() - 8));
+    let overflow = ledger
+        .apply_block(
+            &params,
+            vec![&alice_to_bob, &bob_to_alice],
+            &next_block(&ledger),
+        )
+        .err();
+    assert!(match overflow {
+        Some(Error::Pots {
+            source:
+                pots::Error::Overflow {
+                    pot: EntryType::Fees,
+                    ..
+                },
+        }) => true,
+        _ => false,
+    });
+}
@@ -0,0 +1,386 @@
+#![cfg(test)]
+
+use crate::{
+    account::{self, SpendingCounter},
+    block::{HeaderContentEvalContext, HeaderHash},
+    certificate::{
+        PoolId, PoolManagement, PoolOwnersSigned, PoolRegistration, PoolRetirement,
+        RewardDestination, StakeDelegation,
+    },
+    fragment::Fragment,
+    leadership::genesis::GenesisPraosLeader,
+    ledger::Ledger,
+    testing::{
+        arbitrary::AverageValue,
+        data::AddressData,
+        ledger::{self, ConfigBuilder},
+        tx_builder::TransactionBuilder,
+    },
+    transaction::*,
+    value::Value,
+};
+use chain_addr::{Address, Discrimination, Kind};
+use chain_crypto::{testing::TestCryptoGen, Curve25519_2HashDH, Ed25519, PublicKey, SumEd25519_12};
+use chain_time::DurationSeconds;
+use lazy_static::lazy_static;
+use quickcheck::{Arbitrary, Gen, TestResult};
+use quickcheck_macros::quickcheck;
+use typed_bytes::ByteBuilder;
+
+const NB_ACTORS: usize = 6;
+const NB_POOLS: usize = 3;
+
+fn make_pool(key_gen: &TestCryptoGen) -> PoolRegistration {
+    let owner = key_gen.secret_key::<Ed25519>(0).to_public();
+    PoolRegistration {
+        serial: 1,
+        start_validity: DurationSeconds::from(0).into(),
+        management_threshold: 1,
+        owners: vec![owner.clone()],
+        keys: GenesisPraosLeader {
+            vrf_public_key: key_gen.secret_key::<Curve25519_2HashDH>(1).to_public(),
+            kes_public_key: key_gen.secret_key::<SumEd25519_12>(2).to_public(),
+        },
+        reward_destination: RewardDestination::Account(owner.into()),
+    }
+}
+
+lazy_static! {
+    static ref POOLS: Vec<PoolRegistration> = (0..NB_POOLS as u64)
+        .map(|n| make_pool(&TestCryptoGen(n)))
+        .collect();
+}
+
+fn pool_id(pool: usize) -> PoolId {
+    POOLS[pool].to_id()
+}
+
+fn pool_retirement(pool: usize) -> Fragment {
+    let retirement = PoolRetirement {
+        pool_id: pool_id(pool),
+        retirement_time: DurationSeconds::from(0).into(),
+    };
+    let signed_data = ByteBuilder::new()
+        .sub(|bb| retirement.serialize_in(bb))
+        .finalize();
+    let owner_key = TestCryptoGen(pool as u64).secret_key::<Ed25519>(0);
+    let signature = owner_key.sign(&signed_data);
+    Fragment::PoolManagement(no_input(PoolManagement::Retirement(PoolOwnersSigned {
+        inner: retirement,
+        signatures: vec![(0, signature)],
+    })))
+}
+
+fn no_input<Extra>(extra: Extra) -> AuthenticatedTransaction<Address, Extra> {
+    AuthenticatedTransaction {
+        transaction: Transaction {
+            inputs: vec![],
+            outputs: vec![],
+            extra,
+        },
+        witnesses: vec![],
+    }
+}
+
+fn stake_delegation(account: PublicKey<Ed25519>, pool_id: PoolId) -> Fragment {
+    Fragment::StakeDelegation(no_input(StakeDelegation {
+        account_id: AccountIdentifier::from_single_account(account.into()),
+        pool_id,
+    }))
+}
+
+/// the key of the account holding the stake of the actor, if any
+fn stake_key(actor: &AddressData) -> Option<PublicKey<Ed25519>> {
+    match actor.kind() {
+        Kind::Account(key) => Some(key),
+        Kind::Group(_, delegation_key) => Some(delegation_key),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum StakeOp {
+    /// move all the funds of an actor to another one
+    Transfer { from: usize, to: usize },
+    /// delegate the stake key of an actor to one of the pools
+    Delegate { actor: usize, pool: usize },
+    /// retire one of the pools, its delegators become dangling stake
+    Retire { pool: usize },
+}
+
+impl Arbitrary for StakeOp {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        match u8::arbitrary(g) % 5 {
+            0 | 1 => StakeOp::Transfer {
+                from: usize::arbitrary(g) % NB_ACTORS,
+                to: usize::arbitrary(g) % NB_ACTORS,
+            },
+            2 | 3 => StakeOp::Delegate {
+                actor: usize::arbitrary(g) % NB_ACTORS,
+                pool: usize::arbitrary(g) % NB_POOLS,
+            },
+            _ => StakeOp::Retire {
+                pool: usize::arbitrary(g) % NB_POOLS,
+            },
+        }
+    }
+}
+
+struct TestLedger {
+    block0_hash: HeaderHash,
+    ledger: Ledger,
+    actors: Vec<AddressData>,
+}
+
+impl TestLedger {
+    fn new(initial_values: &[AverageValue]) -> Self {
+        let actors: Vec<AddressData> = (0..NB_ACTORS)
+            .map(|idx| match idx % 3 {
+                0 => AddressData::utxo(Discrimination::Test),
+                1 => AddressData::delegation(Discrimination::Test),
+                _ => AddressData::account(Discrimination::Test),
+            })
+            .collect();
+        let outputs = actors
+            .iter()
+            .zip(initial_values.iter().cycle())
+            .map(|(actor, value)| actor.make_output((*value).into()))
+            .collect();
+
+        let mut fragments = vec![ledger::create_initial_transactions(&outputs)];
+        fragments.extend(
+            POOLS
+                .iter()
+                .map(|pool| Fragment::PoolRegistration(no_input(pool.clone()))),
+        );
+        let (block0_hash, ledger) =
+            ledger::create_initial_fake_ledger(&fragments, ConfigBuilder::new().build()).unwrap();
+        TestLedger {
+            block0_hash,
+            ledger,
+            actors,
+        }
+    }
+
+    fn pool_exists(&self, pool: usize) -> bool {
+        self.ledger.delegation().stake_pool_exists(&pool_id(pool))
+    }
+
+    fn apply(&mut self, op: &StakeOp) {
+        let fragment = match op {
+            StakeOp::Transfer { from, to } => match self.transfer(*from, *to) {
+                None => return,
+                Some(fragment) => fragment,
+            },
+            StakeOp::Delegate { actor, pool } => match stake_key(&self.actors[*actor]) {
+                Some(key) if self.pool_exists(*pool) => stake_delegation(key, pool_id(*pool)),
+                _ => return,
+            },
+            StakeOp::Retire { pool } if self.pool_exists(*pool) => pool_retirement(*pool),
+            StakeOp::Retire { .. } => return,
+        };
+        let metadata = HeaderContentEvalContext {
+            block_date: self.ledger.date(),
+            chain_length: self.ledger.chain_length(),
+            nonce: None,
+        };
+        self.ledger = self
+            .ledger
+            .apply_fragment(&self.ledger.get_ledger_parameters(), &fragment, &metadata)
+            .unwrap();
+    }
+
+    fn transfer(&mut self, from: usize, to: usize) -> Option<Fragment> {
+        let receiver = self.actors[to].address.clone();
+        let sender = &mut self.actors[from];
+        let input = match sender.kind() {
+            Kind::Account(key) => {
+                let state = self.ledger.accounts().get_state(&key.into()).ok()?;
+                if state.value() == Value::zero() {
+                    return None;
+                }
+                sender.spending_counter = Some(SpendingCounter::from(state.get_counter()));
+                sender.make_input(state.value(), None)
+            }
+            _ => {
+                let entry = self
+                    .ledger
+                    .utxos()
+                    .iter()
+                    .find(|entry| entry.output.address == sender.address)?;
+                sender.make_input(entry.output.value, Some(entry))
+            }
+        };
+        let value = input.value;
+        let fragment = TransactionBuilder::new()
+            .with_input(input)
+            .with_output(Output::from_address(receiver, value))
+            .authenticate()
+            .with_witness(&self.block0_hash, sender)
+            .as_message();
+        Some(fragment)
+    }
+}
+
+#[quickcheck]
+pub fn incremental_stake_distribution_matches_recomputation(
+    initial_values: Vec<AverageValue>,
+    ops: Vec<StakeOp>,
+) -> TestResult {
+    if initial_values.is_empty() {
+        return TestResult::discard();
+    }
+    let mut test_ledger = TestLedger::new(&initial_values);
+    for op in ops.iter() {
+        test_ledger.apply(op);
+        let ledger = &test_ledger.ledger;
+        if ledger.get_stake_distribution() != ledger.recompute_stake_distribution() {
+            return TestResult::error(format!("stake distribution mismatch after {:?}", op));
+        }
+    }
+    TestResult::passed()
+}
+
+#[test]
+pub fn account_stake_follows_delegation() {
+    let mut test_ledger = TestLedger::new(&[AverageValue(Value(1000))]);
+    let account = stake_key(&test_ledger.actors[2]).unwrap();
+    let identifier: account::Identifier = account.into();
+
+    test_ledger.apply(&StakeOp::Delegate { actor: 2, pool: 0 });
+    let distribution = test_ledger.ledger.get_stake_distribution();
+    assert_eq!(distribution.get_stake_for(&pool_id(0)), Some(Value(1000)));
+    assert_eq!(
+        distribution,
+        test_ledger.ledger.recompute_stake_distribution()
+    );
+
+    test_ledger.apply(&StakeOp::Delegate { actor: 2, pool: 1 });
+    let distribution = test_ledger.ledger.get_stake_distribution();
+    assert_eq!(distribution.get_stake_for(&pool_id(0)), Some(Value::zero()));
+    assert_eq!(distribution.get_stake_for(&pool_id(1)), Some(Value(1000)));
+
+    test_ledger.apply(&StakeOp::Transfer { from: 2, to: 0 });
+    let distribution = test_ledger.ledger.get_stake_distribution();
+    assert_eq!(distribution.get_stake_for(&pool_id(1)), Some(Value::zero()));
+    assert_eq!(
+        distribution,
+        test_ledger.ledger.recompute_stake_distribution()
+    );
+    assert_eq!(
+        test_ledger
+            .ledger
+            .accounts()
+            .get_state(&identifier)
+            .unwrap()
+            .value(),
+        Value::zero()
+    );
+}
+
+#[test]
+pub fn retired_pool_stake_is_dangling() {
+    let mut test_ledger = TestLedger::new(&[AverageValue(Value(1000))]);
+    // the group address and the account both delegate to the same pool
+    test_ledger.apply(&StakeOp::Delegate { actor: 1, pool: 2 });
+    test_ledger.apply(&StakeOp::Delegate { actor: 2, pool: 2 });
+    test_ledger.apply(&StakeOp::Retire { pool: 2 });
+
+    let distribution = test_ledger.ledger.get_stake_distribution();
+    assert_eq!(distribution.get_stake_for(&pool_id(2)), None);
+    assert_eq!(distribution.dangling, Value(2000));
+    assert_eq!(
+        distribution,
+        test_ledger.ledger.recompute_stake_distribution()
+    );
+}
+
+/// An epoch transition on a large ledger: applying the first block of
+/// the next epoch and taking the stake distribution of the leader
+/// election, from the running totals or recomputed from scratch. Run
+/// with `cargo +nightly bench --features with-bench`.
+#[cfg(feature = "with-bench")]
+mod bench {
+    use super::*;
+    use crate::block::BlockDate;
+    use crate::leadership::genesis::LeadershipData;
+    use chain_core::property::ChainLength as _;
+    use chain_time::Epoch;
+    use test::Bencher;
+
+    const NB_ACCOUNTS: u32 = 100_000;
+
+    fn large_ledger() -> Ledger {
+        let key_gen = TestCryptoGen(0);
+        let keys: Vec<PublicKey<Ed25519>> = (0..NB_ACCOUNTS)
+            .map(|idx| key_gen.secret_key::<Ed25519>(idx).to_public())
+            .collect();
+
+        let mut fragments: Vec<Fragment> = keys
+            .chunks(254)
+            .map(|chunk| {
+                let outputs = chunk
+                    .iter()
+                    .map(|key| {
+                        let address = Address(Discrimination::Test, Kind::Account(key.clone()));
+                        Output::from_address(address, Value(100))
+                    })
+                    .collect();
+                ledger::create_initial_transactions(&outputs)
+            })
+            .collect();
+        fragments.extend(
+            POOLS
+                .iter()
+                .map(|pool| Fragment::PoolRegistration(no_input(pool.clone()))),
+        );
+        fragments.extend(
+            keys.iter()
+                .enumerate()
+                .map(|(idx, key)| stake_delegation(key.clone(), pool_id(idx % NB_POOLS))),
+        );
+        let (_, ledger) =
+            ledger::create_initial_fake_ledger(&fragments, ConfigBuilder::new().build()).unwrap();
+        assert_eq!(
+            ledger.get_stake_distribution(),
+            ledger.recompute_stake_distribution()
+        );
+        ledger
+    }
+
+    fn apply_next_epoch_block(ledger: &Ledger) -> Ledger {
+        let metadata = HeaderContentEvalContext {
+            block_date: BlockDate {
+                epoch: 1,
+                slot_id: 0,
+            },
+            chain_length: ledger.chain_length().next(),
+            nonce: None,
+        };
+        ledger
+            .apply_block(
+                &ledger.get_ledger_parameters(),
+                std::iter::empty::<&Fragment>(),
+                &metadata,
+            )
+            .unwrap()
+    }
+
+    #[bench]
+    fn epoch_transition_incremental(b: &mut Bencher) {
+        let ledger = large_ledger();
+        b.iter(|| {
+            let ledger = apply_next_epoch_block(&ledger);
+            LeadershipData::new(Epoch(1), &ledger)
+        })
+    }
+
+    #[bench]
+    fn epoch_transition_recomputed(b: &mut Bencher) {
+        let ledger = large_ledger();
+        b.iter(|| {
+            let ledger = apply_next_epoch_block(&ledger);
+            ledger.recompute_stake_distribution()
+        })
+    }
+}
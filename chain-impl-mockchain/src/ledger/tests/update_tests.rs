@@ -0,0 +1,118 @@
+#![cfg(test)]
+
+use crate::{
+    config::ConfigParam,
+    fee::LinearFee,
+    fragment::{config::ConfigParams, Fragment},
+    key::EitherEd25519SecretKey,
+    ledger::Error,
+    testing::{
+        builders::{adopt_update_proposal, UpdateProposalBuilder, UpdateVoteBuilder},
+        keys::KeyFactory,
+        ledger::ConfigBuilder,
+        scenario::{self, prepare_scenario, wallet},
+    },
+    update,
+    value::Value,
+};
+
+#[test]
+pub fn adopted_fee_change_applies_to_next_transactions() {
+    let leaders = KeyFactory::from_seed([1; 32]).make_leader_pair(3);
+    let leader_ids = leaders.iter().map(|leader| leader.id()).collect();
+    let (ledger, mut controller) = prepare_scenario()
+        .with_initials(vec![wallet("alice", 1_000), wallet("bob", 500)])
+        .with_config(
+            ConfigBuilder::new()
+                .with_leaders(&leader_ids)
+                .with_fee(LinearFee::new(1, 1, 0)),
+        )
+        .build()
+        .unwrap();
+
+    let mut changes = ConfigParams::new();
+    changes.push(ConfigParam::LinearFee(LinearFee::new(10, 2, 0)));
+    let (mut ledger, settings) = adopt_update_proposal(&ledger, changes, &leaders).unwrap();
+    assert_eq!(settings.linear_fees(), LinearFee::new(10, 2, 0));
+
+    // 10 + 2 * (1 input + 1 output)
+    controller
+        .transfer_funds("alice", "bob", &mut ledger, 100)
+        .unwrap();
+    assert_eq!(controller.balance("alice", &ledger), Value(886));
+    assert_eq!(controller.balance("bob", &ledger), Value(600));
+    assert_eq!(ledger.pots().fees(), Value(14));
+}
+
+#[test]
+pub fn proposal_without_majority_is_not_adopted() {
+    let leaders = KeyFactory::from_seed([2; 32]).make_leader_pair(3);
+    let leader_ids = leaders.iter().map(|leader| leader.id()).collect();
+    let (ledger, _) = prepare_scenario()
+        .with_config(ConfigBuilder::new().with_leaders(&leader_ids))
+        .build()
+        .unwrap();
+
+    let mut changes = ConfigParams::new();
+    changes.push(ConfigParam::LinearFee(LinearFee::new(10, 2, 0)));
+    let (_, settings) = adopt_update_proposal(&ledger, changes, &leaders[..1]).unwrap();
+    assert_eq!(settings.linear_fees(), LinearFee::new(0, 0, 0));
+}
+
+#[test]
+pub fn vote_from_non_leader_is_rejected() {
+    let mut keys = KeyFactory::from_seed([3; 32]);
+    let leaders = keys.make_leader_pair(1);
+    let outsider = keys.make_leader_pair(1).remove(0);
+    let (ledger, _) = prepare_scenario()
+        .with_config(ConfigBuilder::new().with_leaders(&vec![leaders[0].id()]))
+        .build()
+        .unwrap();
+
+    let proposal = Fragment::UpdateProposal(
+        UpdateProposalBuilder::new()
+            .with_proposal_change(ConfigParam::SlotDuration(10))
+            .sign(&EitherEd25519SecretKey::Extended(leaders[0].key())),
+    );
+    let ledger = scenario::apply(&ledger, &proposal).unwrap();
+
+    let votes = UpdateVoteBuilder::new()
+        .for_proposal(proposal.hash())
+        .voted_by(outsider.id())
+        .as_messages();
+    assert_eq!(
+        scenario::apply(&ledger, &votes[0]).err(),
+        Some(Error::Update {
+            source: update::Error::BadVoter(proposal.hash(), outsider.id())
+        })
+    );
+}
+
+#[test]
+pub fn duplicate_vote_is_rejected() {
+    let leaders = KeyFactory::from_seed([4; 32]).make_leader_pair(1);
+    let (ledger, _) = prepare_scenario()
+        .with_config(ConfigBuilder::new().with_leaders(&vec![leaders[0].id()]))
+        .build()
+        .unwrap();
+
+    let proposal = Fragment::UpdateProposal(
+        UpdateProposalBuilder::new()
+            .with_proposal_change(ConfigParam::SlotDuration(10))
+            .sign(&EitherEd25519SecretKey::Extended(leaders[0].key())),
+    );
+    let ledger = scenario::apply(&ledger, &proposal).unwrap();
+
+    let votes = UpdateVoteBuilder::new()
+        .for_proposal(proposal.hash())
+        .voted_by(leaders[0].id())
+        .voted_by(leaders[0].id())
+        .as_messages();
+    let ledger = scenario::apply(&ledger, &votes[0]).unwrap();
+    assert_eq!(
+        scenario::apply(&ledger, &votes[1]).err(),
+        Some(Error::Update {
+            source: update::Error::DuplicateVote(proposal.hash(), leaders[0].id())
+        })
+    );
+}
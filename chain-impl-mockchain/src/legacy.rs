@@ -1,34 +1,162 @@
-use crate::value::Value;
+use crate::utxo;
+use crate::value::{Value, ValueError};
 
 pub use cardano_legacy_address::Addr as OldAddress;
 
+use cardano_legacy_address::Attributes;
 use chain_core::mempack::{ReadBuf, ReadError, Readable};
 use chain_core::property;
 use chain_crypto::{Ed25519Bip32, PublicKey};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// The maximum number of entries of a `UtxoDeclaration`, which can be
+/// serialized.
+pub const MAX_DECLARATION_ENTRIES: usize = 254;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UtxoDeclaration {
     pub addrs: Vec<(OldAddress, Value)>,
 }
 
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub DeclarationError
+        ZeroValue { index: usize } = "Legacy UTxO declaration entry {index} has a zero value",
+        DuplicateAddress { index: usize, address: OldAddress } = "Legacy UTxO declaration entry {index} duplicates the address {address}",
+}
+
+impl UtxoDeclaration {
+    /// the sum of all the declared values
+    pub fn total_value(&self) -> Result<Value, ValueError> {
+        Value::sum(self.addrs.iter().map(|(_, value)| *value))
+    }
+
+    /// check that no entry declares a zero value and that every address
+    /// is declared only once
+    pub fn validate(&self) -> Result<(), DeclarationError> {
+        let mut seen = BTreeSet::new();
+        for (index, (address, value)) in self.addrs.iter().enumerate() {
+            if *value == Value::zero() {
+                return Err(DeclarationError::ZeroValue { index });
+            }
+            if !seen.insert(address) {
+                return Err(DeclarationError::DuplicateAddress {
+                    index,
+                    address: address.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
 pub fn oldaddress_from_xpub(address: &OldAddress, xpub: &PublicKey<Ed25519Bip32>) -> bool {
     address.identical_with_pubkey_raw(xpub.as_ref())
 }
 
+/// Finds which of some xpubs an address belongs to
+///
+/// The digest of an address depends on the xpub and on the attributes of
+/// the address, so the digests of the xpubs are computed once for each
+/// distinct attributes met, rather than for each address.
+struct XPubMatcher<'a> {
+    xpubs: &'a [PublicKey<Ed25519Bip32>],
+    digests: BTreeMap<Attributes, HashMap<[u8; 28], usize>>,
+}
+
+impl<'a> XPubMatcher<'a> {
+    fn new(xpubs: &'a [PublicKey<Ed25519Bip32>]) -> Self {
+        XPubMatcher {
+            xpubs,
+            digests: BTreeMap::new(),
+        }
+    }
+
+    /// the index of the first xpub owning `address`
+    fn find(&mut self, address: &OldAddress) -> Option<usize> {
+        let address = address.deconstruct();
+        let xpubs = self.xpubs;
+        let digests = self
+            .digests
+            .entry(address.attributes.clone())
+            .or_insert_with(|| {
+                let mut digests = HashMap::new();
+                for (index, xpub) in xpubs.iter().enumerate().rev() {
+                    if let Some(digest) = address.attributes.spending_digest_raw(xpub.as_ref()) {
+                        digests.insert(digest, index);
+                    }
+                }
+                digests
+            });
+        digests.get(&address.addr).cloned()
+    }
+}
+
+/// The entries of `decl` owned by one of `xpubs`, as the index of the
+/// entry, the index of its xpub and its value, in the order of the
+/// declaration
+///
+/// An address matching several xpubs is reported with the first one.
+pub fn scan_declaration(
+    decl: &UtxoDeclaration,
+    xpubs: &[PublicKey<Ed25519Bip32>],
+) -> Vec<(usize, usize, Value)> {
+    let mut matcher = XPubMatcher::new(xpubs);
+    decl.addrs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (address, value))| {
+            matcher
+                .find(address)
+                .map(|xpub_index| (index, xpub_index, *value))
+        })
+        .collect()
+}
+
+/// Same as `scan_declaration` over the unspent outputs of `ledger`,
+/// lazily, giving each entry owned by one of `xpubs` along with the
+/// index of its xpub
+pub fn scan_utxo_ledger<'a>(
+    ledger: &'a utxo::Ledger<OldAddress>,
+    xpubs: &'a [PublicKey<Ed25519Bip32>],
+) -> impl Iterator<Item = (utxo::Entry<'a, OldAddress>, usize)> + 'a {
+    let mut matcher = XPubMatcher::new(xpubs);
+    ledger.iter().filter_map(move |entry| {
+        matcher
+            .find(&entry.output.address)
+            .map(|xpub_index| (entry, xpub_index))
+    })
+}
+
+// The checks shared by `Readable` and `property::Deserialize`, so that
+// reading a declaration from memory or from a stream accepts the same
+// declarations. The errors are the messages of the structure errors.
+
+fn check_entries_count(nb_entries: usize) -> Result<(), String> {
+    if nb_entries > MAX_DECLARATION_ENTRIES {
+        return Err("nb entries".to_string());
+    }
+    Ok(())
+}
+
+fn entry_address(index: usize, bytes: &[u8]) -> Result<OldAddress, String> {
+    use std::convert::TryFrom;
+
+    OldAddress::try_from(bytes)
+        .map_err(|error| format!("invalid legacy address at entry {}: {}", index, error))
+}
+
 impl Readable for UtxoDeclaration {
     fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
-        use std::convert::TryFrom;
-
         let nb_entries = buf.get_u8()? as usize;
-        if nb_entries >= 0xff {
-            return Err(ReadError::StructureInvalid("nb entries".to_string()));
-        }
+        check_entries_count(nb_entries).map_err(ReadError::StructureInvalid)?;
 
         let mut addrs = Vec::with_capacity(nb_entries);
-        for _ in 0..nb_entries {
+        for index in 0..nb_entries {
             let value = Value::read(buf)?;
             let addr_size = buf.get_u16()? as usize;
-            let addr = OldAddress::try_from(buf.get_slice(addr_size)?).unwrap();
+            let addr = entry_address(index, buf.get_slice(addr_size)?)
+                .map_err(ReadError::StructureInvalid)?;
             addrs.push((addr, value))
         }
 
@@ -36,13 +164,40 @@ impl Readable for UtxoDeclaration {
     }
 }
 
+/// Same as `Readable`, reading the entries one by one from the stream
+/// rather than from the whole declaration in memory. The invalid
+/// declarations fail with an error of kind `InvalidData`.
+impl property::Deserialize for UtxoDeclaration {
+    type Error = std::io::Error;
+
+    fn deserialize<R: std::io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+        use chain_core::packer::*;
+
+        let invalid_data =
+            |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let mut codec = Codec::new(reader);
+        let nb_entries = codec.get_u8()? as usize;
+        check_entries_count(nb_entries).map_err(invalid_data)?;
+
+        let mut addrs = Vec::with_capacity(nb_entries);
+        for index in 0..nb_entries {
+            let value = <Value as property::Deserialize>::deserialize(&mut codec)?;
+            let addr_size = codec.get_u16()? as usize;
+            let addr = entry_address(index, &codec.get_bytes(addr_size)?).map_err(invalid_data)?;
+            addrs.push((addr, value))
+        }
+
+        Ok(UtxoDeclaration { addrs })
+    }
+}
+
 impl property::Serialize for UtxoDeclaration {
     type Error = std::io::Error;
     fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
         use chain_core::packer::*;
         use std::io::Write;
 
-        assert!(self.addrs.len() < 255);
+        assert!(self.addrs.len() <= MAX_DECLARATION_ENTRIES);
 
         let mut codec = Codec::new(writer);
         codec.put_u8(self.addrs.len() as u8)?;
@@ -59,9 +214,15 @@ impl property::Serialize for UtxoDeclaration {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::fragment::{Fragment, FragmentId};
+    use crate::testing::keys::KeyFactory;
+    use crate::testing::serialization;
+    use crate::transaction::{Output, TransactionIndex};
     use cardano_legacy_address::ExtendedAddr;
+    use chain_core::mempack::read_exactly;
     use ed25519_bip32::{XPub, XPUB_SIZE};
-    use quickcheck::{Arbitrary, Gen};
+    use quickcheck::{Arbitrary, Gen, TestResult};
+    use quickcheck_macros::quickcheck;
 
     impl Arbitrary for UtxoDeclaration {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
@@ -90,4 +251,275 @@ mod test {
             UtxoDeclaration { addrs }
         }
     }
+
+    fn declaration_bytes(entries: &[(Value, &[u8])]) -> Vec<u8> {
+        let mut bytes = vec![entries.len() as u8];
+        for (value, address) in entries {
+            bytes.extend_from_slice(&value.0.to_be_bytes());
+            bytes.extend_from_slice(&(address.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(address);
+        }
+        bytes
+    }
+
+    fn read_declaration(bytes: &[u8]) -> Result<UtxoDeclaration, ReadError> {
+        UtxoDeclaration::read(&mut ReadBuf::from(bytes))
+    }
+
+    fn address(seed: u8) -> OldAddress {
+        let xpub = XPub::from_slice(&[seed; XPUB_SIZE]).unwrap();
+        ExtendedAddr::new_simple(&xpub, None).to_address()
+    }
+
+    #[quickcheck]
+    fn read_arbitrary_address_bytes_does_not_panic(address: Vec<u8>) -> bool {
+        let bytes = declaration_bytes(&[(Value(1), &address)]);
+        // random bytes are almost never a valid address, but whatever
+        // the outcome, reading must not panic
+        let _ = read_declaration(&bytes);
+        true
+    }
+
+    #[quickcheck]
+    fn read_truncated_address_fails_gracefully(decl: UtxoDeclaration, cut: usize) -> TestResult {
+        if decl.addrs.is_empty() {
+            return TestResult::discard();
+        }
+        let index = cut % decl.addrs.len();
+        let truncated: Vec<Vec<u8>> = decl
+            .addrs
+            .iter()
+            .enumerate()
+            .map(|(i, (address, _))| {
+                let bytes = address.as_ref();
+                if i == index {
+                    bytes[..cut % bytes.len()].to_vec()
+                } else {
+                    bytes.to_vec()
+                }
+            })
+            .collect();
+        let entries: Vec<(Value, &[u8])> = decl
+            .addrs
+            .iter()
+            .zip(truncated.iter())
+            .map(|((_, value), address)| (*value, address.as_slice()))
+            .collect();
+
+        match read_declaration(&declaration_bytes(&entries)) {
+            Err(ReadError::StructureInvalid(message)) => {
+                TestResult::from_bool(message.contains(&format!("entry {}", index)))
+            }
+            Err(error) => TestResult::error(format!("unexpected error: {}", error)),
+            Ok(_) => TestResult::error("truncated address accepted"),
+        }
+    }
+
+    #[quickcheck]
+    fn serialize_read_round_trips(decl: UtxoDeclaration) -> bool {
+        let bytes = property::Serialize::serialize_as_vec(&decl).unwrap();
+        read_declaration(&bytes) == Ok(decl)
+    }
+
+    #[quickcheck]
+    fn streaming_and_in_memory_reads_agree(decl: UtxoDeclaration) -> bool {
+        let bytes = property::Serialize::serialize_as_vec(&decl).unwrap();
+        let streamed = <UtxoDeclaration as property::Deserialize>::deserialize(&bytes[..]);
+        streamed.ok() == Some(decl.clone()) && read_declaration(&bytes) == Ok(decl)
+    }
+
+    #[quickcheck]
+    fn streaming_a_truncated_declaration_fails(decl: UtxoDeclaration, cut: usize) -> bool {
+        let bytes = property::Serialize::serialize_as_vec(&decl).unwrap();
+        let truncated = &bytes[..cut % bytes.len()];
+        match <UtxoDeclaration as property::Deserialize>::deserialize(truncated) {
+            Err(error) => error.kind() == std::io::ErrorKind::UnexpectedEof,
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn streaming_rejects_what_reading_rejects() {
+        let address = address(1);
+        let garbage: &[u8] = &[1, 2, 3];
+        for bytes in &[
+            vec![0xff],
+            declaration_bytes(&[(Value(1), address.as_ref()), (Value(2), garbage)]),
+        ] {
+            let message = match read_declaration(bytes) {
+                Err(ReadError::StructureInvalid(message)) => message,
+                other => panic!("unexpected outcome: {:?}", other),
+            };
+            let error =
+                <UtxoDeclaration as property::Deserialize>::deserialize(&bytes[..]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[quickcheck]
+    fn trailing_bytes_are_rejected(decl: UtxoDeclaration, junk: Vec<u8>) -> TestResult {
+        if junk.is_empty() {
+            return TestResult::discard();
+        }
+        let mut bytes = property::Serialize::serialize_as_vec(&decl).unwrap();
+        bytes.extend_from_slice(&junk);
+        TestResult::from_bool(
+            read_exactly::<UtxoDeclaration>(&bytes) == Err(ReadError::UnconsumedData(junk.len())),
+        )
+    }
+
+    #[test]
+    fn declaration_codec() {
+        serialization::assert_codec_roundtrip::<UtxoDeclaration>();
+        serialization::assert_rejects_truncation::<UtxoDeclaration>();
+        serialization::assert_rejects_trailing_bytes::<UtxoDeclaration>();
+    }
+
+    /// the serialization of `golden_declaration()`
+    const GOLDEN_DECLARATION: &str = "\
+        020000000000000001002b82d818582183581c5f562d853eccd4897b14ccb6bcec2d34fb2f779d6fa8eec6\
+        520bb91ba0001a2c7790820000000000000029002b82d818582183581c8f385edf8fe2fe20df5c51e02b21\
+        af6aa934dfc3068caddd0b8b0d83a0001ad91c4841";
+
+    /// the identifier of the fragment of `golden_declaration()`
+    const GOLDEN_DECLARATION_ID: &str =
+        "7b7cad9d6f4914ae07edec2977bd69650b24aacfd79b43cb10cc5f3a48280cbb";
+
+    fn golden_declaration() -> UtxoDeclaration {
+        UtxoDeclaration {
+            addrs: vec![(address(1), Value(1)), (address(2), Value(41))],
+        }
+    }
+
+    #[test]
+    fn golden_declaration_serialization_is_stable() {
+        let bytes = hex::decode(GOLDEN_DECLARATION).unwrap();
+        serialization::assert_serialization_stable::<UtxoDeclaration>(&bytes);
+        assert_eq!(
+            property::Serialize::serialize_as_vec(&golden_declaration()).unwrap(),
+            bytes
+        );
+
+        let raw = Fragment::OldUtxoDeclaration(golden_declaration()).to_raw();
+        let id: FragmentId = GOLDEN_DECLARATION_ID.parse().unwrap();
+        assert_eq!(serialization::fragment_id_of(raw.as_ref()), id);
+        assert_eq!(Fragment::from_raw(&raw).unwrap().hash(), id);
+    }
+
+    #[test]
+    fn total_value_sums_entries() {
+        let decl = UtxoDeclaration {
+            addrs: vec![(address(1), Value(1)), (address(2), Value(41))],
+        };
+        assert_eq!(decl.total_value(), Ok(Value(42)));
+
+        let decl = UtxoDeclaration {
+            addrs: vec![
+                (address(1), Value(u64::max_value())),
+                (address(2), Value(1)),
+            ],
+        };
+        assert!(decl.total_value().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_zero_values_and_duplicates() {
+        let first = address(1);
+        let second = address(2);
+
+        let valid = UtxoDeclaration {
+            addrs: vec![(first.clone(), Value(1)), (second.clone(), Value(2))],
+        };
+        assert_eq!(valid.validate(), Ok(()));
+
+        let zero = UtxoDeclaration {
+            addrs: vec![(first.clone(), Value(1)), (second.clone(), Value::zero())],
+        };
+        assert_eq!(
+            zero.validate(),
+            Err(DeclarationError::ZeroValue { index: 1 })
+        );
+
+        let duplicate = UtxoDeclaration {
+            addrs: vec![(first.clone(), Value(1)), (first.clone(), Value(2))],
+        };
+        assert_eq!(
+            duplicate.validate(),
+            Err(DeclarationError::DuplicateAddress {
+                index: 1,
+                address: first
+            })
+        );
+    }
+
+    fn wallets_and_foreign_addresses() -> (Vec<PublicKey<Ed25519Bip32>>, Vec<OldAddress>) {
+        let mut keys = KeyFactory::from_seed([3; 32]);
+        let wallets: Vec<_> = (0..4).map(|i| keys.legacy_wallet(&[i])).collect();
+        let xpubs = wallets.iter().map(|wallet| wallet.public_key()).collect();
+        // the address of the third wallet on another network
+        let xpub = XPub::from_slice(wallets[2].public_key().as_ref()).unwrap();
+        let on_mainnet = ExtendedAddr::new_simple(&xpub, Some(764_824_073)).to_address();
+        let addresses = vec![
+            address(1),
+            wallets[1].address().clone(),
+            address(2),
+            wallets[3].address().clone(),
+            on_mainnet,
+            wallets[1].address().clone(),
+            keys.legacy_wallet(&[0]).address().clone(),
+        ];
+        (xpubs, addresses)
+    }
+
+    #[test]
+    fn scan_declaration_finds_the_owned_entries() {
+        let (xpubs, addresses) = wallets_and_foreign_addresses();
+        let decl = UtxoDeclaration {
+            addrs: addresses
+                .into_iter()
+                .enumerate()
+                .map(|(i, address)| (address, Value(i as u64 + 1)))
+                .collect(),
+        };
+        assert_eq!(
+            scan_declaration(&decl, &xpubs),
+            vec![
+                (1, 1, Value(2)),
+                (3, 3, Value(4)),
+                (4, 2, Value(5)),
+                (5, 1, Value(6))
+            ]
+        );
+        for (index, xpub_index, _) in scan_declaration(&decl, &xpubs) {
+            assert!(oldaddress_from_xpub(
+                &decl.addrs[index].0,
+                &xpubs[xpub_index]
+            ));
+        }
+        assert!(scan_declaration(&decl, &[]).is_empty());
+        assert!(scan_declaration(&decl, &xpubs[..1]).is_empty());
+    }
+
+    #[test]
+    fn scan_utxo_ledger_finds_the_owned_outputs() {
+        let (xpubs, addresses) = wallets_and_foreign_addresses();
+        let outputs: Vec<_> = TransactionIndex::iter_to(addresses.len())
+            .zip(addresses.into_iter())
+            .map(|(index, address)| {
+                let output = Output {
+                    address,
+                    value: Value(1),
+                };
+                (index, output)
+            })
+            .collect();
+        let fragment_id = FragmentId::hash_bytes(b"declaration");
+        let ledger = utxo::Ledger::new().add(&fragment_id, &outputs).unwrap();
+
+        let found: Vec<(u8, usize)> = scan_utxo_ledger(&ledger, &xpubs)
+            .map(|(entry, xpub_index)| (entry.output_index.as_u8(), xpub_index))
+            .collect();
+        assert_eq!(found, vec![(1, 1), (3, 3), (4, 2), (5, 1)]);
+    }
 }
@@ -1,6 +1,10 @@
+#![cfg_attr(feature = "with-bench", feature(test))]
 #[cfg(any(test, feature = "property-test-api"))]
 #[macro_use]
 extern crate quickcheck;
+#[cfg(test)]
+#[cfg(feature = "with-bench")]
+extern crate test;
 #[macro_use(custom_error)]
 extern crate custom_error;
 
@@ -3,7 +3,7 @@ use crate::key::{
 };
 use chain_core::mempack::{ReadBuf, ReadError, Readable};
 use chain_core::property;
-use chain_crypto::{Ed25519, PublicKey, Verification};
+use chain_crypto::{AsymmetricPublicKey, Ed25519, PublicKey, Verification, VerificationAlgorithm};
 
 use std::collections::BTreeMap;
 
@@ -32,7 +32,23 @@ impl std::fmt::Display for Witness {
     }
 }
 
+// the sizes of the serialized count of signatures, and of each
+// signature with its index and public key
+const COUNT_SIZE: usize = 1;
+const SIGNATURE_ENTRY_SIZE: usize = 2
+    + <Ed25519 as AsymmetricPublicKey>::PUBLIC_KEY_SIZE
+    + <Ed25519 as VerificationAlgorithm>::SIGNATURE_SIZE;
+
 impl Witness {
+    pub fn signatures_count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The serialized size of a witness of `signatures_count` signatures
+    pub fn serialized_size_of(signatures_count: usize) -> usize {
+        COUNT_SIZE + signatures_count * SIGNATURE_ENTRY_SIZE
+    }
+
     pub fn verify(&self, declaration: &Declaration, msg: &WitnessMultisigData) -> bool {
         let mut v = Vec::new();
         for (ti, pk, sig) in self.0.iter() {
@@ -7,13 +7,15 @@
 //! temporaly, leaving no way to do garbage collection
 
 use crate::block::ChainLength;
-use crate::ledger::Ledger;
-use chain_core::property::BlockId as _;
+use crate::ledger::{pots::Pots, Ledger};
+use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::property::{BlockId as _, Serialize as _};
 use chain_storage::store::BlockStore;
 use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 type BlockId = crate::key::Hash;
+type StateDigest = crate::key::Hash;
 
 //
 // The multiverse is characterized by a single origin and multiple state of a given time
@@ -31,17 +33,204 @@ type BlockId = crate::key::Hash;
 // t=0                            t=latest known
 //
 pub struct Multiverse<State> {
-    states_by_hash: HashMap<BlockId, State>,
+    states_by_hash: HashMap<BlockId, Arc<State>>,
     states_by_chain_length: BTreeMap<ChainLength, HashSet<BlockId>>, // FIXME: use multimap?
     roots: Arc<RwLock<Roots>>,
+    gc_cursor: Option<GcCursor>,
+    /// parent of the states added with `add_with_parent`
+    parents: HashMap<BlockId, BlockId>,
+    /// time of the states added with `add_with_time`
+    times: HashMap<BlockId, u64>,
+    /// the states added with a digest, see `insert_with_digest`, with the
+    /// number of blocks sharing each of them
+    states_by_digest: HashMap<StateDigest, (Arc<State>, usize)>,
+    /// digest of the states shared through `states_by_digest`
+    digests: HashMap<BlockId, StateDigest>,
+    gc_policy: GcPolicy,
+    /// called with the block of every deleted state, see `on_delete`.
+    /// Only accessed through `get_mut`, the mutex is only there to keep
+    /// the multiverse `Sync`.
+    on_delete: Mutex<Vec<DeleteCallback>>,
+    /// the summaries computed by `summary`, until their state is deleted
+    summaries: Mutex<HashMap<BlockId, StateSummary>>,
+    /// the sizes estimated by `estimated_size`, until their state is deleted
+    sizes: Mutex<HashMap<BlockId, usize>>,
+    /// the last states accessed with `get_cached`, the most recent first
+    recent: Vec<(BlockId, Arc<State>)>,
+}
+
+/// The number of states remembered by `get_cached`.
+const RECENT_STATES: usize = 4;
+
+type DeleteCallback = Box<dyn FnMut(&BlockId) + Send>;
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub MultiverseError
+        Conflict { hash: BlockId } = "A different state is already stored for block {hash}",
+        DuplicateState { hash: BlockId } = "More than one state is given for block {hash}",
+        PinnedStateNotFound { hash: BlockId } = "No state is given for the pinned block {hash}",
+        UnrecordedRoot { hash: BlockId } = "A GCRoot was dropped for block {hash}, which had no recorded root",
+}
+
+custom_error! {
+    pub SnapshotError
+        StateNotFound { hash: BlockId } = "No state stored for block {hash}",
+        Io { source: std::io::Error } = "I/O error while reading or writing a state snapshot",
+        Read { source: ReadError } = "Invalid state snapshot",
 }
 
 /// Keep all states that are this close to the longest chain.
 const SUFFIX_TO_KEEP: u32 = 50;
 
+/// The end of the gap of states collected after a state kept at
+/// `chain_length`, half way to the longest chain, so that the kept
+/// states get exponentially closer to each other.
+fn gap_end(chain_length: ChainLength, longest_chain: ChainLength) -> ChainLength {
+    chain_length + chain_length.distance(longest_chain) / 2
+}
+
+/// Outcome of a bounded garbage collection step, see `Multiverse::gc_step`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcProgress {
+    /// number of states deleted by the step
+    pub deleted: usize,
+    /// estimated bytes held by the deleted states, counting only the sizes
+    /// already estimated, see `Multiverse::estimated_size`
+    pub freed_bytes: usize,
+    /// the current collection pass is complete, the next step starts a new one
+    pub finished: bool,
+}
+
+/// Outcome of a full garbage collection, see `Multiverse::gc`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcStats {
+    /// number of states deleted
+    pub deleted: usize,
+    /// estimated bytes held by the deleted states, counting only the sizes
+    /// already estimated, see `Multiverse::estimated_size`
+    pub freed_bytes: usize,
+}
+
+/// Criteria of `Multiverse::gc_at` on top of those of `Multiverse::gc`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcPolicy {
+    /// delete the unpinned states added with a time more than this much
+    /// before the time of the collection, whatever their chain length
+    pub max_age: Option<u64>,
+}
+
+/// What the multiverse holds, see `Multiverse::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultiverseStats {
+    /// number of states stored
+    pub states: usize,
+    /// number of distinct chain lengths of the stored states
+    pub chain_lengths: usize,
+    /// number of stored states pinned by at least one GCRoot
+    pub pinned_roots: usize,
+    /// lowest and highest chain length of the stored states
+    pub chain_length_span: Option<(ChainLength, ChainLength)>,
+    /// sum of the estimated sizes of the stored states
+    pub estimated_bytes: usize,
+}
+
+/// How forked the stored states are, see `Multiverse::fork_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ForkStats {
+    /// number of chain lengths with more than one state
+    pub contested_heights: usize,
+    /// greatest number of states of the same chain length
+    pub max_states_at_height: usize,
+    /// number of chain lengths from the lowest contested one up to the
+    /// highest, a bound on the number of blocks a fork switch would revert
+    pub deepest_fork: u32,
+    /// number of states of the highest chain length
+    pub tip_branches: usize,
+}
+
+/// The parts of a state compared by `Multiverse::compare`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSummary {
+    pub chain_length: ChainLength,
+    /// the digest of the unspent outputs, see `utxo::Ledger::digest`
+    pub utxo_digest: StateDigest,
+    pub pots: Pots,
+}
+
+/// How two states of the multiverse differ, see `Multiverse::compare`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateComparison {
+    pub same_chain_length: bool,
+    pub utxo_digest_equal: bool,
+    pub pots_equal: bool,
+    /// chain length of the second state minus that of the first one
+    pub chain_length_delta: i64,
+}
+
+/// A summary of a state, to compare states without going through all
+/// of their content
+///
+/// Computing the summary can take as long as going through the state,
+/// the multiverse computes it once per state.
+pub trait Summarize {
+    fn summarize(&self) -> StateSummary;
+}
+
+impl Summarize for Ledger {
+    fn summarize(&self) -> StateSummary {
+        StateSummary {
+            chain_length: self.chain_length(),
+            utxo_digest: self.utxos().digest(),
+            pots: self.pots().clone(),
+        }
+    }
+}
+
+/// An approximation of the memory held by a state
+///
+/// The states of a multiverse usually share most of their memory, so the
+/// estimates of several states add up to more than they really hold,
+/// and deleting a state frees less than its estimate.
+pub trait EstimateSize {
+    /// estimated size of the state, in bytes
+    fn estimate_size(&self) -> usize;
+}
+
+impl EstimateSize for Ledger {
+    fn estimate_size(&self) -> usize {
+        Ledger::estimate_size(self)
+    }
+}
+
+/// Where a collection pass interrupted by `gc_step` resumes
+struct GcCursor {
+    /// longest chain when the pass started, which decides the states to keep
+    longest_chain: ChainLength,
+    /// next chain length to look at
+    next: ChainLength,
+    to_keep: ChainLength,
+}
+
 struct Roots {
     /// Record how many GCRoot objects currently exist for this block ID.
     roots: HashMap<BlockId, usize>,
+    /// the first block of a GCRoot dropped without a recorded count,
+    /// reported by `Multiverse::check_roots` since a drop cannot fail
+    unrecorded: Option<BlockId>,
+}
+
+// The counts are updated in a single step while the lock is held, so
+// they are still consistent if a thread panicked with the lock taken;
+// recover from the poisoning instead of failing every later access.
+impl Roots {
+    fn read(roots: &RwLock<Roots>) -> RwLockReadGuard<Roots> {
+        roots.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(roots: &RwLock<Roots>) -> RwLockWriteGuard<Roots> {
+        roots.write().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 /// A RAII wrapper around a block identifier that keeps the state
@@ -54,7 +243,7 @@ pub struct GCRoot {
 impl GCRoot {
     fn new(hash: BlockId, roots: Arc<RwLock<Roots>>) -> Self {
         {
-            let mut roots = roots.write().unwrap();
+            let mut roots = Roots::write(&roots);
             *roots.roots.entry(hash.clone()).or_insert(0) += 1;
         }
 
@@ -71,21 +260,36 @@ impl std::ops::Deref for GCRoot {
 
 impl Drop for GCRoot {
     fn drop(&mut self) {
-        let mut roots = self.roots.write().unwrap();
-        if let Entry::Occupied(mut entry) = roots.roots.entry(self.hash.clone()) {
-            if *entry.get() > 1 {
-                *entry.get_mut() -= 1;
-            } else {
-                //println!("state for block {:?} became garbage", self.hash);
-                entry.remove_entry();
-                // put on GC list?
+        let mut roots = Roots::write(&self.roots);
+        let recorded = match roots.roots.entry(self.hash.clone()) {
+            Entry::Occupied(mut entry) => {
+                if *entry.get() > 1 {
+                    *entry.get_mut() -= 1;
+                } else {
+                    //println!("state for block {:?} became garbage", self.hash);
+                    entry.remove_entry();
+                    // put on GC list?
+                }
+                true
             }
-        } else {
-            unreachable!();
+            Entry::Vacant(_) => false,
+        };
+        if !recorded && roots.unrecorded.is_none() {
+            roots.unrecorded = Some(self.hash.clone());
         }
     }
 }
 
+/// The GC roots pinning a chain of states, see `Multiverse::pin_chain`
+///
+/// Dropping it releases all the pins at once.
+pub struct PinnedChain {
+    /// roots of the tip and of its stored ancestors, nearest first
+    pub roots: Vec<GCRoot>,
+    /// blocks of the chain whose state is not stored, nearest first
+    pub missing: Vec<BlockId>,
+}
+
 impl<State> Multiverse<State> {
     pub fn new() -> Self {
         Multiverse {
@@ -93,50 +297,528 @@ impl<State> Multiverse<State> {
             states_by_chain_length: BTreeMap::new(),
             roots: Arc::new(RwLock::new(Roots {
                 roots: HashMap::new(),
+                unrecorded: None,
             })),
+            gc_cursor: None,
+            parents: HashMap::new(),
+            times: HashMap::new(),
+            states_by_digest: HashMap::new(),
+            digests: HashMap::new(),
+            gc_policy: GcPolicy::default(),
+            on_delete: Mutex::new(Vec::new()),
+            summaries: Mutex::new(HashMap::new()),
+            sizes: Mutex::new(HashMap::new()),
+            recent: Vec::with_capacity(RECENT_STATES),
         }
     }
+
+    /// Set the criteria of `gc_at`. The default policy has no criteria,
+    /// so `gc_at` collects the same states as `gc`.
+    pub fn set_gc_policy(&mut self, policy: GcPolicy) {
+        self.gc_policy = policy;
+    }
+
+    pub fn gc_policy(&self) -> GcPolicy {
+        self.gc_policy
+    }
+
     fn make_root(&mut self, k: BlockId) -> GCRoot {
         debug_assert!(self.states_by_hash.contains_key(&k));
         GCRoot::new(k, self.roots.clone())
     }
 
     pub fn get(&self, k: &BlockId) -> Option<&State> {
-        self.states_by_hash.get(&k)
+        self.states_by_hash.get(&k).map(|state| &**state)
+    }
+
+    /// Same as `get`, but the last few states accessed this way are
+    /// looked up first, e.g. for the parent state of a block which is
+    /// needed for every fragment of the block.
+    ///
+    /// The deleted states are forgotten along with the other ones, so
+    /// this always agrees with `get`.
+    pub fn get_cached(&mut self, k: &BlockId) -> Option<&State> {
+        match self.recent.iter().position(|(id, _)| id == k) {
+            Some(position) => self.recent[..=position].rotate_right(1),
+            None => {
+                let state = self.states_by_hash.get(k)?.clone();
+                self.recent.truncate(RECENT_STATES - 1);
+                self.recent.insert(0, (k.clone(), state));
+            }
+        }
+        Some(&*self.recent[0].1)
+    }
+
+    /// Same as `get`, but the state can be kept without borrowing the
+    /// multiverse. Note that it is not pinned: the multiverse may still
+    /// delete its own reference to it.
+    pub fn get_shared(&self, k: &BlockId) -> Option<Arc<State>> {
+        self.states_by_hash.get(&k).cloned()
     }
 
-    pub fn get_from_root(&self, root: &GCRoot) -> &State {
+    pub fn get_from_root(&self, root: &GCRoot) -> Arc<State> {
         assert!(Arc::ptr_eq(&root.roots, &self.roots));
-        self.get(&*root).unwrap()
+        self.get_shared(&*root).unwrap()
     }
 
-    /// Return the number of states stored in memory.
+    /// Whether a state is stored for block `k`.
+    pub fn contains(&self, k: &BlockId) -> bool {
+        self.states_by_hash.contains_key(k)
+    }
+
+    /// Return the number of blocks with a state stored in memory.
     pub fn nr_states(&self) -> usize {
         self.states_by_hash.len()
     }
 
+    /// Return the number of states stored in memory, counting once the
+    /// states shared by several blocks, see `insert_with_digest`.
+    pub fn nr_distinct_states(&self) -> usize {
+        self.states_by_hash
+            .values()
+            .map(|state| &**state as *const State)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Fail with the block of the first GCRoot dropped while no root
+    /// was recorded for its block, which means the counts of the roots
+    /// are wrong and states may be collected while still pinned.
+    pub fn check_roots(&self) -> Result<(), MultiverseError> {
+        match &Roots::read(&self.roots).unrecorded {
+            None => Ok(()),
+            Some(hash) => Err(MultiverseError::UnrecordedRoot { hash: hash.clone() }),
+        }
+    }
+
+    /// Return the highest chain length of the stored states.
+    pub fn latest_chain_length(&self) -> Option<ChainLength> {
+        self.states_by_chain_length.keys().next_back().cloned()
+    }
+
+    /// The recorded ancestors of the state of block `k` that are still
+    /// stored, nearest first. The walk stops at the first ancestor that
+    /// is not stored or whose parent was not recorded, and at the first
+    /// one met again if the recorded parents make a cycle.
+    pub fn ancestry(&self, k: &BlockId) -> Vec<BlockId> {
+        let mut ancestors = Vec::new();
+        let mut visited = HashSet::new();
+        visited.insert(k);
+        let mut current = k;
+        while let Some(parent) = self.parents.get(current) {
+            if !self.states_by_hash.contains_key(parent) || !visited.insert(parent) {
+                break;
+            }
+            ancestors.push(parent.clone());
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// Pin the state of `tip` and of its `depth` nearest ancestors, so
+    /// that switching back to any of them later does not have to
+    /// rebuild its state. The ancestors are found with the parents
+    /// recorded by `add_with_parent`.
+    ///
+    /// The blocks of the chain whose state is not stored are reported
+    /// in `PinnedChain::missing` instead. The walk stops at the first
+    /// block whose parent was not recorded.
+    pub fn pin_chain(&mut self, tip: &BlockId, depth: u32) -> PinnedChain {
+        let mut pinned = PinnedChain {
+            roots: Vec::new(),
+            missing: Vec::new(),
+        };
+        let mut current = Some(tip.clone());
+        // the tip, then `depth` ancestors
+        for _ in 0..=u64::from(depth) {
+            let k = match current {
+                Some(k) => k,
+                None => break,
+            };
+            current = self.parents.get(&k).cloned();
+            if self.states_by_hash.contains_key(&k) {
+                pinned.roots.push(self.make_root(k));
+            } else {
+                pinned.missing.push(k);
+            }
+        }
+        pinned
+    }
+
+    /// The states added with `add_with_parent` whose parent is not stored,
+    /// either because it was never added or because it was collected.
+    pub fn orphans(&self) -> Vec<BlockId> {
+        let mut orphans: Vec<BlockId> = self
+            .parents
+            .iter()
+            .filter(|(_, parent)| !self.states_by_hash.contains_key(parent))
+            .map(|(k, _)| k.clone())
+            .collect();
+        orphans.sort();
+        orphans
+    }
+
+    /// Iterate over the states whose chain length is between `from` and
+    /// `to` (both included), in increasing chain length order then in
+    /// block identifier order for the states of the same length.
+    pub fn range<'a>(
+        &'a self,
+        from: ChainLength,
+        to: ChainLength,
+    ) -> impl Iterator<Item = (ChainLength, &'a BlockId, &'a State)> + 'a {
+        self.states_by_chain_length
+            .range(from..)
+            .take_while(move |(chain_length, _)| **chain_length <= to)
+            .flat_map(move |(chain_length, hashes)| {
+                let mut hashes: Vec<&BlockId> = hashes.iter().collect();
+                hashes.sort();
+                hashes
+                    .into_iter()
+                    .map(move |k| (*chain_length, k, &*self.states_by_hash[k]))
+            })
+    }
+
+    /// The stored state with the greatest chain length not above
+    /// `length`, e.g. to compare checkpoints about `length` blocks
+    /// from the origin although `gc` left gaps between the stored
+    /// states.
+    ///
+    /// Of several states of that length, the one that the parents
+    /// recorded by `add_with_parent` link to a state of the longest
+    /// chain is preferred, otherwise the one of lowest block id.
+    pub fn closest_state_at_or_below(
+        &self,
+        length: ChainLength,
+    ) -> Option<(ChainLength, &BlockId, &State)> {
+        let (chain_length, hashes) = self.states_by_chain_length.range(..=length).next_back()?;
+        let k = match self.on_longest_chain(hashes) {
+            Some(k) => k,
+            None => hashes.iter().min()?,
+        };
+        Some((*chain_length, k, &*self.states_by_hash[k]))
+    }
+
+    /// Count the forks of the stored states, in one pass over the chain
+    /// lengths.
+    ///
+    /// The forks are only told apart by their chain length, not by the
+    /// parents recorded by `add_with_parent`, so this is a best effort:
+    /// e.g. two forks of the same heights count as one contested range.
+    pub fn fork_stats(&self) -> ForkStats {
+        let mut stats = ForkStats::default();
+        let mut lowest_contested = None;
+        for (chain_length, hashes) in self.states_by_chain_length.iter() {
+            if hashes.len() > 1 {
+                stats.contested_heights += 1;
+                lowest_contested = lowest_contested.or(Some(*chain_length));
+            }
+            stats.max_states_at_height = std::cmp::max(stats.max_states_at_height, hashes.len());
+        }
+        if let Some((latest, hashes)) = self.states_by_chain_length.iter().next_back() {
+            stats.tip_branches = hashes.len();
+            if let Some(lowest) = lowest_contested {
+                stats.deepest_fork = latest.distance(lowest) + 1;
+            }
+        }
+        stats
+    }
+
+    // the first of `hashes` found walking back the recorded parents
+    // from the states of the longest chain, in block id order. A walk
+    // stops when it meets a block again, if the parents make a cycle.
+    fn on_longest_chain<'a>(&self, hashes: &'a HashSet<BlockId>) -> Option<&'a BlockId> {
+        let latest = self.latest_chain_length()?;
+        let mut tips: Vec<&BlockId> = self.states_by_chain_length[&latest].iter().collect();
+        tips.sort();
+        tips.into_iter().find_map(|tip| {
+            let mut visited = HashSet::new();
+            let mut current = tip;
+            loop {
+                if let Some(k) = hashes.get(current) {
+                    return Some(k);
+                }
+                if !visited.insert(current) {
+                    return None;
+                }
+                current = self.parents.get(current)?;
+            }
+        })
+    }
+}
+
+impl<State: EstimateSize> Multiverse<State> {
+    /// The estimated size of the state of block `k`, estimated the first
+    /// time it is asked for then kept until the state is deleted, as
+    /// estimating it can take as long as going through the state.
+    pub fn estimated_size(&self, k: &BlockId) -> Option<usize> {
+        let state = self.states_by_hash.get(k)?;
+        let mut sizes = self.sizes.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(
+            *sizes
+                .entry(k.clone())
+                .or_insert_with(|| state.estimate_size()),
+        )
+    }
+
+    /// Count what the multiverse holds, with the estimated size of every
+    /// stored state, see `estimated_size`.
+    pub fn stats(&self) -> MultiverseStats {
+        let pinned_roots = {
+            let roots = Roots::read(&self.roots);
+            roots
+                .roots
+                .keys()
+                .filter(|k| self.states_by_hash.contains_key(k))
+                .count()
+        };
+        let chain_length_span = match (
+            self.states_by_chain_length.keys().next(),
+            self.states_by_chain_length.keys().next_back(),
+        ) {
+            (Some(min), Some(max)) => Some((*min, *max)),
+            _ => None,
+        };
+        MultiverseStats {
+            states: self.states_by_hash.len(),
+            chain_lengths: self.states_by_chain_length.len(),
+            pinned_roots,
+            chain_length_span,
+            estimated_bytes: self
+                .states_by_hash
+                .keys()
+                .filter_map(|k| self.estimated_size(k))
+                .sum(),
+        }
+    }
+}
+
+impl<State: Summarize> Multiverse<State> {
+    /// The summary of the state of block `k`, computed the first time
+    /// it is asked for then kept until the state is deleted.
+    pub fn summary(&self, k: &BlockId) -> Option<StateSummary> {
+        let state = self.states_by_hash.get(k)?;
+        let mut summaries = self
+            .summaries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Some(
+            summaries
+                .entry(k.clone())
+                .or_insert_with(|| state.summarize())
+                .clone(),
+        )
+    }
+
+    /// Compare the summaries of the states of blocks `a` and `b`, e.g.
+    /// to tell quickly where two forks diverge. `None` if either state
+    /// is not stored.
+    pub fn compare(&self, a: &BlockId, b: &BlockId) -> Option<StateComparison> {
+        let a = self.summary(a)?;
+        let b = self.summary(b)?;
+        Some(StateComparison {
+            same_chain_length: a.chain_length == b.chain_length,
+            utxo_digest_equal: a.utxo_digest == b.utxo_digest,
+            pots_equal: a.pots == b.pots,
+            chain_length_delta: if b.chain_length >= a.chain_length {
+                i64::from(b.chain_length.distance(a.chain_length))
+            } else {
+                -i64::from(b.chain_length.distance(a.chain_length))
+            },
+        })
+    }
+}
+
+impl<State: PartialEq> Multiverse<State> {
     /// Add a state to the multiverse. Return a GCRoot object that
     /// pins the state into memory.
-    pub fn insert(&mut self, chain_length: ChainLength, k: BlockId, st: State) -> GCRoot {
-        self.states_by_chain_length
-            .entry(chain_length)
-            .or_insert(HashSet::new())
-            .insert(k.clone());
-        self.states_by_hash.entry(k.clone()).or_insert(st);
-        self.make_root(k)
+    ///
+    /// Adding the same state twice for a given block is allowed and
+    /// simply returns a new GCRoot, but adding a different state for
+    /// a block already present is an error.
+    pub fn insert(
+        &mut self,
+        chain_length: ChainLength,
+        k: BlockId,
+        st: State,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.insert_shared(chain_length, k, Arc::new(st))
+    }
+
+    /// Same as `insert`, but the multiverse shares the state with the
+    /// caller instead of owning it.
+    pub fn insert_shared(
+        &mut self,
+        chain_length: ChainLength,
+        k: BlockId,
+        st: Arc<State>,
+    ) -> Result<GCRoot, MultiverseError> {
+        match self.states_by_hash.entry(k.clone()) {
+            Entry::Occupied(entry) => {
+                if !Arc::ptr_eq(entry.get(), &st) && **entry.get() != *st {
+                    return Err(MultiverseError::Conflict { hash: k });
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(st);
+                self.states_by_chain_length
+                    .entry(chain_length)
+                    .or_insert(HashSet::new())
+                    .insert(k.clone());
+            }
+        }
+        Ok(self.make_root(k))
+    }
+
+    /// Same as `insert`, but if a state equal to `st` was added with the
+    /// same `digest` for another block which is still stored, e.g. the
+    /// tip of a fork which converged with this one, the blocks share it
+    /// instead of storing it twice. The shared state is only freed once
+    /// all the blocks sharing it are deleted.
+    ///
+    /// The digest is only a fingerprint to find the state to share: a
+    /// state that differs from the one of the same digest is stored on
+    /// its own. A digest of the serialized state fits, as well as any
+    /// cheaper one the caller has.
+    pub fn insert_with_digest(
+        &mut self,
+        chain_length: ChainLength,
+        k: BlockId,
+        st: State,
+        digest: StateDigest,
+    ) -> Result<GCRoot, MultiverseError> {
+        let (shared, tracked) = match self.states_by_digest.get(&digest) {
+            Some((state, _)) if **state == st => (state.clone(), true),
+            // not the same state after all, keep it apart
+            Some(_) => (Arc::new(st), false),
+            None => (Arc::new(st), true),
+        };
+        let is_new = !self.states_by_hash.contains_key(&k);
+        let root = self.insert_shared(chain_length, k.clone(), shared.clone())?;
+        if is_new && tracked {
+            self.digests.insert(k, digest.clone());
+            self.states_by_digest
+                .entry(digest)
+                .or_insert_with(|| (shared, 0))
+                .1 += 1;
+        }
+        Ok(root)
     }
 }
 
 impl Multiverse<Ledger> {
+    /// A multiverse holding `states`, e.g. restored from snapshots,
+    /// along with GC roots pinning the states of the `pinned` blocks
+    ///
+    /// This is the same as adding the states one by one, but the maps are
+    /// built in a single pass and no GC root is made for the states
+    /// which are not pinned. Each block can only be given one state, and
+    /// each pinned block must be one of them.
+    pub fn from_states<I>(
+        states: I,
+        pinned: &[BlockId],
+    ) -> Result<(Self, Vec<GCRoot>), MultiverseError>
+    where
+        I: IntoIterator<Item = (BlockId, Ledger)>,
+    {
+        let states = states.into_iter();
+        let mut multiverse = Multiverse::new();
+        multiverse.states_by_hash.reserve(states.size_hint().0);
+        for (k, st) in states {
+            match multiverse.states_by_hash.entry(k.clone()) {
+                Entry::Occupied(_) => return Err(MultiverseError::DuplicateState { hash: k }),
+                Entry::Vacant(entry) => {
+                    multiverse
+                        .states_by_chain_length
+                        .entry(st.chain_length())
+                        .or_insert_with(HashSet::new)
+                        .insert(k);
+                    entry.insert(Arc::new(st));
+                }
+            }
+        }
+
+        let mut roots = Vec::with_capacity(pinned.len());
+        for k in pinned {
+            if !multiverse.states_by_hash.contains_key(k) {
+                return Err(MultiverseError::PinnedStateNotFound { hash: k.clone() });
+            }
+            roots.push(multiverse.make_root(k.clone()));
+        }
+        Ok((multiverse, roots))
+    }
+
     /// Add a state to the multiverse. Return a GCRoot object that
     /// pins the state into memory.
-    pub fn add(&mut self, k: BlockId, st: Ledger) -> GCRoot {
+    pub fn add(&mut self, k: BlockId, st: Ledger) -> Result<GCRoot, MultiverseError> {
         self.insert(st.chain_length(), k, st)
     }
 
-    fn delete(&mut self, k: &BlockId) {
+    /// Same as `add`, without cloning a state the caller keeps too.
+    pub fn add_shared(&mut self, k: BlockId, st: Arc<Ledger>) -> Result<GCRoot, MultiverseError> {
+        self.insert_shared(st.chain_length(), k, st)
+    }
+
+    /// Same as `add`, also recording `parent` as the block the state
+    /// derives from, see `ancestry` and `orphans`.
+    pub fn add_with_parent(
+        &mut self,
+        k: BlockId,
+        parent: BlockId,
+        st: Ledger,
+    ) -> Result<GCRoot, MultiverseError> {
+        let root = self.add(k.clone(), st)?;
+        self.parents.insert(k, parent);
+        Ok(root)
+    }
+
+    /// Same as `insert_with_digest`, for a ledger state.
+    pub fn add_with_digest(
+        &mut self,
+        k: BlockId,
+        st: Ledger,
+        digest: StateDigest,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.insert_with_digest(st.chain_length(), k, st, digest)
+    }
+
+    /// Same as `add`, also recording `time` as the time the state was
+    /// added, for the `max_age` criterion of `gc_at`. The time is in the
+    /// unit the caller chooses, e.g. seconds or slots, as long as `gc_at`
+    /// is given times in the same unit. Adding a state again keeps the
+    /// time it was first added with.
+    pub fn add_with_time(
+        &mut self,
+        k: BlockId,
+        st: Ledger,
+        time: u64,
+    ) -> Result<GCRoot, MultiverseError> {
+        let root = self.add(k.clone(), st)?;
+        self.times.entry(k).or_insert(time);
+        Ok(root)
+    }
+
+    fn delete(&mut self, k: &BlockId) -> Arc<Ledger> {
         //println!("deleting state {:?}", k);
         let st = self.states_by_hash.remove(&k).unwrap();
+        self.parents.remove(&k);
+        self.times.remove(&k);
+        self.recent.retain(|(id, _)| id != k);
+        self.summaries
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&k);
+        self.sizes
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&k);
+        if let Some(digest) = self.digests.remove(&k) {
+            if let Entry::Occupied(mut entry) = self.states_by_digest.entry(digest) {
+                entry.get_mut().1 -= 1;
+                if entry.get().1 == 0 {
+                    entry.remove();
+                }
+            }
+        }
         // Remove the hash from states_by_chain_length, then prune
         // the latter.
         if let std::collections::btree_map::Entry::Occupied(mut entry) =
@@ -151,49 +833,354 @@ impl Multiverse<Ledger> {
         } else {
             unreachable!();
         }
+        let callbacks = self
+            .on_delete
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        for callback in callbacks.iter_mut() {
+            callback(k);
+        }
+        st
+    }
+
+    /// Register `callback` to be called with the block of every state
+    /// deleted from now on, by `gc`, `gc_step`, `gc_at`, `prune_below` or
+    /// `drain_below`, e.g. to evict the entries of a cache keyed by
+    /// block. The callbacks are called in the order they were
+    /// registered, and the states of a collection are deleted by
+    /// increasing chain length, then by block id.
+    ///
+    /// A callback only gets the block id: it cannot reach the multiverse,
+    /// which is being modified when it is called. No lock of the
+    /// multiverse is held at that time, so a callback may drop GCRoots.
+    /// For a `SharedMultiverse` the callbacks are called under its write
+    /// lock, so they must not use it.
+    pub fn on_delete(&mut self, callback: Box<dyn FnMut(&BlockId) + Send>) {
+        self.on_delete
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(callback);
+    }
+
+    /// Unregister all the callbacks registered with `on_delete`.
+    pub fn clear_callbacks(&mut self) {
+        self.on_delete
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
     }
 
     /// Once the state are old in the timeline, they are less
     /// and less likely to be used anymore, so we leave
     /// a gap between different version that gets bigger and bigger
-    pub fn gc(&mut self) {
+    ///
+    /// The states pinned by a GCRoot are never deleted, so collecting a
+    /// multiverse whose states are all pinned, or an empty one, deletes
+    /// nothing.
+    pub fn gc(&mut self) -> GcStats {
+        let mut stats = GcStats {
+            deleted: 0,
+            freed_bytes: 0,
+        };
+        let mut step = |multiverse: &mut Self| {
+            let progress = multiverse.gc_step(usize::max_value());
+            stats.deleted += progress.deleted;
+            stats.freed_bytes += progress.freed_bytes;
+            progress.finished
+        };
+        // complete a pass left unfinished by `gc_step` before doing a
+        // full one from the current longest chain
+        if self.gc_cursor.is_some() {
+            while !step(self) {}
+        }
+        while !step(self) {}
+        stats
+    }
+
+    /// Same as `gc`, then also delete the unpinned states older than the
+    /// `max_age` of the policy at time `now`, see `set_gc_policy`. Only
+    /// the states added with `add_with_time` have an age, the others are
+    /// left to `gc`.
+    pub fn gc_at(&mut self, now: u64) -> GcStats {
+        let mut stats = self.gc();
+        let garbage = self.aged(now);
+        let (deleted, freed_bytes) = self.delete_unpinned(&garbage);
+        stats.deleted += deleted;
+        stats.freed_bytes += freed_bytes;
+        stats
+    }
+
+    /// The states older than the `max_age` of the policy at time `now`,
+    /// by increasing chain length, then by block id.
+    fn aged(&self, now: u64) -> Vec<BlockId> {
+        let max_age = match self.gc_policy.max_age {
+            Some(max_age) => max_age,
+            None => return vec![],
+        };
+        let is_aged = |k: &&BlockId| match self.times.get(*k) {
+            Some(time) => now.saturating_sub(*time) > max_age,
+            None => false,
+        };
+        self.states_by_chain_length
+            .values()
+            .flat_map(|hashes| {
+                let mut hashes: Vec<&BlockId> = hashes.iter().filter(is_aged).collect();
+                hashes.sort();
+                hashes
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Same as `gc`, but delete at most `max_deletions` states, so the
+    /// collection can be spread over several calls. Each call resumes
+    /// the pass where the previous one stopped.
+    ///
+    /// A pass keeps the states `gc` would keep for the longest chain at
+    /// the time the pass started. States added while a pass is in
+    /// progress are only collected if the pass did not reach their
+    /// chain length yet; otherwise they are left to the next pass.
+    pub fn gc_step(&mut self, max_deletions: usize) -> GcProgress {
+        let mut cursor = match self.gc_cursor.take().or_else(|| self.gc_start()) {
+            Some(cursor) => cursor,
+            None => {
+                return GcProgress {
+                    deleted: 0,
+                    freed_bytes: 0,
+                    finished: true,
+                }
+            }
+        };
+
+        let (garbage, finished) = self.gc_scan(&mut cursor, max_deletions);
+        if !finished {
+            self.gc_cursor = Some(cursor);
+        }
+
+        let (deleted, freed_bytes) = self.delete_unpinned(&garbage);
+        GcProgress {
+            deleted,
+            freed_bytes,
+            finished,
+        }
+    }
+
+    /// The cursor of a new collection pass, `None` if there is nothing
+    /// to collect.
+    fn gc_start(&self) -> Option<GcCursor> {
+        self.latest_chain_length().map(|longest_chain| GcCursor {
+            longest_chain,
+            next: ChainLength(0),
+            to_keep: ChainLength(0),
+        })
+    }
+
+    /// Find at most `max_deletions` states to collect from `cursor`,
+    /// moving the cursor past them. Also return whether the pass is
+    /// complete.
+    fn gc_scan(&self, cursor: &mut GcCursor, max_deletions: usize) -> (Vec<BlockId>, bool) {
         let mut garbage = vec![];
+        let mut finished = true;
 
-        {
-            let roots = self.roots.read().unwrap();
+        let roots = Roots::read(&self.roots);
 
-            let longest_chain = self.states_by_chain_length.iter().next_back().unwrap().0;
+        for (chain_length, hashes) in self.states_by_chain_length.range(cursor.next..) {
+            // Keep states close to the current longest
+            // chain. FIXME: we should keep only the state that is
+            // an ancestor of the current longest chain. However,
+            // checking ancestry requires access to BlockStore.
+            if chain_length.saturating_add(SUFFIX_TO_KEEP) >= cursor.longest_chain {
+                break;
+            }
+            // Keep states in gaps that get exponentially smaller
+            // as they get closer to the longest chain.
+            if chain_length >= &cursor.to_keep {
+                cursor.to_keep = gap_end(*chain_length, cursor.longest_chain);
+                cursor.next = chain_length.next();
+                continue;
+            }
 
-            let mut to_keep = ChainLength(0);
+            // Keep states that are GC roots.
+            let mut unpinned: Vec<&BlockId> = hashes
+                .iter()
+                .filter(|k| !roots.roots.contains_key(k))
+                .collect();
+            // the order of deletion is visible from `on_delete`
+            unpinned.sort();
+            let budget = max_deletions - garbage.len();
+            if unpinned.len() > budget {
+                // come back to this chain length for the rest
+                garbage.extend(unpinned.into_iter().take(budget).cloned());
+                cursor.next = *chain_length;
+                finished = false;
+                break;
+            }
+            garbage.extend(unpinned.into_iter().cloned());
+            cursor.next = chain_length.next();
+            if garbage.len() == max_deletions {
+                finished = false;
+                break;
+            }
+        }
 
-            for (chain_length, hashes) in &self.states_by_chain_length {
-                // Keep states close to the current longest
-                // chain. FIXME: we should keep only the state that is
-                // an ancestor of the current longest chain. However,
-                // checking ancestry requires access to BlockStore.
-                if chain_length.0 + SUFFIX_TO_KEEP >= longest_chain.0 {
-                    break;
-                }
-                // Keep states in gaps that get exponentially smaller
-                // as they get closer to the longest chain.
-                if chain_length >= &to_keep {
-                    to_keep = ChainLength(chain_length.0 + (longest_chain.0 - chain_length.0) / 2);
-                } else {
-                    for k in hashes {
-                        // Keep states that are GC roots.
-                        if !roots.roots.contains_key(&k) {
-                            garbage.push(k.clone());
-                        }
+        (garbage, finished)
+    }
+
+    /// Delete the states of `garbage` that are still stored and still
+    /// not pinned, as a GCRoot may have been made for one of them since
+    /// it was found. Return the number of states deleted and the bytes
+    /// they held, from the sizes already estimated: estimating the others
+    /// would go through every deleted state.
+    fn delete_unpinned(&mut self, garbage: &[BlockId]) -> (usize, usize) {
+        let mut deleted = 0;
+        let mut freed_bytes = 0;
+        for k in garbage {
+            // not held while deleting, the `on_delete` callbacks may
+            // drop roots
+            if Roots::read(&self.roots).roots.contains_key(k)
+                || !self.states_by_hash.contains_key(k)
+            {
+                continue;
+            }
+            freed_bytes += self
+                .sizes
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(k)
+                .cloned()
+                .unwrap_or(0);
+            self.delete(k);
+            deleted += 1;
+        }
+        (deleted, freed_bytes)
+    }
+
+    /// Remove every unpinned state with a chain length strictly lower
+    /// than `length`, without keeping any gap like `gc` does. This is
+    /// meant for states no fork can go back to anymore (e.g. finalized
+    /// blocks). States pinned by a GCRoot are always kept, and so is the
+    /// most recent ancestor below `length` of every pinned state at or
+    /// above `length`. It is found walking back the parents recorded by
+    /// `add_with_parent`; where no parent is recorded, the states of the
+    /// highest chain length below `length` are kept instead, so that
+    /// states added with `add` are kept the same way.
+    ///
+    /// Returns the number of states removed.
+    pub fn prune_below(&mut self, length: ChainLength) -> usize {
+        let garbage = self.unpinned_below(length);
+        for k in &garbage {
+            self.delete(k);
+        }
+        garbage.len()
+    }
+
+    /// Same as `prune_below`, but return the removed states instead of
+    /// dropping them, e.g. to move them to storage, by increasing chain
+    /// length then by block id. They can be loaded back with
+    /// `from_states`.
+    ///
+    /// A state still shared with the caller or with another block, see
+    /// `get_shared` and `insert_with_digest`, is returned as a copy.
+    pub fn drain_below(&mut self, length: ChainLength) -> Vec<(BlockId, ChainLength, Ledger)> {
+        self.unpinned_below(length)
+            .into_iter()
+            .map(|k| {
+                let st = self.delete(&k);
+                let st = Arc::try_unwrap(st).unwrap_or_else(|st| (*st).clone());
+                (k, st.chain_length(), st)
+            })
+            .collect()
+    }
+
+    /// The unpinned states with a chain length strictly lower than
+    /// `length`, by increasing chain length then by block id, leaving
+    /// out the ancestors required by the pinned states.
+    fn unpinned_below(&self, length: ChainLength) -> Vec<BlockId> {
+        let roots = Roots::read(&self.roots);
+        let required: HashSet<&BlockId> = roots
+            .roots
+            .keys()
+            .flat_map(|k| self.ancestors_below(k, length))
+            .collect();
+        self.states_by_chain_length
+            .range(..length)
+            .flat_map(|(_, hashes)| {
+                let mut hashes: Vec<&BlockId> = hashes.iter().collect();
+                hashes.sort();
+                hashes
+            })
+            .filter(|k| !roots.roots.contains_key(k) && !required.contains(k))
+            .cloned()
+            .collect()
+    }
+
+    // the stored states `k` needs below `length`: none if `k` itself is
+    // below `length`, else its nearest ancestor with a chain length
+    // strictly lower than `length`. Once the recorded parents stop, or
+    // give a parent whose chain length is not lower than its child's,
+    // every state of the highest chain length below `length` may be the
+    // ancestor.
+    fn ancestors_below(&self, k: &BlockId, length: ChainLength) -> Vec<&BlockId> {
+        let mut current = k;
+        let mut chain_length = match self.states_by_hash.get(k) {
+            Some(st) => st.chain_length(),
+            None => return Vec::new(),
+        };
+        if chain_length < length {
+            return Vec::new();
+        }
+        loop {
+            let parent = self.parents.get(current).and_then(|parent| {
+                self.states_by_hash
+                    .get(parent)
+                    .map(|st| (parent, st.chain_length()))
+            });
+            match parent {
+                Some((parent, parent_length)) if parent_length < chain_length => {
+                    if parent_length < length {
+                        return vec![parent];
                     }
+                    current = parent;
+                    chain_length = parent_length;
+                }
+                _ => {
+                    return self
+                        .states_by_chain_length
+                        .range(..length)
+                        .next_back()
+                        .map(|(_, hashes)| hashes.iter().collect())
+                        .unwrap_or_default()
                 }
             }
         }
+    }
 
-        //println!("deleting {} states from multiverse", garbage.len());
+    /// Write a snapshot of the state of block `k`, to be read back
+    /// with `import`.
+    pub fn export<W: std::io::Write>(
+        &self,
+        k: &BlockId,
+        mut writer: W,
+    ) -> Result<(), SnapshotError> {
+        let state = self
+            .get(k)
+            .ok_or_else(|| SnapshotError::StateNotFound { hash: k.clone() })?;
+        k.serialize(&mut writer)?;
+        state.serialize(&mut writer)?;
+        Ok(())
+    }
 
-        for k in garbage {
-            self.delete(&k);
-        }
+    /// Read a snapshot written by `export`, returning the block
+    /// identifier and its state. The state is not added to the
+    /// multiverse.
+    pub fn import<R: std::io::Read>(mut reader: R) -> Result<(BlockId, Ledger), SnapshotError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut buf = ReadBuf::from(&bytes);
+        let k = BlockId::read(&mut buf)?;
+        let state = Ledger::read(&mut buf)?;
+        buf.expect_end()?;
+        Ok((k, state))
     }
 
     /// Get the chain state at block 'k' from memory if present;
@@ -252,59 +1239,424 @@ impl Multiverse<Ledger> {
             // FIXME: add the intermediate states to memory?
         }
 
-        Ok(self.add(k, state))
+        // the state was not present when we started, so it cannot conflict
+        Ok(self.add(k, state).unwrap())
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::Multiverse;
-    use crate::block::{Block, BlockBuilder, ConsensusVersion};
-    use crate::config::{Block0Date, ConfigParam};
-    use crate::fragment::{ConfigParams, Fragment};
-    use crate::leadership::bft::LeaderId;
-    use crate::ledger::Ledger;
-    use crate::milli::Milli;
-    use chain_addr::Discrimination;
-    use chain_core::property::{Block as _, ChainLength as _};
-    use chain_crypto::{Ed25519, SecretKey};
-    use chain_storage::store::BlockStore;
-    use chain_time::{Epoch, SlotDuration, TimeEra, TimeFrame, Timeline};
-    use std::time::SystemTime;
+/// Number of states deleted under one write lock by `SharedMultiverse::gc`
+const SHARED_GC_BATCH: usize = 64;
 
-    fn apply_block(state: &Ledger, block: &Block) -> Ledger {
-        if state.chain_length().0 != 0 {
-            assert_eq!(state.chain_length().0 + 1, block.chain_length().0);
-        }
-        state
-            .apply_block(
-                &state.get_ledger_parameters(),
-                block.fragments(),
-                &block.header.to_content_eval_context(),
-            )
-            .unwrap()
+/// A multiverse shared between threads
+///
+/// This exposes the API of `Multiverse` with `&self` methods. Reads
+/// only take a read lock on the multiverse and return the states as
+/// `Arc`s, so they do not block each other. Additions take the write
+/// lock, and collections find their garbage under the read lock then
+/// delete it in small batches, each under a short write lock.
+///
+/// Panics if an earlier operation panicked while holding the write lock,
+/// as the multiverse may have been left half updated.
+pub struct SharedMultiverse<State> {
+    inner: RwLock<Multiverse<State>>,
+    /// the pass in progress, only touched by the thread collecting
+    gc_cursor: Mutex<Option<GcCursor>>,
+}
+
+impl<State> SharedMultiverse<State> {
+    pub fn new() -> Self {
+        SharedMultiverse::from(Multiverse::new())
     }
 
-    #[test]
-    pub fn multiverse() {
-        const NUM_BLOCK_PER_EPOCH: u32 = 1000;
-        let mut multiverse = Multiverse::new();
+    /// Same as `Multiverse::set_gc_policy`.
+    pub fn set_gc_policy(&self, policy: GcPolicy) {
+        self.write().set_gc_policy(policy)
+    }
 
-        let system_time = SystemTime::UNIX_EPOCH;
-        let timeline = Timeline::new(system_time);
-        let tf = TimeFrame::new(timeline, SlotDuration::from_secs(10));
+    fn read(&self) -> RwLockReadGuard<Multiverse<State>> {
+        self.inner.read().expect("multiverse lock poisoned")
+    }
 
-        let slot0 = tf.slot0();
-        let era = TimeEra::new(slot0, Epoch(0), NUM_BLOCK_PER_EPOCH);
+    fn write(&self) -> RwLockWriteGuard<Multiverse<State>> {
+        self.inner.write().expect("multiverse lock poisoned")
+    }
 
-        let leader_key: SecretKey<Ed25519> = SecretKey::generate(rand_os::OsRng::new().unwrap());
-        let leader_pub_key = leader_key.to_public();
+    /// Same as `Multiverse::get_shared`.
+    pub fn get(&self, k: &BlockId) -> Option<Arc<State>> {
+        self.read().get_shared(k)
+    }
 
-        let mut store = chain_storage::memory::MemoryBlockStore::new();
+    /// Same as `Multiverse::get_from_root`.
+    pub fn get_from_root(&self, root: &GCRoot) -> Arc<State> {
+        self.read().get_from_root(root)
+    }
 
-        let mut genesis_block = BlockBuilder::new();
-        let mut ents = ConfigParams::new();
-        ents.push(ConfigParam::Discrimination(Discrimination::Test));
+    /// Same as `Multiverse::contains`.
+    pub fn contains(&self, k: &BlockId) -> bool {
+        self.read().contains(k)
+    }
+
+    /// Same as `Multiverse::check_roots`.
+    pub fn check_roots(&self) -> Result<(), MultiverseError> {
+        self.read().check_roots()
+    }
+
+    /// Same as `Multiverse::nr_states`.
+    pub fn nr_states(&self) -> usize {
+        self.read().nr_states()
+    }
+
+    /// Same as `Multiverse::nr_distinct_states`.
+    pub fn nr_distinct_states(&self) -> usize {
+        self.read().nr_distinct_states()
+    }
+
+    /// Same as `Multiverse::latest_chain_length`.
+    pub fn latest_chain_length(&self) -> Option<ChainLength> {
+        self.read().latest_chain_length()
+    }
+
+    /// Same as `Multiverse::ancestry`.
+    pub fn ancestry(&self, k: &BlockId) -> Vec<BlockId> {
+        self.read().ancestry(k)
+    }
+
+    /// Same as `Multiverse::pin_chain`, under the write lock.
+    pub fn pin_chain(&self, tip: &BlockId, depth: u32) -> PinnedChain {
+        self.write().pin_chain(tip, depth)
+    }
+
+    /// Same as `Multiverse::orphans`.
+    pub fn orphans(&self) -> Vec<BlockId> {
+        self.read().orphans()
+    }
+
+    /// Same as `Multiverse::range`, collecting the states as the
+    /// multiverse cannot stay borrowed.
+    pub fn range(
+        &self,
+        from: ChainLength,
+        to: ChainLength,
+    ) -> Vec<(ChainLength, BlockId, Arc<State>)> {
+        let multiverse = self.read();
+        multiverse
+            .range(from, to)
+            .map(|(chain_length, k, _)| {
+                (
+                    chain_length,
+                    k.clone(),
+                    multiverse.states_by_hash[k].clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Same as `Multiverse::closest_state_at_or_below`, with a shared
+    /// reference to the state.
+    pub fn closest_state_at_or_below(
+        &self,
+        length: ChainLength,
+    ) -> Option<(ChainLength, BlockId, Arc<State>)> {
+        let multiverse = self.read();
+        multiverse
+            .closest_state_at_or_below(length)
+            .map(|(chain_length, k, _)| {
+                (
+                    chain_length,
+                    k.clone(),
+                    multiverse.states_by_hash[k].clone(),
+                )
+            })
+    }
+
+    /// Same as `Multiverse::fork_stats`.
+    pub fn fork_stats(&self) -> ForkStats {
+        self.read().fork_stats()
+    }
+
+    /// Take back the multiverse, e.g. once the other threads are done.
+    pub fn into_inner(self) -> Multiverse<State> {
+        let mut multiverse = self.inner.into_inner().expect("multiverse lock poisoned");
+        multiverse.gc_cursor = self
+            .gc_cursor
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        multiverse
+    }
+}
+
+impl<State> From<Multiverse<State>> for SharedMultiverse<State> {
+    fn from(mut multiverse: Multiverse<State>) -> Self {
+        SharedMultiverse {
+            gc_cursor: Mutex::new(multiverse.gc_cursor.take()),
+            inner: RwLock::new(multiverse),
+        }
+    }
+}
+
+impl<State: EstimateSize> SharedMultiverse<State> {
+    /// Same as `Multiverse::stats`.
+    pub fn stats(&self) -> MultiverseStats {
+        self.read().stats()
+    }
+}
+
+impl<State: Summarize> SharedMultiverse<State> {
+    /// Same as `Multiverse::summary`.
+    pub fn summary(&self, k: &BlockId) -> Option<StateSummary> {
+        self.read().summary(k)
+    }
+
+    /// Same as `Multiverse::compare`.
+    pub fn compare(&self, a: &BlockId, b: &BlockId) -> Option<StateComparison> {
+        self.read().compare(a, b)
+    }
+}
+
+impl<State: PartialEq> SharedMultiverse<State> {
+    /// Same as `Multiverse::insert`.
+    pub fn insert(
+        &self,
+        chain_length: ChainLength,
+        k: BlockId,
+        st: State,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.insert_shared(chain_length, k, Arc::new(st))
+    }
+
+    /// Same as `Multiverse::insert_shared`.
+    pub fn insert_shared(
+        &self,
+        chain_length: ChainLength,
+        k: BlockId,
+        st: Arc<State>,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.write().insert_shared(chain_length, k, st)
+    }
+
+    /// Same as `Multiverse::insert_with_digest`.
+    pub fn insert_with_digest(
+        &self,
+        chain_length: ChainLength,
+        k: BlockId,
+        st: State,
+        digest: StateDigest,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.write().insert_with_digest(chain_length, k, st, digest)
+    }
+}
+
+impl SharedMultiverse<Ledger> {
+    /// Same as `Multiverse::add`.
+    pub fn add(&self, k: BlockId, st: Ledger) -> Result<GCRoot, MultiverseError> {
+        self.add_shared(k, Arc::new(st))
+    }
+
+    /// Same as `Multiverse::add_shared`.
+    pub fn add_shared(&self, k: BlockId, st: Arc<Ledger>) -> Result<GCRoot, MultiverseError> {
+        self.write().add_shared(k, st)
+    }
+
+    /// Same as `Multiverse::add_with_parent`.
+    pub fn add_with_parent(
+        &self,
+        k: BlockId,
+        parent: BlockId,
+        st: Ledger,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.write().add_with_parent(k, parent, st)
+    }
+
+    /// Same as `Multiverse::add_with_digest`.
+    pub fn add_with_digest(
+        &self,
+        k: BlockId,
+        st: Ledger,
+        digest: StateDigest,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.write().add_with_digest(k, st, digest)
+    }
+
+    /// Same as `Multiverse::add_with_time`.
+    pub fn add_with_time(
+        &self,
+        k: BlockId,
+        st: Ledger,
+        time: u64,
+    ) -> Result<GCRoot, MultiverseError> {
+        self.write().add_with_time(k, st, time)
+    }
+
+    /// Same as `Multiverse::gc`, deleting at most `SHARED_GC_BATCH`
+    /// states under each write lock.
+    pub fn gc(&self) -> GcStats {
+        let mut stats = GcStats {
+            deleted: 0,
+            freed_bytes: 0,
+        };
+        let mut step = || {
+            let progress = self.gc_step(SHARED_GC_BATCH);
+            stats.deleted += progress.deleted;
+            stats.freed_bytes += progress.freed_bytes;
+            progress.finished
+        };
+        // same as `Multiverse::gc`, finish the pass left by `gc_step`
+        if self.gc_cursor().is_some() {
+            while !step() {}
+        }
+        while !step() {}
+        stats
+    }
+
+    /// Same as `Multiverse::gc_at`. The aged states are found under the
+    /// read lock, then deleted under a single write lock.
+    pub fn gc_at(&self, now: u64) -> GcStats {
+        let mut stats = self.gc();
+        let garbage = self.read().aged(now);
+        if !garbage.is_empty() {
+            let (deleted, freed_bytes) = self.write().delete_unpinned(&garbage);
+            stats.deleted += deleted;
+            stats.freed_bytes += freed_bytes;
+        }
+        stats
+    }
+
+    /// Same as `Multiverse::gc_step`. The states to delete are found
+    /// under the read lock; the write lock is only taken to delete them.
+    ///
+    /// A GCRoot made by another thread in between still pins its state:
+    /// the pinned states are skipped when deleting, and left to the
+    /// next pass.
+    pub fn gc_step(&self, max_deletions: usize) -> GcProgress {
+        // held for the whole step so concurrent collections do not scan
+        // the same chain lengths twice
+        let mut cursor_slot = self.gc_cursor();
+        let (garbage, finished) = {
+            let multiverse = self.read();
+            let cursor = cursor_slot.take().or_else(|| multiverse.gc_start());
+            let mut cursor = match cursor {
+                Some(cursor) => cursor,
+                None => {
+                    return GcProgress {
+                        deleted: 0,
+                        freed_bytes: 0,
+                        finished: true,
+                    }
+                }
+            };
+            let (garbage, finished) = multiverse.gc_scan(&mut cursor, max_deletions);
+            if !finished {
+                *cursor_slot = Some(cursor);
+            }
+            (garbage, finished)
+        };
+
+        let (deleted, freed_bytes) = if garbage.is_empty() {
+            (0, 0)
+        } else {
+            self.write().delete_unpinned(&garbage)
+        };
+        GcProgress {
+            deleted,
+            freed_bytes,
+            finished,
+        }
+    }
+
+    fn gc_cursor(&self) -> MutexGuard<Option<GcCursor>> {
+        self.gc_cursor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Same as `Multiverse::prune_below`, under the write lock.
+    pub fn prune_below(&self, length: ChainLength) -> usize {
+        self.write().prune_below(length)
+    }
+
+    /// Same as `Multiverse::drain_below`, under the write lock.
+    pub fn drain_below(&self, length: ChainLength) -> Vec<(BlockId, ChainLength, Ledger)> {
+        self.write().drain_below(length)
+    }
+
+    /// Same as `Multiverse::export`, under the read lock.
+    pub fn export<W: std::io::Write>(&self, k: &BlockId, writer: W) -> Result<(), SnapshotError> {
+        self.read().export(k, writer)
+    }
+
+    /// Same as `Multiverse::get_from_storage`. The write lock is held
+    /// while the blocks are applied.
+    pub fn get_from_storage<S: BlockStore<Block = crate::block::Block>>(
+        &self,
+        k: BlockId,
+        store: &S,
+    ) -> Result<GCRoot, chain_storage::error::Error> {
+        self.write().get_from_storage(k, store)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BlockId, ForkStats, GcPolicy, GcStats, Multiverse, MultiverseError, MultiverseStats,
+        SharedMultiverse, SnapshotError, StateComparison, SUFFIX_TO_KEEP,
+    };
+    use crate::block::{Block, BlockBuilder, BlockDate, ChainLength, ConsensusVersion};
+    use crate::config::{Block0Date, ConfigParam};
+    use crate::fragment::{ConfigParams, Fragment};
+    use crate::key::Hash;
+    use crate::leadership::bft::LeaderId;
+    use crate::ledger::Ledger;
+    use crate::milli::Milli;
+    use crate::testing::keys::KeyFactory;
+    use crate::testing::ChainBuilder;
+    use chain_addr::Discrimination;
+    use chain_core::property::{Block as _, ChainLength as _};
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_storage::store::BlockStore;
+    use chain_time::{Epoch, SlotDuration, TimeEra, TimeFrame, Timeline};
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    const NUM_BLOCK_PER_EPOCH: u32 = 1000;
+
+    fn apply_block(state: &Ledger, block: &Block) -> Ledger {
+        if state.chain_length().0 != 0 {
+            assert_eq!(state.chain_length().next(), block.chain_length());
+        }
+        state
+            .apply_block(
+                &state.get_ledger_parameters(),
+                block.fragments(),
+                &block.header.to_content_eval_context(),
+            )
+            .unwrap()
+    }
+
+    fn make_era() -> TimeEra {
+        let system_time = SystemTime::UNIX_EPOCH;
+        let timeline = Timeline::new(system_time);
+        let tf = TimeFrame::new(timeline, SlotDuration::from_secs(10));
+
+        let slot0 = tf.slot0();
+        TimeEra::new(slot0, Epoch(0), NUM_BLOCK_PER_EPOCH)
+    }
+
+    fn leader_key() -> SecretKey<Ed25519> {
+        KeyFactory::from_seed([0; 32]).next_key()
+    }
+
+    fn make_genesis(leader_key: &SecretKey<Ed25519>) -> (Block, Ledger) {
+        let leader_pub_key = leader_key.to_public();
+
+        let mut genesis_block = BlockBuilder::new();
+        let mut ents = ConfigParams::new();
+        ents.push(ConfigParam::Discrimination(Discrimination::Test));
         ents.push(ConfigParam::ConsensusVersion(ConsensusVersion::Bft));
         ents.push(ConfigParam::AddBftLeader(LeaderId::from(leader_pub_key)));
         ents.push(ConfigParam::Block0Date(Block0Date(0)));
@@ -316,36 +1668,60 @@ mod test {
         ents.push(ConfigParam::SlotsPerEpoch(NUM_BLOCK_PER_EPOCH));
         genesis_block.message(Fragment::Initial(ents));
         let genesis_block = genesis_block.make_genesis_block();
-        let mut date = genesis_block.date();
         let genesis_state = Ledger::new(genesis_block.id(), genesis_block.fragments()).unwrap();
         assert_eq!(genesis_state.chain_length().0, 0);
-        store.put_block(&genesis_block).unwrap();
-        multiverse.add(genesis_block.id(), genesis_state.clone());
+        (genesis_block, genesis_state)
+    }
 
-        let mut state = genesis_state;
+    fn make_next_block(
+        leader_key: &SecretKey<Ed25519>,
+        parent: &Block,
+        state: &Ledger,
+        date: BlockDate,
+    ) -> (Block, Ledger) {
+        let mut block = BlockBuilder::new();
+        block.chain_length(state.chain_length().next());
+        block.parent(parent.id());
+        block.date(date);
+        let block = block.make_bft_block(leader_key).unwrap();
+        let state = apply_block(state, &block);
+        (block, state)
+    }
+
+    #[test]
+    pub fn multiverse() {
+        let mut multiverse = Multiverse::new();
+
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+
+        let mut store = chain_storage::memory::MemoryBlockStore::new();
+
+        let (genesis_block, genesis_state) = chain.genesis();
+        store.put_block(genesis_block).unwrap();
+        multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+
+        let mut state = Arc::new(genesis_state.clone());
         let mut _root = None;
-        let mut parent = genesis_block.id();
+        let mut parent = genesis_block.clone();
         let mut ids = vec![];
-        for i in 1..10001 {
-            let mut block = BlockBuilder::new();
-            block.chain_length(state.chain_length.next());
-            block.parent(parent);
-            date = date.next(&era);
-            block.date(date);
-            let block = block.make_bft_block(&leader_key);
-            state = apply_block(&state, &block);
+        for (i, (block, next_state)) in (1..10001).zip(chain.by_ref()) {
+            state = Arc::new(next_state);
             assert_eq!(state.chain_length().0, i);
-            assert_eq!(state.date, block.date());
+            assert_eq!(state.date(), block.date());
             store.put_block(&block).unwrap();
-            _root = Some(multiverse.add(block.id(), state.clone()));
+            _root = Some(multiverse.add_shared(block.id(), state.clone()).unwrap());
             multiverse.gc();
             ids.push(block.id());
-            parent = block.id();
+            parent = block;
             assert!(
                 multiverse.nr_states()
                     <= super::SUFFIX_TO_KEEP as usize + ((i as f32).log2()) as usize
             );
         }
+        // the chain went through 10 epochs
+        assert_eq!(parent.date().epoch, 10);
 
         {
             let root = multiverse
@@ -355,13 +1731,14 @@ mod test {
             assert_eq!(state.chain_length().0, 10000);
         }
 
-        {
+        let reconstructed = {
             let root = multiverse
                 .get_from_storage(ids[1234].clone(), &store)
                 .unwrap();
             let state = multiverse.get_from_root(&root);
             assert_eq!(state.chain_length().0, 1235);
-        }
+            state
+        };
 
         {
             let root = multiverse
@@ -371,10 +1748,1525 @@ mod test {
             assert_eq!(state.chain_length().0, 9501);
         }
 
+        // the tip is shared with the multiverse instead of cloned
+        assert_eq!(Arc::strong_count(&state), 2);
+        assert_eq!(Arc::strong_count(&reconstructed), 2);
+
         let before = multiverse.nr_states();
         multiverse.gc();
         let after = multiverse.nr_states();
         assert_eq!(before, after + 2);
+
+        // the reconstructed state is no longer pinned so gc dropped it,
+        // but it outlives the multiverse's reference
+        assert!(multiverse.get(&ids[1234]).is_none());
+        assert_eq!(Arc::strong_count(&reconstructed), 1);
+        assert_eq!(reconstructed.chain_length().0, 1235);
+        assert_eq!(Arc::strong_count(&state), 2);
+
+        // a snapshot of the tip behaves like the state kept in memory
+        let mut snapshot = Vec::new();
+        multiverse.export(&parent.id(), &mut snapshot).unwrap();
+        let (id, imported_state) = Multiverse::<Ledger>::import(&snapshot[..]).unwrap();
+        assert_eq!(id, parent.id());
+        assert!(imported_state == *state);
+
+        let (block, next_state) = chain.next().unwrap();
+        assert!(apply_block(&imported_state, &block) == next_state);
+    }
+
+    #[test]
+    pub fn fork_is_added_next_to_the_main_chain() {
+        let mut multiverse = Multiverse::new();
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        let mut main = vec![];
+        for (block, state) in chain.by_ref().take(20) {
+            let root = multiverse.add_with_parent(block.id(), block.parent_id(), state);
+            roots.push(root.unwrap());
+            main.push(block.id());
+        }
+
+        let mut fork = vec![];
+        for (block, state) in chain.fork_at(12).take(5) {
+            assert!(!main.contains(&block.id()));
+            let root = multiverse.add_with_parent(block.id(), block.parent_id(), state);
+            roots.push(root.unwrap());
+            fork.push(block.id());
+        }
+
+        assert_eq!(multiverse.nr_states(), 1 + 20 + 5);
+        for length in 13..18 {
+            assert_eq!(
+                multiverse.states_by_chain_length[&ChainLength(length)].len(),
+                2
+            );
+        }
+        assert_eq!(multiverse.get(&fork[4]).unwrap().chain_length().0, 17);
+
+        // the fork branches off the block of height 12 of the main chain
+        let ancestry = multiverse.ancestry(&fork[4]);
+        assert_eq!(ancestry.len(), 4 + 12 + 1);
+        let fork_ancestors: Vec<_> = fork[..4].iter().rev().cloned().collect();
+        assert_eq!(ancestry[..4], fork_ancestors[..]);
+        assert_eq!(ancestry[4], main[11]);
+
+        // of the two states of a length, the one of the main chain, which
+        // is longer, is preferred
+        let (_, id, _) = multiverse
+            .closest_state_at_or_below(ChainLength(15))
+            .unwrap();
+        assert_eq!(id, &main[14]);
+    }
+
+    #[test]
+    pub fn states_are_restored_in_bulk() {
+        let states: Vec<_> = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH)
+            .take(1000)
+            .map(|(block, state)| (block.id(), state))
+            .collect();
+        let ids: Vec<_> = states.iter().map(|(id, _)| id.clone()).collect();
+        let pinned = [ids[10].clone(), ids[500].clone(), ids[999].clone()];
+
+        let (mut multiverse, roots) = Multiverse::from_states(states.clone(), &pinned).unwrap();
+        assert_eq!(multiverse.nr_states(), 1000);
+        assert_eq!(multiverse.states_by_chain_length.len(), 1000);
+        let root_ids: Vec<_> = roots.iter().map(|root| (**root).clone()).collect();
+        assert_eq!(root_ids, pinned);
+
+        multiverse.gc();
+        assert!(multiverse.nr_states() < 1000);
+        for i in &[10, 500, 999] {
+            assert!(multiverse.get(&ids[*i]).unwrap() == &states[*i].1);
+        }
+        let by_length: usize = multiverse
+            .states_by_chain_length
+            .iter()
+            .map(|(length, hashes)| {
+                for k in hashes {
+                    assert_eq!(multiverse.get(k).unwrap().chain_length(), *length);
+                }
+                hashes.len()
+            })
+            .sum();
+        assert_eq!(by_length, multiverse.nr_states());
+
+        let mut duplicated = states[..3].to_vec();
+        duplicated.push(states[1].clone());
+        match Multiverse::from_states(duplicated, &[]) {
+            Err(MultiverseError::DuplicateState { hash }) => assert_eq!(hash, ids[1]),
+            _ => panic!("a block given two states should be rejected"),
+        }
+        match Multiverse::from_states(states[..3].to_vec(), &[ids[3].clone()]) {
+            Err(MultiverseError::PinnedStateNotFound { hash }) => assert_eq!(hash, ids[3]),
+            _ => panic!("pinning a block without state should fail"),
+        }
+    }
+
+    #[test]
+    pub fn export_unknown_state_fails() {
+        let multiverse: Multiverse<Ledger> = Multiverse::new();
+        let (genesis_block, _) = make_genesis(&leader_key());
+        match multiverse.export(&genesis_block.id(), Vec::new()) {
+            Err(SnapshotError::StateNotFound { hash }) => assert_eq!(hash, genesis_block.id()),
+            _ => panic!("exporting an unknown state should fail"),
+        }
+    }
+
+    #[test]
+    pub fn block_ids_are_reproducible() {
+        let era = make_era();
+        let make_chain = || {
+            let leader_key = leader_key();
+            let (genesis_block, genesis_state) = make_genesis(&leader_key);
+            let date = genesis_block.date().next(&era);
+            let (block, _) = make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+            (genesis_block.id(), block.id())
+        };
+        assert_eq!(make_chain(), make_chain());
+    }
+
+    #[test]
+    pub fn add_same_state_twice_is_idempotent() {
+        let mut multiverse = Multiverse::new();
+        let leader_key = leader_key();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+
+        let root1 = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        let root2 = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        assert_eq!(multiverse.nr_states(), 1);
+        assert_eq!(multiverse.states_by_chain_length.len(), 1);
+        assert_eq!(*root1, *root2);
+
+        drop(root1);
+        multiverse.gc();
+        assert!(multiverse.get(&genesis_block.id()).is_some());
+
+        drop(root2);
+        multiverse.gc();
+        assert_eq!(multiverse.nr_states(), 1);
+    }
+
+    #[test]
+    pub fn add_conflicting_state_is_rejected() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let date = genesis_block.date().next(&era);
+        let (_, next_state) = make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+
+        let _root = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        match multiverse.add(genesis_block.id(), next_state) {
+            Err(MultiverseError::Conflict { hash }) => assert_eq!(hash, genesis_block.id()),
+            Ok(_) => panic!("conflicting state should not be accepted"),
+        }
+
+        // the maps must have been left untouched by the failed addition
+        assert_eq!(multiverse.nr_states(), 1);
+        assert_eq!(multiverse.states_by_chain_length.len(), 1);
+        assert!(multiverse.get(&genesis_block.id()).unwrap() == &genesis_state);
+
+        multiverse.gc();
+        assert_eq!(multiverse.nr_states(), 1);
+    }
+
+    #[test]
+    pub fn gc_step_matches_full_gc() {
+        let mut reference = Multiverse::new();
+        let mut incremental = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let mut date = genesis_block.date();
+        reference
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        incremental
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+
+        let mut state = genesis_state;
+        let mut parent = genesis_block;
+        let mut pinned = vec![];
+        let mut pass_running = false;
+        let mut steps = 0;
+        for i in 1..600 {
+            date = date.next(&era);
+            let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+            state = next_state;
+            let reference_root = reference.add(block.id(), state.clone()).unwrap();
+            let incremental_root = incremental.add(block.id(), state.clone()).unwrap();
+            if i == 300 {
+                pinned.push((block.id(), reference_root, incremental_root));
+            }
+            parent = block;
+
+            // a pass deletes what a full collection does when the pass starts
+            if !pass_running {
+                reference.gc();
+            }
+            let progress = incremental.gc_step(3);
+            assert!(progress.deleted <= 3);
+            pass_running = !progress.finished;
+            steps += 1;
+        }
+        while pass_running {
+            pass_running = !incremental.gc_step(3).finished;
+            steps += 1;
+        }
+
+        let kept = |multiverse: &Multiverse<Ledger>| -> HashSet<BlockId> {
+            multiverse.states_by_hash.keys().cloned().collect()
+        };
+        assert!(steps >= 599);
+        assert_eq!(kept(&incremental), kept(&reference));
+        assert!(incremental.get(&pinned[0].0).is_some());
+        assert!(incremental.nr_states() < 600);
+
+        // a full collection from there doesn't find anything else to delete
+        reference.gc();
+        incremental.gc();
+        assert_eq!(kept(&incremental), kept(&reference));
+    }
+
+    #[test]
+    pub fn prune_below_keeps_pinned_states() {
+        for with_parents in &[false, true] {
+            let mut multiverse = Multiverse::new();
+            let era = make_era();
+            let leader_key = leader_key();
+
+            let (genesis_block, genesis_state) = make_genesis(&leader_key);
+            let mut date = genesis_block.date();
+            let mut ids = vec![genesis_block.id()];
+            let mut pinned = None;
+            multiverse
+                .add(genesis_block.id(), genesis_state.clone())
+                .unwrap();
+
+            let mut state = genesis_state;
+            let mut parent = genesis_block;
+            for i in 1..1000 {
+                date = date.next(&era);
+                let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+                state = next_state;
+                let root = if *with_parents {
+                    multiverse.add_with_parent(block.id(), parent.id(), state.clone())
+                } else {
+                    multiverse.add(block.id(), state.clone())
+                }
+                .unwrap();
+                if i == 600 {
+                    pinned = Some(root);
+                }
+                ids.push(block.id());
+                parent = block;
+            }
+            assert_eq!(multiverse.nr_states(), 1000);
+
+            let removed = multiverse.prune_below(ChainLength(700));
+            assert_eq!(removed, 699);
+            assert_eq!(multiverse.nr_states(), 301);
+            assert_eq!(multiverse.states_by_chain_length.len(), 301);
+
+            for (chain_length, id) in ids.iter().enumerate() {
+                let present = multiverse.get(id).is_some();
+                assert_eq!(
+                    present,
+                    chain_length == 600 || chain_length >= 700,
+                    "state at chain length {} with parents {}",
+                    chain_length,
+                    with_parents
+                );
+            }
+
+            // nothing left to prune
+            assert_eq!(multiverse.prune_below(ChainLength(700)), 0);
+
+            // once unpinned, the state can be pruned
+            drop(pinned);
+            assert_eq!(multiverse.prune_below(ChainLength(700)), 1);
+            assert_eq!(multiverse.nr_states(), 300);
+        }
     }
 
+    #[test]
+    pub fn drained_states_can_be_loaded_back() {
+        let mut multiverse = Multiverse::new();
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut ids = vec![genesis_block.id()];
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        for (block, state) in chain.by_ref().take(9) {
+            ids.push(block.id());
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+        // keep the root of the state at chain length 3 only
+        let pinned = roots.remove(3);
+        drop(roots);
+
+        let drained = multiverse.drain_below(ChainLength(6));
+        let drained_ids: Vec<BlockId> = drained.iter().map(|(k, _, _)| k.clone()).collect();
+        let expected: Vec<BlockId> = [0, 1, 2, 4, 5].iter().map(|i| ids[*i].clone()).collect();
+        assert_eq!(drained_ids, expected);
+        for (k, chain_length, st) in &drained {
+            assert_eq!(*chain_length, st.chain_length());
+            assert!(!multiverse.contains(k));
+        }
+        assert!(multiverse.contains(&*pinned));
+        assert_eq!(multiverse.nr_states(), 5);
+        assert_eq!(multiverse.states_by_chain_length.len(), 5);
+        assert!(multiverse.drain_below(ChainLength(6)).is_empty());
+
+        let (restored, roots) = Multiverse::from_states(
+            drained.iter().map(|(k, _, st)| (k.clone(), st.clone())),
+            &[],
+        )
+        .unwrap();
+        assert!(roots.is_empty());
+        assert_eq!(restored.nr_states(), 5);
+        for (k, chain_length, st) in &drained {
+            assert!(restored.get(k) == Some(st));
+            assert_eq!(restored.states_by_chain_length[chain_length].len(), 1);
+        }
+
+        // once unpinned, the state can be drained too
+        drop(pinned);
+        let drained = multiverse.drain_below(ChainLength(6));
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].0, ids[3]);
+    }
+
+    #[test]
+    pub fn prune_below_keeps_the_ancestor_of_pinned_states() {
+        for with_parents in &[false, true] {
+            let mut multiverse = Multiverse::new();
+            let era = make_era();
+            let leader_key = leader_key();
+
+            let (genesis_block, genesis_state) = make_genesis(&leader_key);
+            let mut date = genesis_block.date();
+            let mut ids = vec![genesis_block.id()];
+            let mut pinned = vec![];
+            multiverse
+                .add(genesis_block.id(), genesis_state.clone())
+                .unwrap();
+
+            let mut state = genesis_state;
+            let mut parent = genesis_block;
+            for i in 1..20 {
+                date = date.next(&era);
+                let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+                state = next_state;
+                let root = if *with_parents {
+                    multiverse.add_with_parent(block.id(), parent.id(), state.clone())
+                } else {
+                    multiverse.add(block.id(), state.clone())
+                }
+                .unwrap();
+                if i == 8 || i == 19 {
+                    pinned.push(root);
+                }
+                ids.push(block.id());
+                parent = block;
+            }
+
+            // 8 is kept on its own, the tip keeps the most recent state
+            // below 12
+            assert_eq!(multiverse.prune_below(ChainLength(12)), 10);
+            for (chain_length, id) in ids.iter().enumerate() {
+                assert_eq!(
+                    multiverse.get(id).is_some(),
+                    chain_length == 8 || chain_length >= 11,
+                    "state at chain length {} with parents {}",
+                    chain_length,
+                    with_parents
+                );
+            }
+
+            drop(pinned);
+            assert_eq!(multiverse.prune_below(ChainLength(12)), 2);
+            assert_eq!(multiverse.nr_states(), 8);
+        }
+    }
+
+    #[test]
+    pub fn converging_forks_share_their_state() {
+        let mut multiverse = Multiverse::new();
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (block1, state1) = chain.next().unwrap();
+        let (block2, state2) = chain.next().unwrap();
+        let (fork2, _) = chain.fork_at(1).next().unwrap();
+        let digest = Hash::hash_serializable(&state2).unwrap();
+
+        let root2 = multiverse
+            .add_with_digest(block2.id(), state2.clone(), digest.clone())
+            .unwrap();
+        // the fork ends up in the same state as the main chain
+        let fork_root = multiverse
+            .add_with_digest(fork2.id(), state2.clone(), digest.clone())
+            .unwrap();
+        assert_eq!(multiverse.nr_states(), 2);
+        assert_eq!(multiverse.nr_distinct_states(), 1);
+        assert!(Arc::ptr_eq(
+            &multiverse.get_shared(&block2.id()).unwrap(),
+            &multiverse.get_shared(&fork2.id()).unwrap()
+        ));
+
+        // another state under the same digest is not shared
+        let root1 = multiverse
+            .add_with_digest(block1.id(), state1.clone(), digest.clone())
+            .unwrap();
+        assert_eq!(multiverse.nr_states(), 3);
+        assert_eq!(multiverse.nr_distinct_states(), 2);
+        assert!(multiverse.get(&block1.id()) == Some(&state1));
+
+        // collecting one of the blocks keeps the state of the other
+        drop(root2);
+        assert_eq!(multiverse.prune_below(ChainLength(3)), 1);
+        assert!(multiverse.get(&fork2.id()) == Some(&state2));
+        assert_eq!(multiverse.states_by_digest[&digest].1, 1);
+
+        drop(fork_root);
+        assert_eq!(multiverse.prune_below(ChainLength(3)), 1);
+        assert_eq!(multiverse.nr_distinct_states(), 1);
+        assert!(multiverse.states_by_digest.is_empty());
+        assert!(multiverse.digests.is_empty());
+        drop(root1);
+    }
+
+    #[test]
+    pub fn gc_of_an_empty_multiverse_does_nothing() {
+        let mut multiverse = Multiverse::<Ledger>::new();
+        assert_eq!(
+            multiverse.gc(),
+            GcStats {
+                deleted: 0,
+                freed_bytes: 0
+            }
+        );
+        assert!(multiverse.gc_step(1).finished);
+        assert_eq!(multiverse.gc_at(u64::max_value()).deleted, 0);
+        assert_eq!(multiverse.nr_states(), 0);
+    }
+
+    #[test]
+    pub fn gc_keeps_a_single_unpinned_state() {
+        let mut multiverse = Multiverse::new();
+        let chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        drop(
+            multiverse
+                .add(genesis_block.id(), genesis_state.clone())
+                .unwrap(),
+        );
+        assert_eq!(multiverse.gc().deleted, 0);
+        assert!(multiverse.gc_step(1).finished);
+        assert!(multiverse.contains(&genesis_block.id()));
+    }
+
+    #[test]
+    pub fn gc_deletes_nothing_when_all_the_states_are_pinned() {
+        let mut multiverse = Multiverse::new();
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        for (block, state) in chain.by_ref().take(3 * SUFFIX_TO_KEEP as usize) {
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+
+        assert_eq!(multiverse.gc().deleted, 0);
+        // a bounded pass still goes through all the chain lengths once
+        let mut steps = 0;
+        while !multiverse.gc_step(1).finished {
+            steps += 1;
+            assert!(steps <= roots.len(), "the pass does not end");
+        }
+        assert_eq!(multiverse.nr_states(), roots.len());
+    }
+
+    // the gaps computed on raw u32s before the ChainLength helpers
+    #[quickcheck]
+    fn gap_end_is_half_way_to_the_longest_chain(a: u32, b: u32) -> bool {
+        let (low, high) = (std::cmp::min(a, b), std::cmp::max(a, b));
+        gap_end(ChainLength(low), ChainLength(high)) == ChainLength(low + (high - low) / 2)
+            && gap_end(ChainLength(low), ChainLength(high)) <= ChainLength(high)
+    }
+
+    #[test]
+    pub fn gap_end_does_not_overflow() {
+        let max = ChainLength(std::u32::MAX);
+        assert_eq!(gap_end(ChainLength(0), max), ChainLength(std::u32::MAX / 2));
+        assert_eq!(
+            gap_end(ChainLength(std::u32::MAX - 1), max),
+            ChainLength(std::u32::MAX - 1)
+        );
+        assert_eq!(gap_end(max, max), max);
+    }
+
+    #[test]
+    pub fn gc_keeps_the_states_at_the_boundaries() {
+        let mut multiverse = Multiverse::new();
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut ids = vec![genesis_block.id()];
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        for (block, state) in chain.by_ref().take(199) {
+            ids.push(block.id());
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+        let deleted = Arc::new(Mutex::new(HashSet::new()));
+        let recorder = deleted.clone();
+        multiverse.on_delete(Box::new(move |k| {
+            recorder.lock().unwrap().insert(k.clone());
+        }));
+
+        // from a tip at 199, the gaps keep the states at 0 and 99, and
+        // the suffix all the states from 149; the state at 98, although
+        // in a gap, is pinned
+        let pinned = roots.swap_remove(98);
+        roots.clear();
+        multiverse.gc();
+        let kept: Vec<usize> = (0..ids.len())
+            .filter(|length| multiverse.contains(&ids[*length]))
+            .collect();
+        let expected: Vec<usize> = [0, 98, 99].iter().cloned().chain(149..200).collect();
+        assert_eq!(kept, expected);
+
+        let deleted = deleted.lock().unwrap();
+        assert_eq!(deleted.len(), ids.len() - expected.len());
+        for length in &expected {
+            assert!(!deleted.contains(&ids[*length]));
+        }
+        assert_eq!(*pinned, ids[98]);
+    }
+
+    #[test]
+    pub fn gc_at_evicts_the_aged_states() {
+        let mut multiverse = Multiverse::new();
+        let chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut states = vec![(genesis_block.id(), genesis_state.clone())];
+        states.extend(chain.take(40).map(|(block, state)| (block.id(), state)));
+
+        // the states of even chain length are added at 10 times their
+        // chain length, the others without a time
+        let mut pinned = None;
+        for (i, (id, state)) in states.iter().enumerate() {
+            let root = if i % 2 == 0 {
+                multiverse
+                    .add_with_time(id.clone(), state.clone(), i as u64 * 10)
+                    .unwrap()
+            } else {
+                multiverse.add(id.clone(), state.clone()).unwrap()
+            };
+            if i == 4 {
+                pinned = Some(root);
+            }
+        }
+
+        // all the states are close enough to the longest chain for `gc`
+        // to keep them, and there is no age limit yet
+        assert_eq!(
+            multiverse.gc_at(400),
+            GcStats {
+                deleted: 0,
+                freed_bytes: 0
+            }
+        );
+        assert_eq!(multiverse.nr_states(), 41);
+
+        multiverse.set_gc_policy(GcPolicy { max_age: Some(200) });
+        assert_eq!(multiverse.gc().deleted, 0);
+        // the states added before time 200, but the pinned one
+        assert_eq!(multiverse.gc_at(400).deleted, 9);
+        for (i, (id, _)) in states.iter().enumerate() {
+            assert_eq!(
+                multiverse.get(id).is_some(),
+                i % 2 == 1 || i == 4 || i >= 20,
+                "state at chain length {}",
+                i
+            );
+        }
+
+        drop(pinned);
+        assert_eq!(multiverse.gc_at(400).deleted, 1);
+        assert_eq!(multiverse.nr_states(), 31);
+        assert_eq!(multiverse.times.len(), 11);
+    }
+
+    #[test]
+    pub fn deleted_states_are_notified() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let mut date = genesis_block.date();
+        let mut ids = vec![genesis_block.id()];
+        let mut kept_root = None;
+        multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+
+        let mut state = genesis_state;
+        let mut parent = genesis_block;
+        for i in 1..200 {
+            date = date.next(&era);
+            let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+            state = next_state;
+            let root = multiverse.add(block.id(), state.clone()).unwrap();
+            if i == 180 {
+                kept_root = Some(root);
+            }
+            ids.push(block.id());
+            parent = block;
+        }
+
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let first_ids = first.clone();
+        multiverse.on_delete(Box::new(move |k| first_ids.lock().unwrap().push(k.clone())));
+        let second_ids = second.clone();
+        multiverse.on_delete(Box::new(move |k| {
+            // dropping a root must not deadlock with the collection
+            kept_root.take();
+            second_ids.lock().unwrap().push(k.clone())
+        }));
+
+        let stats = multiverse.gc();
+        // a single chain, so deleted by increasing chain length
+        let evicted: Vec<BlockId> = ids
+            .iter()
+            .filter(|id| multiverse.get(id).is_none())
+            .cloned()
+            .collect();
+        assert!(stats.deleted > 0);
+        assert_eq!(evicted.len(), stats.deleted);
+        assert_eq!(*first.lock().unwrap(), evicted);
+        assert_eq!(*second.lock().unwrap(), evicted);
+
+        multiverse.clear_callbacks();
+        assert!(multiverse.prune_below(ChainLength(100)) > 0);
+        assert_eq!(first.lock().unwrap().len(), evicted.len());
+        assert_eq!(second.lock().unwrap().len(), evicted.len());
+    }
+
+    #[test]
+    pub fn range_over_forks() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+        assert_eq!(multiverse.latest_chain_length(), None);
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+
+        // main chain up to length 5, a fork from length 2 up to 4 and a
+        // fork from length 3 up to 6, each fork skipping some slots so
+        // that its blocks differ from the main chain
+        let forks = [(0, 5, 0), (2, 4, 1), (3, 6, 2)];
+        let mut chains: Vec<Vec<(Block, Ledger)>> = vec![];
+        for (fork_point, length, skip) in forks.iter() {
+            let mut chain: Vec<(Block, Ledger)> = match chains.first() {
+                None => vec![(genesis_block.clone(), genesis_state.clone())],
+                Some(main) => main[..=*fork_point].to_vec(),
+            };
+            let mut date = chain.last().unwrap().0.date();
+            for _ in 0..*skip {
+                date = date.next(&era);
+            }
+            while chain.len() <= *length {
+                date = date.next(&era);
+                let (parent, state) = chain.last().unwrap();
+                let (block, state) = make_next_block(&leader_key, parent, state, date);
+                roots.push(multiverse.add(block.id(), state.clone()).unwrap());
+                chain.push((block, state));
+            }
+            chains.push(chain);
+        }
+        assert_eq!(multiverse.latest_chain_length(), Some(ChainLength(6)));
+
+        let range: Vec<_> = multiverse
+            .range(ChainLength(3), ChainLength(4))
+            .map(|(chain_length, id, state)| {
+                assert_eq!(state.chain_length(), chain_length);
+                (chain_length, id.clone())
+            })
+            .collect();
+        let mut expected = vec![
+            (ChainLength(3), chains[0][3].0.id()),
+            (ChainLength(3), chains[1][3].0.id()),
+            (ChainLength(4), chains[0][4].0.id()),
+            (ChainLength(4), chains[1][4].0.id()),
+            (ChainLength(4), chains[2][4].0.id()),
+        ];
+        expected.sort();
+        assert_eq!(range, expected);
+
+        // inverted bounds give nothing, equal bounds give a single length
+        assert_eq!(multiverse.range(ChainLength(4), ChainLength(3)).count(), 0);
+        assert_eq!(multiverse.range(ChainLength(6), ChainLength(6)).count(), 1);
+
+        // pruned lengths are not reported
+        drop(roots);
+        multiverse.prune_below(ChainLength(4));
+        let lengths: Vec<_> = multiverse
+            .range(ChainLength(0), ChainLength(10))
+            .map(|(chain_length, _, _)| chain_length)
+            .collect();
+        assert_eq!(
+            lengths,
+            vec![
+                ChainLength(4),
+                ChainLength(4),
+                ChainLength(4),
+                ChainLength(5),
+                ChainLength(5),
+                ChainLength(6)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn collected_ancestor_leaves_orphans() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let genesis_root = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        let date = genesis_block.date().next(&era);
+        let (ancestor, ancestor_state) =
+            make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+        let ancestor_root = multiverse
+            .add_with_parent(ancestor.id(), genesis_block.id(), ancestor_state.clone())
+            .unwrap();
+
+        // two forks from the common ancestor, one skipping a slot
+        let mut tips = vec![];
+        let mut fork_roots = vec![];
+        for skip in 0..2 {
+            let mut date = ancestor.date().next(&era);
+            for _ in 0..skip {
+                date = date.next(&era);
+            }
+            let (block, state) = make_next_block(&leader_key, &ancestor, &ancestor_state, date);
+            fork_roots.push(
+                multiverse
+                    .add_with_parent(block.id(), ancestor.id(), state)
+                    .unwrap(),
+            );
+            tips.push(block.id());
+        }
+        tips.sort();
+
+        assert!(multiverse.orphans().is_empty());
+        assert_eq!(
+            multiverse.ancestry(&tips[0]),
+            vec![ancestor.id(), genesis_block.id()]
+        );
+
+        // the pinned forks keep their common ancestor
+        drop(ancestor_root);
+        assert_eq!(multiverse.prune_below(ChainLength(2)), 0);
+        assert!(multiverse.get(&ancestor.id()).is_some());
+
+        // the genesis is pinned, only the ancestor can go
+        drop(fork_roots);
+        assert_eq!(multiverse.prune_below(ChainLength(2)), 1);
+        assert_eq!(multiverse.orphans(), tips);
+        assert!(multiverse.ancestry(&tips[1]).is_empty());
+        assert!(!multiverse.parents.contains_key(&ancestor.id()));
+
+        drop(genesis_root);
+        assert_eq!(multiverse.prune_below(ChainLength(3)), 3);
+        assert!(multiverse.orphans().is_empty());
+        assert!(multiverse.parents.is_empty());
+    }
+
+    #[test]
+    pub fn ancestry_stops_on_cycles() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let date = genesis_block.date().next(&era);
+        let (block, state) = make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+
+        // a state recorded as its own parent
+        let _genesis_root = multiverse
+            .add_with_parent(genesis_block.id(), genesis_block.id(), genesis_state)
+            .unwrap();
+        assert!(multiverse.ancestry(&genesis_block.id()).is_empty());
+
+        // two states recorded as the parent of each other
+        let _root = multiverse
+            .add_with_parent(block.id(), genesis_block.id(), state)
+            .unwrap();
+        multiverse.parents.insert(genesis_block.id(), block.id());
+        assert_eq!(multiverse.ancestry(&block.id()), vec![genesis_block.id()]);
+        assert_eq!(multiverse.ancestry(&genesis_block.id()), vec![block.id()]);
+    }
+
+    #[test]
+    pub fn closest_state_skips_the_collected_lengths() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let mut date = genesis_block.date();
+        multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        let mut state = genesis_state;
+        let mut parent = genesis_block;
+        for _ in 1..300 {
+            date = date.next(&era);
+            let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+            state = next_state;
+            multiverse.add(block.id(), state.clone()).unwrap();
+            parent = block;
+        }
+        multiverse.gc();
+
+        let kept: Vec<u32> = multiverse
+            .states_by_chain_length
+            .keys()
+            .map(|chain_length| chain_length.0)
+            .collect();
+        assert!(kept.len() < 300);
+        assert!(kept.windows(2).any(|pair| pair[1] - pair[0] > 1));
+
+        for length in 0..320 {
+            let expected = kept.iter().rev().find(|kept| **kept <= length).unwrap();
+            let (chain_length, k, found) = multiverse
+                .closest_state_at_or_below(ChainLength(length))
+                .unwrap();
+            assert_eq!(chain_length, ChainLength(*expected));
+            assert_eq!(found.chain_length(), chain_length);
+            assert!(multiverse.get(k).is_some());
+        }
+        assert!(Multiverse::<Ledger>::new()
+            .closest_state_at_or_below(ChainLength(10))
+            .is_none());
+    }
+
+    #[test]
+    pub fn closest_state_prefers_the_longest_chain() {
+        let mut with_parents = Multiverse::new();
+        let mut without_parents = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let date = genesis_block.date().next(&era);
+        let (first, first_state) =
+            make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+
+        // two blocks of length 2, then the chain only goes on from the
+        // one with the highest id
+        let mut forks: Vec<(Block, Ledger)> = (0..2)
+            .map(|skip| {
+                let mut date = first.date().next(&era);
+                for _ in 0..skip {
+                    date = date.next(&era);
+                }
+                make_next_block(&leader_key, &first, &first_state, date)
+            })
+            .collect();
+        forks.sort_by_key(|(block, _)| block.id());
+        let (tip, tip_state) = {
+            let (block, state) = &forks[1];
+            let date = block.date().next(&era).next(&era);
+            make_next_block(&leader_key, block, state, date)
+        };
+
+        let mut roots = vec![];
+        let mut blocks = vec![
+            (first.clone(), genesis_block.id(), first_state),
+            (tip.clone(), forks[1].0.id(), tip_state),
+        ];
+        for (block, state) in forks.iter() {
+            blocks.push((block.clone(), first.id(), state.clone()));
+        }
+        for (block, parent, state) in blocks {
+            roots.push(
+                with_parents
+                    .add_with_parent(block.id(), parent, state.clone())
+                    .unwrap(),
+            );
+            roots.push(without_parents.add(block.id(), state).unwrap());
+        }
+
+        let closest = |multiverse: &Multiverse<Ledger>, length| {
+            let (chain_length, k, _) = multiverse
+                .closest_state_at_or_below(ChainLength(length))
+                .unwrap();
+            (chain_length, k.clone())
+        };
+        assert_eq!(closest(&with_parents, 2), (ChainLength(2), forks[1].0.id()));
+        assert_eq!(
+            closest(&without_parents, 2),
+            (ChainLength(2), forks[0].0.id())
+        );
+        assert_eq!(closest(&with_parents, 7), (ChainLength(3), tip.id()));
+        assert_eq!(closest(&without_parents, 1), (ChainLength(1), first.id()));
+
+        // a cycle of recorded parents ends the walk, the lowest id is taken
+        with_parents.parents.insert(tip.id(), tip.id());
+        assert_eq!(closest(&with_parents, 2), (ChainLength(2), forks[0].0.id()));
+    }
+
+    #[test]
+    pub fn pinned_chain_survives_a_fork_switch() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let mut date = genesis_block.date();
+        let genesis_root = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+
+        // the first fork, followed up to length 120
+        let mut fork_a = vec![(genesis_block, genesis_state)];
+        for _ in 1..=120 {
+            date = date.next(&era);
+            let (parent, parent_state) = fork_a.last().unwrap();
+            let (block, state) = make_next_block(&leader_key, parent, parent_state, date);
+            multiverse
+                .add_with_parent(block.id(), parent.id(), state.clone())
+                .unwrap();
+            fork_a.push((block, state));
+        }
+
+        // a longer fork branching at length 10, which becomes the tip
+        let mut fork_b = vec![fork_a[10].clone()];
+        for _ in 11..=130 {
+            date = date.next(&era);
+            let (parent, parent_state) = fork_b.last().unwrap();
+            let (block, state) = make_next_block(&leader_key, parent, parent_state, date);
+            multiverse
+                .add_with_parent(block.id(), parent.id(), state.clone())
+                .unwrap();
+            fork_b.push((block, state));
+        }
+        let tip = fork_b.last().unwrap().0.id();
+
+        drop(genesis_root);
+        let pinned = multiverse.pin_chain(&tip, 130);
+        assert_eq!(pinned.roots.len(), 131);
+        assert!(pinned.missing.is_empty());
+        assert_eq!(*pinned.roots[0], tip);
+        assert_eq!(*pinned.roots[130], fork_a[0].0.id());
+
+        let collected = multiverse.gc();
+        assert!(collected.deleted > 0);
+        // the whole new chain is kept, down to the genesis
+        for (block, _) in fork_a[..=10].iter().chain(fork_b.iter()) {
+            assert!(multiverse.get(&block.id()).is_some());
+        }
+        // the abandoned fork is collected, but for the few lengths gc
+        // keeps on every fork
+        let kept_from_a = fork_a[11..]
+            .iter()
+            .filter(|(block, _)| block.chain_length() + SUFFIX_TO_KEEP < ChainLength(130))
+            .filter(|(block, _)| multiverse.get(&block.id()).is_some())
+            .count();
+        assert!(kept_from_a < 10);
+
+        // dropping the pins releases the chain
+        drop(pinned);
+        assert_eq!(multiverse.stats().pinned_roots, 0);
+    }
+
+    #[test]
+    pub fn pin_chain_reports_missing_states() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let date = genesis_block.date().next(&era);
+        let (block, state) = make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+        // the parent state was never added
+        let _root = multiverse
+            .add_with_parent(block.id(), genesis_block.id(), state)
+            .unwrap();
+
+        let pinned = multiverse.pin_chain(&block.id(), 5);
+        assert_eq!(pinned.roots.len(), 1);
+        assert_eq!(pinned.missing, vec![genesis_block.id()]);
+
+        let pinned = multiverse.pin_chain(&block.id(), 0);
+        assert_eq!(pinned.roots.len(), 1);
+        assert!(pinned.missing.is_empty());
+    }
+
+    #[test]
+    pub fn poisoned_roots_are_recovered() {
+        let mut multiverse = Multiverse::new();
+        let leader_key = leader_key();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let genesis_root = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+
+        let roots = multiverse.roots.clone();
+        let result = std::panic::catch_unwind(move || {
+            let _roots = roots.write().unwrap();
+            panic!("poisoning the roots");
+        });
+        assert!(result.is_err());
+        assert!(multiverse.roots.is_poisoned());
+
+        let date = genesis_block.date().next(&make_era());
+        let (block, state) = make_next_block(&leader_key, &genesis_block, &genesis_state, date);
+        let root = multiverse.add(block.id(), state).unwrap();
+        multiverse.gc();
+        assert_eq!(multiverse.nr_states(), 2);
+
+        drop(genesis_root);
+        drop(root);
+        assert_eq!(multiverse.prune_below(ChainLength(2)), 2);
+        assert_eq!(multiverse.nr_states(), 0);
+    }
+
+    #[test]
+    pub fn unrecorded_root_drops_are_reported() {
+        let mut multiverse = Multiverse::new();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key());
+        let root = multiverse.add(genesis_block.id(), genesis_state).unwrap();
+        assert_eq!(multiverse.check_roots(), Ok(()));
+
+        Roots::write(&multiverse.roots).roots.clear();
+        drop(root);
+        assert_eq!(
+            multiverse.check_roots(),
+            Err(MultiverseError::UnrecordedRoot {
+                hash: genesis_block.id()
+            })
+        );
+    }
+
+    #[test]
+    pub fn stats_follow_adds_and_collections() {
+        let mut multiverse = Multiverse::new();
+        assert_eq!(
+            multiverse.stats(),
+            MultiverseStats {
+                states: 0,
+                chain_lengths: 0,
+                pinned_roots: 0,
+                chain_length_span: None,
+                estimated_bytes: 0,
+            }
+        );
+
+        let era = make_era();
+        let leader_key = leader_key();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let _genesis_root = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+        let mut previous = multiverse.stats();
+        assert_eq!(previous.states, 1);
+        assert_eq!(previous.pinned_roots, 1);
+        assert_eq!(
+            previous.chain_length_span,
+            Some((ChainLength(0), ChainLength(0)))
+        );
+        assert!(previous.estimated_bytes > 0);
+
+        let mut date = genesis_block.date();
+        let mut state = genesis_state;
+        let mut parent = genesis_block;
+        for i in 1..120 {
+            date = date.next(&era);
+            let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+            state = next_state;
+            // only the genesis state stays pinned
+            multiverse.add(block.id(), state.clone()).unwrap();
+            parent = block;
+
+            let stats = multiverse.stats();
+            assert_eq!(stats.states, previous.states + 1);
+            assert_eq!(stats.chain_lengths, previous.chain_lengths + 1);
+            assert_eq!(stats.pinned_roots, 1);
+            assert_eq!(
+                stats.chain_length_span,
+                Some((ChainLength(0), ChainLength(i)))
+            );
+            assert!(stats.estimated_bytes > previous.estimated_bytes);
+            previous = stats;
+        }
+
+        let collected = multiverse.gc();
+        let stats = multiverse.stats();
+        assert!(collected.deleted > 0);
+        assert_eq!(stats.states, previous.states - collected.deleted);
+        assert_eq!(
+            stats.estimated_bytes,
+            previous.estimated_bytes - collected.freed_bytes
+        );
+        assert_eq!(stats.pinned_roots, 1);
+        assert_eq!(
+            stats.chain_length_span,
+            Some((ChainLength(0), ChainLength(119)))
+        );
+        assert_eq!(
+            multiverse.sizes.lock().unwrap().len(),
+            multiverse.states_by_hash.len()
+        );
+        assert_eq!(
+            multiverse.gc(),
+            GcStats {
+                deleted: 0,
+                freed_bytes: 0,
+            }
+        );
+    }
+
+    #[test]
+    pub fn gc_does_not_estimate_the_sizes_of_the_deleted_states() {
+        let mut multiverse = Multiverse::new();
+        let era = make_era();
+        let leader_key = leader_key();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+        let _genesis_root = multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap();
+
+        let mut date = genesis_block.date();
+        let mut state = genesis_state;
+        let mut parent = genesis_block;
+        for _ in 1..120 {
+            date = date.next(&era);
+            let (block, next_state) = make_next_block(&leader_key, &parent, &state, date);
+            state = next_state;
+            multiverse.add(block.id(), state.clone()).unwrap();
+            parent = block;
+        }
+
+        let states = multiverse.states_by_hash.len();
+        let collected = multiverse.gc();
+        assert!(collected.deleted > 0);
+        assert_eq!(collected.freed_bytes, 0);
+        assert_eq!(multiverse.states_by_hash.len(), states - collected.deleted);
+        assert!(multiverse.sizes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn fork_stats_count_the_forks_by_height() {
+        let mut multiverse = Multiverse::new();
+        assert_eq!(multiverse.fork_stats(), ForkStats::default());
+
+        let mut main = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = main.genesis();
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        for (block, state) in main.by_ref().take(10) {
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+        assert_eq!(
+            multiverse.fork_stats(),
+            ForkStats {
+                contested_heights: 0,
+                max_states_at_height: 1,
+                deepest_fork: 0,
+                tip_branches: 1,
+            }
+        );
+
+        // a fork of the last 3 blocks and one of the last 6, contesting
+        // the chain lengths 5 to 10: switching forks reverts 6 blocks
+        let forks = vec![(main.fork_at(7), 3), (main.fork_at(4), 6)];
+        for (fork, length) in forks {
+            for (block, state) in fork.take(length) {
+                roots.push(multiverse.add(block.id(), state).unwrap());
+            }
+        }
+        assert_eq!(
+            multiverse.fork_stats(),
+            ForkStats {
+                contested_heights: 6,
+                max_states_at_height: 3,
+                deepest_fork: 6,
+                tip_branches: 3,
+            }
+        );
+    }
+
+    #[test]
+    pub fn get_cached_forgets_the_collected_states() {
+        let mut multiverse = Multiverse::new();
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut ids = vec![genesis_block.id()];
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        for (block, state) in chain.by_ref().take(50) {
+            ids.push(block.id());
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+
+        // cache old states that gc is about to delete
+        for id in &ids[1..5] {
+            assert!(multiverse.get_cached(id).is_some());
+        }
+        roots.truncate(1);
+        multiverse.gc();
+        assert!(ids[1..5].iter().any(|id| !multiverse.contains(id)));
+        for id in &ids {
+            let cached = multiverse
+                .get_cached(id)
+                .map(|state| state as *const Ledger);
+            let expected = multiverse.get(id).map(|state| state as *const Ledger);
+            assert_eq!(cached, expected);
+        }
+        assert!(multiverse
+            .recent
+            .iter()
+            .all(|(id, _)| multiverse.contains(id)));
+    }
+
+    // operations on the states of a short chain, referred to by height
+    #[derive(Clone, Debug)]
+    enum CacheOp {
+        Add(u8),
+        Unpin(u8),
+        Get(u8),
+        Gc,
+    }
+
+    const CACHE_OP_HEIGHTS: u8 = 12;
+
+    impl Arbitrary for CacheOp {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let height = u8::arbitrary(g) % CACHE_OP_HEIGHTS;
+            match u8::arbitrary(g) % 8 {
+                0 | 1 => CacheOp::Add(height),
+                2 => CacheOp::Unpin(height),
+                3 => CacheOp::Gc,
+                _ => CacheOp::Get(height),
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn get_cached_agrees_with_get(ops: Vec<CacheOp>) -> bool {
+        let mut chain = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = chain.genesis();
+        let mut states = vec![(genesis_block.id(), genesis_state.clone())];
+        let blocks = chain.take(CACHE_OP_HEIGHTS as usize - 1);
+        states.extend(blocks.map(|(block, state)| (block.id(), state)));
+
+        let mut multiverse = Multiverse::new();
+        let mut roots: Vec<_> = states.iter().map(|_| None).collect();
+        for op in ops {
+            match op {
+                CacheOp::Add(height) => {
+                    let (id, state) = &states[height as usize];
+                    roots[height as usize] =
+                        Some(multiverse.add(id.clone(), state.clone()).unwrap());
+                }
+                CacheOp::Unpin(height) => roots[height as usize] = None,
+                CacheOp::Gc => {
+                    multiverse.gc();
+                }
+                CacheOp::Get(height) => {
+                    let id = &states[height as usize].0;
+                    let cached = multiverse
+                        .get_cached(id)
+                        .map(|state| state as *const Ledger);
+                    let expected = multiverse.get(id).map(|state| state as *const Ledger);
+                    if cached != expected {
+                        return false;
+                    }
+                }
+            }
+        }
+        multiverse.recent.len() <= super::RECENT_STATES
+            && multiverse
+                .recent
+                .iter()
+                .all(|(id, _)| multiverse.contains(id))
+    }
+
+    #[test]
+    pub fn compare_summaries_of_forks() {
+        use crate::testing::data::AddressData;
+        use crate::transaction::TransactionIndex;
+        use crate::value::Value;
+
+        let mut multiverse = Multiverse::new();
+        let mut main = ChainBuilder::bft(vec![leader_key()], NUM_BLOCK_PER_EPOCH);
+        let (genesis_block, genesis_state) = main.genesis();
+        let mut main_ids = vec![genesis_block.id()];
+        let mut roots = vec![multiverse
+            .add(genesis_block.id(), genesis_state.clone())
+            .unwrap()];
+        for (block, state) in main.by_ref().take(5) {
+            main_ids.push(block.id());
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+        // a fork of the last 2 blocks, whose tip got an output and some
+        // treasury the main chain does not have
+        let mut fork_ids = Vec::new();
+        for (block, mut state) in main.fork_at(3).take(2) {
+            if state.chain_length().0 == 5 {
+                let output = AddressData::utxo(Discrimination::Test).make_output(Value(1));
+                state.utxos = state
+                    .utxos
+                    .add(
+                        &Hash::hash_bytes(b"fork"),
+                        &[(TransactionIndex::new(0).unwrap(), output)],
+                    )
+                    .unwrap();
+                state.pots.treasury = Value(5);
+            }
+            fork_ids.push(block.id());
+            roots.push(multiverse.add(block.id(), state).unwrap());
+        }
+
+        let compare = |a: &BlockId, b: &BlockId| multiverse.compare(a, b).unwrap();
+        assert_eq!(
+            compare(&main_ids[5], &main_ids[5]),
+            StateComparison {
+                same_chain_length: true,
+                utxo_digest_equal: true,
+                pots_equal: true,
+                chain_length_delta: 0,
+            }
+        );
+        assert_eq!(
+            compare(&main_ids[3], &main_ids[4]),
+            StateComparison {
+                same_chain_length: false,
+                utxo_digest_equal: true,
+                pots_equal: true,
+                chain_length_delta: 1,
+            }
+        );
+        assert_eq!(
+            compare(&main_ids[5], &fork_ids[1]),
+            StateComparison {
+                same_chain_length: true,
+                utxo_digest_equal: false,
+                pots_equal: false,
+                chain_length_delta: 0,
+            }
+        );
+        assert_eq!(
+            compare(&fork_ids[1], &main_ids[4]),
+            StateComparison {
+                same_chain_length: false,
+                utxo_digest_equal: false,
+                pots_equal: false,
+                chain_length_delta: -1,
+            }
+        );
+        assert_eq!(multiverse.compare(&main_ids[5], &Hash::zero()), None);
+        assert_eq!(multiverse.summaries.lock().unwrap().len(), 4);
+
+        drop(roots);
+        assert_eq!(multiverse.prune_below(ChainLength(5)), 6);
+        assert_eq!(multiverse.summaries.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    pub fn shared_multiverse_readers_and_collector() {
+        const READERS: usize = 4;
+        let era = make_era();
+        let leader_key = leader_key();
+        let (genesis_block, genesis_state) = make_genesis(&leader_key);
+
+        let mut chain = vec![(genesis_block.id(), genesis_state.clone())];
+        let mut date = genesis_block.date();
+        let mut parent = genesis_block;
+        for _ in 1..300 {
+            date = date.next(&era);
+            let (block, state) =
+                make_next_block(&leader_key, &parent, &chain.last().unwrap().1, date);
+            chain.push((block.id(), state));
+            parent = block;
+        }
+        let ids: Arc<Vec<BlockId>> = Arc::new(chain.iter().map(|(id, _)| id.clone()).collect());
+
+        let shared = Arc::new(SharedMultiverse::new());
+        let mut reference = Multiverse::new();
+        let genesis_root = Arc::new(shared.add(ids[0].clone(), genesis_state.clone()).unwrap());
+        let _reference_root = reference.add(ids[0].clone(), genesis_state).unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let (finished_tx, finished_rx) = mpsc::channel();
+        let mut handles = vec![];
+        for reader in 0..READERS {
+            let shared = shared.clone();
+            let ids = ids.clone();
+            let genesis_root = genesis_root.clone();
+            let done = done.clone();
+            let finished_tx = finished_tx.clone();
+            handles.push(thread::spawn(move || {
+                let mut reads = 0;
+                let mut latest = ChainLength(0);
+                let mut i = reader;
+                while !done.load(Ordering::SeqCst) {
+                    // the pinned genesis state is never collected
+                    let genesis = shared.get_from_root(&genesis_root);
+                    assert_eq!(genesis.chain_length(), ChainLength(0));
+                    assert!(shared.get(&ids[0]).is_some());
+
+                    // a state that is found is the one of its block
+                    i = (i + 7) % ids.len();
+                    if let Some(state) = shared.get(&ids[i]) {
+                        assert_eq!(state.chain_length(), ChainLength(i as u32));
+                    }
+
+                    // the longest chain is always kept
+                    let current = shared.latest_chain_length().unwrap();
+                    assert!(current >= latest);
+                    latest = current;
+
+                    let stats = shared.stats();
+                    assert!(stats.states >= 1);
+                    assert!(stats.pinned_roots >= 1);
+                    reads += 1;
+                }
+                finished_tx.send(()).unwrap();
+                reads
+            }));
+        }
+
+        let writer = {
+            let shared = shared.clone();
+            let finished_tx = finished_tx.clone();
+            let chain = chain.clone();
+            thread::spawn(move || {
+                let mut collected = 0;
+                for (id, state) in chain.into_iter().skip(1) {
+                    // only the genesis state stays pinned
+                    shared.add(id, state).unwrap();
+                    collected += shared.gc().deleted;
+                }
+                finished_tx.send(()).unwrap();
+                collected
+            })
+        };
+        for (id, state) in chain.into_iter().skip(1) {
+            reference.add(id, state).unwrap();
+            reference.gc();
+        }
+
+        finished_rx
+            .recv_timeout(Duration::from_secs(120))
+            .expect("the collector is stuck");
+        let collected = writer.join().unwrap();
+        done.store(true, Ordering::SeqCst);
+        for _ in 0..READERS {
+            finished_rx
+                .recv_timeout(Duration::from_secs(120))
+                .expect("a reader is stuck");
+        }
+        for handle in handles {
+            assert!(handle.join().unwrap() > 0);
+        }
+
+        drop(genesis_root);
+        let shared = Arc::try_unwrap(shared).ok().unwrap().into_inner();
+        let kept = |multiverse: &Multiverse<Ledger>| -> HashSet<BlockId> {
+            multiverse.states_by_hash.keys().cloned().collect()
+        };
+        assert!(collected > 0);
+        assert_eq!(collected + shared.nr_states(), 300);
+        assert_eq!(kept(&shared), kept(&reference));
+    }
 }
@@ -9,6 +9,7 @@ use crate::{
     block::ConsensusVersion,
     config::ConfigParam,
     fee::LinearFee,
+    key::HashScheme,
     leadership::{bft, genesis},
 };
 use std::convert::TryFrom;
@@ -31,6 +32,8 @@ pub struct Settings {
     /// it expires at the start of epoch 'epoch_p +
     /// proposal_expiration + 1'. FIXME: make updateable.
     pub proposal_expiration: u32,
+    /// How the ids of the fragments and blocks of the chain are computed
+    pub hash_scheme: HashScheme,
 }
 
 pub const SLOTS_PERCENTAGE_RANGE: u8 = 100;
@@ -49,6 +52,7 @@ impl Settings {
             bft_leaders: Arc::new(Vec::new()),
             linear_fees: Arc::new(LinearFee::new(0, 0, 0)),
             proposal_expiration: 100,
+            hash_scheme: HashScheme::Legacy,
         }
     }
 
@@ -112,12 +116,18 @@ impl Settings {
                 ConfigParam::ProposalExpiration(d) => {
                     new_state.proposal_expiration = *d;
                 }
+                ConfigParam::HashScheme(d) => {
+                    new_state.hash_scheme = *d;
+                }
             }
         }
 
         Ok(new_state)
     }
 
+    /// The parameters that rebuild these settings when applied to
+    /// `Settings::new()`, all but the consensus nonce which is not a
+    /// parameter and has to be kept next to them.
     pub fn to_config_params(&self) -> ConfigParams {
         let mut params = ConfigParams::new();
 
@@ -137,8 +147,15 @@ impl Settings {
         }
         params.push(ConfigParam::LinearFee(*self.linear_fees));
         params.push(ConfigParam::ProposalExpiration(self.proposal_expiration));
+        params.push(ConfigParam::HashScheme(self.hash_scheme));
 
-        debug_assert_eq!(self, &Settings::new().apply(&params).unwrap());
+        debug_assert_eq!(
+            self,
+            &Settings {
+                consensus_nonce: self.consensus_nonce.clone(),
+                ..Settings::new().apply(&params).unwrap()
+            }
+        );
 
         params
     }
@@ -1,7 +1,7 @@
 use crate::account;
 use crate::certificate::PoolId;
 use crate::{utxo, value::Value};
-use chain_addr::{Address, Kind};
+use chain_addr::Address;
 use std::collections::HashMap;
 
 use super::delegation::DelegationState;
@@ -98,29 +98,25 @@ pub fn get_distribution(
     for output in utxos.values() {
         // We're only interested in "group" addresses
         // (i.e. containing a spending key and a stake key).
-        match output.address.kind() {
-            Kind::Account(_) | Kind::Multisig(_) => {
-                // single or multisig account are not present in utxos
-                panic!("internal error: accounts in utxo")
-            }
-            Kind::Group(_spending_key, account_key) => {
-                // is there an account linked to this
-                match accounts.get_state(&account_key.clone().into()) {
-                    Err(_) => panic!("internal error: group's account should always be created"),
-                    Ok(st) => {
-                        // Is this stake key a member of a stake pool?
-                        if let Some(pool_id) = &st.delegation() {
-                            dist.get_mut(pool_id).map_or_else(
-                                || dangling = (dangling + output.value).unwrap(),
-                                |v| distribution_add(v, output.value),
-                            );
-                        }
+        if let Some((_spending_key, account_key)) = output.address.group_keys() {
+            // is there an account linked to this
+            match accounts.get_state(&account_key.clone().into()) {
+                Err(_) => panic!("internal error: group's account should always be created"),
+                Ok(st) => {
+                    // Is this stake key a member of a stake pool?
+                    if let Some(pool_id) = &st.delegation() {
+                        dist.get_mut(pool_id).map_or_else(
+                            || dangling = (dangling + output.value).unwrap(),
+                            |v| distribution_add(v, output.value),
+                        );
                     }
                 }
             }
-            Kind::Single(_) => {
-                unassigned = (unassigned + output.value).unwrap();
-            }
+        } else if output.address.single_spending_key().is_some() {
+            unassigned = (unassigned + output.value).unwrap();
+        } else {
+            // single or multisig account are not present in utxos
+            panic!("internal error: accounts in utxo")
         }
     }
 
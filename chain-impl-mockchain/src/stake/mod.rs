@@ -1,5 +1,7 @@
 mod delegation;
 mod distribution;
+mod tracker;
 
 pub use delegation::*;
 pub use distribution::*;
+pub use tracker::*;
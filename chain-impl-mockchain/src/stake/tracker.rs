@@ -0,0 +1,388 @@
+use crate::account;
+use crate::certificate::PoolId;
+use crate::utxo;
+use crate::value::Value;
+use chain_addr::Address;
+use imhamt::{Hamt, InsertOrUpdateError, UpdateError};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+
+use super::delegation::DelegationState;
+use super::distribution::{PoolStakeDistribution, StakeDistribution};
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub StakeError
+        Overflow = "The total amount of stake overflows",
+        NegativeStake = "The amount of stake cannot be negative",
+        UnknownDelegation { pool_id: PoolId } = "Removing stake from a delegation without stake",
+        UnknownGroupAccount { account: account::Identifier } = "Spending from group utxos of {account}, which has none",
+}
+
+/// Running totals of the stake, maintained by the ledger as accounts
+/// and utxos are credited, debited or change delegation.
+///
+/// The delegated stake is kept per delegation target whether the pool is
+/// registered or not; the split between pool stake and dangling stake is
+/// only done when taking the snapshot, so registering or retiring a pool
+/// does not need to touch the totals.
+///
+/// The totals follow the same rules as `get_distribution`:
+///
+/// * single utxos and undelegated accounts are unassigned;
+/// * group utxos count towards the pool of their account, or towards
+///   nothing if that account is not delegated;
+/// * multisig accounts are not counted.
+#[derive(Clone, PartialEq, Eq)]
+pub struct StakeTracker {
+    unassigned: Value,
+    delegated: HashMap<PoolId, Value>,
+    // value of the group utxos attached to each account
+    group_values: Hamt<DefaultHasher, account::Identifier, Value>,
+}
+
+impl StakeTracker {
+    pub fn new() -> Self {
+        StakeTracker {
+            unassigned: Value::zero(),
+            delegated: HashMap::new(),
+            group_values: Hamt::new(),
+        }
+    }
+
+    /// rebuild the totals from the accounts and utxos of a ledger
+    pub fn from_state(
+        accounts: &account::Ledger,
+        utxos: &utxo::Ledger<Address>,
+    ) -> Result<Self, StakeError> {
+        let mut tracker = StakeTracker::new();
+        for (_, account_state) in accounts.iter() {
+            tracker
+                .add_account_value(account_state.delegation().as_ref(), account_state.value())?;
+        }
+        for output in utxos.values() {
+            if let Some((_, group_key)) = output.address.group_keys() {
+                let account_id = group_key.clone().into();
+                let delegation = accounts
+                    .get_state(&account_id)
+                    .ok()
+                    .and_then(|state| state.delegation().clone());
+                tracker.add_group_utxo(&account_id, delegation.as_ref(), output.value)?;
+            } else {
+                tracker.add_single_utxo(output.value)?;
+            }
+        }
+        Ok(tracker)
+    }
+
+    fn add_delegated(&mut self, pool_id: &PoolId, value: Value) -> Result<(), StakeError> {
+        let total = self
+            .delegated
+            .get(pool_id)
+            .cloned()
+            .unwrap_or_else(Value::zero);
+        let total = (total + value).map_err(|_| StakeError::Overflow)?;
+        self.delegated.insert(pool_id.clone(), total);
+        Ok(())
+    }
+
+    fn remove_delegated(&mut self, pool_id: &PoolId, value: Value) -> Result<(), StakeError> {
+        if value == Value::zero() {
+            return Ok(());
+        }
+        let total = self
+            .delegated
+            .get(pool_id)
+            .ok_or_else(|| StakeError::UnknownDelegation {
+                pool_id: pool_id.clone(),
+            })?;
+        let total = (*total - value).map_err(|_| StakeError::NegativeStake)?;
+        if total == Value::zero() {
+            self.delegated.remove(pool_id);
+        } else {
+            self.delegated.insert(pool_id.clone(), total);
+        }
+        Ok(())
+    }
+
+    /// value credited to an account with the given delegation
+    pub fn add_account_value(
+        &mut self,
+        delegation: Option<&PoolId>,
+        value: Value,
+    ) -> Result<(), StakeError> {
+        match delegation {
+            None => {
+                self.unassigned = (self.unassigned + value).map_err(|_| StakeError::Overflow)?;
+                Ok(())
+            }
+            Some(pool_id) => self.add_delegated(pool_id, value),
+        }
+    }
+
+    /// value debited from an account with the given delegation
+    pub fn remove_account_value(
+        &mut self,
+        delegation: Option<&PoolId>,
+        value: Value,
+    ) -> Result<(), StakeError> {
+        match delegation {
+            None => {
+                self.unassigned =
+                    (self.unassigned - value).map_err(|_| StakeError::NegativeStake)?;
+                Ok(())
+            }
+            Some(pool_id) => self.remove_delegated(pool_id, value),
+        }
+    }
+
+    /// a utxo was added to a single address
+    pub fn add_single_utxo(&mut self, value: Value) -> Result<(), StakeError> {
+        self.add_account_value(None, value)
+    }
+
+    /// a utxo was spent from a single address
+    pub fn remove_single_utxo(&mut self, value: Value) -> Result<(), StakeError> {
+        self.remove_account_value(None, value)
+    }
+
+    /// a utxo was added to a group address whose account has the given delegation
+    pub fn add_group_utxo(
+        &mut self,
+        account: &account::Identifier,
+        delegation: Option<&PoolId>,
+        value: Value,
+    ) -> Result<(), StakeError> {
+        let add = |total: &Value| (*total + value).map(Some);
+        let group_values = match self
+            .group_values
+            .insert_or_update(account.clone(), value, add)
+        {
+            Ok(group_values) => group_values,
+            Err(InsertOrUpdateError::Insert(_)) => unreachable!(),
+            Err(InsertOrUpdateError::Update(_)) => return Err(StakeError::Overflow),
+        };
+        if let Some(pool_id) = delegation {
+            self.add_delegated(pool_id, value)?;
+        }
+        self.group_values = group_values;
+        Ok(())
+    }
+
+    /// a utxo was spent from a group address whose account has the given delegation
+    pub fn remove_group_utxo(
+        &mut self,
+        account: &account::Identifier,
+        delegation: Option<&PoolId>,
+        value: Value,
+    ) -> Result<(), StakeError> {
+        let remove = |total: &Value| {
+            (*total - value).map(|left| {
+                if left == Value::zero() {
+                    None
+                } else {
+                    Some(left)
+                }
+            })
+        };
+        let group_values = match self.group_values.update(account, remove) {
+            Ok(group_values) => group_values,
+            Err(UpdateError::KeyNotFound) => {
+                return Err(StakeError::UnknownGroupAccount {
+                    account: account.clone(),
+                })
+            }
+            Err(UpdateError::ValueCallbackError(_)) => return Err(StakeError::NegativeStake),
+        };
+        if let Some(pool_id) = delegation {
+            self.remove_delegated(pool_id, value)?;
+        }
+        self.group_values = group_values;
+        Ok(())
+    }
+
+    /// the account holding `account_value` changed its delegation from `from` to `to`,
+    /// moving its own value and the value of its group utxos
+    pub fn change_delegation(
+        &mut self,
+        account: &account::Identifier,
+        account_value: Value,
+        from: Option<&PoolId>,
+        to: Option<&PoolId>,
+    ) -> Result<(), StakeError> {
+        self.remove_account_value(from, account_value)?;
+        self.add_account_value(to, account_value)?;
+
+        let group_value = self
+            .group_values
+            .lookup(account)
+            .cloned()
+            .unwrap_or_else(Value::zero);
+        if let Some(pool_id) = from {
+            self.remove_delegated(pool_id, group_value)?;
+        }
+        if let Some(pool_id) = to {
+            self.add_delegated(pool_id, group_value)?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot of the stake distribution for the pools registered in `dstate`
+    ///
+    /// This is proportional to the number of registered pools and of
+    /// delegation targets, not to the number of accounts and utxos.
+    pub fn distribution(&self, dstate: &DelegationState) -> StakeDistribution {
+        let to_pools = dstate
+            .stake_pools
+            .iter()
+            .map(|(id, _)| {
+                let total_stake = self.delegated.get(id).cloned().unwrap_or_else(Value::zero);
+                (id.clone(), PoolStakeDistribution { total_stake })
+            })
+            .collect();
+        let dangling = self
+            .delegated
+            .iter()
+            .filter(|(id, _)| !dstate.stake_pool_exists(id))
+            .fold(Value::zero(), |sum, (_, value)| (sum + *value).unwrap());
+
+        StakeDistribution {
+            unassigned: self.unassigned,
+            dangling,
+            to_pools,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certificate::{PoolRegistration, RewardDestination};
+    use crate::leadership::genesis::GenesisPraosLeader;
+    use chain_crypto::{testing::TestCryptoGen, Curve25519_2HashDH, Ed25519, SumEd25519_12};
+    use chain_time::DurationSeconds;
+
+    fn make_pool(key_gen: &TestCryptoGen) -> PoolRegistration {
+        let owner = key_gen.secret_key::<Ed25519>(0).to_public();
+        PoolRegistration {
+            serial: 1,
+            start_validity: DurationSeconds::from(0).into(),
+            management_threshold: 1,
+            owners: vec![owner.clone()],
+            keys: GenesisPraosLeader {
+                vrf_public_key: key_gen.secret_key::<Curve25519_2HashDH>(1).to_public(),
+                kes_public_key: key_gen.secret_key::<SumEd25519_12>(2).to_public(),
+            },
+            reward_destination: RewardDestination::Account(owner.into()),
+        }
+    }
+
+    fn account(key_gen: &TestCryptoGen, idx: u32) -> account::Identifier {
+        key_gen.secret_key::<Ed25519>(idx).to_public().into()
+    }
+
+    #[test]
+    pub fn retired_pool_stake_becomes_dangling() {
+        let key_gen = TestCryptoGen(0);
+        let pool = make_pool(&key_gen);
+        let pool_id = pool.to_id();
+        let dstate = DelegationState::new().register_stake_pool(pool).unwrap();
+
+        let mut tracker = StakeTracker::new();
+        tracker
+            .add_account_value(Some(&pool_id), Value(10))
+            .unwrap();
+        tracker.add_single_utxo(Value(5)).unwrap();
+
+        let distribution = tracker.distribution(&dstate);
+        assert_eq!(distribution.get_stake_for(&pool_id), Some(Value(10)));
+        assert_eq!(distribution.dangling, Value::zero());
+        assert_eq!(distribution.unassigned, Value(5));
+
+        let dstate = dstate.deregister_stake_pool(&pool_id).unwrap();
+        let distribution = tracker.distribution(&dstate);
+        assert_eq!(distribution.get_stake_for(&pool_id), None);
+        assert_eq!(distribution.dangling, Value(10));
+        assert_eq!(distribution.unassigned, Value(5));
+    }
+
+    #[test]
+    pub fn group_utxos_follow_account_delegation() {
+        let key_gen = TestCryptoGen(0);
+        let pool = make_pool(&key_gen);
+        let pool_id = pool.to_id();
+        let dstate = DelegationState::new().register_stake_pool(pool).unwrap();
+        let account = account(&key_gen, 10);
+
+        let mut tracker = StakeTracker::new();
+        // an undelegated account doesn't give any stake to its group utxos
+        tracker.add_group_utxo(&account, None, Value(7)).unwrap();
+        tracker.add_account_value(None, Value(3)).unwrap();
+        let distribution = tracker.distribution(&dstate);
+        assert_eq!(distribution.get_stake_for(&pool_id), Some(Value::zero()));
+        assert_eq!(distribution.unassigned, Value(3));
+
+        tracker
+            .change_delegation(&account, Value(3), None, Some(&pool_id))
+            .unwrap();
+        let distribution = tracker.distribution(&dstate);
+        assert_eq!(distribution.get_stake_for(&pool_id), Some(Value(10)));
+        assert_eq!(distribution.unassigned, Value::zero());
+
+        tracker
+            .remove_group_utxo(&account, Some(&pool_id), Value(7))
+            .unwrap();
+        tracker
+            .change_delegation(&account, Value(3), Some(&pool_id), None)
+            .unwrap();
+        assert!(
+            tracker
+                == StakeTracker {
+                    unassigned: Value(3),
+                    ..StakeTracker::new()
+                }
+        );
+    }
+
+    #[test]
+    pub fn inconsistent_removals_are_errors() {
+        let key_gen = TestCryptoGen(0);
+        let pool_id = make_pool(&key_gen).to_id();
+        let account = account(&key_gen, 10);
+
+        let mut tracker = StakeTracker::new();
+        assert_eq!(
+            tracker.remove_account_value(Some(&pool_id), Value(1)),
+            Err(StakeError::UnknownDelegation {
+                pool_id: pool_id.clone()
+            })
+        );
+        assert_eq!(
+            tracker.remove_single_utxo(Value(1)),
+            Err(StakeError::NegativeStake)
+        );
+        assert_eq!(
+            tracker.remove_group_utxo(&account, None, Value(1)),
+            Err(StakeError::UnknownGroupAccount {
+                account: account.clone()
+            })
+        );
+
+        tracker
+            .add_group_utxo(&account, Some(&pool_id), Value(2))
+            .unwrap();
+        assert_eq!(
+            tracker.remove_group_utxo(&account, Some(&pool_id), Value(3)),
+            Err(StakeError::NegativeStake)
+        );
+        assert_eq!(
+            tracker.add_account_value(Some(&pool_id), Value(u64::max_value())),
+            Err(StakeError::Overflow)
+        );
+        // the failed operations left the totals as they were
+        assert_eq!(
+            tracker.distribution(&DelegationState::new()).dangling,
+            Value(2)
+        );
+    }
+}
@@ -1,11 +1,13 @@
 pub mod address;
 pub mod kind_type;
 pub mod output;
+pub mod pots;
 pub mod transaction;
 pub mod update_proposal;
 pub mod utils;
+pub mod utxo;
 
-use crate::transaction::Output;
+use crate::transaction::{Output, TransactionIndex};
 use crate::value::Value;
 use chain_addr::Address;
 use quickcheck::{Arbitrary, Gen};
@@ -23,6 +25,12 @@ impl Arbitrary for Value {
     }
 }
 
+impl Arbitrary for TransactionIndex {
+    fn arbitrary<G: Gen>(gen: &mut G) -> Self {
+        TransactionIndex::new(u8::arbitrary(gen) % 255).unwrap()
+    }
+}
+
 // Average value used in test where value is larger than zero
 #[derive(Debug, Copy, Clone)]
 pub struct NonZeroValue(pub Value);
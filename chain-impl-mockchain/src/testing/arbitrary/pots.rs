@@ -0,0 +1,17 @@
+use super::AverageValue;
+use crate::ledger::pots::Pots;
+use quickcheck::{Arbitrary, Gen};
+
+/// Pots of values small enough to be summed with the other funds of a
+/// ledger without overflowing. The breakdown of the fees of the open
+/// epoch is not generated.
+impl Arbitrary for Pots {
+    fn arbitrary<G: Gen>(gen: &mut G) -> Self {
+        Pots {
+            fees: AverageValue::arbitrary(gen).into(),
+            treasury: AverageValue::arbitrary(gen).into(),
+            rewards: AverageValue::arbitrary(gen).into(),
+            epoch_fees: None,
+        }
+    }
+}
@@ -132,7 +132,7 @@ impl ArbitraryValidTransactionData {
         self.input_addresses
             .iter()
             .cloned()
-            .map(|x| self.make_single_input(x, &mut ledger.utxos()))
+            .map(|x| self.make_single_input(x, &mut ledger.utxos().iter()))
             .collect()
     }
 
@@ -282,7 +282,7 @@ impl UtxoVerifier {
     pub fn verify(&self, ledger: &Ledger) -> Result<(), Error> {
         let expected_utxo_snapshots = &self.calculate_current_utxo();
         for utxo_snapshot in expected_utxo_snapshots {
-            if !ledger.utxos().any(|x| {
+            if !ledger.utxos().iter().any(|x| {
                 x.output.address.clone() == utxo_snapshot.address_data.address.clone()
                     && x.output.value.0 == utxo_snapshot.value.0
             }) {
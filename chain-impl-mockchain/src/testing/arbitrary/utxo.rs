@@ -0,0 +1,104 @@
+use super::AverageValue;
+use crate::fragment::FragmentId;
+use crate::transaction::{Output, TransactionIndex, UtxoPointer};
+use crate::utxo::Ledger;
+use chain_addr::Address;
+use quickcheck::{Arbitrary, Gen};
+
+/// Maximum number of outputs a single fragment can hold in the UTxO ledger
+const MAX_OUTPUTS_PER_FRAGMENT: usize = 254;
+
+/// Generate a UTxO ledger holding exactly `n_fragments` fragments, each of them
+/// having between 1 and 254 outputs of non zero value.
+pub fn arbitrary_ledger<G: Gen>(gen: &mut G, n_fragments: usize) -> Ledger<Address> {
+    let mut ledger = Ledger::new();
+    let mut added = 0;
+    while added < n_fragments {
+        let fragment_id = FragmentId::arbitrary(gen);
+        let outputs = arbitrary_outputs(gen);
+        // fragment ids are random, a collision only means we have to pick another one
+        if let Ok(next) = ledger.add(&fragment_id, &outputs) {
+            ledger = next;
+            added += 1;
+        }
+    }
+    ledger
+}
+
+fn arbitrary_outputs<G: Gen>(gen: &mut G) -> Vec<(TransactionIndex, Output<Address>)> {
+    let n = usize::arbitrary(gen) % MAX_OUTPUTS_PER_FRAGMENT + 1;
+    TransactionIndex::iter_to(n)
+        .map(|index| {
+            let output = Output {
+                address: Address::arbitrary(gen),
+                value: AverageValue::arbitrary(gen).into(),
+            };
+            (index, output)
+        })
+        .collect()
+}
+
+/// Pick a random unspent output from the ledger, returns `None` if the ledger is empty
+pub fn choose_spendable_input<G: Gen>(
+    gen: &mut G,
+    ledger: &Ledger<Address>,
+) -> Option<UtxoPointer> {
+    let count = ledger.iter().count();
+    if count == 0 {
+        return None;
+    }
+    let index = usize::arbitrary(gen) % count;
+    ledger
+        .iter()
+        .nth(index)
+        .map(|entry| UtxoPointer::from_entry(&entry))
+}
+
+/// The pointers to the unspent outputs of the ledger, grouped by fragment
+fn fragments_of(ledger: &Ledger<Address>) -> Vec<Vec<UtxoPointer>> {
+    let mut fragments: Vec<Vec<UtxoPointer>> = Vec::new();
+    // the iterator yields all the outputs of a fragment one after the other
+    for entry in ledger.iter() {
+        let pointer = UtxoPointer::from_entry(&entry);
+        match fragments.last_mut() {
+            Some(pointers) if pointers[0].transaction_id == pointer.transaction_id => {
+                pointers.push(pointer)
+            }
+            _ => fragments.push(vec![pointer]),
+        }
+    }
+    fragments
+}
+
+impl Arbitrary for Ledger<Address> {
+    fn arbitrary<G: Gen>(gen: &mut G) -> Self {
+        let n_fragments = usize::arbitrary(gen) % 10;
+        arbitrary_ledger(gen, n_fragments)
+    }
+
+    /// shrink by dropping whole fragments, one at a time
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let ledger = self.clone();
+        Box::new(
+            fragments_of(self)
+                .into_iter()
+                .map(move |pointers| ledger.remove_multiple(&pointers).unwrap().0),
+        )
+    }
+}
+
+/// A non empty UTxO ledger alongside one of its unspent outputs
+#[derive(Clone, Debug)]
+pub struct SpendableLedger {
+    pub ledger: Ledger<Address>,
+    pub input: UtxoPointer,
+}
+
+impl Arbitrary for SpendableLedger {
+    fn arbitrary<G: Gen>(gen: &mut G) -> Self {
+        let n_fragments = usize::arbitrary(gen) % 10 + 1;
+        let ledger = arbitrary_ledger(gen, n_fragments);
+        let input = choose_spendable_input(gen, &ledger).unwrap();
+        SpendableLedger { ledger, input }
+    }
+}
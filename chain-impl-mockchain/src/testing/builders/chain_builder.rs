@@ -0,0 +1,167 @@
+use crate::{
+    block::{Block, BlockBuilder, ConsensusVersion},
+    config::{Block0Date, ConfigParam},
+    fragment::{ConfigParams, Fragment},
+    leadership::bft::LeaderId,
+    ledger::{Error, Ledger},
+    milli::Milli,
+};
+use chain_addr::Discrimination;
+use chain_core::property::{Block as _, ChainLength as _};
+use chain_crypto::{Ed25519, SecretKey};
+use std::collections::BTreeMap;
+
+/// the parameters of a BFT genesis block led by `leader_keys`, in this
+/// order, with `slots_per_epoch` slots of 10 seconds per epoch
+pub fn bft_config(leader_keys: &[SecretKey<Ed25519>], slots_per_epoch: u32) -> ConfigParams {
+    let mut ents = ConfigParams::new();
+    ents.push(ConfigParam::Discrimination(Discrimination::Test));
+    ents.push(ConfigParam::ConsensusVersion(ConsensusVersion::Bft));
+    for leader_key in leader_keys {
+        ents.push(ConfigParam::AddBftLeader(LeaderId::from(
+            leader_key.to_public(),
+        )));
+    }
+    ents.push(ConfigParam::Block0Date(Block0Date(0)));
+    ents.push(ConfigParam::SlotDuration(10));
+    ents.push(ConfigParam::KESUpdateSpeed(12 * 3600));
+    ents.push(ConfigParam::ConsensusGenesisPraosActiveSlotsCoeff(
+        Milli::HALF,
+    ));
+    ents.push(ConfigParam::SlotsPerEpoch(slots_per_epoch));
+    ents
+}
+
+/// A chain of BFT blocks, one per slot, iterated with the state of the
+/// ledger after each block.
+///
+/// The blocks are signed in turn by the leader keys, which must be given
+/// in the order of the leaders in the genesis config. The blocks are
+/// empty unless fragments were injected with `with_fragment_at`.
+pub struct ChainBuilder {
+    leader_keys: Vec<SecretKey<Ed25519>>,
+    // the blocks so far, the genesis block first, so that the block of
+    // chain length `n` is at index `n`
+    chain: Vec<(Block, Ledger)>,
+    fragments: BTreeMap<u32, Vec<Fragment>>,
+    skipped_slots: u32,
+}
+
+impl ChainBuilder {
+    pub fn new(config: ConfigParams, leader_keys: Vec<SecretKey<Ed25519>>) -> Result<Self, Error> {
+        assert!(!leader_keys.is_empty(), "a chain needs a leader");
+        let mut genesis_block = BlockBuilder::new();
+        genesis_block.message(Fragment::Initial(config));
+        let genesis_block = genesis_block.make_genesis_block();
+        let genesis_state = Ledger::new(genesis_block.id(), genesis_block.fragments())?;
+        Ok(ChainBuilder {
+            leader_keys,
+            chain: vec![(genesis_block, genesis_state)],
+            fragments: BTreeMap::new(),
+            skipped_slots: 0,
+        })
+    }
+
+    /// the chain of `bft_config(&leader_keys, slots_per_epoch)`
+    pub fn bft(leader_keys: Vec<SecretKey<Ed25519>>, slots_per_epoch: u32) -> Self {
+        let config = bft_config(&leader_keys, slots_per_epoch);
+        ChainBuilder::new(config, leader_keys).expect("invalid BFT genesis config")
+    }
+
+    pub fn genesis(&self) -> (&Block, &Ledger) {
+        let (block, state) = &self.chain[0];
+        (block, state)
+    }
+
+    /// the last block built, with the state after it
+    pub fn tip(&self) -> (&Block, &Ledger) {
+        let (block, state) = self.chain.last().unwrap();
+        (block, state)
+    }
+
+    /// Put `fragment` in the block of chain length `height`, after the
+    /// fragments injected there before.
+    ///
+    /// Panics if the block of that height was built already.
+    pub fn with_fragment_at(&mut self, height: u32, fragment: Fragment) -> &mut Self {
+        assert!(
+            height as usize >= self.chain.len(),
+            "the block of height {} is built already",
+            height
+        );
+        self.fragments
+            .entry(height)
+            .or_insert_with(Vec::new)
+            .push(fragment);
+        self
+    }
+
+    /// A builder continuing the chain from its block of chain length
+    /// `height`.
+    ///
+    /// The first block of the fork is one slot later than the block of
+    /// this chain at the same height, so that the blocks of the fork
+    /// differ from those of this chain even without fragments. The
+    /// fragments injected in this chain past `height` are not injected
+    /// in the fork.
+    ///
+    /// Panics if the block of that height was not built yet.
+    pub fn fork_at(&self, height: u32) -> ChainBuilder {
+        assert!(
+            (height as usize) < self.chain.len(),
+            "the block of height {} is not built yet",
+            height
+        );
+        ChainBuilder {
+            leader_keys: self.leader_keys.clone(),
+            chain: self.chain[..=height as usize].to_vec(),
+            fragments: BTreeMap::new(),
+            skipped_slots: 1,
+        }
+    }
+}
+
+impl Iterator for ChainBuilder {
+    type Item = (Block, Ledger);
+
+    /// the next block of the chain and the state after it; the chain
+    /// never ends, so this never returns `None`
+    ///
+    /// Panics if the block cannot be signed or if the ledger rejects its
+    /// fragments.
+    fn next(&mut self) -> Option<Self::Item> {
+        let (parent, state) = self.chain.last().unwrap();
+        let mut date = parent.date();
+        for _ in 0..=self.skipped_slots {
+            date = date.next(state.era());
+        }
+        self.skipped_slots = 0;
+        let chain_length = state.chain_length().next();
+        let leader_key = &self.leader_keys[date.slot_id as usize % self.leader_keys.len()];
+
+        let mut block = BlockBuilder::new();
+        block.chain_length(chain_length);
+        block.parent(parent.id());
+        block.date(date);
+        if let Some(fragments) = self.fragments.remove(&chain_length.0) {
+            block.messages(fragments);
+        }
+        let block = block.make_bft_block(leader_key).unwrap_or_else(|error| {
+            panic!(
+                "block of height {} cannot be signed: {}",
+                chain_length.0, error
+            )
+        });
+        let state = state
+            .apply_block(
+                &state.get_ledger_parameters(),
+                block.fragments(),
+                &block.header.to_content_eval_context(),
+            )
+            .unwrap_or_else(|error| {
+                panic!("block of height {} rejected: {}", chain_length.0, error)
+            });
+        self.chain.push((block.clone(), state.clone()));
+        Some((block, state))
+    }
+}
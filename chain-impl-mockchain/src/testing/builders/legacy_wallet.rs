@@ -0,0 +1,154 @@
+use crate::{
+    block::HeaderHash,
+    fragment::{Fragment, FragmentId},
+    legacy::{self, OldAddress, UtxoDeclaration},
+    transaction::{
+        Input, TransactionIndex, TransactionSignDataHash, UtxoPointer, Witness, WitnessUtxoData,
+    },
+    value::Value,
+};
+use cardano_legacy_address::ExtendedAddr;
+use chain_crypto::{Ed25519Bip32, PublicKey, Signature};
+use ed25519_bip32::{DerivationIndex, DerivationScheme, XPrv};
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub LegacyWitnessError
+        AddressMismatch { address: OldAddress } = "Legacy address {address} was not made from the signing key",
+}
+
+/// A wallet of the legacy era, owning the `OldAddress` of the key
+/// derived from its root key along its derivation path
+#[derive(Clone)]
+pub struct LegacyWallet {
+    root: XPrv,
+    path: Vec<DerivationIndex>,
+    key: XPrv,
+    address: OldAddress,
+}
+
+impl LegacyWallet {
+    pub fn new(root: XPrv, path: &[DerivationIndex]) -> Self {
+        let key = path.iter().fold(root.clone(), |key, index| {
+            key.derive(DerivationScheme::V2, *index)
+        });
+        let address = ExtendedAddr::new_simple(&key.public(), None).to_address();
+        LegacyWallet {
+            root,
+            path: path.to_vec(),
+            key,
+            address,
+        }
+    }
+
+    pub fn root(&self) -> &XPrv {
+        &self.root
+    }
+
+    pub fn path(&self) -> &[DerivationIndex] {
+        &self.path
+    }
+
+    pub fn address(&self) -> &OldAddress {
+        &self.address
+    }
+
+    pub fn public_key(&self) -> PublicKey<Ed25519Bip32> {
+        PublicKey::from_binary(self.key.public().as_ref()).unwrap()
+    }
+
+    /// a fragment declaring `value` on the address of each wallet, to put
+    /// in the initial block
+    pub fn declaration(wallets: &[(&LegacyWallet, Value)]) -> Fragment {
+        Fragment::OldUtxoDeclaration(UtxoDeclaration {
+            addrs: wallets
+                .iter()
+                .map(|(wallet, value)| (wallet.address.clone(), *value))
+                .collect(),
+        })
+    }
+
+    /// an input spending the `index`-th entry of the declaration `declaration_id`
+    pub fn make_input(declaration_id: FragmentId, index: TransactionIndex, value: Value) -> Input {
+        Input::from_utxo(UtxoPointer {
+            transaction_id: declaration_id,
+            output_index: index,
+            value,
+        })
+    }
+
+    /// a witness for spending the funds of the wallet in the transaction
+    pub fn make_witness(
+        &self,
+        block0: &HeaderHash,
+        transaction_hash: &TransactionSignDataHash,
+    ) -> Witness {
+        self.make_witness_for(&self.address, block0, transaction_hash)
+            .unwrap()
+    }
+
+    /// same as `make_witness`, spending the funds of `address` instead
+    ///
+    /// Fails without signing anything if `address` is not the address of
+    /// the key of the wallet, as the ledger would reject the witness.
+    pub fn make_witness_for(
+        &self,
+        address: &OldAddress,
+        block0: &HeaderHash,
+        transaction_hash: &TransactionSignDataHash,
+    ) -> Result<Witness, LegacyWitnessError> {
+        let public_key = self.public_key();
+        if !legacy::oldaddress_from_xpub(address, &public_key) {
+            return Err(LegacyWitnessError::AddressMismatch {
+                address: address.clone(),
+            });
+        }
+        let data = WitnessUtxoData::new(block0, transaction_hash);
+        let signature: ed25519_bip32::Signature<u8> = self.key.sign(data.as_ref());
+        let signature = Signature::from_binary(signature.as_ref()).unwrap();
+        Ok(Witness::OldUtxo(public_key, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::keys::KeyFactory;
+
+    #[test]
+    pub fn address_matches_the_derived_key_only() {
+        let mut keys = KeyFactory::from_seed([1; 32]);
+        let wallet = keys.legacy_wallet(&[0x8000_0000, 3]);
+        let same = LegacyWallet::new(wallet.root().clone(), wallet.path());
+        let other_path = LegacyWallet::new(wallet.root().clone(), &[0x8000_0000, 4]);
+
+        assert_eq!(wallet.address(), same.address());
+        assert!(legacy::oldaddress_from_xpub(
+            wallet.address(),
+            &wallet.public_key()
+        ));
+        assert!(!legacy::oldaddress_from_xpub(
+            wallet.address(),
+            &other_path.public_key()
+        ));
+    }
+
+    #[test]
+    pub fn mismatching_address_is_detected_before_signing() {
+        let mut keys = KeyFactory::from_seed([2; 32]);
+        let wallet = keys.legacy_wallet(&[0]);
+        let other = keys.legacy_wallet(&[0]);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        let tx_id = TransactionSignDataHash::from([1; 32]);
+
+        assert!(wallet
+            .make_witness_for(wallet.address(), &block0, &tx_id)
+            .is_ok());
+        assert_eq!(
+            wallet.make_witness_for(other.address(), &block0, &tx_id),
+            Err(LegacyWitnessError::AddressMismatch {
+                address: other.address().clone()
+            })
+        );
+    }
+}
@@ -1,5 +1,11 @@
+pub mod chain_builder;
+pub mod legacy_wallet;
 pub mod tx_builder;
+pub mod update_builder;
 pub mod witness_builder;
 
+pub use chain_builder::*;
+pub use legacy_wallet::*;
 pub use tx_builder::*;
+pub use update_builder::*;
 pub use witness_builder::*;
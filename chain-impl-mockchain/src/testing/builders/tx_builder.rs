@@ -1,17 +1,54 @@
 use crate::{
+    account::SpendingCounter,
     block::HeaderHash,
-    fee::LinearFee,
-    fragment::Fragment,
+    fee::{FeeAlgorithm, LinearFee},
+    fragment::{Fragment, FragmentId},
+    key::{AccountPublicKey, EitherEd25519SecretKey},
     ledger::OutputAddress,
-    testing::{data::AddressData, witness_builder},
-    transaction::{AuthenticatedTransaction, Input, NoExtra, Output, Transaction, Witness},
+    testing::{data::AddressData, witness_builder, LegacyWallet},
+    transaction::{
+        estimate_transaction_size, AuthenticatedTransaction, Input, InputType, NoExtra, Output,
+        Transaction, TransactionIndex, TransactionSignDataHash, UtxoPointer, Witness, WitnessKind,
+    },
     txbuilder::{OutputPolicy, TransactionBuilder as Builder},
+    utxo::{self, SelectionError, SelectionStrategy},
+    value::{Value, ValueError},
 };
-use chain_addr::Address;
+use chain_addr::{Address, Discrimination, Kind};
+use chain_crypto::{Ed25519, PublicKey};
+use std::collections::BTreeMap;
+
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub SealError
+        SignerCount { inputs: usize, signers: usize } = "{signers} signers given for {inputs} inputs",
+        WrongSigner { index: usize } = "Input {index} is not signed with the witness of its kind",
+        MissingSpendingCounter { index: usize } = "The spending counter of the account of input {index} is unknown",
+}
+
+/// The key signing the witness of an input, see
+/// `TransactionBuilder::seal_with`
+#[derive(Debug, Clone)]
+pub enum WitnessSigner {
+    Utxo(EitherEd25519SecretKey),
+    Account(EitherEd25519SecretKey),
+}
+
+impl<'a> From<&'a AddressData> for WitnessSigner {
+    fn from(address_data: &'a AddressData) -> Self {
+        match address_data.address.kind() {
+            Kind::Account(_) => WitnessSigner::Account(address_data.private_key()),
+            _ => WitnessSigner::Utxo(address_data.private_key()),
+        }
+    }
+}
 
 pub struct TransactionBuilder {
     inputs: Vec<Input>,
     outputs: Vec<OutputAddress>,
+    /// the spending counters of the account inputs added with
+    /// `with_account_input`, by index of the input
+    counters: BTreeMap<usize, SpendingCounter>,
 }
 
 impl TransactionBuilder {
@@ -19,6 +56,7 @@ impl TransactionBuilder {
         TransactionBuilder {
             inputs: Vec::new(),
             outputs: Vec::new(),
+            counters: BTreeMap::new(),
         }
     }
 
@@ -42,6 +80,205 @@ impl TransactionBuilder {
         self
     }
 
+    /// Spend `value` from the account of `account`, whose spending
+    /// counter is `counter`
+    pub fn with_account_input(
+        &mut self,
+        account: &PublicKey<Ed25519>,
+        value: Value,
+        counter: SpendingCounter,
+    ) -> &mut Self {
+        self.counters.insert(self.inputs.len(), counter);
+        self.inputs.push(Input::from_account_public_key(
+            AccountPublicKey::from_raw(account.clone()),
+            value,
+        ));
+        self
+    }
+
+    /// Send `value` to the account of `account`, on the test
+    /// discrimination
+    pub fn with_account_output(&mut self, account: &PublicKey<Ed25519>, value: Value) -> &mut Self {
+        let address = Address(Discrimination::Test, Kind::Account(account.clone()));
+        self.outputs.push(Output::from_address(address, value));
+        self
+    }
+
+    /// Same as `with_account_input`, for the account of `key`, e.g. a
+    /// key of a `KeyFactory`
+    pub fn with_account_input_key(
+        &mut self,
+        key: &EitherEd25519SecretKey,
+        value: Value,
+        counter: SpendingCounter,
+    ) -> &mut Self {
+        self.with_account_input(&key.to_public(), value, counter)
+    }
+
+    /// Same as `with_account_output`, to the account of `key`, e.g. a
+    /// key of a `KeyFactory`
+    pub fn with_account_output_key(
+        &mut self,
+        key: &EitherEd25519SecretKey,
+        value: Value,
+    ) -> &mut Self {
+        self.with_account_output(&key.to_public(), value)
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    /// An upper bound of the size of the transaction sealed with
+    /// `seal_with`, see `transaction::estimate_transaction_size`
+    pub fn estimated_size(&self) -> usize {
+        let witness_kinds: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|input| match input.get_type() {
+                InputType::Utxo => WitnessKind::Utxo,
+                InputType::Account => WitnessKind::Account,
+            })
+            .collect();
+        estimate_transaction_size(self.inputs.len(), self.outputs.len(), &witness_kinds)
+    }
+
+    /// The transaction with a witness for each input, made by the signer
+    /// of the same index
+    ///
+    /// A utxo input must be signed by a `WitnessSigner::Utxo`, and an
+    /// account input by a `WitnessSigner::Account`, with the spending
+    /// counter given to `with_account_input`.
+    pub fn seal_with(
+        &self,
+        block0: &HeaderHash,
+        signers: &[WitnessSigner],
+    ) -> Result<AuthenticatedTransaction<Address, NoExtra>, SealError> {
+        if signers.len() != self.inputs.len() {
+            return Err(SealError::SignerCount {
+                inputs: self.inputs.len(),
+                signers: signers.len(),
+            });
+        }
+        let transaction = Transaction {
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            extra: NoExtra,
+        };
+        let transaction_hash = transaction.hash();
+        let mut witnesses = Vec::with_capacity(signers.len());
+        for (index, (input, signer)) in self.inputs.iter().zip(signers).enumerate() {
+            let witness = match (input.get_type(), signer) {
+                (InputType::Utxo, WitnessSigner::Utxo(key)) => {
+                    witness_builder::make_utxo_witness(block0, key, &transaction_hash)
+                }
+                (InputType::Account, WitnessSigner::Account(key)) => {
+                    let counter = self
+                        .counters
+                        .get(&index)
+                        .ok_or(SealError::MissingSpendingCounter { index })?;
+                    witness_builder::make_account_witness(block0, counter, key, &transaction_hash)
+                }
+                _ => return Err(SealError::WrongSigner { index }),
+            };
+            witnesses.push(witness);
+        }
+        Ok(AuthenticatedTransaction {
+            transaction,
+            witnesses,
+        })
+    }
+
+    /// Spend utxos of `owner`, chosen with `strategy`, to pay for the
+    /// outputs and the fees, sending the change back to `owner`
+    pub fn with_selected_inputs<F>(
+        &mut self,
+        utxos: &utxo::Ledger<Address>,
+        owner: &AddressData,
+        strategy: SelectionStrategy,
+        fees: &F,
+    ) -> Result<&mut Self, SelectionError>
+    where
+        F: FeeAlgorithm<Transaction<Address, NoExtra>>,
+    {
+        let outputs_value = Value::sum(self.outputs.iter().map(|output| output.value)).unwrap();
+        // the fee of the transaction with `nb_inputs` more inputs and
+        // `nb_change` more outputs, which only stand in for the ones to
+        // add since the values do not matter to the fee
+        let fee = |nb_inputs: usize, nb_change: usize| {
+            let placeholder = UtxoPointer::new(
+                FragmentId::zero(),
+                TransactionIndex::new(0).unwrap(),
+                Value::zero(),
+            );
+            let mut draft = Transaction {
+                inputs: self.inputs.clone(),
+                outputs: self.outputs.clone(),
+                extra: NoExtra,
+            };
+            draft
+                .inputs
+                .extend(std::iter::repeat(Input::from_utxo(placeholder)).take(nb_inputs));
+            draft
+                .outputs
+                .extend(std::iter::repeat(owner.make_output(Value::zero())).take(nb_change));
+            fees.calculate(&draft)
+                .expect("the fee of the transaction does not fit in a value")
+        };
+        let select = |fee: Value| {
+            let owned = utxos
+                .iter()
+                .filter(|entry| entry.output.address == owner.address);
+            utxo::select_inputs_from(owned, (outputs_value + fee).unwrap(), strategy)
+        };
+
+        // the fee depends on the number of inputs, so grow the number of
+        // inputs until the selection fits the fee it was made for
+        let mut nb_inputs = 1;
+        let (entries, change) = loop {
+            let (entries, change) = select(fee(nb_inputs, 0))?;
+            // nothing to pay for at all
+            if entries.is_empty() {
+                break (entries, change);
+            }
+            if entries.len() == nb_inputs && change == Value::zero() {
+                break (entries, change);
+            }
+            let (entries, change) = select(fee(nb_inputs, 1))?;
+            if entries.len() == nb_inputs && change != Value::zero() {
+                break (entries, change);
+            }
+            nb_inputs = std::cmp::max(nb_inputs + 1, entries.len());
+        };
+
+        for entry in entries {
+            let value = entry.output.value;
+            self.inputs.push(owner.make_input(value, Some(entry)));
+        }
+        if change != Value::zero() {
+            self.outputs.push(owner.make_output(change));
+        }
+        Ok(self)
+    }
+
+    /// Send `amount` out of the utxo `entry` of `owner` to `receiver`,
+    /// sending the rest back to `owner`
+    pub fn with_payment_from(
+        &mut self,
+        entry: utxo::Entry<Address>,
+        owner: &AddressData,
+        receiver: &Address,
+        amount: Value,
+    ) -> Result<&mut Self, ValueError> {
+        let (spent, change) = utxo::split_output(&entry, amount, owner.address.clone())?;
+        self.inputs
+            .push(owner.make_input(entry.output.value, Some(entry)));
+        self.outputs
+            .push(Output::from_address(receiver.clone(), spent.value));
+        self.outputs.extend(change);
+        Ok(self)
+    }
+
     pub fn authenticate(&self) -> TransactionAuthenticator {
         let transaction = Transaction {
             inputs: self.inputs.clone(),
@@ -123,6 +360,23 @@ impl TransactionAuthenticator {
         self
     }
 
+    /// Add `witness` as is, e.g. one made by a `FaultyWitnessBuilder`
+    /// for the hash of the transaction
+    pub fn with_raw_witness(&mut self, witness: Witness) -> &mut Self {
+        self.witnesses.push(witness);
+        self
+    }
+
+    pub fn transaction_hash(&self) -> TransactionSignDataHash {
+        self.transaction.hash()
+    }
+
+    pub fn with_legacy_witness(&mut self, block0: &HeaderHash, wallet: &LegacyWallet) -> &mut Self {
+        let witness = wallet.make_witness(&block0, &self.transaction.hash());
+        self.witnesses.push(witness);
+        self
+    }
+
     pub fn as_message(&self) -> Fragment {
         let signed_tx = self.seal();
         Fragment::Transaction(signed_tx)
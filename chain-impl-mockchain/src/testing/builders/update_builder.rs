@@ -0,0 +1,166 @@
+use crate::{
+    block::HeaderContentEvalContext,
+    config::ConfigParam,
+    fragment::{config::ConfigParams, Fragment},
+    key::EitherEd25519SecretKey,
+    leadership::bft::LeaderId,
+    ledger::{Error, Ledger},
+    setting::Settings,
+    testing::{data::LeaderPair, scenario},
+    update::{
+        SignedUpdateProposal, SignedUpdateVote, UpdateProposal, UpdateProposalId,
+        UpdateProposalWithProposer, UpdateVote,
+    },
+};
+use chain_core::property::ChainLength;
+
+pub struct UpdateProposalBuilder {
+    changes: ConfigParams,
+    proposer_id: Option<LeaderId>,
+}
+
+impl UpdateProposalBuilder {
+    pub fn new() -> Self {
+        UpdateProposalBuilder {
+            changes: ConfigParams::new(),
+            proposer_id: None,
+        }
+    }
+
+    pub fn with_proposal_changes(&mut self, changes: ConfigParams) -> &mut Self {
+        for change in changes.iter().cloned() {
+            self.changes.push(change);
+        }
+        self
+    }
+
+    pub fn with_proposal_change(&mut self, change: ConfigParam) -> &mut Self {
+        self.changes.push(change);
+        self
+    }
+
+    pub fn proposed_by(&mut self, leader_id: LeaderId) -> &mut Self {
+        self.proposer_id = Some(leader_id);
+        self
+    }
+
+    /// Same as `proposed_by`, e.g. for a leader of
+    /// `KeyFactory::make_leader_pair`
+    pub fn proposed_by_leader(&mut self, leader: &LeaderPair) -> &mut Self {
+        self.proposed_by(leader.id())
+    }
+
+    /// the signed proposal, proposed by the leader owning `key` unless
+    /// another proposer was given
+    ///
+    /// Proposals do not carry a signature yet, so the key only decides
+    /// the default proposer.
+    pub fn sign(&self, key: &EitherEd25519SecretKey) -> SignedUpdateProposal {
+        let proposer_id = self
+            .proposer_id
+            .clone()
+            .unwrap_or_else(|| key.to_public().into());
+        SignedUpdateProposal {
+            proposal: UpdateProposalWithProposer {
+                proposal: UpdateProposal {
+                    changes: self.changes.clone(),
+                },
+                proposer_id,
+            },
+        }
+    }
+}
+
+/// Votes for a single proposal
+///
+/// The voters are not checked against anything: a vote from a non leader or
+/// the same voter given twice make fragments for the ledger to reject.
+pub struct UpdateVoteBuilder {
+    proposal_id: Option<UpdateProposalId>,
+    voters: Vec<LeaderId>,
+}
+
+impl UpdateVoteBuilder {
+    pub fn new() -> Self {
+        UpdateVoteBuilder {
+            proposal_id: None,
+            voters: Vec::new(),
+        }
+    }
+
+    pub fn for_proposal(&mut self, proposal_id: UpdateProposalId) -> &mut Self {
+        self.proposal_id = Some(proposal_id);
+        self
+    }
+
+    pub fn voted_by(&mut self, leader_id: LeaderId) -> &mut Self {
+        self.voters.push(leader_id);
+        self
+    }
+
+    pub fn voted_by_leaders(&mut self, leaders: &[LeaderPair]) -> &mut Self {
+        self.voters.extend(leaders.iter().map(|leader| leader.id()));
+        self
+    }
+
+    /// one signed vote per voter, in the order they were given
+    pub fn build(&self) -> Vec<SignedUpdateVote> {
+        let proposal_id = self
+            .proposal_id
+            .clone()
+            .expect("votes need the id of the proposal");
+        self.voters
+            .iter()
+            .cloned()
+            .map(|voter_id| SignedUpdateVote {
+                vote: UpdateVote {
+                    proposal_id: proposal_id.clone(),
+                    voter_id,
+                },
+            })
+            .collect()
+    }
+
+    pub fn as_messages(&self) -> Vec<Fragment> {
+        self.build().into_iter().map(Fragment::UpdateVote).collect()
+    }
+}
+
+/// propose `changes` as the first of `leaders`, vote for it with all of them
+/// and apply an empty block in the next epoch, so the proposal is adopted if
+/// the leaders are a majority of the BFT leaders of the ledger
+pub fn adopt_update_proposal(
+    ledger: &Ledger,
+    changes: ConfigParams,
+    leaders: &[LeaderPair],
+) -> Result<(Ledger, Settings), Error> {
+    let proposer = leaders.first().expect("a proposal needs a proposer");
+    let proposal = Fragment::UpdateProposal(
+        UpdateProposalBuilder::new()
+            .with_proposal_changes(changes)
+            .sign(&EitherEd25519SecretKey::Extended(proposer.key())),
+    );
+    let mut ledger = scenario::apply(ledger, &proposal)?;
+
+    let votes = UpdateVoteBuilder::new()
+        .for_proposal(proposal.hash())
+        .voted_by_leaders(leaders)
+        .as_messages();
+    for vote in votes.iter() {
+        ledger = scenario::apply(&ledger, vote)?;
+    }
+
+    let metadata = HeaderContentEvalContext {
+        block_date: ledger.date().next_epoch(),
+        chain_length: ledger.chain_length().next(),
+        nonce: None,
+    };
+    let no_fragments: Vec<Fragment> = Vec::new();
+    let ledger = ledger.apply_block(
+        &ledger.get_ledger_parameters(),
+        no_fragments.iter(),
+        &metadata,
+    )?;
+    let settings = ledger.settings_snapshot();
+    Ok((ledger, settings))
+}
@@ -1,11 +1,17 @@
 use crate::{
     account::SpendingCounter,
     block::HeaderHash,
-    key::EitherEd25519SecretKey,
+    key::{AccountPublicKey, EitherEd25519SecretKey, SpendingPublicKey},
     testing::data::AddressData,
-    transaction::{TransactionSignDataHash, Witness},
+    transaction::{TransactionSignDataHash, Witness, WitnessAccountData, WitnessUtxoData},
 };
 use chain_addr::Kind;
+use chain_core::{
+    mempack::{ReadBuf, Readable},
+    property::Serialize,
+};
+use chain_crypto::{Ed25519, PublicKey, Verification};
+use std::convert::TryFrom;
 
 pub fn make_witness(
     block0: &HeaderHash,
@@ -39,3 +45,407 @@ pub fn make_account_witness(
 ) -> Witness {
     Witness::new_account(block0, transaction_hash, spending_counter, secret_key)
 }
+
+/// the size of the signature of a utxo or an account witness, in bytes
+const SIGNATURE_SIZE: usize = 64;
+
+/// Makes witnesses of a transaction with one defect each, for testing
+/// that the ledger rejects them.
+///
+/// The witnesses are made as `make_witness` would for the same address,
+/// except for the defect.
+#[derive(Debug, Clone)]
+pub struct FaultyWitnessBuilder {
+    block0: HeaderHash,
+    secret_key: EitherEd25519SecretKey,
+    /// `None` for a utxo witness
+    spending_counter: Option<SpendingCounter>,
+    transaction_hash: TransactionSignDataHash,
+}
+
+impl FaultyWitnessBuilder {
+    /// Panics if `address_data` is an account without a spending counter.
+    pub fn new(
+        block0: &HeaderHash,
+        address_data: &AddressData,
+        transaction_hash: TransactionSignDataHash,
+    ) -> Self {
+        let spending_counter = match address_data.address.kind() {
+            Kind::Account(_) => Some(
+                address_data
+                    .spending_counter
+                    .expect("an account witness needs a spending counter"),
+            ),
+            _ => None,
+        };
+        FaultyWitnessBuilder {
+            block0: *block0,
+            secret_key: address_data.private_key(),
+            spending_counter,
+            transaction_hash,
+        }
+    }
+
+    /// the witness without defect
+    pub fn valid(&self) -> Witness {
+        self.sign(
+            &self.block0,
+            self.spending_counter.as_ref(),
+            &self.secret_key,
+        )
+    }
+
+    /// a witness made for another block0 hash
+    pub fn with_wrong_block0(&self) -> Witness {
+        let block0 = HeaderHash::hash_bytes(self.block0.as_ref());
+        self.sign(&block0, self.spending_counter.as_ref(), &self.secret_key)
+    }
+
+    /// the valid witness with the bit `n` of its signature flipped
+    ///
+    /// Panics if the signature has no bit `n`.
+    pub fn with_flipped_signature_bit(&self, n: usize) -> Witness {
+        assert!(n < SIGNATURE_SIZE * 8, "a signature has no bit {}", n);
+        let mut bytes = self.valid().serialize_as_vec().unwrap();
+        // the signature comes right after the tag of the witness
+        bytes[1 + n / 8] ^= 1 << (n % 8);
+        Witness::read(&mut ReadBuf::from(&bytes)).unwrap()
+    }
+
+    /// a witness signing the spending counter `delta` away from the
+    /// expected one
+    ///
+    /// Panics for a utxo witness, or if the counter is out of range.
+    pub fn with_wrong_counter(&self, delta: i64) -> Witness {
+        assert!(delta != 0, "the counter of the valid witness");
+        let counter = self
+            .spending_counter
+            .expect("a utxo witness has no spending counter");
+        let counter = u32::try_from(i64::from(u32::from(counter)) + delta)
+            .expect("spending counter out of range");
+        self.sign(
+            &self.block0,
+            Some(&SpendingCounter::from(counter)),
+            &self.secret_key,
+        )
+    }
+
+    /// a witness signed with `other_secret` instead of the key of the
+    /// address
+    pub fn with_mismatched_key(&self, other_secret: &EitherEd25519SecretKey) -> Witness {
+        self.sign(&self.block0, self.spending_counter.as_ref(), other_secret)
+    }
+
+    /// the serialized valid witness, without the last `bytes` bytes of
+    /// its signature
+    ///
+    /// A signature has a fixed size, so these bytes cannot be read back
+    /// into a `Witness`.
+    pub fn with_truncated_signature(&self, bytes: usize) -> Vec<u8> {
+        assert!(
+            bytes > 0 && bytes <= SIGNATURE_SIZE,
+            "cannot truncate {} bytes of a signature",
+            bytes
+        );
+        let mut serialized = self.valid().serialize_as_vec().unwrap();
+        serialized.truncate(serialized.len() - bytes);
+        serialized
+    }
+
+    fn sign(
+        &self,
+        block0: &HeaderHash,
+        spending_counter: Option<&SpendingCounter>,
+        secret_key: &EitherEd25519SecretKey,
+    ) -> Witness {
+        match spending_counter {
+            None => make_utxo_witness(block0, secret_key, &self.transaction_hash),
+            Some(counter) => {
+                make_account_witness(block0, counter, secret_key, &self.transaction_hash)
+            }
+        }
+    }
+}
+
+/// What a witness is checked against, besides the block0 hash and the
+/// transaction
+#[derive(Debug, Clone)]
+pub struct WitnessContext {
+    /// counter of the spending account, `None` for a utxo witness
+    pub spending_counter: Option<SpendingCounter>,
+    /// other block0 hashes the witness may have been made for by mistake,
+    /// e.g. the ones of other test networks
+    pub other_block0s: Vec<HeaderHash>,
+}
+
+impl WitnessContext {
+    pub fn utxo() -> Self {
+        WitnessContext {
+            spending_counter: None,
+            other_block0s: Vec::new(),
+        }
+    }
+
+    pub fn account(spending_counter: SpendingCounter) -> Self {
+        WitnessContext {
+            spending_counter: Some(spending_counter),
+            other_block0s: Vec::new(),
+        }
+    }
+
+    pub fn with_other_block0(mut self, block0: HeaderHash) -> Self {
+        self.other_block0s.push(block0);
+        self
+    }
+}
+
+/// Why a witness does not verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessFailure {
+    /// a utxo witness where an account one is expected, or the other way
+    WrongKind,
+    /// old utxo and multisig witnesses cannot be checked
+    Unsupported,
+    /// the witness verifies with the spending counter just before or
+    /// after the expected one
+    SpendingCounter { signed: SpendingCounter },
+    /// the witness verifies with one of the other block0 hashes
+    Block0 { signed: HeaderHash },
+    /// none of the above: wrong key, another transaction or an unknown
+    /// block0 hash
+    Signature,
+}
+
+#[derive(Debug, Clone)]
+pub struct WitnessVerificationReport {
+    /// the bytes the witness is expected to sign
+    pub signed_data: Vec<u8>,
+    pub verification: Verification,
+    /// the first cause found when the witness does not verify
+    pub failure: Option<WitnessFailure>,
+}
+
+/// explain why `witness` does or does not verify for the given key,
+/// block0 hash and transaction
+pub fn verify_witness(
+    witness: &Witness,
+    expected_pk: &PublicKey<Ed25519>,
+    block0: &HeaderHash,
+    tx_id: &TransactionSignDataHash,
+    context: &WitnessContext,
+) -> WitnessVerificationReport {
+    let signed_data = |block0: &HeaderHash, counter: Option<&SpendingCounter>| -> Vec<u8> {
+        match counter {
+            None => WitnessUtxoData::new(block0, tx_id).as_ref().to_vec(),
+            Some(counter) => WitnessAccountData::new(block0, tx_id, counter)
+                .as_ref()
+                .to_vec(),
+        }
+    };
+    let verifies = |block0: &HeaderHash, counter: Option<&SpendingCounter>| -> Option<bool> {
+        let verification = match (witness, counter) {
+            (Witness::Utxo(signature), None) => signature.verify(
+                &SpendingPublicKey::from_raw(expected_pk.clone()),
+                &WitnessUtxoData::new(block0, tx_id),
+            ),
+            (Witness::Account(signature), Some(counter)) => signature.verify(
+                &AccountPublicKey::from_raw(expected_pk.clone()),
+                &WitnessAccountData::new(block0, tx_id, counter),
+            ),
+            _ => return None,
+        };
+        Some(verification == Verification::Success)
+    };
+
+    let counter = context.spending_counter.as_ref();
+    let report = |verification, failure| WitnessVerificationReport {
+        signed_data: signed_data(block0, counter),
+        verification,
+        failure,
+    };
+
+    match (witness, counter) {
+        (Witness::OldUtxo(_, _), _) | (Witness::Multisig(_), _) => {
+            return report(Verification::Failed, Some(WitnessFailure::Unsupported))
+        }
+        (Witness::Utxo(_), None) | (Witness::Account(_), Some(_)) => {}
+        _ => return report(Verification::Failed, Some(WitnessFailure::WrongKind)),
+    }
+
+    if verifies(block0, counter) == Some(true) {
+        return report(Verification::Success, None);
+    }
+
+    if let Some(counter) = counter {
+        let counter = u32::from(*counter);
+        let neighbours = vec![counter.checked_sub(1), counter.checked_add(1)];
+        for neighbour in neighbours.into_iter().flatten() {
+            let neighbour = SpendingCounter::from(neighbour);
+            if verifies(block0, Some(&neighbour)) == Some(true) {
+                return report(
+                    Verification::Failed,
+                    Some(WitnessFailure::SpendingCounter { signed: neighbour }),
+                );
+            }
+        }
+    }
+
+    for other_block0 in context.other_block0s.iter() {
+        if verifies(other_block0, counter) == Some(true) {
+            return report(
+                Verification::Failed,
+                Some(WitnessFailure::Block0 {
+                    signed: *other_block0,
+                }),
+            );
+        }
+    }
+
+    report(Verification::Failed, Some(WitnessFailure::Signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::keys::KeyFactory;
+    use chain_addr::Discrimination;
+
+    fn tx_id() -> TransactionSignDataHash {
+        TransactionSignDataHash::from([1; 32])
+    }
+
+    #[test]
+    pub fn valid_witnesses_are_reported_as_such() {
+        let mut keys = KeyFactory::from_seed([1; 32]);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+
+        let utxo = keys.utxo_address(Discrimination::Test);
+        let witness = make_witness(&block0, &utxo, tx_id());
+        let report = verify_witness(
+            &witness,
+            &utxo.public_key(),
+            &block0,
+            &tx_id(),
+            &WitnessContext::utxo(),
+        );
+        assert_eq!(report.verification, Verification::Success);
+        assert_eq!(report.failure, None);
+        assert_eq!(
+            report.signed_data,
+            WitnessUtxoData::new(&block0, &tx_id()).as_ref()
+        );
+
+        let account = keys.account_address(Discrimination::Test);
+        let witness = make_witness(&block0, &account, tx_id());
+        let report = verify_witness(
+            &witness,
+            &account.public_key(),
+            &block0,
+            &tx_id(),
+            &WitnessContext::account(SpendingCounter::zero()),
+        );
+        assert_eq!(report.verification, Verification::Success);
+        assert_eq!(report.failure, None);
+    }
+
+    #[test]
+    pub fn wrong_spending_counter_is_identified() {
+        let account = KeyFactory::from_seed([2; 32]).account_address(Discrimination::Test);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        let witness = make_account_witness(
+            &block0,
+            &SpendingCounter::from(4),
+            &account.private_key(),
+            &tx_id(),
+        );
+
+        let report = verify_witness(
+            &witness,
+            &account.public_key(),
+            &block0,
+            &tx_id(),
+            &WitnessContext::account(SpendingCounter::from(5)),
+        );
+        assert_eq!(report.verification, Verification::Failed);
+        assert_eq!(
+            report.failure,
+            Some(WitnessFailure::SpendingCounter {
+                signed: SpendingCounter::from(4)
+            })
+        );
+        assert_eq!(
+            report.signed_data,
+            WitnessAccountData::new(&block0, &tx_id(), &SpendingCounter::from(5)).as_ref()
+        );
+    }
+
+    #[test]
+    pub fn wrong_block0_is_identified() {
+        let utxo = KeyFactory::from_seed([3; 32]).utxo_address(Discrimination::Test);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        let other_block0 = HeaderHash::hash_bytes(b"other block0");
+        let witness = make_witness(&other_block0, &utxo, tx_id());
+
+        let report = verify_witness(
+            &witness,
+            &utxo.public_key(),
+            &block0,
+            &tx_id(),
+            &WitnessContext::utxo(),
+        );
+        assert_eq!(report.failure, Some(WitnessFailure::Signature));
+
+        let report = verify_witness(
+            &witness,
+            &utxo.public_key(),
+            &block0,
+            &tx_id(),
+            &WitnessContext::utxo().with_other_block0(other_block0),
+        );
+        assert_eq!(report.verification, Verification::Failed);
+        assert_eq!(
+            report.failure,
+            Some(WitnessFailure::Block0 {
+                signed: other_block0
+            })
+        );
+    }
+
+    #[test]
+    pub fn witness_of_the_wrong_kind_is_identified() {
+        let account = KeyFactory::from_seed([4; 32]).account_address(Discrimination::Test);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        let witness = make_witness(&block0, &account, tx_id());
+
+        let report = verify_witness(
+            &witness,
+            &account.public_key(),
+            &block0,
+            &tx_id(),
+            &WitnessContext::utxo(),
+        );
+        assert_eq!(report.failure, Some(WitnessFailure::WrongKind));
+    }
+
+    #[test]
+    pub fn truncated_signatures_cannot_be_read() {
+        let mut keys = KeyFactory::from_seed([5; 32]);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        for address in &[
+            keys.utxo_address(Discrimination::Test),
+            keys.account_address(Discrimination::Test),
+        ] {
+            let faulty = FaultyWitnessBuilder::new(&block0, address, tx_id());
+            let valid = faulty.valid().serialize_as_vec().unwrap();
+            assert_eq!(
+                Witness::read(&mut ReadBuf::from(&valid)).unwrap(),
+                faulty.valid()
+            );
+            for bytes in &[1, 32, SIGNATURE_SIZE] {
+                let truncated = faulty.with_truncated_signature(*bytes);
+                assert_eq!(truncated.len(), valid.len() - bytes);
+                assert!(Witness::read(&mut ReadBuf::from(&truncated)).is_err());
+            }
+        }
+    }
+}
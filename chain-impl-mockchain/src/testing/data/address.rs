@@ -1,6 +1,6 @@
 use crate::{
     account::SpendingCounter,
-    key::EitherEd25519SecretKey,
+    key::{AccountPublicKey, EitherEd25519SecretKey},
     transaction::{Input, Output},
     utxo::Entry,
     value::Value,
@@ -98,7 +98,10 @@ impl AddressData {
     pub fn make_input(&self, value: Value, utxo: Option<Entry<Address>>) -> Input {
         match self.address.kind() {
             Kind::Account { .. } => {
-                Input::from_account_public_key(self.public_key(), value.clone())
+                Input::from_account_public_key(
+                    AccountPublicKey::from_raw(self.public_key()),
+                    value.clone(),
+                )
             }
             Kind::Single { .. } | Kind::Group { .. } | Kind::Multisig { .. } => {
                 Input::from_utxo_entry(utxo.expect(&format!(
@@ -0,0 +1,144 @@
+use crate::{
+    account::SpendingCounter,
+    key::EitherEd25519SecretKey,
+    leadership::bft::LeaderId,
+    testing::{
+        builders::LegacyWallet,
+        data::{AddressData, LeaderPair},
+    },
+};
+use chain_addr::{Address, Discrimination, Kind};
+use chain_crypto::{AsymmetricKey, Ed25519Bip32, Ed25519Extended, SecretKey};
+use ed25519_bip32::DerivationIndex;
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+
+/// Deterministic source of keys for the test builders
+///
+/// Two factories created from the same seed give the same sequence of
+/// keys, so a failing test can be replayed with the exact same material.
+pub struct KeyFactory {
+    rng: ChaChaRng,
+}
+
+impl KeyFactory {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        KeyFactory {
+            rng: ChaChaRng::from_seed(seed),
+        }
+    }
+
+    /// next secret key of any algorithm
+    pub fn next_key<A: AsymmetricKey>(&mut self) -> SecretKey<A> {
+        SecretKey::generate(&mut self.rng)
+    }
+
+    /// next key to spend utxos with
+    pub fn next_spending_key(&mut self) -> EitherEd25519SecretKey {
+        EitherEd25519SecretKey::Extended(self.next_key::<Ed25519Extended>())
+    }
+
+    /// next key to spend from an account with
+    pub fn next_account_key(&mut self) -> EitherEd25519SecretKey {
+        EitherEd25519SecretKey::Extended(self.next_key::<Ed25519Extended>())
+    }
+
+    /// the next `n` BFT leaders, each leader id matching its key
+    pub fn make_leader_pair(&mut self, n: usize) -> Vec<LeaderPair> {
+        (0..n)
+            .map(|_| {
+                let leader_key = self.next_key::<Ed25519Extended>();
+                LeaderPair::new(LeaderId(leader_key.to_public()), leader_key)
+            })
+            .collect()
+    }
+
+    /// a single address with the next spending key
+    pub fn utxo_address(&mut self, discrimination: Discrimination) -> AddressData {
+        let sk = self.next_spending_key();
+        let address = Address(discrimination, Kind::Single(sk.to_public()));
+        AddressData::new(sk, None, address)
+    }
+
+    /// an account address with the next account key
+    pub fn account_address(&mut self, discrimination: Discrimination) -> AddressData {
+        let sk = self.next_account_key();
+        let address = Address(discrimination, Kind::Account(sk.to_public()));
+        AddressData::new(sk, Some(SpendingCounter::zero()), address)
+    }
+
+    /// a group address with the next spending key, delegating to the next account key
+    pub fn delegation_address(&mut self, discrimination: Discrimination) -> AddressData {
+        let sk = self.next_spending_key();
+        let delegation_key = self.next_account_key().to_public();
+        let address = Address(discrimination, Kind::Group(sk.to_public(), delegation_key));
+        AddressData::new(sk, None, address)
+    }
+
+    /// a legacy wallet with the next root key, deriving its key along `path`
+    pub fn legacy_wallet(&mut self, path: &[DerivationIndex]) -> LegacyWallet {
+        LegacyWallet::new(Ed25519Bip32::generate(&mut self.rng), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::HeaderHash,
+        testing::builders::{TransactionBuilder, UpdateProposalBuilder, WitnessSigner},
+        value::Value,
+    };
+    use chain_core::property::Serialize;
+
+    #[test]
+    pub fn same_seed_gives_same_keys() {
+        let mut factory1 = KeyFactory::from_seed([1; 32]);
+        let mut factory2 = KeyFactory::from_seed([1; 32]);
+        for _ in 0..10 {
+            assert_eq!(
+                factory1.next_spending_key().to_public(),
+                factory2.next_spending_key().to_public()
+            );
+            assert_eq!(
+                factory1.next_account_key().to_public(),
+                factory2.next_account_key().to_public()
+            );
+        }
+        assert_eq!(factory1.make_leader_pair(3), factory2.make_leader_pair(3));
+    }
+
+    #[test]
+    pub fn builders_take_the_keys_of_a_factory() {
+        let seal = |seed| {
+            let mut factory = KeyFactory::from_seed(seed);
+            let alice = factory.next_account_key();
+            let bob = factory.next_account_key();
+            TransactionBuilder::new()
+                .with_account_input_key(&alice, Value(40), SpendingCounter::zero())
+                .with_account_output_key(&bob, Value(40))
+                .seal_with(&HeaderHash::zero(), &[WitnessSigner::Account(alice)])
+                .unwrap()
+                .serialize_as_vec()
+                .unwrap()
+        };
+        assert_eq!(seal([1; 32]), seal([1; 32]));
+        assert_ne!(seal([1; 32]), seal([2; 32]));
+
+        let leaders = KeyFactory::from_seed([1; 32]).make_leader_pair(2);
+        let proposal = UpdateProposalBuilder::new()
+            .proposed_by_leader(&leaders[1])
+            .sign(&EitherEd25519SecretKey::Extended(leaders[0].key()));
+        assert_eq!(proposal.proposal.proposer_id, leaders[1].id());
+    }
+
+    #[test]
+    pub fn different_seeds_give_different_keys() {
+        let mut factory1 = KeyFactory::from_seed([1; 32]);
+        let mut factory2 = KeyFactory::from_seed([2; 32]);
+        assert_ne!(
+            factory1.next_spending_key().to_public(),
+            factory2.next_spending_key().to_public()
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use crate::block::ConsensusVersion;
 use crate::block::HeaderHash;
-use crate::config::ConfigParam;
+use crate::config::{Block0Date, ConfigParam};
+use crate::fee::LinearFee;
 use crate::fragment::config::ConfigParams;
 use crate::fragment::Fragment;
+use crate::key::HashScheme;
 use crate::leadership::bft::LeaderId;
 use crate::ledger::Error;
 use crate::ledger::Ledger;
@@ -15,21 +17,27 @@ use std::vec::Vec;
 use crate::testing::tx_builder::TransactionBuilder;
 
 pub struct ConfigBuilder {
+    block0_date: Block0Date,
     slot_duration: u8,
     slots_per_epoch: u32,
     active_slots_coeff: Milli,
     discrimination: Discrimination,
     leaders: Vec<LeaderId>,
+    linear_fee: Option<LinearFee>,
+    hash_scheme: Option<HashScheme>,
 }
 
 impl ConfigBuilder {
     pub fn new() -> Self {
         ConfigBuilder {
+            block0_date: Block0Date(0),
             slot_duration: 20,
             slots_per_epoch: 21600,
             active_slots_coeff: Milli::HALF,
             discrimination: Discrimination::Test,
             leaders: Vec::new(),
+            linear_fee: None,
+            hash_scheme: None,
         }
     }
 
@@ -38,6 +46,11 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn with_block0_date(&mut self, block0_date: Block0Date) -> &mut Self {
+        self.block0_date = block0_date;
+        self
+    }
+
     pub fn with_slot_duration(&mut self, slot_duration: u8) -> &mut Self {
         self.slot_duration = slot_duration;
         self
@@ -58,6 +71,16 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn with_fee(&mut self, linear_fee: LinearFee) -> &mut Self {
+        self.linear_fee = Some(linear_fee);
+        self
+    }
+
+    pub fn with_hash_scheme(&mut self, hash_scheme: HashScheme) -> &mut Self {
+        self.hash_scheme = Some(hash_scheme);
+        self
+    }
+
     fn create_single_bft_leader() -> LeaderId {
         let leader_prv_key: SecretKey<Ed25519Extended> =
             SecretKey::generate(rand_os::OsRng::new().unwrap());
@@ -78,13 +101,19 @@ impl ConfigBuilder {
             ie.push(ConfigParam::AddBftLeader(leader_id));
         }
 
-        ie.push(ConfigParam::Block0Date(crate::config::Block0Date(0)));
+        ie.push(ConfigParam::Block0Date(self.block0_date));
         ie.push(ConfigParam::SlotDuration(self.slot_duration));
         ie.push(ConfigParam::ConsensusGenesisPraosActiveSlotsCoeff(
             self.active_slots_coeff,
         ));
         ie.push(ConfigParam::SlotsPerEpoch(self.slots_per_epoch));
         ie.push(ConfigParam::KESUpdateSpeed(3600 * 12));
+        if let Some(linear_fee) = self.linear_fee {
+            ie.push(ConfigParam::LinearFee(linear_fee));
+        }
+        if let Some(hash_scheme) = self.hash_scheme {
+            ie.push(ConfigParam::HashScheme(hash_scheme));
+        }
         ie
     }
 }
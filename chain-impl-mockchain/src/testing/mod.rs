@@ -1,10 +1,18 @@
 pub mod arbitrary;
 pub mod builders;
 pub mod data;
+pub mod keys;
 pub mod ledger;
+pub mod scenario;
+pub mod serialization;
+pub mod total_value;
+pub mod utxo_model;
+pub mod wallets;
 
 pub use arbitrary::*;
 pub use builders::*;
+pub use serialization::{assert_serialization_stable, fragment_id_of};
+pub use total_value::{assert_value_conserved, TotalValue};
 
 use crate::key::Hash;
 use crate::{
@@ -0,0 +1,236 @@
+//! Ledger scenarios between named wallets
+//!
+//! ```ignore
+//! let (mut ledger, mut controller) = prepare_scenario()
+//!     .with_initials(vec![wallet("alice", 1_000), wallet("bob", 500)])
+//!     .with_config(ConfigBuilder::new().with_fee(LinearFee::new(10, 1, 0)))
+//!     .build()
+//!     .unwrap();
+//! controller.transfer_funds("alice", "bob", &mut ledger, 100).unwrap();
+//! ```
+
+use crate::{
+    account::{Identifier, SpendingCounter},
+    block::{HeaderContentEvalContext, HeaderHash},
+    config::ConfigParam,
+    fee::FeeAlgorithm,
+    fragment::{config::ConfigParams, Fragment},
+    ledger::{Error, Ledger},
+    testing::{
+        assert_value_conserved,
+        data::AddressData,
+        ledger::{self, ConfigBuilder},
+        tx_builder::TransactionBuilder,
+    },
+    transaction::{NoExtra, Output, Transaction},
+    value::Value,
+};
+use chain_addr::{Address, Discrimination};
+
+/// a wallet to create in the initial block of the scenario
+pub struct WalletTemplate {
+    alias: String,
+    value: Value,
+}
+
+/// a wallet named `alias` starting with `value` on its account
+pub fn wallet(alias: &str, value: u64) -> WalletTemplate {
+    WalletTemplate {
+        alias: alias.to_owned(),
+        value: Value(value),
+    }
+}
+
+/// an account wallet of the scenario, with its own spending counter
+#[derive(Clone, Debug)]
+pub struct Wallet {
+    alias: String,
+    address_data: AddressData,
+}
+
+impl Wallet {
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    pub fn address(&self) -> &Address {
+        &self.address_data.address
+    }
+
+    pub fn account_id(&self) -> Identifier {
+        self.address_data.public_key().into()
+    }
+
+    pub fn spending_counter(&self) -> SpendingCounter {
+        self.address_data.spending_counter.unwrap()
+    }
+}
+
+pub struct ScenarioBuilder {
+    initials: Vec<WalletTemplate>,
+    config: Option<ConfigParams>,
+}
+
+pub fn prepare_scenario() -> ScenarioBuilder {
+    ScenarioBuilder {
+        initials: Vec::new(),
+        config: None,
+    }
+}
+
+impl ScenarioBuilder {
+    pub fn with_initials(&mut self, initials: Vec<WalletTemplate>) -> &mut Self {
+        self.initials.extend(initials);
+        self
+    }
+
+    pub fn with_config(&mut self, config: &mut ConfigBuilder) -> &mut Self {
+        self.config = Some(config.build());
+        self
+    }
+
+    /// create the ledger with a block0 funding all the wallets
+    pub fn build(&mut self) -> Result<(Ledger, Controller), Error> {
+        let config = self
+            .config
+            .take()
+            .unwrap_or_else(|| ConfigBuilder::new().build());
+        let discrimination = config
+            .iter()
+            .filter_map(|param| match param {
+                ConfigParam::Discrimination(discrimination) => Some(*discrimination),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(Discrimination::Test);
+
+        let wallets: Vec<Wallet> = self
+            .initials
+            .iter()
+            .map(|template| Wallet {
+                alias: template.alias.clone(),
+                address_data: AddressData::account(discrimination),
+            })
+            .collect();
+        let outputs: Vec<Output<Address>> = wallets
+            .iter()
+            .zip(self.initials.iter())
+            .map(|(wallet, template)| wallet.address_data.make_output(template.value))
+            .collect();
+        let fragments = if outputs.is_empty() {
+            vec![]
+        } else {
+            vec![ledger::create_initial_transactions(&outputs)]
+        };
+
+        let (block0_hash, ledger) = ledger::create_initial_fake_ledger(&fragments, config)?;
+        Ok((
+            ledger,
+            Controller {
+                block0_hash,
+                wallets,
+            },
+        ))
+    }
+}
+
+/// Produce and apply transactions between the wallets of a scenario
+pub struct Controller {
+    block0_hash: HeaderHash,
+    wallets: Vec<Wallet>,
+}
+
+impl Controller {
+    pub fn block0_hash(&self) -> &HeaderHash {
+        &self.block0_hash
+    }
+
+    pub fn wallet(&self, alias: &str) -> Option<&Wallet> {
+        self.wallets.iter().find(|wallet| wallet.alias == alias)
+    }
+
+    fn wallet_mut(&mut self, alias: &str) -> &mut Wallet {
+        self.wallets
+            .iter_mut()
+            .find(|wallet| wallet.alias == alias)
+            .unwrap_or_else(|| panic!("unknown wallet {}", alias))
+    }
+
+    /// the value of the account of the wallet in the ledger
+    pub fn balance(&self, alias: &str, ledger: &Ledger) -> Value {
+        let wallet = self
+            .wallet(alias)
+            .unwrap_or_else(|| panic!("unknown wallet {}", alias));
+        ledger
+            .accounts()
+            .get_state(&wallet.account_id())
+            .map(|state| state.value())
+            .unwrap_or_else(|_| Value::zero())
+    }
+
+    /// a signed transaction moving `value` from `from` to `to`, the sender
+    /// paying the fees of the ledger on top of it
+    pub fn transfer(&self, from: &str, to: &str, ledger: &Ledger, value: u64) -> Fragment {
+        let sender = self
+            .wallet(from)
+            .unwrap_or_else(|| panic!("unknown wallet {}", from));
+        let receiver = self
+            .wallet(to)
+            .unwrap_or_else(|| panic!("unknown wallet {}", to));
+        let output = receiver.address_data.make_output(Value(value));
+
+        let unbalanced = Transaction {
+            inputs: vec![sender.address_data.make_input(Value(value), None)],
+            outputs: vec![output.clone()],
+            extra: NoExtra,
+        };
+        let fee = ledger
+            .get_ledger_parameters()
+            .fees
+            .calculate(&unbalanced)
+            .unwrap();
+        let input = sender
+            .address_data
+            .make_input((Value(value) + fee).unwrap(), None);
+
+        TransactionBuilder::new()
+            .with_input(input)
+            .with_output(output)
+            .authenticate()
+            .with_witness(&self.block0_hash, &sender.address_data)
+            .as_message()
+    }
+
+    /// apply a transfer to the ledger, moving the spending counter of the
+    /// sender forward if it is accepted
+    pub fn transfer_funds(
+        &mut self,
+        from: &str,
+        to: &str,
+        ledger: &mut Ledger,
+        value: u64,
+    ) -> Result<(), Error> {
+        let fragment = self.transfer(from, to, ledger, value);
+        *ledger = apply(ledger, &fragment)?;
+
+        let sender = &mut self.wallet_mut(from).address_data;
+        let counter = sender.spending_counter.unwrap();
+        sender.spending_counter = Some(counter.increment().unwrap());
+        Ok(())
+    }
+}
+
+/// apply a fragment at the current date of the ledger
+///
+/// Panics if the fragment is accepted but changes the total value held
+/// by the ledger, see `assert_value_conserved`.
+pub fn apply(ledger: &Ledger, fragment: &Fragment) -> Result<Ledger, Error> {
+    let metadata = HeaderContentEvalContext {
+        block_date: ledger.date(),
+        chain_length: ledger.chain_length(),
+        nonce: None,
+    };
+    let after = ledger.apply_fragment(&ledger.get_ledger_parameters(), fragment, &metadata)?;
+    assert_value_conserved(ledger, &after);
+    Ok(after)
+}
@@ -0,0 +1,152 @@
+//! Checks every type with a binary encoding should pass, run over
+//! arbitrary values of the type or over golden byte vectors.
+
+use crate::fragment::FragmentId;
+use crate::key::{FragmentIdTag, HashScheme};
+use chain_core::mempack::{read_exactly, ReadError, Readable};
+use chain_core::property::Serialize;
+use quickcheck::{Arbitrary, QuickCheck, StdThreadGen, TestResult};
+use std::fmt::Debug;
+
+/// Size of the values whose serialization is truncated at every byte,
+/// which takes a time quadratic in the length of the serialization
+const TRUNCATION_SIZE: usize = 10;
+
+/// Number of bytes shown on each side of the first difference by
+/// `assert_serialization_stable`
+const DIFF_CONTEXT: usize = 8;
+
+fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>, TestResult> {
+    value
+        .serialize_as_vec()
+        .map_err(|error| TestResult::error(format!("serialization: {}", error)))
+}
+
+/// Arbitrary values read back from their serialization are the same,
+/// the whole serialization being read.
+pub fn assert_codec_roundtrip<T>()
+where
+    T: Serialize + Readable + Eq + Debug + Arbitrary,
+{
+    fn roundtrip<T: Serialize + Readable + Eq>(value: T) -> TestResult {
+        let bytes = match serialize(&value) {
+            Ok(bytes) => bytes,
+            Err(result) => return result,
+        };
+        match read_exactly::<T>(&bytes) {
+            Ok(decoded) => TestResult::from_bool(decoded == value),
+            Err(error) => TestResult::error(format!("deserialization: {}", error)),
+        }
+    }
+    QuickCheck::new().quickcheck(roundtrip::<T> as fn(T) -> TestResult);
+}
+
+/// Reading the serialization of an arbitrary value cut at any byte fails
+/// with a `ReadError`, and does not panic.
+pub fn assert_rejects_truncation<T>()
+where
+    T: Serialize + Readable + Debug + Arbitrary,
+{
+    fn truncated<T: Serialize + Readable + Debug>(value: T) -> TestResult {
+        let bytes = match serialize(&value) {
+            Ok(bytes) => bytes,
+            Err(result) => return result,
+        };
+        for cut in 0..bytes.len() {
+            if let Ok(decoded) = read_exactly::<T>(&bytes[..cut]) {
+                return TestResult::error(format!(
+                    "{:?} read from the first {} of {} bytes",
+                    decoded,
+                    cut,
+                    bytes.len()
+                ));
+            }
+        }
+        TestResult::passed()
+    }
+    QuickCheck::new()
+        .gen(StdThreadGen::new(TRUNCATION_SIZE))
+        .quickcheck(truncated::<T> as fn(T) -> TestResult);
+}
+
+/// Reading the serialization of an arbitrary value followed by more bytes
+/// fails with `ReadError::UnconsumedData`.
+pub fn assert_rejects_trailing_bytes<T>()
+where
+    T: Serialize + Readable + Debug + Arbitrary,
+{
+    fn trailing<T: Serialize + Readable + Debug>(value: T, junk: Vec<u8>) -> TestResult {
+        if junk.is_empty() {
+            return TestResult::discard();
+        }
+        let mut bytes = match serialize(&value) {
+            Ok(bytes) => bytes,
+            Err(result) => return result,
+        };
+        bytes.extend_from_slice(&junk);
+        match read_exactly::<T>(&bytes) {
+            Err(ReadError::UnconsumedData(left)) => TestResult::from_bool(left == junk.len()),
+            Err(error) => TestResult::error(format!("unexpected error: {}", error)),
+            Ok(decoded) => TestResult::error(format!("{:?} read with trailing bytes", decoded)),
+        }
+    }
+    QuickCheck::new().quickcheck(trailing::<T> as fn(T, Vec<u8>) -> TestResult);
+}
+
+/// `bytes` read as a `T` serialize back to exactly `bytes`.
+///
+/// Otherwise the identifiers of the fragments embedding a `T`, which are
+/// hashes of their bytes, would change when a node re-serializes them,
+/// and the fragments would no longer be found by the identifiers peers
+/// know them by. Use it with golden vectors pinning the encoding.
+///
+/// Panics if `bytes` cannot be read, or with the offset of the first
+/// difference and the bytes around it if they do not serialize back.
+pub fn assert_serialization_stable<T: Serialize + Readable>(bytes: &[u8]) {
+    let value = match read_exactly::<T>(bytes) {
+        Ok(value) => value,
+        Err(error) => panic!("deserialization: {}", error),
+    };
+    let serialized = match value.serialize_as_vec() {
+        Ok(serialized) => serialized,
+        Err(error) => panic!("serialization: {}", error),
+    };
+    if serialized == bytes {
+        return;
+    }
+    let offset = bytes
+        .iter()
+        .zip(serialized.iter())
+        .position(|(byte, reserialized)| byte != reserialized)
+        .unwrap_or_else(|| std::cmp::min(bytes.len(), serialized.len()));
+    panic!(
+        "re-serialization differs at offset {} ({} bytes read, {} written)\n  \
+         read:    {}\n  written: {}",
+        offset,
+        bytes.len(),
+        serialized.len(),
+        hex_context(bytes, offset),
+        hex_context(&serialized, offset),
+    );
+}
+
+/// The identifier of the fragment serialized as `bytes`, without the
+/// size prefix, as `Fragment::hash` computes it from the bytes of the
+/// fragment.
+pub fn fragment_id_of(bytes: &[u8]) -> FragmentId {
+    HashScheme::Legacy.hash::<FragmentIdTag>(bytes)
+}
+
+// the bytes around `offset` in hexadecimal, the byte at `offset` first
+// after the `|`; `offset` is at most the length of `bytes`
+fn hex_context(bytes: &[u8], offset: usize) -> String {
+    let start = offset.saturating_sub(DIFF_CONTEXT);
+    let end = std::cmp::min(bytes.len(), offset + DIFF_CONTEXT);
+    format!(
+        "{}{}|{}{}",
+        if start > 0 { "..." } else { "" },
+        hex::encode(&bytes[start..offset]),
+        hex::encode(&bytes[offset..end]),
+        if end < bytes.len() { "..." } else { "" },
+    )
+}
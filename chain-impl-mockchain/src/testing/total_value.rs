@@ -0,0 +1,77 @@
+use crate::ledger::Ledger;
+use crate::value::{Value, ValueError};
+use std::fmt;
+
+/// The funds of a ledger, by where they are held
+///
+/// Applying a fragment moves funds between these places but never
+/// creates or destroys any, so the sum of the funds is the same before
+/// and after, see `assert_value_conserved`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotalValue {
+    pub utxos: Value,
+    pub legacy_utxos: Value,
+    pub accounts: Value,
+    pub multisig: Value,
+    pub pots: Value,
+}
+
+impl TotalValue {
+    pub fn of(ledger: &Ledger) -> Result<Self, ValueError> {
+        Ok(TotalValue {
+            utxos: ledger.utxos().total_value()?,
+            legacy_utxos: ledger.utxos_legacy().total_value()?,
+            accounts: ledger.accounts().get_total_value()?,
+            multisig: ledger.multisig().get_total_value()?,
+            pots: ledger.pots().total_value()?,
+        })
+    }
+
+    /// the sum of all the funds
+    pub fn total(&self) -> Result<Value, ValueError> {
+        Value::sum(
+            [
+                self.utxos,
+                self.legacy_utxos,
+                self.accounts,
+                self.multisig,
+                self.pots,
+            ]
+            .iter()
+            .cloned(),
+        )
+    }
+}
+
+impl fmt::Display for TotalValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "utxos: {}, legacy utxos: {}, accounts: {}, multisig: {}, pots: {}",
+            self.utxos, self.legacy_utxos, self.accounts, self.multisig, self.pots
+        )
+    }
+}
+
+/// Panics, listing the funds of both ledgers, if `after` does not hold
+/// as much as `before` or if the funds of either one overflow.
+pub fn assert_value_conserved(before: &Ledger, after: &Ledger) {
+    let total_of = |ledger: &Ledger, name: &str| {
+        let funds = TotalValue::of(ledger)
+            .unwrap_or_else(|error| panic!("funds of the ledger {} overflow: {}", name, error));
+        let total = funds
+            .total()
+            .unwrap_or_else(|error| panic!("total of the ledger {} overflows: {}", name, error));
+        (funds, total)
+    };
+    let (funds_before, total_before) = total_of(before, "before");
+    let (funds_after, total_after) = total_of(after, "after");
+    assert!(
+        total_before == total_after,
+        "value is not conserved: {} before, {} after\n  before: {}\n  after:  {}",
+        total_before,
+        total_after,
+        funds_before,
+        funds_after
+    );
+}
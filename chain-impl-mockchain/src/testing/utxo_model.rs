@@ -0,0 +1,491 @@
+//! A reference model of the UTxO `Ledger`, to check it against on
+//! arbitrary sequences of operations.
+//!
+//! The model keeps every unspent output in a single `BTreeMap`, where
+//! the ledger keeps a persistent trie of the fragments, each with the
+//! map of its unspent outputs, and the fragments added without outputs
+//! apart. The two must agree on the result of every operation, errors
+//! included, and on their contents after it, see `check_against_model`.
+
+use crate::fragment::FragmentId;
+use crate::transaction::{Output, TransactionIndex, UtxoPointer};
+use crate::utxo::{EntryOwned, Error, Ledger};
+use crate::value::Value;
+use chain_addr::Address;
+use quickcheck::{Arbitrary, Gen};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The UTxO ledger as a map of its unspent outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelLedger<OutAddress> {
+    outputs: BTreeMap<(FragmentId, TransactionIndex), Output<OutAddress>>,
+    /// the fragments added without outputs, which the ledger keeps as
+    /// fragments with no unspent outputs; those emptied by spending their
+    /// outputs are removed from the ledger instead
+    empty_fragments: BTreeSet<FragmentId>,
+}
+
+impl<OutAddress: Clone> Default for ModelLedger<OutAddress> {
+    fn default() -> Self {
+        ModelLedger::new()
+    }
+}
+
+impl<OutAddress: Clone> ModelLedger<OutAddress> {
+    pub fn new() -> Self {
+        ModelLedger {
+            outputs: BTreeMap::new(),
+            empty_fragments: BTreeSet::new(),
+        }
+    }
+
+    fn has_fragment(&self, tid: &FragmentId) -> bool {
+        self.empty_fragments.contains(tid)
+            || self
+                .outputs
+                .keys()
+                .any(|(fragment_id, _)| fragment_id == tid)
+    }
+
+    /// Same as `Ledger::add`, an index given twice keeping the last of
+    /// its outputs
+    pub fn add(
+        &mut self,
+        tid: &FragmentId,
+        outs: &[(TransactionIndex, Output<OutAddress>)],
+    ) -> Result<(), Error> {
+        if self.has_fragment(tid) {
+            return Err(Error::AlreadyExists { fragment: *tid });
+        }
+        if outs.is_empty() {
+            self.empty_fragments.insert(*tid);
+        }
+        for (index, output) in outs {
+            self.outputs.insert((*tid, *index), output.clone());
+        }
+        Ok(())
+    }
+
+    /// Same as `Ledger::remove`
+    pub fn remove(
+        &mut self,
+        tid: &FragmentId,
+        index: TransactionIndex,
+    ) -> Result<Output<OutAddress>, Error> {
+        self.check_removable(tid, &[index])?;
+        Ok(self.outputs.remove(&(*tid, index)).unwrap())
+    }
+
+    /// Same as `Ledger::remove_multiple`, leaving the model unmodified
+    /// on error
+    pub fn remove_multiple(
+        &mut self,
+        pointers: &[UtxoPointer],
+    ) -> Result<Vec<Output<OutAddress>>, Error> {
+        // the ledger checks the fragments in the order of their id
+        let mut by_fragment: BTreeMap<FragmentId, Vec<TransactionIndex>> = BTreeMap::new();
+        for pointer in pointers {
+            by_fragment
+                .entry(pointer.transaction_id)
+                .or_insert_with(Vec::new)
+                .push(pointer.output_index);
+        }
+        for (tid, indices) in &by_fragment {
+            self.check_removable(tid, indices)?;
+        }
+        Ok(pointers
+            .iter()
+            .map(|pointer| {
+                self.outputs
+                    .remove(&(pointer.transaction_id, pointer.output_index))
+                    .unwrap()
+            })
+            .collect())
+    }
+
+    // the error of spending `indices` of `tid` at once, if any
+    fn check_removable(&self, tid: &FragmentId, indices: &[TransactionIndex]) -> Result<(), Error> {
+        let mut seen = BTreeSet::new();
+        for index in indices {
+            if !seen.insert(index) {
+                return Err(Error::DuplicatedIndex {
+                    fragment: *tid,
+                    index: *index,
+                });
+            }
+        }
+        if !self.has_fragment(tid) {
+            return Err(Error::TransactionNotFound { fragment: *tid });
+        }
+        if let Some(index) = indices
+            .iter()
+            .find(|index| !self.outputs.contains_key(&(*tid, **index)))
+        {
+            return Err(Error::IndexNotFound {
+                fragment: *tid,
+                index: *index,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, tid: &FragmentId, index: TransactionIndex) -> Option<&Output<OutAddress>> {
+        self.outputs.get(&(*tid, index))
+    }
+
+    /// The unspent outputs ordered by fragment id then by index, which is
+    /// the order of `Ledger::iter_sorted`
+    pub fn iter(&self) -> impl Iterator<Item = EntryOwned<OutAddress>> + '_ {
+        self.outputs
+            .iter()
+            .map(|((fragment_id, output_index), output)| EntryOwned {
+                fragment_id: *fragment_id,
+                output_index: *output_index,
+                output: output.clone(),
+            })
+    }
+}
+
+/// An operation applied to both the ledger and the model
+#[derive(Debug, Clone)]
+pub enum UtxoOp {
+    Add {
+        fragment_id: FragmentId,
+        outputs: Vec<(TransactionIndex, Output<Address>)>,
+    },
+    Remove {
+        fragment_id: FragmentId,
+        index: TransactionIndex,
+    },
+    RemoveMultiple {
+        pointers: Vec<UtxoPointer>,
+    },
+    Get {
+        fragment_id: FragmentId,
+        index: TransactionIndex,
+    },
+    Iter,
+}
+
+/// What an operation gives back, to compare between the ledger and the
+/// model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtxoOutcome {
+    Added(Result<(), Error>),
+    Removed(Result<Output<Address>, Error>),
+    RemovedMultiple(Result<Vec<Output<Address>>, Error>),
+    Got(Option<Output<Address>>),
+    Iterated(Vec<EntryOwned<Address>>),
+}
+
+/// A sequence of operations on a few fragments, so that they hit the
+/// outputs added by the previous ones.
+///
+/// An `Add` may have no outputs, which adds a fragment that can never be
+/// added again.
+/// Shrinking drops operations, down to a sequence where none of them can
+/// be dropped.
+#[derive(Debug, Clone)]
+pub struct UtxoOps(pub Vec<UtxoOp>);
+
+// the indices are kept low for the operations to find outputs
+const MAX_GENERATED_INDEX: u8 = 8;
+
+fn arbitrary_index<G: Gen>(g: &mut G) -> TransactionIndex {
+    TransactionIndex::new(u8::arbitrary(g) % MAX_GENERATED_INDEX).unwrap()
+}
+
+impl Arbitrary for UtxoOps {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let fragment_ids: Vec<FragmentId> = (0..usize::arbitrary(g) % 4 + 1)
+            .map(|_| FragmentId::arbitrary(g))
+            .collect();
+        let n_ops = usize::arbitrary(g) % 40;
+        let ops = (0..n_ops)
+            .map(|_| {
+                let fragment_id = fragment_ids[usize::arbitrary(g) % fragment_ids.len()];
+                match u8::arbitrary(g) % 10 {
+                    0..=2 => UtxoOp::Add {
+                        fragment_id,
+                        outputs: (0..usize::arbitrary(g) % 6)
+                            .map(|_| (arbitrary_index(g), Output::arbitrary(g)))
+                            .collect(),
+                    },
+                    3..=5 => UtxoOp::Remove {
+                        fragment_id,
+                        index: arbitrary_index(g),
+                    },
+                    6..=7 => UtxoOp::RemoveMultiple {
+                        pointers: (0..usize::arbitrary(g) % 4)
+                            .map(|_| {
+                                let fragment_id =
+                                    fragment_ids[usize::arbitrary(g) % fragment_ids.len()];
+                                UtxoPointer::new(fragment_id, arbitrary_index(g), Value::zero())
+                            })
+                            .collect(),
+                    },
+                    8 => UtxoOp::Get {
+                        fragment_id,
+                        index: arbitrary_index(g),
+                    },
+                    _ => UtxoOp::Iter,
+                }
+            })
+            .collect();
+        UtxoOps(ops)
+    }
+
+    /// shrink to the first half of the operations, then by dropping one
+    /// operation at a time
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let ops = self.0.clone();
+        let half = if ops.len() > 1 {
+            Some(UtxoOps(ops[..ops.len() / 2].to_vec()))
+        } else {
+            None
+        };
+        let dropped = (0..ops.len()).map(move |i| {
+            let mut ops = ops.clone();
+            ops.remove(i);
+            UtxoOps(ops)
+        });
+        Box::new(half.into_iter().chain(dropped))
+    }
+}
+
+/// The first disagreement between the ledger and the model
+#[derive(Debug, Clone)]
+pub struct ModelMismatch {
+    /// the position of the operation in the sequence
+    pub step: usize,
+    pub op: UtxoOp,
+    pub ledger: String,
+    pub model: String,
+}
+
+impl fmt::Display for ModelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "step {} ({:?}): the ledger gives {} where the model gives {}",
+            self.step, self.op, self.ledger, self.model
+        )
+    }
+}
+
+fn apply_to_ledger(ledger: &mut Ledger<Address>, op: &UtxoOp) -> UtxoOutcome {
+    match op {
+        UtxoOp::Add {
+            fragment_id,
+            outputs,
+        } => UtxoOutcome::Added(ledger.add(fragment_id, outputs).map(|next| {
+            *ledger = next;
+        })),
+        UtxoOp::Remove { fragment_id, index } => {
+            UtxoOutcome::Removed(ledger.remove(fragment_id, *index).map(|(next, output)| {
+                *ledger = next;
+                output
+            }))
+        }
+        UtxoOp::RemoveMultiple { pointers } => {
+            UtxoOutcome::RemovedMultiple(ledger.remove_multiple(pointers).map(|(next, outputs)| {
+                *ledger = next;
+                outputs
+            }))
+        }
+        UtxoOp::Get { fragment_id, index } => UtxoOutcome::Got(
+            ledger
+                .get(fragment_id, index)
+                .map(|entry| entry.output.clone()),
+        ),
+        UtxoOp::Iter => {
+            // `iter` has no order of its own, `iter_sorted` is checked
+            // after every step
+            let mut entries: Vec<_> = ledger.iter().map(|entry| entry.to_owned()).collect();
+            entries.sort_by(|a, b| {
+                (&a.fragment_id, a.output_index).cmp(&(&b.fragment_id, b.output_index))
+            });
+            UtxoOutcome::Iterated(entries)
+        }
+    }
+}
+
+fn apply_to_model(model: &mut ModelLedger<Address>, op: &UtxoOp) -> UtxoOutcome {
+    match op {
+        UtxoOp::Add {
+            fragment_id,
+            outputs,
+        } => UtxoOutcome::Added(model.add(fragment_id, outputs)),
+        UtxoOp::Remove { fragment_id, index } => {
+            UtxoOutcome::Removed(model.remove(fragment_id, *index))
+        }
+        UtxoOp::RemoveMultiple { pointers } => {
+            UtxoOutcome::RemovedMultiple(model.remove_multiple(pointers))
+        }
+        UtxoOp::Get { fragment_id, index } => {
+            UtxoOutcome::Got(model.get(fragment_id, *index).cloned())
+        }
+        UtxoOp::Iter => UtxoOutcome::Iterated(model.iter().collect()),
+    }
+}
+
+/// Apply `ops` to an empty ledger and an empty model, checking that they
+/// give the same outcome for every operation and have the same entries
+/// after it.
+pub fn check_against_model(ops: &[UtxoOp]) -> Result<(), ModelMismatch> {
+    let mut ledger = Ledger::new();
+    let mut model = ModelLedger::new();
+    for (step, op) in ops.iter().enumerate() {
+        let mismatch = |ledger: String, model: String| ModelMismatch {
+            step,
+            op: op.clone(),
+            ledger,
+            model,
+        };
+
+        let ledger_outcome = apply_to_ledger(&mut ledger, op);
+        let model_outcome = apply_to_model(&mut model, op);
+        if ledger_outcome != model_outcome {
+            return Err(mismatch(
+                format!("{:?}", ledger_outcome),
+                format!("{:?}", model_outcome),
+            ));
+        }
+
+        let ledger_entries = ledger.to_sorted_vec();
+        let model_entries: Vec<_> = model.iter().collect();
+        if ledger_entries != model_entries {
+            return Err(mismatch(
+                format!("the entries {:?}", ledger_entries),
+                format!("the entries {:?}", model_entries),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{QuickCheck, StdGen, TestResult};
+    use quickcheck_macros::quickcheck;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    fn agrees_with_the_model(ops: UtxoOps) -> TestResult {
+        match check_against_model(&ops.0) {
+            Ok(()) => TestResult::passed(),
+            Err(mismatch) => TestResult::error(mismatch.to_string()),
+        }
+    }
+
+    #[quickcheck]
+    fn utxo_ledger_agrees_with_the_model(ops: UtxoOps) -> TestResult {
+        agrees_with_the_model(ops)
+    }
+
+    // the same check from fixed seeds, so that a failure shows up on
+    // every run and can be reproduced
+    #[test]
+    fn utxo_ledger_agrees_with_the_model_from_fixed_seeds() {
+        for seed in 0..4 {
+            QuickCheck::new()
+                .gen(StdGen::new(ChaChaRng::from_seed([seed; 32]), 100))
+                .tests(50)
+                .quickcheck(agrees_with_the_model as fn(UtxoOps) -> TestResult);
+        }
+    }
+
+    #[test]
+    fn emptied_fragments_can_be_added_again() {
+        let mut g = StdGen::new(ChaChaRng::from_seed([0; 32]), 10);
+        let fragment_id = FragmentId::hash_bytes(&[1]);
+        let mut output = || Output::arbitrary(&mut g);
+        let index = |i| TransactionIndex::new(i).unwrap();
+        let pointers = |indices: &[u8]| {
+            indices
+                .iter()
+                .map(|i| UtxoPointer::new(fragment_id, index(*i), Value::zero()))
+                .collect::<Vec<_>>()
+        };
+        let ops = vec![
+            UtxoOp::Add {
+                fragment_id,
+                outputs: vec![(index(0), output()), (index(2), output())],
+            },
+            UtxoOp::RemoveMultiple {
+                pointers: pointers(&[2, 2]),
+            },
+            UtxoOp::Remove {
+                fragment_id,
+                index: index(0),
+            },
+            UtxoOp::Remove {
+                fragment_id,
+                index: index(0),
+            },
+            UtxoOp::RemoveMultiple {
+                pointers: pointers(&[2, 1]),
+            },
+            UtxoOp::Remove {
+                fragment_id,
+                index: index(2),
+            },
+            UtxoOp::Get {
+                fragment_id,
+                index: index(2),
+            },
+            UtxoOp::Add {
+                fragment_id,
+                outputs: vec![(index(1), output()), (index(1), output())],
+            },
+            UtxoOp::Iter,
+        ];
+        assert!(check_against_model(&ops).is_ok());
+    }
+
+    #[test]
+    fn fragments_added_without_outputs_cannot_be_added_again() {
+        let mut g = StdGen::new(ChaChaRng::from_seed([1; 32]), 10);
+        let fragment_id = FragmentId::hash_bytes(&[2]);
+        let mut output = || Output::arbitrary(&mut g);
+        let index = |i| TransactionIndex::new(i).unwrap();
+
+        let ledger = Ledger::<Address>::new().add(&fragment_id, &[]).unwrap();
+        assert_eq!(
+            ledger.add(&fragment_id, &[(index(0), output())]).err(),
+            Some(Error::AlreadyExists {
+                fragment: fragment_id
+            })
+        );
+
+        let ops = vec![
+            UtxoOp::Add {
+                fragment_id,
+                outputs: vec![],
+            },
+            UtxoOp::Add {
+                fragment_id,
+                outputs: vec![],
+            },
+            UtxoOp::Add {
+                fragment_id,
+                outputs: vec![(index(0), output())],
+            },
+            UtxoOp::Remove {
+                fragment_id,
+                index: index(0),
+            },
+            UtxoOp::RemoveMultiple {
+                pointers: vec![UtxoPointer::new(fragment_id, index(0), Value::zero())],
+            },
+            UtxoOp::Get {
+                fragment_id,
+                index: index(0),
+            },
+            UtxoOp::Iter,
+        ];
+        assert!(check_against_model(&ops).is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+//! Stable identities for test fixtures, named instead of generated, so
+//! that fixture files can refer to "alice" or "bob".
+
+use crate::{key::EitherEd25519SecretKey, testing::data::AddressData};
+use chain_addr::{Address, Discrimination, Kind};
+use chain_crypto::{Ed25519, PublicKey};
+
+/// The keys and single address of the identity `name`, see `named`
+#[derive(Clone, Debug)]
+pub struct NamedWallet {
+    pub name: String,
+    pub secret_key: EitherEd25519SecretKey,
+    pub public_key: PublicKey<Ed25519>,
+    /// the single address of `public_key`, on the test discrimination
+    pub address: Address,
+}
+
+impl NamedWallet {
+    /// the wallet as used by the transaction builders
+    pub fn address_data(&self) -> AddressData {
+        AddressData::new(self.secret_key.clone(), None, self.address.clone())
+    }
+}
+
+/// The identity `name`, whose key is
+/// `EitherEd25519SecretKey::from_passphrase(name)`: the same name always
+/// gives the same keys and address
+pub fn named(name: &str) -> NamedWallet {
+    let secret_key = EitherEd25519SecretKey::from_passphrase(name);
+    let public_key = secret_key.to_public();
+    let address = Address(Discrimination::Test, Kind::Single(public_key.clone()));
+    NamedWallet {
+        name: name.to_owned(),
+        secret_key,
+        public_key,
+        address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_wallets_are_reproducible() {
+        let alice = named("alice");
+        assert_eq!(alice.address, named("alice").address);
+        assert_ne!(alice.address, named("bob").address);
+        assert_eq!(
+            alice.address.kind(),
+            &Kind::Single(alice.public_key.clone())
+        );
+        assert_eq!(alice.address_data().public_key(), alice.public_key);
+    }
+}
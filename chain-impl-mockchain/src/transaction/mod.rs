@@ -3,7 +3,7 @@ mod transfer;
 mod utxo;
 mod witness;
 
-use chain_addr::Address;
+use chain_addr::{Address, ADDR_SIZE_GROUP};
 use chain_core::mempack::{read_vec, ReadBuf, ReadError, Readable};
 use chain_core::property;
 
@@ -51,6 +51,35 @@ impl<Extra: Readable> Readable for AuthenticatedTransaction<Address, Extra> {
     }
 }
 
+// the serialized sizes of the parts of a transaction: the counts of
+// inputs and outputs, an input, and the value of an output
+const COUNTS_SIZE: usize = 2;
+const VALUE_SIZE: usize = std::mem::size_of::<u64>();
+const INPUT_SIZE: usize = 1 + VALUE_SIZE + INPUT_PTR_SIZE;
+
+/// Estimate the serialized size of an `AuthenticatedTransaction` without
+/// extra, of `n_inputs` inputs witnessed by witnesses of `witness_kinds`
+/// and of `n_outputs` outputs, before making it, e.g. to know the fees
+/// of a size-based fee algorithm.
+///
+/// The size of an output depends on the kind of its address: the
+/// estimate is for the largest one, the group addresses, so that it is
+/// an upper bound of the actual size.
+pub fn estimate_transaction_size(
+    n_inputs: usize,
+    n_outputs: usize,
+    witness_kinds: &[WitnessKind],
+) -> usize {
+    let witnesses_size: usize = witness_kinds
+        .iter()
+        .map(|kind| kind.serialized_size())
+        .sum();
+    COUNTS_SIZE
+        + n_inputs * INPUT_SIZE
+        + n_outputs * (ADDR_SIZE_GROUP + VALUE_SIZE)
+        + witnesses_size
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -72,6 +101,24 @@ mod test {
         fn signed_transaction_encode_decode(transaction: AuthenticatedTransaction<Address, NoExtra>) -> TestResult {
             chain_core::property::testing::serialization_bijection_r(transaction)
         }
+        fn estimated_size_bounds_the_serialized_size(transaction: AuthenticatedTransaction<Address, NoExtra>) -> bool {
+            use chain_core::property::Serialize as _;
+            let size = transaction.serialize_as_vec().unwrap().len();
+            let kinds: Vec<_> = transaction.witnesses.iter().map(Witness::kind).collect();
+            let estimate = estimate_transaction_size(
+                transaction.transaction.inputs.len(),
+                transaction.transaction.outputs.len(),
+                &kinds,
+            );
+            // only the addresses smaller than group addresses make a difference
+            let slack: usize = transaction
+                .transaction
+                .outputs
+                .iter()
+                .map(|output| ADDR_SIZE_GROUP - output.address.to_bytes().len())
+                .sum();
+            estimate == size + slack
+        }
     }
 
     impl Arbitrary for UtxoPointer {
@@ -1,7 +1,7 @@
-use super::utxo::UtxoPointer;
+use super::utxo::{TransactionIndex, UtxoPointer, INDEX_ACCOUNT};
 use crate::account::Identifier;
 use crate::fragment::FragmentId;
-use crate::key::SpendingPublicKey;
+use crate::key::AccountPublicKey;
 use crate::legacy::OldAddress;
 use crate::utxo::Entry;
 use crate::value::*;
@@ -80,7 +80,7 @@ pub enum InputEnum {
 
 impl Input {
     pub fn get_type(&self) -> InputType {
-        if self.index_or_account == 0xff {
+        if self.index_or_account == INDEX_ACCOUNT {
             InputType::Account
         } else {
             InputType::Utxo
@@ -91,7 +91,7 @@ impl Input {
         let mut input_ptr = [0u8; INPUT_PTR_SIZE];
         input_ptr.clone_from_slice(utxo_pointer.transaction_id.as_ref());
         Input {
-            index_or_account: utxo_pointer.output_index,
+            index_or_account: utxo_pointer.output_index.as_u8(),
             value: utxo_pointer.value,
             input_ptr: input_ptr,
         }
@@ -101,13 +101,13 @@ impl Input {
         let mut input_ptr = [0u8; INPUT_PTR_SIZE];
         input_ptr.clone_from_slice(utxo_entry.fragment_id.as_ref());
         Input {
-            index_or_account: utxo_entry.output_index,
+            index_or_account: utxo_entry.output_index.as_u8(),
             value: utxo_entry.output.value,
             input_ptr: input_ptr,
         }
     }
 
-    pub fn from_account_public_key(public_key: SpendingPublicKey, value: Value) -> Self {
+    pub fn from_account_public_key(public_key: AccountPublicKey, value: Value) -> Self {
         Input::from_account(
             AccountIdentifier::from_single_account(Identifier::from(public_key)),
             value,
@@ -118,7 +118,7 @@ impl Input {
         let mut input_ptr = [0u8; INPUT_PTR_SIZE];
         input_ptr.copy_from_slice(&id.0);
         Input {
-            index_or_account: 0xff,
+            index_or_account: INDEX_ACCOUNT,
             value: value,
             input_ptr: input_ptr,
         }
@@ -135,15 +135,15 @@ impl Input {
     }
 
     pub fn to_enum(&self) -> InputEnum {
-        match self.get_type() {
-            InputType::Account => {
+        match TransactionIndex::new(self.index_or_account) {
+            None => {
                 let account_identifier = self.input_ptr.clone();
                 let id = AccountIdentifier(account_identifier);
                 InputEnum::AccountInput(id, self.value)
             }
-            InputType::Utxo => InputEnum::UtxoInput(UtxoPointer::new(
+            Some(index) => InputEnum::UtxoInput(UtxoPointer::new(
                 FragmentId::from(self.input_ptr.clone()),
-                self.index_or_account,
+                index,
                 self.value,
             )),
         }
@@ -217,6 +217,16 @@ impl<Address: Readable> Output<Address> {
     }
 }
 
+impl<Address: Clone> Output<Address> {
+    /// The same output, sending `value` instead
+    pub fn with_value(&self, value: Value) -> Self {
+        Output {
+            address: self.address.clone(),
+            value,
+        }
+    }
+}
+
 impl<Address: Readable> Readable for Output<Address> {
     fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
         let address = Address::read(buf)?;
@@ -1,7 +1,75 @@
 use crate::fragment::FragmentId;
+use crate::utxo::Entry;
 use crate::value::*;
+use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::property;
+use std::str::FromStr;
 
-pub type TransactionIndex = u8;
+/// The index of an output within the outputs of a transaction
+///
+/// An input uses the same byte for the index of the output it spends or
+/// for `INDEX_ACCOUNT` when it spends from an account, so 255 is never
+/// a valid index and a transaction has at most 255 outputs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TransactionIndex(u8);
+
+/// The byte marking an input spending from an account rather than from
+/// an output
+pub const INDEX_ACCOUNT: u8 = 0xff;
+
+impl TransactionIndex {
+    /// The largest number of outputs of a transaction
+    pub const MAX_OUTPUTS: usize = INDEX_ACCOUNT as usize;
+
+    /// `None` for `INDEX_ACCOUNT`, which is not an output index
+    pub fn new(index: u8) -> Option<Self> {
+        if index == INDEX_ACCOUNT {
+            None
+        } else {
+            Some(TransactionIndex(index))
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        self.0
+    }
+
+    /// The indices of the first `n` outputs of a transaction, stopping at
+    /// `MAX_OUTPUTS`
+    pub fn iter_to(n: usize) -> impl Iterator<Item = Self> + Clone {
+        (0..std::cmp::min(n, Self::MAX_OUTPUTS)).map(|index| TransactionIndex(index as u8))
+    }
+}
+
+impl From<TransactionIndex> for u8 {
+    fn from(index: TransactionIndex) -> u8 {
+        index.0
+    }
+}
+
+impl std::fmt::Display for TransactionIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Readable for TransactionIndex {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let index = buf.get_u8()?;
+        TransactionIndex::new(index).ok_or_else(|| {
+            ReadError::StructureInvalid(format!("invalid transaction index {}", index))
+        })
+    }
+}
+
+impl property::Serialize for TransactionIndex {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        use chain_core::packer::*;
+        let mut codec = Codec::new(writer);
+        codec.put_u8(self.0)
+    }
+}
 
 /// Unspent transaction pointer.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -25,14 +93,141 @@ impl UtxoPointer {
             value,
         }
     }
+
+    /// the pointer to the output of `entry`, expecting its value
+    pub fn from_entry<OutAddress>(entry: &Entry<OutAddress>) -> Self {
+        UtxoPointer::new(
+            entry.fragment_id.clone(),
+            entry.output_index,
+            entry.output.value,
+        )
+    }
+}
+
+custom_error! {
+    #[derive(Clone, PartialEq)]
+    pub UtxoPointerParseError
+        MissingIndex = "Expected an output pointer of the form <fragment id>.<index>",
+        InvalidFragmentId { source: chain_crypto::hash::Error } = "Invalid fragment id: {source}",
+        InvalidIndex { index: String } = "Invalid output index {index}, expected a number below 255",
 }
 
+/// `<fragment id in hex>.<output index>`, the format of the command line
+/// tools; the expected value is not part of it
 impl std::fmt::Display for UtxoPointer {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "{}@{}.{}",
-            self.transaction_id, self.output_index, self.value
-        )
+        write!(f, "{}.{}", self.transaction_id, self.output_index)
+    }
+}
+
+/// Parse the format of `Display`
+///
+/// As the string does not tell the value of the output, the pointer
+/// expects a zero value: resolve it against a ledger with
+/// `utxo::Ledger::get_pointed` to know the actual output.
+impl FromStr for UtxoPointer {
+    type Err = UtxoPointerParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '.');
+        let transaction_id = parts.next().unwrap_or("");
+        let index = parts.next().ok_or(UtxoPointerParseError::MissingIndex)?;
+        let transaction_id = FragmentId::from_str(transaction_id)?;
+        let output_index = index
+            .parse::<u8>()
+            .ok()
+            .and_then(TransactionIndex::new)
+            .ok_or_else(|| UtxoPointerParseError::InvalidIndex {
+                index: index.to_string(),
+            })?;
+        Ok(UtxoPointer::new(
+            transaction_id,
+            output_index,
+            Value::zero(),
+        ))
+    }
+}
+
+impl Readable for UtxoPointer {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        let transaction_id = FragmentId::read(buf)?;
+        let output_index = TransactionIndex::read(buf)?;
+        let value = Value::read(buf)?;
+        Ok(UtxoPointer::new(transaction_id, output_index, value))
+    }
+}
+
+impl property::Serialize for UtxoPointer {
+    type Error = std::io::Error;
+    fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+        use chain_core::property::Serialize as _;
+        self.transaction_id.serialize(&mut writer)?;
+        self.output_index.serialize(&mut writer)?;
+        self.value.serialize(&mut writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_core::mempack::read_exactly;
+    use chain_core::property::Serialize;
+
+    #[test]
+    fn index_of_an_account_is_rejected() {
+        assert_eq!(TransactionIndex::new(INDEX_ACCOUNT), None);
+        assert!(read_exactly::<TransactionIndex>(&[INDEX_ACCOUNT]).is_err());
+        assert_eq!(TransactionIndex::new(254).map(u8::from), Some(254));
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        for index in &[0, 254] {
+            let index = TransactionIndex::new(*index).unwrap();
+            let bytes = index.serialize_as_vec().unwrap();
+            assert_eq!(bytes, vec![index.as_u8()]);
+            assert_eq!(read_exactly::<TransactionIndex>(&bytes).unwrap(), index);
+        }
+    }
+
+    #[test]
+    fn iter_to_stops_at_the_largest_index() {
+        assert_eq!(TransactionIndex::iter_to(0).count(), 0);
+        let indices: Vec<u8> = TransactionIndex::iter_to(3).map(u8::from).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        let last = TransactionIndex::iter_to(1000).last().unwrap();
+        assert_eq!(TransactionIndex::iter_to(1000).count(), 255);
+        assert_eq!(last.as_u8(), 254);
+    }
+
+    #[test]
+    fn pointer_display_and_parse_round_trip() {
+        let fragment_id = FragmentId::hash_bytes(b"pointed fragment");
+        let index = TransactionIndex::new(254).unwrap();
+        let pointer = UtxoPointer::new(fragment_id, index, Value::zero());
+        let text = pointer.to_string();
+        assert_eq!(text, format!("{}.254", fragment_id));
+        assert_eq!(text.parse::<UtxoPointer>(), Ok(pointer));
+
+        let pointer = UtxoPointer::new(fragment_id, index, Value(42));
+        let bytes = pointer.serialize_as_vec().unwrap();
+        assert_eq!(read_exactly::<UtxoPointer>(&bytes).unwrap(), pointer);
+    }
+
+    #[test]
+    fn invalid_pointers_are_rejected() {
+        let fragment_id = FragmentId::hash_bytes(b"pointed fragment");
+        for index in &["255", "256", "-1", "", "a"] {
+            assert_eq!(
+                format!("{}.{}", fragment_id, index).parse::<UtxoPointer>(),
+                Err(UtxoPointerParseError::InvalidIndex {
+                    index: index.to_string()
+                })
+            );
+        }
+        assert_eq!(
+            fragment_id.to_string().parse::<UtxoPointer>(),
+            Err(UtxoPointerParseError::MissingIndex)
+        );
+        assert!("abcd.0".parse::<UtxoPointer>().is_err());
     }
 }
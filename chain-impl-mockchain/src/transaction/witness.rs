@@ -2,13 +2,17 @@ use super::transaction::*;
 use crate::account;
 use crate::block::HeaderHash;
 use crate::key::{
-    deserialize_public_key, deserialize_signature, serialize_public_key, serialize_signature,
-    EitherEd25519SecretKey, SpendingPublicKey, SpendingSignature,
+    ct_eq, deserialize_public_key, deserialize_signature, serialize_public_key,
+    serialize_signature, AccountSignature, EitherEd25519SecretKey, SpendingPublicKey,
+    SpendingSignature,
 };
 use crate::multisig;
 use chain_core::mempack::{ReadBuf, ReadError, Readable};
 use chain_core::property;
-use chain_crypto::{Ed25519Bip32, PublicKey, Signature, Verification};
+use chain_crypto::{
+    AsymmetricPublicKey, Ed25519, Ed25519Bip32, PublicKey, Signature, Verification,
+    VerificationAlgorithm,
+};
 
 /// Structure that proofs that certain user agrees with
 /// some data. This structure is used to sign `Transaction`
@@ -30,11 +34,11 @@ pub enum Witness {
 impl PartialEq for Witness {
     fn eq(&self, rhs: &Self) -> bool {
         match (self, rhs) {
-            (Witness::Utxo(s1), Witness::Utxo(s2)) => s1.as_ref() == s2.as_ref(),
-            (Witness::Account(s1), Witness::Account(s2)) => s1.as_ref() == s2.as_ref(),
+            (Witness::Utxo(s1), Witness::Utxo(s2)) => ct_eq(s1.as_ref(), s2.as_ref()),
+            (Witness::Account(s1), Witness::Account(s2)) => ct_eq(s1.as_ref(), s2.as_ref()),
             (Witness::Multisig(s1), Witness::Multisig(s2)) => s1 == s2,
             (Witness::OldUtxo(p1, s1), Witness::OldUtxo(p2, s2)) => {
-                s1.as_ref() == s2.as_ref() && p1 == p2
+                ct_eq(s1.as_ref(), s2.as_ref()) && p1 == p2
             }
             (_, _) => false,
         }
@@ -124,8 +128,7 @@ impl Witness {
         secret_key: &EitherEd25519SecretKey,
     ) -> Self {
         let wud = WitnessUtxoData::new(block0, sign_data_hash);
-        let sig = secret_key.sign(&wud);
-        Witness::Utxo(sig)
+        Witness::Utxo(SpendingSignature::sign(secret_key, &wud))
     }
 
     pub fn new_account(
@@ -135,8 +138,7 @@ impl Witness {
         secret_key: &EitherEd25519SecretKey,
     ) -> Self {
         let wud = WitnessAccountData::new(block0, sign_data_hash, spending_counter);
-        let sig = secret_key.sign(&wud);
-        Witness::Account(sig)
+        Witness::Account(AccountSignature::sign(secret_key, &wud))
     }
 
     // Verify the given `TransactionSignDataHash` using the witness.
@@ -162,6 +164,67 @@ const WITNESS_TAG_UTXO: u8 = 1u8;
 const WITNESS_TAG_ACCOUNT: u8 = 2u8;
 const WITNESS_TAG_MULTISIG: u8 = 3u8;
 
+// the size of the tag of a serialized witness
+const WITNESS_TAG_SIZE: usize = 1;
+
+/// Serialized size of a `Witness::Utxo`
+pub const UTXO_WITNESS_SIZE: usize =
+    WITNESS_TAG_SIZE + <Ed25519 as VerificationAlgorithm>::SIGNATURE_SIZE;
+
+/// Serialized size of a `Witness::Account`
+pub const ACCOUNT_WITNESS_SIZE: usize =
+    WITNESS_TAG_SIZE + <Ed25519 as VerificationAlgorithm>::SIGNATURE_SIZE;
+
+/// Serialized size of a `Witness::OldUtxo`: the xpub then the signature
+pub const LEGACY_WITNESS_SIZE: usize = WITNESS_TAG_SIZE
+    + <Ed25519Bip32 as AsymmetricPublicKey>::PUBLIC_KEY_SIZE
+    + <Ed25519Bip32 as VerificationAlgorithm>::SIGNATURE_SIZE;
+
+/// The kinds of witnesses, to know the size of a witness before making
+/// it, see `WitnessKind::serialized_size`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessKind {
+    Utxo,
+    Account,
+    OldUtxo,
+    /// a multisig witness with this number of signatures
+    Multisig {
+        signatures: usize,
+    },
+}
+
+impl WitnessKind {
+    /// The serialized size of the witnesses of this kind
+    pub fn serialized_size(self) -> usize {
+        match self {
+            WitnessKind::Utxo => UTXO_WITNESS_SIZE,
+            WitnessKind::Account => ACCOUNT_WITNESS_SIZE,
+            WitnessKind::OldUtxo => LEGACY_WITNESS_SIZE,
+            WitnessKind::Multisig { signatures } => {
+                WITNESS_TAG_SIZE + multisig::Witness::serialized_size_of(signatures)
+            }
+        }
+    }
+}
+
+impl Witness {
+    pub fn kind(&self) -> WitnessKind {
+        match self {
+            Witness::Utxo(_) => WitnessKind::Utxo,
+            Witness::Account(_) => WitnessKind::Account,
+            Witness::OldUtxo(_, _) => WitnessKind::OldUtxo,
+            Witness::Multisig(msig) => WitnessKind::Multisig {
+                signatures: msig.signatures_count(),
+            },
+        }
+    }
+
+    /// The number of bytes of the serialized witness
+    pub fn serialized_size(&self) -> usize {
+        self.kind().serialized_size()
+    }
+}
+
 impl property::Serialize for Witness {
     type Error = std::io::Error;
 
@@ -177,11 +240,11 @@ impl property::Serialize for Witness {
             }
             Witness::Utxo(sig) => {
                 codec.put_u8(WITNESS_TAG_UTXO)?;
-                serialize_signature(sig, codec.into_inner())
+                serialize_signature(sig.as_raw(), codec.into_inner())
             }
             Witness::Account(sig) => {
                 codec.put_u8(WITNESS_TAG_ACCOUNT)?;
-                serialize_signature(sig, codec.into_inner())
+                serialize_signature(sig.as_raw(), codec.into_inner())
             }
             Witness::Multisig(msig) => {
                 codec.put_u8(WITNESS_TAG_MULTISIG)?;
@@ -199,8 +262,8 @@ impl Readable for Witness {
                 let sig = deserialize_signature(buf)?;
                 Ok(Witness::OldUtxo(xpub, sig))
             }
-            WITNESS_TAG_UTXO => deserialize_signature(buf).map(Witness::Utxo),
-            WITNESS_TAG_ACCOUNT => deserialize_signature(buf).map(Witness::Account),
+            WITNESS_TAG_UTXO => SpendingSignature::read(buf).map(Witness::Utxo),
+            WITNESS_TAG_ACCOUNT => AccountSignature::read(buf).map(Witness::Account),
             WITNESS_TAG_MULTISIG => {
                 let msig = multisig::Witness::read(buf)?;
                 Ok(Witness::Multisig(msig))
@@ -213,6 +276,10 @@ impl Readable for Witness {
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::key::AccountPublicKey;
+    use crate::multisig::{Index, TreeIndex, WitnessBuilder};
+    use crate::testing::keys::KeyFactory;
+    use chain_core::property::Serialize as _;
     use chain_crypto::SecretKey;
     use quickcheck::{Arbitrary, Gen};
 
@@ -242,7 +309,7 @@ pub mod test {
 
     impl Arbitrary for Witness {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
-            Witness::Utxo(SpendingSignature::arbitrary(g))
+            Witness::Utxo(SpendingSignature::from_raw(Arbitrary::arbitrary(g)))
         }
     }
 
@@ -252,9 +319,73 @@ pub mod test {
         /// \forall w=Witness(tx) => w.verifies(tx)
         /// ```
         fn prop_witness_verifies_own_tx(sk: TransactionSigningKey, tx:TransactionSignDataHash, block0: HeaderHash) -> bool {
-            let pk = sk.0.to_public();
+            let pk = SpendingPublicKey::from_secret(&sk.0);
             let witness = Witness::new_utxo(&block0, &tx, &sk.0);
             witness.verify_utxo(&pk, &block0, &tx) == Verification::Success
         }
     }
+
+    #[test]
+    fn signatures_only_verify_with_the_key_of_their_use() {
+        let sk = EitherEd25519SecretKey::Normal(KeyFactory::from_seed([1; 32]).next_key());
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        let tx = TransactionSignDataHash::from([1; 32]);
+        let data = WitnessUtxoData::new(&block0, &tx);
+
+        let witness = Witness::new_account(&block0, &tx, &account::SpendingCounter::zero(), &sk);
+        assert_eq!(
+            witness.verify_utxo(&SpendingPublicKey::from_secret(&sk), &block0, &tx),
+            Verification::Failed
+        );
+
+        // using an account signature as a spending one takes going through
+        // the raw types
+        let signature = AccountSignature::sign(&sk, &data);
+        let account_pk = AccountPublicKey::from_secret(&sk);
+        assert_eq!(signature.verify(&account_pk, &data), Verification::Success);
+        let signature = SpendingSignature::from_raw(signature.into_raw());
+        let spending_pk = SpendingPublicKey::from_raw(account_pk.into_raw());
+        assert_eq!(signature.verify(&spending_pk, &data), Verification::Success);
+    }
+
+    #[test]
+    fn witness_sizes_are_their_serialized_sizes() {
+        let mut keys = KeyFactory::from_seed([2; 32]);
+        let block0 = HeaderHash::hash_bytes(b"block0");
+        let tx = TransactionSignDataHash::from([2; 32]);
+        let counter = account::SpendingCounter::zero();
+        let sk = EitherEd25519SecretKey::Normal(keys.next_key());
+
+        let legacy_key: SecretKey<Ed25519Bip32> = keys.next_key();
+        let legacy_signature = legacy_key.sign(&WitnessUtxoData::new(&block0, &tx));
+        let multisig_data = WitnessMultisigData::new(&block0, &tx, &counter);
+        let mut multisig = WitnessBuilder::new();
+        for index in 0..3 {
+            let key: SecretKey<Ed25519> = keys.next_key();
+            let signature = key.sign(&multisig_data);
+            let index = TreeIndex::D1(Index::from_u8(index).unwrap());
+            multisig.append(index, key.to_public(), signature);
+        }
+
+        let witnesses = vec![
+            (Witness::new_utxo(&block0, &tx, &sk), UTXO_WITNESS_SIZE),
+            (
+                Witness::new_account(&block0, &tx, &counter, &sk),
+                ACCOUNT_WITNESS_SIZE,
+            ),
+            (
+                Witness::OldUtxo(legacy_key.to_public(), legacy_signature),
+                LEGACY_WITNESS_SIZE,
+            ),
+            (
+                Witness::Multisig(multisig.finalize()),
+                WitnessKind::Multisig { signatures: 3 }.serialized_size(),
+            ),
+        ];
+        for (witness, size) in witnesses {
+            let bytes = witness.serialize_as_vec().unwrap();
+            assert_eq!(bytes.len(), size, "size of a {}", witness);
+            assert_eq!(witness.serialized_size(), size, "size of a {}", witness);
+        }
+    }
 }
@@ -477,7 +477,7 @@ mod test {
             .unwrap();
 
         // assert
-        let actual_params = ledger.settings.to_config_params();
+        let actual_params = ledger.settings_snapshot().to_config_params();
         let expected_params = update_proposal_data.proposal_settings();
 
         let mut all_settings_equal = true;
@@ -502,10 +502,10 @@ mod test {
         block_signing_key: &SecretKey<Ed25519>,
     ) -> Block {
         let mut block_builder = BlockBuilder::new();
-        block_builder.chain_length(ledger.chain_length.next());
+        block_builder.chain_length(ledger.chain_length().next());
         block_builder.parent(block0_hash);
         block_builder.date(date.next_epoch());
-        block_builder.make_bft_block(block_signing_key)
+        block_builder.make_bft_block(block_signing_key).unwrap()
     }
 
 }
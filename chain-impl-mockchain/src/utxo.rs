@@ -5,42 +5,56 @@
 //!
 
 use crate::fragment::FragmentId;
-use crate::transaction::{Output, TransactionIndex};
+use crate::key::{Hash, HashContext, HashPrefix, PrefixResolutionError};
+use crate::transaction::{Output, TransactionIndex, UtxoPointer};
+use crate::value::{Value, ValueError};
+use chain_core::property::{self, Serialize as _};
 use std::collections::btree_map;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::BTreeMap;
+use std::io::Write;
 
-use imhamt::{Hamt, HamtIter, InsertError, RemoveError, ReplaceError, UpdateError};
+use imhamt::{Difference, Hamt, HamtIter, InsertError, RemoveError, ReplaceError, UpdateError};
 
 custom_error! {
     #[derive(Clone, PartialEq, Eq)]
     pub Error
-        AlreadyExists = "Transaction ID Already exist",
-        TransactionNotFound = "Transaction is not found",
-        IndexNotFound = "Index not found",
+        AlreadyExists { fragment: FragmentId } = "Outputs of fragment {fragment} already exist",
+        TransactionNotFound { fragment: FragmentId } = "Fragment {fragment} has no unspent outputs",
+        IndexNotFound { fragment: FragmentId, index: TransactionIndex } = "Output {index} of fragment {fragment} is not unspent",
+        DuplicatedIndex { fragment: FragmentId, index: TransactionIndex } = "Output {index} of fragment {fragment} is spent more than once",
+        IndexAlreadyUnspent { fragment: FragmentId, index: TransactionIndex } = "Output {index} of fragment {fragment} is already unspent",
 }
 
-impl From<InsertError> for Error {
-    fn from(_: InsertError) -> Error {
-        Error::AlreadyExists
+custom_error! {
+    #[derive(Clone, PartialEq, Eq)]
+    pub SelectionError
+        NotEnoughFunds { available: Value, needed: Value } = "Not enough funds: {available} available, {needed} needed",
+}
+
+// the errors of the hamt do not tell the key, so it is passed alongside
+
+impl From<(InsertError, FragmentId)> for Error {
+    fn from((_, fragment): (InsertError, FragmentId)) -> Error {
+        Error::AlreadyExists { fragment }
     }
 }
 
-impl From<UpdateError<()>> for Error {
-    fn from(_: UpdateError<()>) -> Error {
-        Error::TransactionNotFound
+impl From<(UpdateError<()>, FragmentId)> for Error {
+    fn from((_, fragment): (UpdateError<()>, FragmentId)) -> Error {
+        Error::TransactionNotFound { fragment }
     }
 }
 
-impl From<ReplaceError> for Error {
-    fn from(_: ReplaceError) -> Error {
-        Error::TransactionNotFound
+impl From<(ReplaceError, FragmentId)> for Error {
+    fn from((_, fragment): (ReplaceError, FragmentId)) -> Error {
+        Error::TransactionNotFound { fragment }
     }
 }
 
-impl From<RemoveError> for Error {
-    fn from(_: RemoveError) -> Error {
-        Error::TransactionNotFound
+impl From<(RemoveError, FragmentId)> for Error {
+    fn from((_, fragment): (RemoveError, FragmentId)) -> Error {
+        Error::TransactionNotFound { fragment }
     }
 }
 
@@ -50,7 +64,6 @@ struct TransactionUnspents<OutAddress>(BTreeMap<TransactionIndex, Output<OutAddr
 
 impl<OutAddress: Clone> TransactionUnspents<OutAddress> {
     pub fn from_outputs(outs: &[(TransactionIndex, Output<OutAddress>)]) -> Self {
-        assert!(outs.len() < 255);
         let mut b = BTreeMap::new();
         for (index, output) in outs.iter() {
             let r = b.insert(*index, output.clone());
@@ -62,21 +75,52 @@ impl<OutAddress: Clone> TransactionUnspents<OutAddress> {
 
     pub fn remove_input(
         &self,
+        tid: &FragmentId,
         index: TransactionIndex,
     ) -> Result<(Self, Output<OutAddress>), Error> {
-        assert!(index < 255);
         let mut t = self.0.clone();
         match t.remove(&index) {
-            None => Err(Error::IndexNotFound),
+            None => Err(Error::IndexNotFound {
+                fragment: tid.clone(),
+                index,
+            }),
             Some(o) => Ok((TransactionUnspents(t), o)),
         }
     }
+
+    /// same as `remove_input` for each of `indices`, copying the unspent
+    /// outputs only once
+    pub fn remove_inputs(
+        &self,
+        tid: &FragmentId,
+        indices: &[TransactionIndex],
+    ) -> Result<(Self, Vec<Output<OutAddress>>), Error> {
+        let mut t = self.0.clone();
+        let mut outputs = Vec::with_capacity(indices.len());
+        for index in indices {
+            match t.remove(index) {
+                None => {
+                    return Err(Error::IndexNotFound {
+                        fragment: tid.clone(),
+                        index: *index,
+                    })
+                }
+                Some(o) => outputs.push(o),
+            }
+        }
+        Ok((TransactionUnspents(t), outputs))
+    }
 }
 
 /// Ledger of UTXO
 #[derive(Clone, PartialEq, Eq)]
 pub struct Ledger<OutAddress>(Hamt<DefaultHasher, FragmentId, TransactionUnspents<OutAddress>>);
 
+/// Iterator over the entries of the ledger
+///
+/// Cloning the iterator gives a new one starting from the same position.
+/// This is not an `ExactSizeIterator`: the ledger only knows its number
+/// of fragments, counting their unspent outputs takes a full traversal.
 pub struct Iter<'a, V> {
     hamt_iter: HamtIter<'a, FragmentId, TransactionUnspents<V>>,
     unspents_iter: Option<(
@@ -85,21 +129,91 @@ pub struct Iter<'a, V> {
     )>,
 }
 
+/// Iterator over the outputs of the ledger, see `Iter`
 pub struct Values<'a, V> {
     hamt_iter: HamtIter<'a, FragmentId, TransactionUnspents<V>>,
     unspents_iter: Option<btree_map::Iter<'a, TransactionIndex, Output<V>>>,
 }
 
+impl<'a, V> Clone for Iter<'a, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            hamt_iter: self.hamt_iter.clone(),
+            unspents_iter: self.unspents_iter.clone(),
+        }
+    }
+}
+
+impl<'a, V> Clone for Values<'a, V> {
+    fn clone(&self) -> Self {
+        Values {
+            hamt_iter: self.hamt_iter.clone(),
+            unspents_iter: self.unspents_iter.clone(),
+        }
+    }
+}
+
 /// structure used by the iterator or the getter of the UTxO `Ledger`
 ///
 #[derive(Debug)]
 pub struct Entry<'a, OutputAddress> {
     pub fragment_id: FragmentId,
-    pub output_index: u8,
+    pub output_index: TransactionIndex,
     pub output: &'a Output<OutputAddress>,
 }
 
+/// owned version of `Entry`, independent of the lifetime of the ledger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryOwned<OutputAddress> {
+    pub fragment_id: FragmentId,
+    pub output_index: TransactionIndex,
+    pub output: Output<OutputAddress>,
+}
+
+impl<'a, OutputAddress: Clone> Entry<'a, OutputAddress> {
+    pub fn to_owned(&self) -> EntryOwned<OutputAddress> {
+        EntryOwned {
+            fragment_id: self.fragment_id.clone(),
+            output_index: self.output_index,
+            output: self.output.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_entry<S: serde::Serializer, OutputAddress>(
+    entry: &EntryOwned<OutputAddress>,
+    address: String,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+
+    let mut state = serializer.serialize_struct("EntryOwned", 4)?;
+    state.serialize_field("fragment_id", &entry.fragment_id.to_string())?;
+    state.serialize_field("output_index", &entry.output_index)?;
+    state.serialize_field("address", &address)?;
+    state.serialize_field("value", &entry.output.value.0)?;
+    state.end()
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for EntryOwned<chain_addr::Address> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_entry(self, self.output.address.base32(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for EntryOwned<crate::legacy::OldAddress> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_entry(self, self.output.address.to_string(), serializer)
+    }
+}
+
 impl<OutAddress> Ledger<OutAddress> {
+    /// iterate over the entries of the ledger, in an unspecified order:
+    /// the order of the fragments depends on the hashing of their ids,
+    /// use `iter_sorted` for an order that only depends on the entries
     pub fn iter<'a>(&'a self) -> Iter<'a, OutAddress> {
         Iter {
             hamt_iter: self.0.iter(),
@@ -107,6 +221,45 @@ impl<OutAddress> Ledger<OutAddress> {
         }
     }
 
+    /// iterate over the entries of the ledger by increasing fragment id,
+    /// then by increasing index
+    ///
+    /// Only the fragment ids are sorted up front, the entries of each
+    /// fragment being kept in index order already.
+    pub fn iter_sorted<'a>(&'a self) -> impl Iterator<Item = Entry<'a, OutAddress>> + 'a {
+        let mut fragments: Vec<_> = self.0.iter().collect();
+        fragments.sort_by(|(a, _), (b, _)| a.cmp(b));
+        fragments.into_iter().flat_map(|(tid, unspents)| {
+            unspents.0.iter().map(move |(index, output)| Entry {
+                fragment_id: tid.clone(),
+                output_index: *index,
+                output,
+            })
+        })
+    }
+
+    /// split the entries of the ledger into `n_chunks` iterators, which
+    /// can be run on as many threads
+    ///
+    /// The chunks are disjoint and together have all the entries. They
+    /// are split along the branches of the root of the underlying trie,
+    /// so they are only roughly the same size, and some of them are empty
+    /// when asking for more chunks than there are branches.
+    ///
+    /// Panics if `n_chunks` is zero.
+    pub fn iter_chunks<'a>(&'a self, n_chunks: usize) -> Vec<Iter<'a, OutAddress>> {
+        assert!(n_chunks > 0, "cannot split the ledger in zero chunks");
+        let branches = self.0.root_branches();
+        (0..n_chunks)
+            .map(|i| Iter {
+                hamt_iter: self
+                    .0
+                    .iter_branches(i * branches / n_chunks..(i + 1) * branches / n_chunks),
+                unspents_iter: None,
+            })
+            .collect()
+    }
+
     pub fn values<'a>(&'a self) -> Values<'a, OutAddress> {
         Values {
             hamt_iter: self.0.iter(),
@@ -114,6 +267,30 @@ impl<OutAddress> Ledger<OutAddress> {
         }
     }
 
+    /// iterate over the entries holding at least `min`
+    pub fn iter_filter_value<'a>(
+        &'a self,
+        min: Value,
+    ) -> impl Iterator<Item = Entry<'a, OutAddress>> + 'a {
+        self.iter().filter(move |entry| entry.output.value >= min)
+    }
+
+    /// iterate over the entries whose output matches `pred`
+    pub fn iter_by<'a, P>(&'a self, pred: P) -> impl Iterator<Item = Entry<'a, OutAddress>> + 'a
+    where
+        P: Fn(&Output<OutAddress>) -> bool + 'a,
+    {
+        self.iter().filter(move |entry| pred(entry.output))
+    }
+
+    /// the first entry, in iteration order, whose output matches `pred`
+    pub fn find<'a, P>(&'a self, pred: P) -> Option<Entry<'a, OutAddress>>
+    where
+        P: Fn(&Output<OutAddress>) -> bool,
+    {
+        self.iter().find(|entry| pred(entry.output))
+    }
+
     pub fn get<'a>(
         &'a self,
         tid: &FragmentId,
@@ -128,6 +305,204 @@ impl<OutAddress> Ledger<OutAddress> {
                 output: output,
             })
     }
+
+    /// the output `pointer` points to, whatever value it expects
+    pub fn get_pointed<'a>(&'a self, pointer: &UtxoPointer) -> Option<Entry<'a, OutAddress>> {
+        self.get(&pointer.transaction_id, &pointer.output_index)
+    }
+
+    /// iterate over the fragments that still have unspent outputs, each
+    /// with its unspent outputs in index order
+    pub fn iter_fragments<'a>(
+        &'a self,
+    ) -> impl Iterator<
+        Item = (
+            &'a FragmentId,
+            btree_map::Iter<'a, TransactionIndex, Output<OutAddress>>,
+        ),
+    > + 'a {
+        self.0
+            .iter()
+            .map(|(tid, unspents)| (tid, unspents.0.iter()))
+    }
+
+    /// the indices of the unspent outputs of the fragment, in order, or
+    /// `None` if none of its outputs is left
+    pub fn unspent_indices(&self, tid: &FragmentId) -> Option<Vec<TransactionIndex>> {
+        self.0
+            .lookup(tid)
+            .map(|unspents| unspents.0.keys().cloned().collect())
+    }
+
+    /// the fragments with unspent outputs whose id starts with `prefix`
+    ///
+    /// This goes through all the fragments of the ledger. The prefix is
+    /// never empty, see `HashPrefix::new`, so this does not list the
+    /// whole ledger.
+    pub fn find_by_prefix(&self, prefix: &HashPrefix) -> Vec<&FragmentId> {
+        self.0
+            .iter()
+            .map(|(tid, _)| tid)
+            .filter(|tid| prefix.matches(tid))
+            .collect()
+    }
+
+    /// the only fragment with unspent outputs whose id starts with
+    /// `prefix`, stopping as soon as a second one is found
+    pub fn resolve_prefix(
+        &self,
+        prefix: &HashPrefix,
+    ) -> Result<&FragmentId, PrefixResolutionError> {
+        prefix.resolve(self.0.iter().map(|(tid, _)| tid))
+    }
+
+    /// the sum of the values of the unspent outputs
+    pub fn total_value(&self) -> Result<Value, ValueError> {
+        Value::sum(self.values().map(|output| output.value))
+    }
+
+    pub fn contains(&self, tid: &FragmentId, index: &TransactionIndex) -> bool {
+        self.0
+            .lookup(tid)
+            .map(|unspents| unspents.0.contains_key(index))
+            .unwrap_or(false)
+    }
+
+    /// statistics on the values of the unspent outputs, the outputs
+    /// holding less than `dust_threshold` being counted as dust
+    ///
+    /// Fails if the total value of the outputs overflows.
+    pub fn stats(&self, dust_threshold: Value) -> Result<UtxoStats, ValueError> {
+        let mut stats = UtxoStats {
+            n_outputs: 0,
+            n_fragments: self.0.size(),
+            total: Value::zero(),
+            dust_outputs: 0,
+            dust_total: Value::zero(),
+            max_output: Value::zero(),
+            histogram: [0; UTXO_STATS_BUCKETS],
+        };
+        for output in self.values() {
+            stats.n_outputs += 1;
+            stats.total = stats.total.checked_add(output.value)?;
+            if output.value < dust_threshold {
+                stats.dust_outputs += 1;
+                stats.dust_total = stats.dust_total.checked_add(output.value)?;
+            }
+            stats.max_output = std::cmp::max(stats.max_output, output.value);
+            stats.histogram[UtxoStats::bucket(output.value)] += 1;
+        }
+        Ok(stats)
+    }
+}
+
+const UTXO_STATS_BUCKETS: usize = 16;
+
+/// Statistics on the unspent outputs of a ledger, see `Ledger::stats`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoStats {
+    pub n_outputs: usize,
+    pub n_fragments: usize,
+    pub total: Value,
+    pub dust_outputs: usize,
+    pub dust_total: Value,
+    pub max_output: Value,
+    /// the number of outputs by magnitude of their value: bucket `i`
+    /// counts the values in `[2^(4i), 2^(4i+4))`, the outputs of zero
+    /// value being counted in the first bucket
+    pub histogram: [u64; UTXO_STATS_BUCKETS],
+}
+
+impl UtxoStats {
+    fn bucket(value: Value) -> usize {
+        match value.0 {
+            0 => 0,
+            v => (63 - v.leading_zeros() as usize) / 4,
+        }
+    }
+}
+
+impl std::fmt::Display for UtxoStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} outputs in {} fragments, holding {}",
+            self.n_outputs, self.n_fragments, self.total
+        )?;
+        writeln!(f, "largest output: {}", self.max_output)?;
+        writeln!(
+            f,
+            "dust: {} outputs, holding {}",
+            self.dust_outputs, self.dust_total
+        )?;
+        write!(f, "outputs by value:")?;
+        for (i, count) in self.histogram.iter().enumerate() {
+            if *count != 0 {
+                write!(f, "\n  [2^{}, 2^{}): {}", 4 * i, 4 * i + 4, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<OutAddress: Clone> Ledger<OutAddress> {
+    /// same as `get`, but the entry does not borrow the ledger
+    pub fn get_owned(
+        &self,
+        tid: &FragmentId,
+        index: &TransactionIndex,
+    ) -> Option<EntryOwned<OutAddress>> {
+        self.get(tid, index).map(|entry| entry.to_owned())
+    }
+
+    /// iterate over owned copies of all the entries of the ledger
+    pub fn iter_owned<'a>(&'a self) -> impl Iterator<Item = EntryOwned<OutAddress>> + 'a {
+        self.iter().map(|entry| entry.to_owned())
+    }
+
+    /// owned copies of the entries of the ledger, in the order of
+    /// `iter_sorted`, e.g. to compare ledgers in tests
+    pub fn to_sorted_vec(&self) -> Vec<EntryOwned<OutAddress>> {
+        self.iter_sorted().map(|entry| entry.to_owned()).collect()
+    }
+}
+
+impl<OutAddress: property::Serialize<Error = std::io::Error>> Ledger<OutAddress> {
+    /// write the unspent outputs in an encoding which only depends on
+    /// the outputs, not on how the ledger was built
+    ///
+    /// The outputs are written by increasing fragment id, then by
+    /// increasing index. Each output is written as the big endian u32
+    /// size of the rest of the entry, then the fragment id, the index,
+    /// the address and the value.
+    pub fn serialize_canonical<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let mut bytes = Vec::new();
+        for entry in self.iter_sorted() {
+            bytes.clear();
+            entry.fragment_id.serialize(&mut bytes)?;
+            entry.output_index.serialize(&mut bytes)?;
+            entry.output.address.serialize(&mut bytes)?;
+            entry.output.value.serialize(&mut bytes)?;
+            writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+            writer.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// the hash of `serialize_canonical`, the same for all the ledgers
+    /// holding the same unspent outputs
+    pub fn digest(&self) -> Hash {
+        let mut context = HashContext::new();
+        self.serialize_canonical(&mut context)
+            .expect("hashing cannot fail");
+        context.finalize()
+    }
+}
+
+impl<OutAddress: std::fmt::Debug> std::fmt::Debug for Ledger<OutAddress> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
 }
 
 impl<'a, V> Iterator for Values<'a, V> {
@@ -188,15 +563,18 @@ impl<OutAddress: Clone> Ledger<OutAddress> {
 
     /// Add new outputs associated with a specific transaction
     ///
-    /// Error if the transaction already exist
+    /// Error if the transaction already exist. A transaction added with
+    /// no outputs is still there afterwards, so it cannot be added again.
     pub fn add(
         &self,
         tid: &FragmentId,
         outs: &[(TransactionIndex, Output<OutAddress>)],
     ) -> Result<Self, Error> {
-        assert!(outs.len() < 255);
         let b = TransactionUnspents::from_outputs(outs);
-        let next = self.0.insert(tid.clone(), b)?;
+        let next = self
+            .0
+            .insert(tid.clone(), b)
+            .map_err(|e| (e, tid.clone()))?;
         Ok(Ledger(next))
     }
 
@@ -208,44 +586,430 @@ impl<OutAddress: Clone> Ledger<OutAddress> {
         index: TransactionIndex,
     ) -> Result<(Self, Output<OutAddress>), Error> {
         let (treemap, output) = match self.0.lookup(tid) {
-            None => Err(Error::TransactionNotFound),
-            Some(out) => out.remove_input(index),
+            None => Err(Error::TransactionNotFound {
+                fragment: tid.clone(),
+            }),
+            Some(out) => out.remove_input(tid, index),
         }?;
 
-        if treemap.0.is_empty() {
-            Ok((Ledger(self.0.remove(tid)?), output))
-        } else {
-            Ok((Ledger(self.0.replace(tid, treemap)?.0), output))
-        }
+        Ok((self.replace_unspents(tid, treemap)?, output))
     }
 
-    pub fn remove_multiple(
+    // spend several indices from the transaction, returning the outputs
+    // in the order of `indices`
+    fn remove_indices(
         &self,
         tid: &FragmentId,
         indices: &[TransactionIndex],
     ) -> Result<(Self, Vec<Output<OutAddress>>), Error> {
+        check_distinct(tid, indices)?;
         let (treemap, outputs) = match self.0.lookup(tid) {
-            None => Err(Error::TransactionNotFound),
-            Some(out) => {
-                let mut treemap = out.clone();
-                let mut outputs = Vec::with_capacity(indices.len());
-                for index in indices {
-                    let (t, o) = treemap.remove_input(*index)?;
-                    outputs.push(o);
-                    treemap = t;
-                }
-                Ok((treemap, outputs))
-            }
+            None => Err(Error::TransactionNotFound {
+                fragment: tid.clone(),
+            }),
+            Some(out) => out.remove_inputs(tid, indices),
         }?;
 
-        if treemap.0.is_empty() {
-            Ok((Ledger(self.0.remove(tid)?), outputs))
+        Ok((self.replace_unspents(tid, treemap)?, outputs))
+    }
+
+    /// Put back outputs of the transaction, e.g. the ones spent by a block
+    /// that is reverted, whether some of its outputs are still unspent
+    /// or not
+    ///
+    /// This is the inverse of `remove_multiple`: fails if one of the
+    /// indices is already unspent, leaving `self` unmodified.
+    pub fn restore(
+        &self,
+        tid: &FragmentId,
+        outs: &[(TransactionIndex, Output<OutAddress>)],
+    ) -> Result<Self, Error> {
+        let mut unspents = match self.0.lookup(tid) {
+            None => BTreeMap::new(),
+            Some(unspents) => unspents.0.clone(),
+        };
+        for (index, output) in outs {
+            if unspents.insert(*index, output.clone()).is_some() {
+                return Err(Error::IndexAlreadyUnspent {
+                    fragment: tid.clone(),
+                    index: *index,
+                });
+            }
+        }
+        if unspents.is_empty() {
+            Ok(self.clone())
+        } else if self.0.contains_key(tid) {
+            self.replace_unspents(tid, TransactionUnspents(unspents))
+        } else {
+            let next = self
+                .0
+                .insert(tid.clone(), TransactionUnspents(unspents))
+                .map_err(|e| (e, tid.clone()))?;
+            Ok(Ledger(next))
+        }
+    }
+
+    // the ledger never holds a fragment without unspent outputs
+    fn replace_unspents(
+        &self,
+        tid: &FragmentId,
+        unspents: TransactionUnspents<OutAddress>,
+    ) -> Result<Self, Error> {
+        let next = if unspents.0.is_empty() {
+            self.0.remove(tid).map_err(|e| (e, tid.clone()))?
+        } else {
+            self.0
+                .replace(tid, unspents)
+                .map_err(|e| (e, tid.clone()))?
+                .0
+        };
+        Ok(Ledger(next))
+    }
+}
+
+impl<OutAddress: Clone> Ledger<OutAddress> {
+    /// Spend the outputs `pointers` point to, possibly from several
+    /// fragments, returning the outputs in the order of `pointers`
+    ///
+    /// The values the pointers expect are not checked. Fails if an output
+    /// is pointed to twice or is not unspent. Like the other operations,
+    /// this never modifies `self`: on error the original ledger is left
+    /// as it was.
+    pub fn remove_multiple(
+        &self,
+        pointers: &[UtxoPointer],
+    ) -> Result<(Self, Vec<Output<OutAddress>>), Error> {
+        let mut ledger = self.clone();
+        let mut outputs = vec![None; pointers.len()];
+        for (tid, positions) in group_by_fragment(pointers) {
+            let (next, spent) = ledger.remove_indices(tid, &indices_at(pointers, &positions))?;
+            for (position, output) in positions.into_iter().zip(spent) {
+                outputs[position] = Some(output);
+            }
+            ledger = next;
+        }
+        Ok((ledger, outputs.into_iter().map(Option::unwrap).collect()))
+    }
+
+    /// Same as `remove_multiple`, but the outputs are returned in the order
+    /// of their fragment id then index, whatever the order of `pointers`
+    pub fn remove_multiple_unordered(
+        &self,
+        pointers: &[UtxoPointer],
+    ) -> Result<(Self, Vec<Output<OutAddress>>), Error> {
+        let mut sorted = pointers.to_vec();
+        sorted.sort_by(|a, b| {
+            (&a.transaction_id, a.output_index).cmp(&(&b.transaction_id, b.output_index))
+        });
+        self.remove_multiple(&sorted)
+    }
+}
+
+// the positions in `pointers` of the pointers to each fragment
+fn group_by_fragment(pointers: &[UtxoPointer]) -> BTreeMap<&FragmentId, Vec<usize>> {
+    let mut by_fragment: BTreeMap<&FragmentId, Vec<usize>> = BTreeMap::new();
+    for (position, pointer) in pointers.iter().enumerate() {
+        by_fragment
+            .entry(&pointer.transaction_id)
+            .or_insert_with(Vec::new)
+            .push(position);
+    }
+    by_fragment
+}
+
+fn indices_at(pointers: &[UtxoPointer], positions: &[usize]) -> Vec<TransactionIndex> {
+    positions
+        .iter()
+        .map(|position| pointers[*position].output_index)
+        .collect()
+}
+
+fn check_distinct(tid: &FragmentId, indices: &[TransactionIndex]) -> Result<(), Error> {
+    let mut seen = [false; TransactionIndex::MAX_OUTPUTS];
+    for index in indices {
+        if seen[index.as_u8() as usize] {
+            return Err(Error::DuplicatedIndex {
+                fragment: tid.clone(),
+                index: *index,
+            });
+        }
+        seen[index.as_u8() as usize] = true;
+    }
+    Ok(())
+}
+
+/// Changes on top of a ledger, kept aside until they are committed
+///
+/// This is for applying transactions speculatively, e.g. checking the
+/// pending transactions one after the other, each on top of the
+/// previous ones, without building a new ledger for each of them. The
+/// operations fail exactly as they would on the ledger with the changes
+/// applied, and a failed operation leaves the overlay as it was.
+pub struct Overlay<'a, OutAddress> {
+    base: &'a Ledger<OutAddress>,
+    // the new unspent outputs of the changed fragments, `None` for the
+    // fragments with none left
+    changed: BTreeMap<FragmentId, Option<TransactionUnspents<OutAddress>>>,
+}
+
+impl<'a, OutAddress: Clone> Overlay<'a, OutAddress> {
+    pub fn new(base: &'a Ledger<OutAddress>) -> Self {
+        Overlay {
+            base,
+            changed: BTreeMap::new(),
+        }
+    }
+
+    fn lookup(&self, tid: &FragmentId) -> Option<&TransactionUnspents<OutAddress>> {
+        match self.changed.get(tid) {
+            Some(unspents) => unspents.as_ref(),
+            None => self.base.0.lookup(tid),
+        }
+    }
+
+    fn set_unspents(&mut self, tid: &FragmentId, unspents: TransactionUnspents<OutAddress>) {
+        let unspents = if unspents.0.is_empty() {
+            None
         } else {
-            Ok((Ledger(self.0.replace(tid, treemap)?.0), outputs))
+            Some(unspents)
+        };
+        self.changed.insert(tid.clone(), unspents);
+    }
+
+    pub fn get(&self, tid: &FragmentId, index: &TransactionIndex) -> Option<Entry<'_, OutAddress>> {
+        self.lookup(tid)
+            .and_then(|unspents| unspents.0.get(index))
+            .map(|output| Entry {
+                fragment_id: tid.clone(),
+                output_index: *index,
+                output,
+            })
+    }
+
+    /// the unspent outputs of the base ledger that were not spent in the
+    /// overlay, then those added in the overlay
+    pub fn iter(&self) -> impl Iterator<Item = Entry<'_, OutAddress>> + '_ {
+        let changed = &self.changed;
+        self.base
+            .iter_fragments()
+            .filter(move |(tid, _)| !changed.contains_key(tid))
+            .chain(changed.iter().filter_map(|(tid, unspents)| {
+                unspents.as_ref().map(|unspents| (tid, unspents.0.iter()))
+            }))
+            .flat_map(|(tid, outputs)| {
+                outputs.map(move |(index, output)| Entry {
+                    fragment_id: tid.clone(),
+                    output_index: *index,
+                    output,
+                })
+            })
+    }
+
+    /// Same as `Ledger::add`
+    pub fn add(
+        &mut self,
+        tid: &FragmentId,
+        outs: &[(TransactionIndex, Output<OutAddress>)],
+    ) -> Result<(), Error> {
+        if self.lookup(tid).is_some() {
+            return Err(Error::AlreadyExists {
+                fragment: tid.clone(),
+            });
         }
+        self.changed
+            .insert(tid.clone(), Some(TransactionUnspents::from_outputs(outs)));
+        Ok(())
+    }
+
+    /// Same as `Ledger::remove`
+    pub fn remove(
+        &mut self,
+        tid: &FragmentId,
+        index: TransactionIndex,
+    ) -> Result<Output<OutAddress>, Error> {
+        let (unspents, output) = match self.lookup(tid) {
+            None => Err(Error::TransactionNotFound {
+                fragment: tid.clone(),
+            }),
+            Some(unspents) => unspents.remove_input(tid, index),
+        }?;
+        self.set_unspents(tid, unspents);
+        Ok(output)
     }
+
+    /// Same as `Ledger::remove_multiple`
+    pub fn remove_multiple(
+        &mut self,
+        pointers: &[UtxoPointer],
+    ) -> Result<Vec<Output<OutAddress>>, Error> {
+        // nothing is changed until every fragment is known to be spendable
+        let mut changes = Vec::new();
+        let mut outputs = vec![None; pointers.len()];
+        for (tid, positions) in group_by_fragment(pointers) {
+            let indices = indices_at(pointers, &positions);
+            check_distinct(tid, &indices)?;
+            let (unspents, spent) = match self.lookup(tid) {
+                None => Err(Error::TransactionNotFound {
+                    fragment: tid.clone(),
+                }),
+                Some(unspents) => unspents.remove_inputs(tid, &indices),
+            }?;
+            for (position, output) in positions.into_iter().zip(spent) {
+                outputs[position] = Some(output);
+            }
+            changes.push((tid, unspents));
+        }
+        for (tid, unspents) in changes {
+            self.set_unspents(tid, unspents);
+        }
+        Ok(outputs.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// the base ledger with the changes of the overlay
+    pub fn commit(self) -> Result<Ledger<OutAddress>, Error> {
+        let mut ledger = self.base.0.clone();
+        for (tid, unspents) in self.changed {
+            let in_base = self.base.0.contains_key(&tid);
+            ledger = match unspents {
+                Some(unspents) if in_base => {
+                    ledger
+                        .replace(&tid, unspents)
+                        .map_err(|e| (e, tid.clone()))?
+                        .0
+                }
+                Some(unspents) => ledger
+                    .insert(tid.clone(), unspents)
+                    .map_err(|e| (e, tid.clone()))?,
+                // added then spent entirely in the overlay
+                None if !in_base => ledger,
+                None => ledger.remove(&tid).map_err(|e| (e, tid.clone()))?,
+            };
+        }
+        Ok(Ledger(ledger))
+    }
+
+    /// drop the changes, the base ledger is left as it was
+    pub fn discard(self) {}
+}
+
+/// The outputs added and spent to go from a ledger to another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerDelta<OutAddress> {
+    pub added: Vec<EntryOwned<OutAddress>>,
+    pub removed: Vec<EntryOwned<OutAddress>>,
 }
 
+impl<OutAddress: Clone + PartialEq> Ledger<OutAddress> {
+    /// The outputs of `other` not in `self` and the outputs of `self`
+    /// not in `other`, each sorted by fragment id and index
+    ///
+    /// The fragments shared by both ledgers are skipped without being
+    /// compared, so this is cheap for ledgers sharing most of their
+    /// history, e.g. two states of forks of the same chain.
+    pub fn diff(&self, other: &Ledger<OutAddress>) -> LedgerDelta<OutAddress> {
+        let owned =
+            |tid: &FragmentId, index: &TransactionIndex, output: &Output<OutAddress>| EntryOwned {
+                fragment_id: tid.clone(),
+                output_index: *index,
+                output: output.clone(),
+            };
+        let mut delta = LedgerDelta {
+            added: Vec::new(),
+            removed: Vec::new(),
+        };
+        for difference in self.0.differences(&other.0) {
+            match difference {
+                Difference::Removed(tid, unspents) => delta
+                    .removed
+                    .extend(unspents.0.iter().map(|(i, o)| owned(tid, i, o))),
+                Difference::Added(tid, unspents) => delta
+                    .added
+                    .extend(unspents.0.iter().map(|(i, o)| owned(tid, i, o))),
+                Difference::Changed(tid, old, new) => {
+                    for (index, output) in old.0.iter() {
+                        if new.0.get(index) != Some(output) {
+                            delta.removed.push(owned(tid, index, output))
+                        }
+                    }
+                    for (index, output) in new.0.iter() {
+                        if old.0.get(index) != Some(output) {
+                            delta.added.push(owned(tid, index, output))
+                        }
+                    }
+                }
+            }
+        }
+        let key = |entry: &EntryOwned<OutAddress>| (entry.fragment_id.clone(), entry.output_index);
+        delta.added.sort_by_key(key);
+        delta.removed.sort_by_key(key);
+        delta
+    }
+
+    /// Spend the removed outputs of `delta` then add its added outputs,
+    /// so `a.apply_delta(&a.diff(&b))` gives back `b`
+    pub fn apply_delta(&self, delta: &LedgerDelta<OutAddress>) -> Result<Self, Error> {
+        let mut ledger = self.clone();
+        for entry in delta.removed.iter() {
+            ledger = ledger.remove(&entry.fragment_id, entry.output_index)?.0;
+        }
+        for entry in delta.added.iter() {
+            ledger = ledger.insert_output(entry)?;
+        }
+        Ok(ledger)
+    }
+
+    // add one output, to a fragment already in the ledger or not
+    fn insert_output(&self, entry: &EntryOwned<OutAddress>) -> Result<Self, Error> {
+        let tid = &entry.fragment_id;
+        match self.0.lookup(tid) {
+            None => self.add(tid, &[(entry.output_index, entry.output.clone())]),
+            Some(unspents) if unspents.0.contains_key(&entry.output_index) => {
+                Err(Error::AlreadyExists {
+                    fragment: tid.clone(),
+                })
+            }
+            Some(unspents) => {
+                let mut unspents = unspents.clone();
+                unspents.0.insert(entry.output_index, entry.output.clone());
+                self.replace_unspents(tid, unspents)
+            }
+        }
+    }
+}
+
+impl<OutAddress: Clone> Ledger<OutAddress> {
+    /// a ledger with the outputs of every fragment of `iter`
+    ///
+    /// Fails with `Error::AlreadyExists` on the first fragment appearing
+    /// twice.
+    pub fn try_from_iter<I>(iter: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (FragmentId, Vec<(TransactionIndex, Output<OutAddress>)>)>,
+    {
+        Ledger::new().extended(iter)
+    }
+
+    /// a new ledger with the outputs of every fragment of `iter` added to
+    /// this one, which stays untouched
+    ///
+    /// Fails with `Error::AlreadyExists` on the first fragment already in
+    /// the ledger or appearing twice.
+    pub fn extended<I>(&self, iter: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (FragmentId, Vec<(TransactionIndex, Output<OutAddress>)>)>,
+    {
+        let mut ledger = self.clone();
+        for (tid, outputs) in iter {
+            ledger = ledger.add(&tid, &outputs)?;
+        }
+        Ok(ledger)
+    }
+}
+
+/// # Panics
+///
+/// if a fragment appears twice, use `Ledger::try_from_iter` to handle the
+/// error instead.
 impl<OutAddress: Clone>
     std::iter::FromIterator<(FragmentId, Vec<(TransactionIndex, Output<OutAddress>)>)>
     for Ledger<OutAddress>
@@ -255,10 +1019,1233 @@ impl<OutAddress: Clone>
     >(
         iter: I,
     ) -> Self {
+        Ledger::try_from_iter(iter)
+            .unwrap_or_else(|error| panic!("cannot collect the utxo ledger: {}", error))
+    }
+}
+
+/// How `select_inputs` picks the outputs to spend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// the largest outputs first, to spend as few outputs as possible
+    LargestFirst,
+    /// the outputs in the order of their fragment id and index
+    FirstMatch,
+}
+
+/// Choose unspent outputs of `ledger` covering `target`, returning them
+/// along with the change, which is the value selected above `target`
+///
+/// The selection only depends on the content of the ledger and on the
+/// strategy, not on the order of iteration of the ledger.
+pub fn select_inputs<'a, OutAddress>(
+    ledger: &'a Ledger<OutAddress>,
+    target: Value,
+    strategy: SelectionStrategy,
+) -> Result<(Vec<Entry<'a, OutAddress>>, Value), SelectionError> {
+    select_inputs_from(ledger.iter(), target, strategy)
+}
+
+/// Same as `select_inputs`, choosing among `entries` only
+pub fn select_inputs_from<'a, OutAddress, I>(
+    entries: I,
+    target: Value,
+    strategy: SelectionStrategy,
+) -> Result<(Vec<Entry<'a, OutAddress>>, Value), SelectionError>
+where
+    I: IntoIterator<Item = Entry<'a, OutAddress>>,
+{
+    let mut candidates: Vec<_> = entries.into_iter().collect();
+    candidates
+        .sort_by(|a, b| (&a.fragment_id, a.output_index).cmp(&(&b.fragment_id, b.output_index)));
+    if strategy == SelectionStrategy::LargestFirst {
+        // the sort is stable, outputs of the same value stay in id order
+        candidates.sort_by(|a, b| b.output.value.cmp(&a.output.value));
+    }
+
+    // the total can go over the maximum value before reaching the target
+    let needed = u128::from(target.0);
+    let mut total = 0u128;
+    let mut selected = Vec::new();
+    for entry in candidates {
+        if total >= needed {
+            break;
+        }
+        total += u128::from(entry.output.value.0);
+        selected.push(entry);
+    }
+    if total < needed {
+        return Err(SelectionError::NotEnoughFunds {
+            available: Value(total as u64),
+            needed: target,
+        });
+    }
+    Ok((selected, Value((total - needed) as u64)))
+}
+
+/// Split the output of `entry` to spend only `spend` out of it: return
+/// the output of `spend`, sent to the address of the entry, and the
+/// output sending the rest of the value back to `change_address`, or
+/// `None` when the whole value is spent.
+///
+/// Fails when `spend` is above the value of the entry.
+pub fn split_output<OutAddress: Clone>(
+    entry: &Entry<OutAddress>,
+    spend: Value,
+    change_address: OutAddress,
+) -> Result<(Output<OutAddress>, Option<Output<OutAddress>>), ValueError> {
+    let change = entry.output.value.checked_sub(spend)?;
+    let spent = entry.output.with_value(spend);
+    if change == Value::zero() {
+        Ok((spent, None))
+    } else {
+        Ok((spent, Some(Output::from_address(change_address, change))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::arbitrary::utxo::SpendableLedger;
+    use chain_addr::Address;
+    use quickcheck::{Arbitrary, Gen, TestResult};
+    use quickcheck_macros::quickcheck;
+
+    fn idx(index: u8) -> TransactionIndex {
+        TransactionIndex::new(index).unwrap()
+    }
+
+    // pointers to outputs of `fragment_id`, whose values do not matter
+    // to the removals
+    fn pointers_to(fragment_id: &FragmentId, indices: &[TransactionIndex]) -> Vec<UtxoPointer> {
+        indices
+            .iter()
+            .map(|index| UtxoPointer::new(*fragment_id, *index, Value::zero()))
+            .collect()
+    }
+
+    #[quickcheck]
+    fn add_then_remove_round_trips(
+        ledger: Ledger<Address>,
+        fragment_id: FragmentId,
+        outputs: Vec<Output<Address>>,
+    ) -> TestResult {
+        if outputs.is_empty() || outputs.len() >= 255 || ledger.0.contains_key(&fragment_id) {
+            return TestResult::discard();
+        }
+        let indexed: Vec<_> = outputs
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(index, output)| (idx(index as u8), output))
+            .collect();
+        let indices: Vec<_> = indexed.iter().map(|(index, _)| *index).collect();
+
+        let (after, removed) = ledger
+            .add(&fragment_id, &indexed)
+            .unwrap()
+            .remove_multiple(&pointers_to(&fragment_id, &indices))
+            .unwrap();
+        TestResult::from_bool(after == ledger && removed == outputs)
+    }
+
+    #[quickcheck]
+    fn fragments_group_their_unspent_outputs(
+        ledger: Ledger<Address>,
+        fragment_id: FragmentId,
+        outputs: Vec<Output<Address>>,
+    ) -> TestResult {
+        if outputs.len() < 3 || outputs.len() >= 255 || ledger.0.contains_key(&fragment_id) {
+            return TestResult::discard();
+        }
+        let indexed: Vec<_> = outputs
+            .iter()
+            .cloned()
+            .take(3)
+            .enumerate()
+            .map(|(index, output)| (idx(index as u8), output))
+            .collect();
+        let ledger = ledger.add(&fragment_id, &indexed).unwrap();
+        let (ledger, _) = ledger.remove(&fragment_id, idx(1)).unwrap();
+
+        let grouped: Vec<(TransactionIndex, Output<Address>)> = ledger
+            .iter_fragments()
+            .find(|(tid, _)| **tid == fragment_id)
+            .map(|(_, unspents)| unspents.map(|(i, o)| (*i, o.clone())).collect())
+            .unwrap();
+        let partially_spent = grouped == vec![indexed[0].clone(), indexed[2].clone()]
+            && ledger.unspent_indices(&fragment_id) == Some(vec![idx(0), idx(2)])
+            && ledger.contains(&fragment_id, &idx(0))
+            && !ledger.contains(&fragment_id, &idx(1))
+            && ledger
+                .iter_fragments()
+                .map(|(_, unspents)| unspents.count())
+                .sum::<usize>()
+                == ledger.iter().count();
+
+        let (ledger, _) = ledger
+            .remove_multiple(&pointers_to(&fragment_id, &[idx(0), idx(2)]))
+            .unwrap();
+        let fully_spent = ledger.iter_fragments().all(|(tid, _)| *tid != fragment_id)
+            && ledger.unspent_indices(&fragment_id).is_none()
+            && !ledger.contains(&fragment_id, &idx(0));
+
+        TestResult::from_bool(partially_spent && fully_spent)
+    }
+
+    #[quickcheck]
+    fn failed_removals_leave_the_ledger_unchanged(
+        ledger: Ledger<Address>,
+        fragment_id: FragmentId,
+        outputs: Vec<Output<Address>>,
+    ) -> TestResult {
+        if outputs.len() < 3 || outputs.len() >= 255 || ledger.0.contains_key(&fragment_id) {
+            return TestResult::discard();
+        }
+        let indexed: Vec<_> = outputs
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(index, output)| (idx(index as u8), output))
+            .collect();
+        let ledger = ledger.add(&fragment_id, &indexed).unwrap();
+        let original = ledger.clone();
+
+        let duplicated =
+            ledger.remove_multiple(&pointers_to(&fragment_id, &[idx(0), idx(2), idx(0)]));
+        let missing = ledger.remove_multiple(&pointers_to(&fragment_id, &[idx(1), idx(254)]));
+        let unordered =
+            ledger.remove_multiple_unordered(&pointers_to(&fragment_id, &[idx(2), idx(2)]));
+
+        // the ledger can still be spent from after the failures
+        let (_, spent) = ledger
+            .remove_multiple_unordered(&pointers_to(&fragment_id, &[idx(2), idx(0), idx(1)]))
+            .unwrap();
+        TestResult::from_bool(
+            duplicated
+                == Err(Error::DuplicatedIndex {
+                    fragment: fragment_id.clone(),
+                    index: idx(0),
+                })
+                && missing
+                    == Err(Error::IndexNotFound {
+                        fragment: fragment_id.clone(),
+                        index: idx(254),
+                    })
+                && unordered
+                    == Err(Error::DuplicatedIndex {
+                        fragment: fragment_id.clone(),
+                        index: idx(2),
+                    })
+                && ledger == original
+                && spent == outputs[..3].to_vec(),
+        )
+    }
+
+    #[quickcheck]
+    fn restore_undoes_remove_multiple(
+        ledger: Ledger<Address>,
+        pick: usize,
+        spent: Vec<bool>,
+    ) -> TestResult {
+        let fragments: Vec<(FragmentId, Vec<TransactionIndex>)> = ledger
+            .iter_fragments()
+            .map(|(tid, unspents)| (tid.clone(), unspents.map(|(i, _)| *i).collect()))
+            .collect();
+        if fragments.is_empty() {
+            return TestResult::discard();
+        }
+        // past the end of `spent`, every output is spent, emptying the
+        // fragment entirely
+        let (fragment_id, unspent) = &fragments[pick % fragments.len()];
+        let indices: Vec<_> = unspent
+            .iter()
+            .zip(spent.iter().chain(std::iter::repeat(&true)))
+            .filter(|(_, spent)| **spent)
+            .map(|(index, _)| *index)
+            .collect();
+        if indices.is_empty() {
+            return TestResult::discard();
+        }
+
+        let (after, outputs) = ledger
+            .remove_multiple(&pointers_to(fragment_id, &indices))
+            .unwrap();
+        let outputs: Vec<_> = indices.iter().cloned().zip(outputs).collect();
+        let emptied = indices.len() == unspent.len();
+        let restored = after.restore(fragment_id, &outputs).unwrap();
+        let again = restored.restore(fragment_id, &outputs[..1]);
+        TestResult::from_bool(
+            restored == ledger
+                && after.unspent_indices(fragment_id).is_none() == emptied
+                && again
+                    == Err(Error::IndexAlreadyUnspent {
+                        fragment: fragment_id.clone(),
+                        index: indices[0],
+                    }),
+        )
+    }
+
+    #[test]
+    fn restore_merges_with_the_unspent_outputs() {
+        let fragment_id = FragmentId::hash_bytes(b"fragment");
+        let output = |value| Output {
+            address: (),
+            value: Value(value),
+        };
+        let ledger = Ledger::new()
+            .add(
+                &fragment_id,
+                &[
+                    (idx(0), output(1)),
+                    (idx(1), output(2)),
+                    (idx(2), output(3)),
+                ],
+            )
+            .unwrap();
+        let (spent, _) = ledger.remove(&fragment_id, idx(1)).unwrap();
+
+        // `add` refuses a fragment that still has unspent outputs
+        assert_eq!(
+            spent.add(&fragment_id, &[(idx(1), output(2))]),
+            Err(Error::AlreadyExists {
+                fragment: fragment_id.clone()
+            })
+        );
+        assert_eq!(
+            spent.restore(&fragment_id, &[(idx(1), output(2))]),
+            Ok(ledger.clone())
+        );
+        assert_eq!(
+            spent.restore(&fragment_id, &[(idx(1), output(2)), (idx(2), output(3))]),
+            Err(Error::IndexAlreadyUnspent {
+                fragment: fragment_id.clone(),
+                index: idx(2)
+            })
+        );
+        assert_eq!(spent.restore(&fragment_id, &[]), Ok(spent.clone()));
+    }
+
+    #[test]
+    fn fragments_are_found_by_prefix() {
+        let output = Output {
+            address: (),
+            value: Value(1),
+        };
+        let ids: Vec<FragmentId> = [[0xab; 32], [0xac; 32], [0x12; 32]]
+            .iter()
+            .map(|bytes| FragmentId::from_bytes(*bytes))
+            .collect();
+        let ledger: Ledger<()> = ids.iter().fold(Ledger::new(), |ledger, id| {
+            ledger.add(id, &[(idx(0), output.clone())]).unwrap()
+        });
+        let prefix = |s: &str| s.parse::<HashPrefix>().unwrap();
+
+        assert_eq!(ledger.find_by_prefix(&prefix("ab")), vec![&ids[0]]);
+        assert!(ledger.find_by_prefix(&prefix("abac")).is_empty());
+
+        assert_eq!(ledger.resolve_prefix(&prefix("ac")), Ok(&ids[1]));
+        let (spent, _) = ledger.remove(&ids[1], idx(0)).unwrap();
+        assert_eq!(
+            spent.resolve_prefix(&prefix("ac")),
+            Err(PrefixResolutionError::NotFound {
+                prefix: prefix("ac")
+            })
+        );
+
+        let shared = FragmentId::from_bytes({
+            let mut bytes = [0xab; 32];
+            bytes[31] = 0;
+            bytes
+        });
+        let ledger = ledger.add(&shared, &[(idx(0), output)]).unwrap();
+        assert_eq!(
+            ledger
+                .find_by_prefix(&HashPrefix::new(&[0xab; 31]).unwrap())
+                .len(),
+            2
+        );
+        assert_eq!(
+            ledger.resolve_prefix(&prefix("abab")),
+            Err(PrefixResolutionError::AmbiguousPrefix {
+                prefix: prefix("abab")
+            })
+        );
+        assert_eq!(
+            ledger.resolve_prefix(&ids[0].to_string().parse().unwrap()),
+            Ok(&ids[0])
+        );
+    }
+
+    #[test]
+    fn chunks_cover_the_ledger() {
+        let values: Vec<Vec<u64>> = (0..200u64).map(|i| (0..i % 4 + 1).collect()).collect();
+        let values: Vec<&[u64]> = values.iter().map(|v| &v[..]).collect();
+        for ledger in &[ledger_of_values(&[]), ledger_of_values(&values)] {
+            let key = |entry: Entry<()>| (entry.fragment_id.clone(), entry.output_index);
+            let mut expected: Vec<_> = ledger.iter().map(key).collect();
+            expected.sort();
+            for n_chunks in &[1, 3, 16] {
+                let chunks = ledger.iter_chunks(*n_chunks);
+                assert_eq!(chunks.len(), *n_chunks);
+                let mut all: Vec<_> = chunks.into_iter().flatten().map(key).collect();
+                all.sort();
+                assert_eq!(all, expected);
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn digest_ignores_the_order_of_the_additions(ledger: Ledger<Address>, shift: usize) -> bool {
+        let mut fragments: Vec<(FragmentId, Vec<_>)> = ledger
+            .0
+            .iter()
+            .map(|(id, unspents)| {
+                let outputs = unspents.0.iter().rev();
+                (id.clone(), outputs.map(|(i, o)| (*i, o.clone())).collect())
+            })
+            .collect();
+        let rebuild = |fragments: &[(FragmentId, Vec<_>)]| {
+            fragments
+                .iter()
+                .fold(Ledger::new(), |ledger, (id, outputs)| {
+                    ledger.add(id, outputs).unwrap()
+                })
+        };
+        let digest = ledger.digest();
+
+        fragments.reverse();
+        let reversed = rebuild(&fragments);
+        if !fragments.is_empty() {
+            let shift = shift % fragments.len();
+            fragments.rotate_left(shift);
+        }
+        let rotated = rebuild(&fragments);
+        reversed == ledger && reversed.digest() == digest && rotated.digest() == digest
+    }
+
+    #[quickcheck]
+    fn iter_sorted_is_iter_sorted_by_id_and_index(ledger: Ledger<Address>) -> bool {
+        let sorted: Vec<_> = ledger.iter_sorted().map(|entry| entry.to_owned()).collect();
+        let mut expected: Vec<_> = ledger.iter_owned().collect();
+        expected.sort_by(|a, b| {
+            (&a.fragment_id, a.output_index).cmp(&(&b.fragment_id, b.output_index))
+        });
+        sorted == expected && ledger.to_sorted_vec() == expected
+    }
+
+    #[quickcheck]
+    fn sorted_entries_ignore_the_order_of_the_additions(ledger: Ledger<Address>) -> bool {
+        let mut fragments: Vec<(FragmentId, Vec<_>)> = ledger
+            .0
+            .iter()
+            .map(|(id, unspents)| {
+                let outputs = unspents.0.iter().rev();
+                (id.clone(), outputs.map(|(i, o)| (*i, o.clone())).collect())
+            })
+            .collect();
+        fragments.reverse();
+        let reversed = fragments
+            .iter()
+            .fold(Ledger::new(), |ledger, (id, outputs)| {
+                ledger.add(id, outputs).unwrap()
+            });
+        reversed.to_sorted_vec() == ledger.to_sorted_vec()
+    }
+
+    #[quickcheck]
+    fn spending_an_output_changes_the_digest(spendable: SpendableLedger) -> bool {
+        let UtxoPointer {
+            transaction_id: fragment_id,
+            output_index: index,
+            ..
+        } = spendable.input;
+        let (spent, _) = spendable.ledger.remove(&fragment_id, index).unwrap();
+        let mut bytes = Vec::new();
+        spent.serialize_canonical(&mut bytes).unwrap();
+        spent.digest() != spendable.ledger.digest() && spent.digest() == Hash::hash_bytes(&bytes)
+    }
+
+    fn ledger_of_values(fragments: &[&[u64]]) -> Ledger<()> {
+        fragments
+            .iter()
+            .enumerate()
+            .map(|(i, values)| {
+                let outputs = values
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| {
+                        let output = Output {
+                            address: (),
+                            value: Value(*value),
+                        };
+                        (idx(index as u8), output)
+                    })
+                    .collect();
+                (FragmentId::from_bytes([i as u8; 32]), outputs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stats_count_every_output() {
+        let ledger = ledger_of_values(&[&[0, 5, 16], &[300, 1 << 40]]);
+        let stats = ledger.stats(Value(10)).unwrap();
+        let mut histogram = [0; 16];
+        histogram[0] = 2;
+        histogram[1] = 1;
+        histogram[2] = 1;
+        histogram[10] = 1;
+        assert_eq!(
+            stats,
+            UtxoStats {
+                n_outputs: 5,
+                n_fragments: 2,
+                total: Value(321 + (1 << 40)),
+                dust_outputs: 2,
+                dust_total: Value(5),
+                max_output: Value(1 << 40),
+                histogram,
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "5 outputs in 2 fragments, holding 1099511628097\n\
+             largest output: 1099511627776\n\
+             dust: 2 outputs, holding 5\n\
+             outputs by value:\n  \
+             [2^0, 2^4): 2\n  \
+             [2^4, 2^8): 1\n  \
+             [2^8, 2^12): 1\n  \
+             [2^40, 2^44): 1"
+        );
+
+        let empty = Ledger::<()>::new().stats(Value(10)).unwrap();
+        assert_eq!(empty.n_outputs, 0);
+        assert_eq!(empty.max_output, Value::zero());
+        assert_eq!(empty.histogram, [0; 16]);
+    }
+
+    #[test]
+    fn stats_report_an_overflowing_total() {
+        let ledger = ledger_of_values(&[&[u64::max_value()], &[1]]);
+        assert_eq!(ledger.stats(Value(10)), Err(ValueError::Overflow));
+    }
+
+    #[quickcheck]
+    fn owned_entries_outlive_spending(spendable: SpendableLedger) -> TestResult {
+        let entries: Vec<EntryOwned<Address>> = spendable.ledger.iter_owned().collect();
+        let UtxoPointer {
+            transaction_id: fragment_id,
+            output_index: index,
+            ..
+        } = spendable.input;
+        let spent = spendable.ledger.get_owned(&fragment_id, &index).unwrap();
+
+        let (after, output) = spendable.ledger.remove(&fragment_id, index).unwrap();
+        let mut ledger = after;
+        for entry in entries.iter().filter(|entry| **entry != spent) {
+            ledger = ledger
+                .remove(&entry.fragment_id, entry.output_index)
+                .unwrap()
+                .0;
+        }
+
+        TestResult::from_bool(
+            output == spent.output
+                && ledger.iter().count() == 0
+                && entries.len() == spendable.ledger.iter().count()
+                && entries.contains(&spent)
+                && entries.iter().all(|entry| {
+                    spendable
+                        .ledger
+                        .get(&entry.fragment_id, &entry.output_index)
+                        .map(|original| *original.output == entry.output)
+                        .unwrap_or(false)
+                }),
+        )
+    }
+
+    #[quickcheck]
+    fn remove_spendable_input(spendable: SpendableLedger) -> TestResult {
+        let UtxoPointer {
+            transaction_id: fragment_id,
+            output_index: index,
+            ..
+        } = spendable.input;
+        let expected = spendable
+            .ledger
+            .get(&fragment_id, &index)
+            .unwrap()
+            .output
+            .clone();
+        let (after, output) = spendable.ledger.remove(&fragment_id, index).unwrap();
+        TestResult::from_bool(
+            output == expected
+                && after.get(&fragment_id, &index).is_none()
+                && after.iter().count() + 1 == spendable.ledger.iter().count(),
+        )
+    }
+
+    #[quickcheck]
+    fn fragment_ids_with_shared_prefixes(
+        prefix: FragmentId,
+        tails: Vec<u8>,
+        output: Output<Address>,
+    ) -> TestResult {
+        let mut tails = tails;
+        tails.sort();
+        tails.dedup();
+        if tails.len() < 2 {
+            return TestResult::discard();
+        }
+        // the ids only differ in their last byte
+        let ids: Vec<FragmentId> = tails
+            .iter()
+            .map(|tail| {
+                let mut bytes = *prefix.as_bytes();
+                bytes[31] = *tail;
+                FragmentId::from_bytes(bytes)
+            })
+            .collect();
+
         let mut ledger = Ledger::new();
-        for (tid, outputs) in iter {
-            ledger = ledger.add(&tid, &outputs).unwrap();
+        for id in ids.iter() {
+            ledger = ledger.add(id, &[(idx(0), output.clone())]).unwrap();
+        }
+        if !ids.iter().all(|id| ledger.contains(id, &idx(0))) {
+            return TestResult::error("an output is missing after insertion");
+        }
+
+        for (removed, id) in ids.iter().enumerate() {
+            let (after, spent) = ledger.remove(id, idx(0)).unwrap();
+            let remaining = &ids[removed + 1..];
+            if spent != output
+                || after.contains(id, &idx(0))
+                || !remaining.iter().all(|id| after.contains(id, &idx(0)))
+                || after.iter().count() != remaining.len()
+            {
+                return TestResult::error("removal disturbed the other fragments");
+            }
+            ledger = after;
+        }
+        TestResult::from_bool(ledger == Ledger::new())
+    }
+
+    fn selection_ledger() -> Ledger<()> {
+        let outputs: Vec<_> = [10, 20, 30]
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let output = Output {
+                    address: (),
+                    value: Value(*value),
+                };
+                (idx(index as u8), output)
+            })
+            .collect();
+        Ledger::new()
+            .add(&FragmentId::hash_bytes(b"selection"), &outputs)
+            .unwrap()
+    }
+
+    fn selected_values(selected: &[Entry<()>]) -> Vec<u64> {
+        selected.iter().map(|entry| entry.output.value.0).collect()
+    }
+
+    fn selected_pointers<A>(selected: &[Entry<A>]) -> Vec<UtxoPointer> {
+        selected.iter().map(UtxoPointer::from_entry).collect()
+    }
+
+    #[test]
+    fn selection_with_exact_match_has_no_change() {
+        let ledger = selection_ledger();
+        let (selected, change) =
+            select_inputs(&ledger, Value(30), SelectionStrategy::LargestFirst).unwrap();
+        assert_eq!(selected_values(&selected), vec![30]);
+        assert_eq!(change, Value::zero());
+
+        let (selected, change) =
+            select_inputs(&ledger, Value(30), SelectionStrategy::FirstMatch).unwrap();
+        assert_eq!(selected_values(&selected), vec![10, 20]);
+        assert_eq!(change, Value::zero());
+
+        let (selected, change) =
+            select_inputs(&ledger, Value(35), SelectionStrategy::LargestFirst).unwrap();
+        assert_eq!(selected_values(&selected), vec![30, 20]);
+        assert_eq!(change, Value(15));
+    }
+
+    #[test]
+    fn selection_reports_missing_funds() {
+        let ledger = selection_ledger();
+        for strategy in &[
+            SelectionStrategy::LargestFirst,
+            SelectionStrategy::FirstMatch,
+        ] {
+            assert_eq!(
+                select_inputs(&ledger, Value(61), *strategy).map(|(_, change)| change),
+                Err(SelectionError::NotEnoughFunds {
+                    available: Value(60),
+                    needed: Value(61),
+                })
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn selection_is_deterministic(ledger: Ledger<Address>, target: u64) -> TestResult {
+        let available = Value::sum(ledger.values().map(|output| output.value));
+        let target = match available {
+            Ok(Value(0)) | Err(_) => return TestResult::discard(),
+            Ok(available) => Value(target % (available.0 + 1)),
+        };
+        for strategy in &[
+            SelectionStrategy::LargestFirst,
+            SelectionStrategy::FirstMatch,
+        ] {
+            let (selected, change) = select_inputs(&ledger, target, *strategy).unwrap();
+            let reversed: Vec<_> = ledger
+                .iter()
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .collect();
+            let (again, _) = select_inputs_from(reversed, target, *strategy).unwrap();
+            let selected_value = Value::sum(selected.iter().map(|entry| entry.output.value));
+            if selected_pointers(&selected) != selected_pointers(&again)
+                || selected_value != target + change
+            {
+                return TestResult::failed();
+            }
+        }
+        TestResult::passed()
+    }
+
+    fn mixed_values_ledger() -> Ledger<()> {
+        (0..20u8)
+            .map(|i| {
+                let outputs = (0..3)
+                    .map(|index| {
+                        let value = Value(u64::from(i) * 10 + index);
+                        (idx(index as u8), Output { address: (), value })
+                    })
+                    .collect();
+                (FragmentId::hash_bytes(&[i]), outputs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filters_match_their_predicate() {
+        let ledger = mixed_values_ledger();
+        let mut values: Vec<_> = ledger
+            .iter_filter_value(Value(150))
+            .map(|entry| entry.output.value.0)
+            .collect();
+        values.sort();
+        let expected: Vec<_> = (15..20)
+            .flat_map(|i| (0..3).map(move |index| i * 10 + index))
+            .collect();
+        assert_eq!(values, expected);
+
+        let odd = ledger.iter_by(|output| output.value.0 % 2 == 1);
+        assert_eq!(odd.count(), 20);
+        assert!(ledger.find(|output| output.value == Value(52)).is_some());
+        assert!(ledger.find(|output| output.value == Value(53)).is_none());
+    }
+
+    #[test]
+    fn filters_are_lazy() {
+        let ledger = mixed_values_ledger();
+        let is_odd = |output: &Output<()>| output.value.0 % 2 == 1;
+        let position = ledger
+            .iter()
+            .position(|entry| is_odd(entry.output))
+            .unwrap();
+
+        let calls = std::cell::Cell::new(0);
+        let counting = |output: &Output<()>| {
+            calls.set(calls.get() + 1);
+            is_odd(output)
+        };
+        let found = ledger.find(counting).unwrap();
+        assert!(is_odd(found.output));
+        assert_eq!(calls.get(), position + 1);
+
+        calls.set(0);
+        let mut odd = ledger.iter_by(counting);
+        assert_eq!(calls.get(), 0);
+        odd.next().unwrap();
+        assert_eq!(calls.get(), position + 1);
+    }
+
+    #[test]
+    fn cloned_iterators_restart_from_the_same_position() {
+        let ledger = mixed_values_ledger();
+        let mut iter = ledger.iter();
+        let mut values = ledger.values();
+        for _ in 0..7 {
+            iter.next();
+            values.next();
+        }
+        let rest = selected_pointers(&iter.clone().collect::<Vec<_>>());
+        assert_eq!(rest.len(), 53);
+        assert_eq!(rest, selected_pointers(&iter.collect::<Vec<_>>()));
+        assert_eq!(values.clone().count(), 53);
+        assert!(values.clone().eq(values));
+    }
+
+    #[quickcheck]
+    fn delta_replays_the_changes(
+        ledger: Ledger<Address>,
+        spent: Vec<usize>,
+        added: Vec<(FragmentId, Vec<Output<Address>>)>,
+    ) -> TestResult {
+        let entries: Vec<EntryOwned<Address>> = ledger.iter_owned().collect();
+        if entries.is_empty() {
+            return TestResult::discard();
+        }
+        let mut other = ledger.clone();
+        for i in spent {
+            let entry = &entries[i % entries.len()];
+            if let Ok((next, _)) = other.remove(&entry.fragment_id, entry.output_index) {
+                other = next;
+            }
+        }
+        for (fragment_id, outputs) in added {
+            let indexed: Vec<_> = outputs
+                .into_iter()
+                .take(254)
+                .enumerate()
+                .map(|(index, output)| (idx(index as u8), output))
+                .collect();
+            // the ledger never holds a fragment without unspent outputs
+            if indexed.is_empty() {
+                continue;
+            }
+            if let Ok(next) = other.add(&fragment_id, &indexed) {
+                other = next;
+            }
+        }
+
+        let forward = ledger.diff(&other);
+        let backward = other.diff(&ledger);
+        TestResult::from_bool(
+            ledger.apply_delta(&forward) == Ok(other.clone())
+                && other.apply_delta(&backward) == Ok(ledger.clone())
+                && forward.added == backward.removed
+                && forward.removed == backward.added
+                && ledger.diff(&ledger)
+                    == LedgerDelta {
+                        added: vec![],
+                        removed: vec![],
+                    },
+        )
+    }
+
+    #[test]
+    fn duplicated_fragment_is_reported() {
+        let output = |value| Output {
+            address: (),
+            value: Value(value),
+        };
+        let first = FragmentId::hash_bytes(b"first");
+        let second = FragmentId::hash_bytes(b"second");
+        let fragments = vec![
+            (first, vec![(idx(0), output(1))]),
+            (second, vec![(idx(0), output(2))]),
+            (first, vec![(idx(1), output(3))]),
+        ];
+
+        assert_eq!(
+            Ledger::try_from_iter(fragments.clone()),
+            Err(Error::AlreadyExists { fragment: first })
+        );
+        let error = Ledger::try_from_iter(fragments).unwrap_err();
+        assert!(error.to_string().contains(&first.to_string()));
+
+        let ledger = Ledger::try_from_iter(vec![(first, vec![(idx(0), output(1))])]).unwrap();
+        let extended = ledger
+            .extended(vec![(second, vec![(idx(0), output(2))])])
+            .unwrap();
+        assert_eq!(ledger.iter().count(), 1);
+        assert_eq!(extended.iter().count(), 2);
+        assert_eq!(
+            extended.extended(vec![(second, vec![(idx(1), output(3))])]),
+            Err(Error::AlreadyExists { fragment: second })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot collect the utxo ledger")]
+    fn collecting_a_duplicated_fragment_panics() {
+        let fragment_id = FragmentId::hash_bytes(b"fragment");
+        let output = Output {
+            address: (),
+            value: Value(1),
+        };
+        let _: Ledger<()> = vec![
+            (fragment_id, vec![(idx(0), output.clone())]),
+            (fragment_id, vec![(idx(0), output)]),
+        ]
+        .into_iter()
+        .collect();
+    }
+
+    #[test]
+    fn parsed_pointers_find_and_spend_their_outputs() {
+        let fragments: Vec<_> = (0..3u8).map(|i| FragmentId::hash_bytes(&[i])).collect();
+        let mut ledger = Ledger::new();
+        for (i, fragment_id) in fragments.iter().enumerate() {
+            let outputs: Vec<_> = (0..3u8)
+                .map(|index| {
+                    let value = Value(i as u64 * 10 + u64::from(index));
+                    (idx(index), Output { address: (), value })
+                })
+                .collect();
+            ledger = ledger.add(fragment_id, &outputs).unwrap();
+        }
+
+        let pointers: Vec<UtxoPointer> = vec![
+            format!("{}.2", fragments[1]),
+            format!("{}.0", fragments[0]),
+            format!("{}.1", fragments[1]),
+        ]
+        .iter()
+        .map(|text| text.parse().unwrap())
+        .collect();
+        let entry = ledger.get_pointed(&pointers[0]).unwrap();
+        assert_eq!(entry.output.value, Value(12));
+        assert_eq!(
+            UtxoPointer::from_entry(&entry),
+            UtxoPointer::new(fragments[1], idx(2), Value(12))
+        );
+
+        let (after, spent) = ledger.remove_multiple(&pointers).unwrap();
+        let values: Vec<_> = spent.iter().map(|output| output.value).collect();
+        assert_eq!(values, vec![Value(12), Value(0), Value(11)]);
+        assert!(after.get_pointed(&pointers[1]).is_none());
+        assert_eq!(after.iter().count(), 6);
+        assert_eq!(
+            ledger.remove_multiple(&[pointers[0], pointers[0]]),
+            Err(Error::DuplicatedIndex {
+                fragment: fragments[1],
+                index: idx(2)
+            })
+        );
+    }
+
+    #[test]
+    fn spending_a_large_fragment_at_once_or_one_by_one_agree() {
+        let fragment_id = FragmentId::hash_bytes(b"large fragment");
+        let outputs: Vec<_> = (0..250u8)
+            .map(|index| {
+                let output = Output {
+                    address: (),
+                    value: Value(u64::from(index) * 3 + 1),
+                };
+                (idx(index), output)
+            })
+            .collect();
+        let ledger = Ledger::new().add(&fragment_id, &outputs).unwrap();
+
+        // every other index, in a shuffled order
+        let indices: Vec<TransactionIndex> = (0..125u8)
+            .map(|i| idx(((u16::from(i) * 2 * 37) % 250) as u8))
+            .collect();
+        let (at_once, spent) = ledger
+            .remove_multiple(&pointers_to(&fragment_id, &indices))
+            .unwrap();
+
+        let mut one_by_one = ledger.clone();
+        let mut expected = Vec::new();
+        for index in &indices {
+            let (next, output) = one_by_one.remove(&fragment_id, *index).unwrap();
+            expected.push(output);
+            one_by_one = next;
         }
-        ledger
+        assert_eq!(spent, expected);
+        assert!(at_once == one_by_one);
+        assert_eq!(
+            at_once.iter_owned().collect::<Vec<_>>(),
+            one_by_one.iter_owned().collect::<Vec<_>>()
+        );
+        assert_eq!(ledger.iter().count(), 250);
+
+        // indices already spent or listed twice are rejected
+        let mut with_spent = indices.clone();
+        with_spent.push(indices[0]);
+        assert_eq!(
+            at_once.remove_multiple(&pointers_to(&fragment_id, &indices[..1])),
+            Err(Error::IndexNotFound {
+                fragment: fragment_id,
+                index: indices[0]
+            })
+        );
+        assert_eq!(
+            ledger.remove_multiple(&pointers_to(&fragment_id, &with_spent)),
+            Err(Error::DuplicatedIndex {
+                fragment: fragment_id,
+                index: indices[0]
+            })
+        );
+
+        let rest: Vec<TransactionIndex> = TransactionIndex::iter_to(250)
+            .filter(|index| !indices.contains(index))
+            .collect();
+        let (empty, _) = at_once
+            .remove_multiple(&pointers_to(&fragment_id, &rest))
+            .unwrap();
+        assert_eq!(empty.iter().count(), 0);
+        assert!(empty.get(&fragment_id, &rest[0]).is_none());
+    }
+
+    #[quickcheck]
+    fn split_output_conserves_the_value(
+        fragment_id: FragmentId,
+        output: Output<Address>,
+        change_address: Address,
+        spend: u64,
+    ) -> TestResult {
+        let entry = Entry {
+            fragment_id,
+            output_index: idx(0),
+            output: &output,
+        };
+        let spend = match output.value.0.checked_add(1) {
+            Some(bound) => Value(spend % bound),
+            None => Value(spend),
+        };
+        let (spent, change) = split_output(&entry, spend, change_address.clone()).unwrap();
+        let change_value = change.as_ref().map_or(Value::zero(), |change| change.value);
+        TestResult::from_bool(
+            spent == output.with_value(spend)
+                && (spent.value + change_value) == Ok(output.value)
+                && change.map_or(spend == output.value, |change| {
+                    change.address == change_address && change.value != Value::zero()
+                }),
+        )
+    }
+
+    #[quickcheck]
+    fn split_output_cannot_spend_more_than_the_entry(
+        fragment_id: FragmentId,
+        output: Output<Address>,
+        change_address: Address,
+        extra: u64,
+    ) -> TestResult {
+        let spend = match output.value.checked_add(Value(extra % 1000 + 1)) {
+            Ok(spend) => spend,
+            Err(_) => return TestResult::discard(),
+        };
+        let entry = Entry {
+            fragment_id,
+            output_index: idx(0),
+            output: &output,
+        };
+        TestResult::from_bool(
+            split_output(&entry, spend, change_address) == Err(ValueError::NegativeAmount),
+        )
+    }
+
+    #[test]
+    fn errors_name_the_fragment_and_the_index() {
+        let fragment_id = FragmentId::hash_bytes(b"fragment");
+        let other_id = FragmentId::hash_bytes(b"other");
+        let output = Output {
+            address: (),
+            value: Value(1),
+        };
+        let ledger = Ledger::new()
+            .add(
+                &fragment_id,
+                &[(idx(0), output.clone()), (idx(1), output.clone())],
+            )
+            .unwrap();
+        let hex = fragment_id.to_string();
+
+        let error = ledger.add(&fragment_id, &[(idx(2), output)]).unwrap_err();
+        assert_eq!(
+            error,
+            Error::AlreadyExists {
+                fragment: fragment_id
+            }
+        );
+        assert!(error.to_string().contains(&hex));
+
+        let error = ledger.remove(&fragment_id, idx(7)).unwrap_err();
+        assert_eq!(
+            error,
+            Error::IndexNotFound {
+                fragment: fragment_id,
+                index: idx(7)
+            }
+        );
+        assert!(error.to_string().contains(&hex));
+        assert!(error.to_string().contains("7"));
+
+        let error = ledger
+            .remove_multiple(&pointers_to(&fragment_id, &[idx(1), idx(1)]))
+            .unwrap_err();
+        assert!(error.to_string().contains(&hex));
+
+        let error = ledger.remove(&other_id, idx(0)).unwrap_err();
+        assert_eq!(error, Error::TransactionNotFound { fragment: other_id });
+        assert!(error.to_string().contains(&other_id.to_string()));
+        assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    // the operations of the overlay, on a few fragments and indices so
+    // that they often collide
+    #[derive(Clone, Debug)]
+    enum Op {
+        Add(u8, Vec<Output<Address>>),
+        Remove(u8, u8),
+        RemoveMultiple(u8, Vec<u8>),
+    }
+
+    const OP_FRAGMENTS: u8 = 4;
+    const OP_INDICES: u8 = 4;
+
+    fn op_fragment(fragment: u8) -> FragmentId {
+        FragmentId::hash_bytes(&[fragment % OP_FRAGMENTS])
+    }
+
+    fn op_index(index: u8) -> TransactionIndex {
+        idx(index % OP_INDICES)
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            match u8::arbitrary(g) % 3 {
+                0 => {
+                    let mut outputs = Vec::<Output<Address>>::arbitrary(g);
+                    outputs.truncate(OP_INDICES as usize);
+                    Op::Add(u8::arbitrary(g), outputs)
+                }
+                1 => Op::Remove(u8::arbitrary(g), u8::arbitrary(g)),
+                _ => Op::RemoveMultiple(u8::arbitrary(g), Vec::arbitrary(g)),
+            }
+        }
+    }
+
+    fn indexed(outputs: &[Output<Address>]) -> Vec<(TransactionIndex, Output<Address>)> {
+        outputs
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(index, output)| (idx(index as u8), output))
+            .collect()
+    }
+
+    fn apply_op(
+        ledger: &Ledger<Address>,
+        op: &Op,
+    ) -> Result<(Ledger<Address>, Vec<Output<Address>>), Error> {
+        match op {
+            Op::Add(fragment, outputs) => ledger
+                .add(&op_fragment(*fragment), &indexed(outputs))
+                .map(|ledger| (ledger, Vec::new())),
+            Op::Remove(fragment, index) => ledger
+                .remove(&op_fragment(*fragment), op_index(*index))
+                .map(|(ledger, output)| (ledger, vec![output])),
+            Op::RemoveMultiple(fragment, indices) => {
+                let indices: Vec<_> = indices.iter().map(|index| op_index(*index)).collect();
+                ledger.remove_multiple(&pointers_to(&op_fragment(*fragment), &indices))
+            }
+        }
+    }
+
+    fn apply_op_on_overlay(
+        overlay: &mut Overlay<Address>,
+        op: &Op,
+    ) -> Result<Vec<Output<Address>>, Error> {
+        match op {
+            Op::Add(fragment, outputs) => overlay
+                .add(&op_fragment(*fragment), &indexed(outputs))
+                .map(|()| Vec::new()),
+            Op::Remove(fragment, index) => overlay
+                .remove(&op_fragment(*fragment), op_index(*index))
+                .map(|output| vec![output]),
+            Op::RemoveMultiple(fragment, indices) => {
+                let indices: Vec<_> = indices.iter().map(|index| op_index(*index)).collect();
+                overlay.remove_multiple(&pointers_to(&op_fragment(*fragment), &indices))
+            }
+        }
+    }
+
+    fn sorted_entries<'a, I>(entries: I) -> Vec<EntryOwned<Address>>
+    where
+        I: Iterator<Item = Entry<'a, Address>>,
+    {
+        let mut entries: Vec<_> = entries.map(|entry| entry.to_owned()).collect();
+        entries.sort_by(|a, b| {
+            (&a.fragment_id, a.output_index).cmp(&(&b.fragment_id, b.output_index))
+        });
+        entries
+    }
+
+    #[quickcheck]
+    fn overlay_then_commit_is_applying_directly(
+        ledger: Ledger<Address>,
+        setup: Vec<Op>,
+        ops: Vec<Op>,
+    ) -> TestResult {
+        let mut base = ledger;
+        for op in &setup {
+            if let Ok((next, _)) = apply_op(&base, op) {
+                base = next;
+            }
+        }
+
+        let mut direct = base.clone();
+        let mut overlay = Overlay::new(&base);
+        for op in &ops {
+            let expected = apply_op(&direct, op);
+            let got = apply_op_on_overlay(&mut overlay, op);
+            match expected {
+                Ok((next, outputs)) => {
+                    if got != Ok(outputs) {
+                        return TestResult::error(format!("{:?} did not succeed the same", op));
+                    }
+                    direct = next;
+                }
+                Err(error) => {
+                    if got != Err(error) {
+                        return TestResult::error(format!("{:?} did not fail the same", op));
+                    }
+                }
+            }
+            if sorted_entries(overlay.iter()) != sorted_entries(direct.iter()) {
+                return TestResult::error(format!("entries differ after {:?}", op));
+            }
+        }
+        let committed = overlay.commit().unwrap();
+        TestResult::from_bool(committed == direct)
+    }
+
+    #[test]
+    fn discarded_overlay_leaves_the_ledger() {
+        let fragment_id = FragmentId::hash_bytes(b"fragment");
+        let output = Output {
+            address: (),
+            value: Value(1),
+        };
+        let ledger = Ledger::new()
+            .add(&fragment_id, &[(idx(0), output.clone())])
+            .unwrap();
+        let mut overlay = Overlay::new(&ledger);
+        assert_eq!(overlay.remove(&fragment_id, idx(0)), Ok(output.clone()));
+        assert!(overlay.get(&fragment_id, &idx(0)).is_none());
+        assert_eq!(overlay.iter().count(), 0);
+        assert_eq!(
+            overlay.remove(&fragment_id, idx(0)),
+            Err(Error::TransactionNotFound {
+                fragment: fragment_id
+            })
+        );
+        overlay.discard();
+        assert_eq!(ledger.get(&fragment_id, &idx(0)).unwrap().output, &output);
     }
 }
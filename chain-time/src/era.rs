@@ -36,6 +36,16 @@ impl TimeEra {
         }
     }
 
+    /// the first epoch of the era
+    pub fn epoch_start(&self) -> Epoch {
+        self.epoch_start
+    }
+
+    /// the slot at which the era starts
+    pub fn slot_start(&self) -> Slot {
+        self.slot_start
+    }
+
     /// retrieve the number of slots in an epoch during a given Epoch
     pub fn slots_per_epoch(&self) -> u32 {
         self.slots_per_epoch
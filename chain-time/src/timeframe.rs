@@ -14,6 +14,12 @@ impl From<Slot> for u64 {
     }
 }
 
+impl From<u64> for Slot {
+    fn from(s: u64) -> Slot {
+        Slot(s)
+    }
+}
+
 /// Identify a slot in a specific timeframe and a leftover duration
 #[derive(Debug)]
 pub struct SlotAndDuration {
@@ -97,6 +103,11 @@ impl TimeFrame {
         Slot(self.slot_offset.0)
     }
 
+    /// Duration of each slot of this time frame
+    pub fn slot_duration(&self) -> SlotDuration {
+        self.slot_duration
+    }
+
     /// Given a system time get the slot and associated duration leftover
     pub fn slot_at_precise(&self, at: &SystemTime) -> Option<SlotAndDuration> {
         match self.timeline.differential(at) {
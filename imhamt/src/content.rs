@@ -117,6 +117,16 @@ pub enum LeafIterator<'a, K, V> {
     Many(slice::Iter<'a, SharedRef<KV<K, V>>>),
 }
 
+// not derived, the iterator only holds references whatever K and V are
+impl<'a, K, V> Clone for LeafIterator<'a, K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            LeafIterator::One(consumed, o) => LeafIterator::One(*consumed, o),
+            LeafIterator::Many(c) => LeafIterator::Many(c.clone()),
+        }
+    }
+}
+
 impl<'a, K, V> LeafContent<K, V> {
     pub fn iter(&'a self) -> LeafIterator<'a, K, V> {
         match self.content {
@@ -1,8 +1,8 @@
 use super::content::{LeafIterator, KV};
 use super::hash::{Hash, HashedKey, Hasher};
 use super::node::{
-    insert_rec, lookup_one, remove_eq_rec, remove_rec, replace_rec, size_rec, update_rec, Entry,
-    LookupRet, Node, NodeIter,
+    diff_rec, insert_rec, lookup_one, remove_eq_rec, remove_rec, replace_rec, size_rec, update_rec,
+    Entry, LookupRet, Node, NodeIter,
 };
 pub use super::operation::{
     InsertError, InsertOrUpdateError, RemoveError, ReplaceError, UpdateError,
@@ -11,6 +11,7 @@ use super::sharedref::SharedRef;
 use std::iter::FromIterator;
 use std::marker::PhantomData;
 use std::mem::swap;
+use std::ops::Range;
 
 #[derive(Clone)]
 pub struct Hamt<H: Hasher + Default, K: PartialEq + Eq + Hash, V> {
@@ -23,6 +24,26 @@ pub struct HamtIter<'a, K, V> {
     content: Option<LeafIterator<'a, K, V>>,
 }
 
+impl<'a, K, V> Clone for HamtIter<'a, K, V> {
+    fn clone(&self) -> Self {
+        HamtIter {
+            stack: self.stack.clone(),
+            content: self.content.clone(),
+        }
+    }
+}
+
+/// A key whose binding differs between two HAMTs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference<'a, K, V> {
+    /// the key is only in the old HAMT
+    Removed(&'a K, &'a V),
+    /// the key is only in the new HAMT
+    Added(&'a K, &'a V),
+    /// the key is in both, with the old and the new value
+    Changed(&'a K, &'a V, &'a V),
+}
+
 impl<H: Hasher + Default, K: Eq + Hash, V> Hamt<H, K, V> {
     pub fn new() -> Self {
         Hamt {
@@ -156,6 +177,41 @@ impl<H: Hasher + Default, K: Hash + Eq, V> Hamt<H, K, V> {
             content: None,
         }
     }
+
+    /// The number of branches of the root node, see `iter_branches`
+    pub fn root_branches(&self) -> usize {
+        self.root.children.len()
+    }
+
+    /// Iterate over the entries under the root branches in `branches`.
+    ///
+    /// Every entry is under exactly one root branch, so iterating over
+    /// disjoint ranges of branches gives disjoint entries, and over
+    /// `0..root_branches()` gives all the entries in the order of `iter`.
+    /// This allows to split the traversal of a big HAMT between threads.
+    ///
+    /// Panics if the range is not within `0..root_branches()`.
+    pub fn iter_branches(&self, branches: Range<usize>) -> HamtIter<K, V> {
+        HamtIter {
+            stack: vec![self.root.children[branches].iter()],
+            content: None,
+        }
+    }
+}
+
+impl<H: Hasher + Default, K: Eq + Hash, V: PartialEq> Hamt<H, K, V> {
+    /// All the keys bound differently in `self` and in `new`, in no
+    /// particular order.
+    ///
+    /// The subtrees shared by both HAMTs are skipped without looking at
+    /// their content, so comparing a HAMT with one derived from it by a
+    /// few operations costs about the number of operations, not the size
+    /// of the HAMTs.
+    pub fn differences<'a>(&'a self, new: &'a Self) -> Vec<Difference<'a, K, V>> {
+        let mut out = Vec::new();
+        diff_rec(&self.root, &new.root, &mut out);
+        out
+    }
 }
 
 impl<'a, K, V> Iterator for HamtIter<'a, K, V> {
@@ -1,7 +1,7 @@
 pub use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
-const SIZE: usize = 32;
+pub const SIZE: usize = 32;
 
 /// Hash of a key
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -361,6 +361,69 @@ mod tests {
         property_btreemap_eq(&reference, &h)
     }
 
+    #[quickcheck]
+    fn differences_equivalent(xs: Vec<(String, u32)>, changes: Vec<(String, Option<u32>)>) -> bool {
+        let old_reference: BTreeMap<String, u32> = xs.iter().cloned().collect();
+        let old: Hamt<DefaultHasher, String, u32> = old_reference.clone().into_iter().collect();
+        let mut new_reference = old_reference.clone();
+        let mut new = old.clone();
+        for (k, v) in changes.iter() {
+            match (new_reference.get(k).is_some(), v) {
+                (false, None) => {}
+                (true, None) => new = new.remove(k).unwrap(),
+                (false, Some(v)) => new = new.insert(k.clone(), *v).unwrap(),
+                (true, Some(v)) => new = new.replace(k, *v).unwrap().0,
+            }
+            match v {
+                None => new_reference.remove(k),
+                Some(v) => new_reference.insert(k.clone(), *v),
+            };
+        }
+
+        let mut expected = Vec::new();
+        for (k, v) in old_reference.iter() {
+            match new_reference.get(k) {
+                None => expected.push((k, Some(v), None)),
+                Some(v2) if v2 != v => expected.push((k, Some(v), Some(v2))),
+                Some(_) => {}
+            }
+        }
+        for (k, v) in new_reference.iter() {
+            if !old_reference.contains_key(k) {
+                expected.push((k, None, Some(v)))
+            }
+        }
+        expected.sort();
+
+        let mut differences: Vec<_> = old
+            .differences(&new)
+            .into_iter()
+            .map(|d| match d {
+                Difference::Removed(k, v) => (k, Some(v), None),
+                Difference::Added(k, v) => (k, None, Some(v)),
+                Difference::Changed(k, v, v2) => (k, Some(v), Some(v2)),
+            })
+            .collect();
+        differences.sort();
+        differences == expected && old.differences(&old).is_empty()
+    }
+
+    #[quickcheck]
+    fn root_branches_partition_the_entries(xs: Vec<(String, u32)>, split: usize) -> bool {
+        let h: Hamt<DefaultHasher, String, u32> = xs
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+        let branches = h.root_branches();
+        let split = if branches == 0 { 0 } else { split % branches };
+        let all: Vec<_> = h.iter().collect();
+        let parts: Vec<_> = h
+            .iter_branches(0..split)
+            .chain(h.iter_branches(split..branches))
+            .collect();
+        parts == all && (branches == 0) == all.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
 use super::super::bitmap::{ArrayIndex, SmallBitmap};
 use super::super::content::{LeafContent, KV};
-use super::super::hash::{HashedKey, LevelIndex};
+use super::super::hamt::Difference;
+use super::super::hash::{HashedKey, LevelIndex, SIZE};
 use super::super::helper;
 use super::super::operation::*;
 use super::super::sharedref::SharedRef;
@@ -341,6 +342,81 @@ pub fn size_rec<K, V>(node: &Node<K, V>) -> usize {
     sum
 }
 
+fn collect_rec<'a, K, V>(entry: &'a Entry<K, V>, kvs: &mut Vec<(&'a K, &'a V)>) {
+    match entry {
+        Entry::Leaf(content) => kvs.extend(content.iter().map(|kv| (kv.get_key(), kv.get_value()))),
+        Entry::SubNode(sub) => {
+            for c in sub.children.iter() {
+                collect_rec(c, kvs)
+            }
+        }
+    }
+}
+
+// keyed comparison of two small sets of pairs, used when the two trees
+// are not shaped the same way at some index
+fn diff_pairs<'a, K: PartialEq, V: PartialEq>(
+    old: &[(&'a K, &'a V)],
+    new: &[(&'a K, &'a V)],
+    out: &mut Vec<Difference<'a, K, V>>,
+) {
+    for &(k, v) in old {
+        match new.iter().find(|&&(k2, _)| k2 == k) {
+            None => out.push(Difference::Removed(k, v)),
+            Some(&(_, v2)) if v != v2 => out.push(Difference::Changed(k, v, v2)),
+            Some(_) => {}
+        }
+    }
+    for &(k, v) in new {
+        if !old.iter().any(|&(k2, _)| k2 == k) {
+            out.push(Difference::Added(k, v))
+        }
+    }
+}
+
+// Compare recursively two nodes at the same level, skipping the children
+// shared by both nodes.
+pub fn diff_rec<'a, K: PartialEq, V: PartialEq>(
+    old: &'a Node<K, V>,
+    new: &'a Node<K, V>,
+    out: &mut Vec<Difference<'a, K, V>>,
+) {
+    let child = |node: &'a Node<K, V>, idx: LevelIndex| {
+        if node.bitmap.is_set(idx) {
+            Some(node.get_child(node.bitmap.get_index_sparse(idx)))
+        } else {
+            None
+        }
+    };
+    for i in 0..SIZE {
+        let idx = LevelIndex(i);
+        match (child(old, idx), child(new, idx)) {
+            (None, None) => {}
+            (Some(o), Some(n)) if SharedRef::ptr_eq(o, n) => {}
+            (Some(o), Some(n)) => match (o.as_ref(), n.as_ref()) {
+                (Entry::SubNode(o), Entry::SubNode(n)) => diff_rec(o, n, out),
+                (o, n) => {
+                    let mut old_kvs = Vec::new();
+                    let mut new_kvs = Vec::new();
+                    collect_rec(o, &mut old_kvs);
+                    collect_rec(n, &mut new_kvs);
+                    diff_pairs(&old_kvs, &new_kvs, out)
+                }
+            },
+            (Some(o), None) => {
+                let mut kvs = Vec::new();
+                collect_rec(o, &mut kvs);
+                out.extend(kvs.into_iter().map(|(k, v)| Difference::Removed(k, v)))
+            }
+            (None, Some(n)) => {
+                let mut kvs = Vec::new();
+                collect_rec(n, &mut kvs);
+                out.extend(kvs.into_iter().map(|(k, v)| Difference::Added(k, v)))
+            }
+        }
+    }
+}
+
 //// debug
 pub mod debug {
     use super::*;
@@ -4,6 +4,9 @@ pub mod block;
 pub mod content;
 pub mod gossip;
 pub mod p2p;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod transaction;
 
 use crate::error::Error;
 
@@ -0,0 +1,263 @@
+//! An in-memory connection of the client traits to a server
+//! implementation, for testing code that uses the client traits without
+//! a network protocol.
+//!
+//! This module is only available with the `testing` feature.
+
+use super::p2p;
+use super::transaction::TransactionService;
+use crate::error::{Code, Error};
+use crate::filter::FragmentFilter;
+use crate::server::{
+    self,
+    content::{ContentService, TransactionIdBatch},
+};
+use crate::subscription::SubscriptionHandle;
+
+use futures::prelude::*;
+use futures::try_ready;
+
+/// A client connection that calls the methods of a server service
+/// directly.
+///
+/// The client subscriptions are established on behalf of the node
+/// identified by `client_id`, and the subscription handle the service
+/// returns is closed when the client drops the subscription stream,
+/// as a protocol implementation would do when the peer disconnects.
+pub struct Loopback<S: server::P2pService> {
+    service: S,
+    client_id: S::NodeId,
+}
+
+impl<S: server::P2pService> Loopback<S> {
+    /// Connects to `service` as the node `client_id`.
+    pub fn new(service: S, client_id: S::NodeId) -> Self {
+        Loopback { service, client_id }
+    }
+
+    /// The service the client calls.
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    pub fn service_mut(&mut self) -> &mut S {
+        &mut self.service
+    }
+
+    pub fn into_service(self) -> S {
+        self.service
+    }
+}
+
+impl<S: server::P2pService> p2p::P2pService for Loopback<S> {
+    type NodeId = S::NodeId;
+}
+
+impl<S: ContentService> TransactionService for Loopback<S> {
+    type Fragment = S::Fragment;
+    type FragmentId = S::FragmentId;
+    type GetFragmentsStream = S::GetFragmentsStream;
+    type GetFragmentsFuture = S::GetFragmentsFuture;
+    type ProposeFragmentsFuture = S::ProposeFragmentsFuture;
+    type ExchangeFilterFuture = S::ExchangeFilterFuture;
+    type ContentSubscription = LoopbackSubscription<S::ContentSubscription>;
+    type ContentSubscriptionFuture =
+        LoopbackSubscriptionFuture<S::ContentSubscriptionFuture, S::NodeId>;
+
+    fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
+        self.service.get_fragments(ids)
+    }
+
+    fn propose_fragments(
+        &mut self,
+        ids: &TransactionIdBatch<Self::FragmentId>,
+    ) -> Self::ProposeFragmentsFuture {
+        self.service.propose_fragments(ids)
+    }
+
+    fn exchange_filter(&mut self, local: FragmentFilter) -> Self::ExchangeFilterFuture {
+        self.service.exchange_filter(local)
+    }
+
+    fn content_subscription<Out>(&mut self, outbound: Out) -> Self::ContentSubscriptionFuture
+    where
+        Out: Stream<Item = Self::Fragment> + Send + 'static,
+    {
+        let inbound =
+            outbound.map_err(|_| Error::new(Code::Canceled, "the outbound stream failed"));
+        LoopbackSubscriptionFuture {
+            inner: self
+                .service
+                .content_subscription(self.client_id.clone(), inbound),
+            server_id: Some(self.service.node_id()),
+        }
+    }
+}
+
+/// The future returned by the subscription methods of `Loopback`.
+pub struct LoopbackSubscriptionFuture<F, N> {
+    inner: F,
+    server_id: Option<N>,
+}
+
+impl<F, St, N> Future for LoopbackSubscriptionFuture<F, N>
+where
+    F: Future<Item = (St, SubscriptionHandle), Error = Error>,
+{
+    type Item = (LoopbackSubscription<St>, N);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (stream, handle) = try_ready!(self.inner.poll());
+        let server_id = self.server_id.take().expect("polled a finished future");
+        Ok(Async::Ready((
+            LoopbackSubscription { stream, handle },
+            server_id,
+        )))
+    }
+}
+
+/// The inbound stream of a subscription of `Loopback`, closing the
+/// subscription handle of the service when dropped.
+pub struct LoopbackSubscription<St> {
+    stream: St,
+    handle: SubscriptionHandle,
+}
+
+impl<St: Stream> Stream for LoopbackSubscription<St> {
+    type Item = St::Item;
+    type Error = St::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.stream.poll()
+    }
+}
+
+impl<St> Drop for LoopbackSubscription<St> {
+    fn drop(&mut self) {
+        self.handle.close()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gossip::NodeId;
+    use crate::server::testing::{MockContentService, RecordedCall};
+    use chain_core::packer::Codec;
+    use chain_core::property::{Deserialize, Fragment, FragmentId, Serialize};
+    use futures::stream;
+    use std::io::{self, Write};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct TestId(u32);
+
+    impl Serialize for TestId {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            Codec::new(writer).put_u32(self.0)
+        }
+    }
+
+    impl Deserialize for TestId {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            Codec::new(reader).get_u32().map(TestId)
+        }
+    }
+
+    impl FragmentId for TestId {}
+
+    impl NodeId for TestId {}
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TestFragment(TestId);
+
+    impl Serialize for TestFragment {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            self.0.serialize(writer)
+        }
+    }
+
+    impl Deserialize for TestFragment {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            TestId::deserialize(reader).map(TestFragment)
+        }
+    }
+
+    impl Fragment for TestFragment {
+        type Id = TestId;
+
+        fn id(&self) -> Self::Id {
+            self.0.clone()
+        }
+    }
+
+    fn loopback_with(ids: &[u32]) -> Loopback<MockContentService<TestFragment, TestId>> {
+        let mut service = MockContentService::new(TestId(0));
+        for id in ids {
+            service.insert(TestFragment(TestId(*id)));
+        }
+        Loopback::new(service, TestId(9))
+    }
+
+    // a consumer of the client trait, as the application logic would
+    // drive it: the fragments the peer wants from those proposed
+    fn wanted<C: TransactionService>(
+        client: &mut C,
+        ids: &[C::FragmentId],
+    ) -> Result<Vec<C::FragmentId>, Error> {
+        let batch = TransactionIdBatch::new(ids.to_vec())?;
+        let response = client.propose_fragments(&batch).wait()?;
+        batch.check_response(&response)?;
+        Ok(response.unknown_ids().cloned().collect())
+    }
+
+    #[test]
+    fn propose_fragments_round_trips() {
+        let mut client = loopback_with(&[1, 3]);
+        let ids = [TestId(1), TestId(2), TestId(3), TestId(4)];
+        assert_eq!(
+            wanted(&mut client, &ids).unwrap(),
+            vec![TestId(2), TestId(4)]
+        );
+
+        client.service_mut().fail_next_with(Code::Unavailable);
+        let error = wanted(&mut client, &ids).unwrap_err();
+        assert_eq!(error.code(), Code::Unavailable);
+        assert_eq!(
+            client.service().calls(),
+            vec![
+                RecordedCall::ProposeFragments(ids.to_vec()),
+                RecordedCall::ProposeFragments(ids.to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn dropping_the_subscription_closes_it() {
+        let mut client = loopback_with(&[]);
+        client.service_mut().announce(TestFragment(TestId(7)));
+        let (inbound, server_id) = client
+            .content_subscription(stream::empty::<TestFragment, ()>())
+            .wait()
+            .unwrap();
+        assert_eq!(server_id, TestId(0));
+        assert_eq!(client.service().open_subscriptions().len(), 1);
+        assert_eq!(
+            inbound.collect().wait().unwrap(),
+            vec![TestFragment(TestId(7))]
+        );
+        assert!(client.service().open_subscriptions().is_empty());
+        assert_eq!(
+            client.service().calls(),
+            vec![RecordedCall::ContentSubscription(TestId(9))]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use super::p2p::P2pService;
+use crate::error::Error;
+use crate::filter::FragmentFilter;
+use crate::server::content::{GetFragmentsItem, ProposeFragmentsResponse, TransactionIdBatch};
+
+use chain_core::property::{Fragment, FragmentId};
+
+use futures::prelude::*;
+
+/// Interface for the client side of the exchange of transactions and
+/// other fragments with a peer.
+///
+/// This is the counterpart of `server::content::ContentService`: every
+/// method sends the request of the server method with the same name
+/// to the peer, and resolves to what the server responded with. A
+/// client implementation of a network protocol implements this trait,
+/// and the application logic driving the exchange is written against it
+/// independently of the protocol; see `client::testing::Loopback` to
+/// run such code against a server implementation in memory.
+///
+/// This is separate from `client::content::ContentService` so that a
+/// protocol without requests to propose fragments or to exchange filters,
+/// like the gRPC protocol for now, can still implement the latter.
+pub trait TransactionService: P2pService {
+    /// The data type to represent fragments constituting a block.
+    type Fragment: Fragment;
+
+    /// The fragment identifier type for the blockchain.
+    type FragmentId: FragmentId;
+
+    /// The type of an asynchronous stream that provides fragments in
+    /// response to `get_fragments`.
+    type GetFragmentsStream: Stream<
+        Item = GetFragmentsItem<Self::Fragment, Self::FragmentId>,
+        Error = Error,
+    >;
+
+    /// The type of asynchronous futures returned by `get_fragments`.
+    ///
+    /// The future resolves to a stream of the fragments as they are
+    /// received from the peer.
+    type GetFragmentsFuture: Future<Item = Self::GetFragmentsStream, Error = Error>;
+
+    /// The type of asynchronous futures returned by `propose_fragments`.
+    type ProposeFragmentsFuture: Future<
+        Item = ProposeFragmentsResponse<Self::FragmentId>,
+        Error = Error,
+    >;
+
+    /// The type of asynchronous futures returned by `exchange_filter`.
+    type ExchangeFilterFuture: Future<Item = Vec<Self::FragmentId>, Error = Error>;
+
+    /// The type of an asynchronous stream that provides the fragments
+    /// announced by the peer via the bidirectional subscription.
+    type ContentSubscription: Stream<Item = Self::Fragment, Error = Error>;
+
+    /// The type of asynchronous futures returned by method `content_subscription`.
+    ///
+    /// The future resolves to a stream of fragments sent by the remote node
+    /// and the identifier of the node in the network.
+    type ContentSubscriptionFuture: Future<
+        Item = (Self::ContentSubscription, Self::NodeId),
+        Error = Error,
+    >;
+
+    /// Requests the identified fragments from the peer.
+    ///
+    /// The stream yields one item per requested identifier, in the order
+    /// of the request, with `GetFragmentsItem::Unknown` for the fragments
+    /// the peer does not know.
+    fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture;
+
+    /// Proposes fragments to the peer by their identifiers.
+    ///
+    /// The response gives the status of each identifier of the batch, in
+    /// the order of the batch, telling in particular which fragments the
+    /// peer wants to receive. A response that does not follow the batch
+    /// can be detected with `TransactionIdBatch::check_response`.
+    fn propose_fragments(
+        &mut self,
+        ids: &TransactionIdBatch<Self::FragmentId>,
+    ) -> Self::ProposeFragmentsFuture;
+
+    /// Sends a filter of the fragments of this node to the peer.
+    ///
+    /// Resolves to the identifiers of the fragments the peer has and
+    /// which are not in `local`.
+    fn exchange_filter(&mut self, local: FragmentFilter) -> Self::ExchangeFilterFuture;
+
+    /// Establishes a bidirectional stream of notifications for fragments
+    /// created or accepted by either of the peers.
+    ///
+    /// The client can use the stream that the returned future resolves to
+    /// as a long-lived subscription handle.
+    fn content_subscription<S>(&mut self, outbound: S) -> Self::ContentSubscriptionFuture
+    where
+        S: Stream<Item = Self::Fragment> + Send + 'static;
+}
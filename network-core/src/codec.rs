@@ -0,0 +1,424 @@
+//! Byte encodings of protocol messages for the protocol implementations
+//! that do not come with their own, such as NTT.
+//!
+//! A response to `propose_fragments` is encoded with its `Serialize`
+//! implementation, see `encode_response`. The items streamed in response
+//! to `get_fragments` are sent as a `FramedBatch`: the number of items
+//! as a big endian u32, then each item as its length, also a big endian
+//! u32, followed by its serialization. The length prefixes let
+//! `FramedBatchDecoder` produce the items as their bytes arrive.
+
+use crate::error::{Code, Error};
+use crate::server::content::ProposeFragmentsResponse;
+
+use chain_core::property::{Deserialize, Serialize};
+
+use std::io::{self, Write};
+use std::marker::PhantomData;
+
+/// The default largest item accepted by a `FramedBatchDecoder`, in bytes.
+pub const DEFAULT_MAX_ITEM_BYTES: usize = 1 << 20;
+
+const PREFIX_BYTES: usize = 4;
+
+fn io_error(error: io::Error) -> Error {
+    Error::new(Error::code_from_io_kind(error.kind()), error)
+}
+
+fn invalid_argument<E>(error: E) -> Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Error::new(Code::InvalidArgument, error)
+}
+
+/// Writes the encoding of `response` to `writer`.
+pub fn encode_response<Id, W>(
+    response: &ProposeFragmentsResponse<Id>,
+    writer: W,
+) -> Result<(), Error>
+where
+    Id: Serialize,
+    Id::Error: Send + Sync + 'static,
+    W: Write,
+{
+    response.serialize(writer).map_err(io_error)
+}
+
+/// Reads a response to `propose_fragments` taking the whole of `buf`.
+pub fn decode_response<Id: Deserialize>(
+    mut buf: &[u8],
+) -> Result<ProposeFragmentsResponse<Id>, Error> {
+    let response = ProposeFragmentsResponse::deserialize(&mut buf).map_err(invalid_argument)?;
+    if !buf.is_empty() {
+        return Err(invalid_argument(format!(
+            "{} bytes left after the response",
+            buf.len()
+        )));
+    }
+    Ok(response)
+}
+
+/// A batch of items encoded with their lengths, so that they can be
+/// decoded one by one by `FramedBatchDecoder`.
+///
+/// The batch can also be written incrementally, as the items of a stream
+/// become available, with `write_header` then `write_item` for each item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramedBatch<T> {
+    items: Vec<T>,
+}
+
+impl<T> FramedBatch<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        FramedBatch { items }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Writes the start of a batch of `count` items.
+    pub fn write_header<W: Write>(count: u32, mut writer: W) -> Result<(), Error> {
+        writer.write_all(&count.to_be_bytes()).map_err(io_error)
+    }
+}
+
+impl<T> FramedBatch<T>
+where
+    T: Serialize,
+    T::Error: Send + Sync + 'static,
+{
+    /// Writes the next item of a batch started with `write_header`.
+    pub fn write_item<W: Write>(item: &T, mut writer: W) -> Result<(), Error> {
+        let bytes = item
+            .serialize_as_vec()
+            .map_err(|error| Error::new(Code::Internal, error))?;
+        if bytes.len() > u32::max_value() as usize {
+            return Err(Error::new(Code::Internal, "item too large to be framed"));
+        }
+        writer
+            .write_all(&(bytes.len() as u32).to_be_bytes())
+            .and_then(|()| writer.write_all(&bytes))
+            .map_err(io_error)
+    }
+
+    /// Writes the whole batch.
+    pub fn encode<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        if self.items.len() > u32::max_value() as usize {
+            return Err(Error::new(Code::Internal, "too many items in the batch"));
+        }
+        Self::write_header(self.items.len() as u32, &mut writer)?;
+        for item in &self.items {
+            Self::write_item(item, &mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// What `FramedBatchDecoder::next_item` found in the bytes fed so far.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decoded<T> {
+    /// The next item of the batch.
+    Item(T),
+    /// The next item is not complete yet.
+    NeedMoreData,
+    /// All the items of the batch have been decoded.
+    Finished,
+}
+
+/// Incremental decoder of a `FramedBatch`, to be fed with the bytes of
+/// the batch in pieces of any size as they are received.
+///
+/// An item is only buffered up to the largest size given to the decoder,
+/// a frame announcing a larger item is rejected with
+/// `Code::InvalidArgument` before any of it is read.
+pub struct FramedBatchDecoder<T> {
+    buffer: Vec<u8>,
+    remaining: Option<u32>,
+    max_item_bytes: usize,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: Deserialize> FramedBatchDecoder<T> {
+    /// Makes a decoder accepting items of up to `DEFAULT_MAX_ITEM_BYTES`.
+    pub fn new() -> Self {
+        Self::with_max_item_bytes(DEFAULT_MAX_ITEM_BYTES)
+    }
+
+    /// Makes a decoder accepting items of up to `max_item_bytes`.
+    pub fn with_max_item_bytes(max_item_bytes: usize) -> Self {
+        FramedBatchDecoder {
+            buffer: Vec::new(),
+            remaining: None,
+            max_item_bytes,
+            _item: PhantomData,
+        }
+    }
+
+    /// Adds the next received bytes of the batch.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes)
+    }
+
+    /// Decodes the next item from the bytes fed so far.
+    ///
+    /// The bytes fed after the end of the batch are ignored.
+    pub fn next_item(&mut self) -> Result<Decoded<T>, Error> {
+        let remaining = match self.remaining {
+            Some(remaining) => remaining,
+            None => match self.take_prefix() {
+                Some(count) => {
+                    self.remaining = Some(count);
+                    count
+                }
+                None => return Ok(Decoded::NeedMoreData),
+            },
+        };
+        if remaining == 0 {
+            return Ok(Decoded::Finished);
+        }
+
+        let len = match self.peek_prefix() {
+            Some(len) => len as usize,
+            None => return Ok(Decoded::NeedMoreData),
+        };
+        if len > self.max_item_bytes {
+            return Err(invalid_argument(format!(
+                "framed item of {} bytes, at most {} are allowed",
+                len, self.max_item_bytes
+            )));
+        }
+        if self.buffer.len() < PREFIX_BYTES + len {
+            return Ok(Decoded::NeedMoreData);
+        }
+
+        let item = {
+            let mut bytes = &self.buffer[PREFIX_BYTES..PREFIX_BYTES + len];
+            let item = T::deserialize(&mut bytes).map_err(invalid_argument)?;
+            if !bytes.is_empty() {
+                return Err(invalid_argument(format!(
+                    "{} bytes left in the frame of an item",
+                    bytes.len()
+                )));
+            }
+            item
+        };
+        self.buffer.drain(..PREFIX_BYTES + len);
+        self.remaining = Some(remaining - 1);
+        Ok(Decoded::Item(item))
+    }
+
+    fn peek_prefix(&self) -> Option<u32> {
+        if self.buffer.len() < PREFIX_BYTES {
+            return None;
+        }
+        let mut prefix = [0; PREFIX_BYTES];
+        prefix.copy_from_slice(&self.buffer[..PREFIX_BYTES]);
+        Some(u32::from_be_bytes(prefix))
+    }
+
+    fn take_prefix(&mut self) -> Option<u32> {
+        let prefix = self.peek_prefix()?;
+        self.buffer.drain(..PREFIX_BYTES);
+        Some(prefix)
+    }
+}
+
+impl<T: Deserialize> Default for FramedBatchDecoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::content::ProposeFragmentStatus;
+    use chain_core::packer::Codec;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Item(Vec<u8>);
+
+    // a length followed by the bytes, so that items have various sizes
+    impl Serialize for Item {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            let mut codec = Codec::new(writer);
+            codec.put_u16(self.0.len() as u16)?;
+            codec.write_all(&self.0)
+        }
+    }
+
+    impl Deserialize for Item {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            let mut codec = Codec::new(reader);
+            let len = codec.get_u16()? as usize;
+            let mut bytes = vec![0; len];
+            io::Read::read_exact(&mut codec, &mut bytes)?;
+            Ok(Item(bytes))
+        }
+    }
+
+    // xorshift64*, for reproducible chunk sizes
+    fn random_numbers(seed: u64) -> impl Iterator<Item = u64> {
+        let mut state = seed;
+        std::iter::repeat_with(move || {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        })
+    }
+
+    fn items() -> Vec<Item> {
+        (0..20)
+            .map(|i| Item((0..i * 7).map(|byte| byte as u8).collect()))
+            .collect()
+    }
+
+    fn encoded(items: Vec<Item>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        FramedBatch::new(items).encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    // feeds the chunks one by one, taking the completed items after each
+    fn decode_chunks<'a, I>(chunks: I) -> Vec<Item>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut decoder = FramedBatchDecoder::new();
+        let mut decoded = Vec::new();
+        let mut finished = false;
+        for chunk in chunks {
+            assert!(!finished);
+            decoder.feed(chunk);
+            loop {
+                match decoder.next_item().unwrap() {
+                    Decoded::Item(item) => decoded.push(item),
+                    Decoded::NeedMoreData => break,
+                    Decoded::Finished => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+        }
+        assert!(finished);
+        decoded
+    }
+
+    #[test]
+    fn batches_decode_from_chunks_of_any_size() {
+        let bytes = encoded(items());
+        assert_eq!(decode_chunks(vec![&bytes[..]]), items());
+        assert_eq!(decode_chunks(bytes.chunks(1)), items());
+
+        for seed in 1..20 {
+            let mut chunks = Vec::new();
+            let mut rest = &bytes[..];
+            let mut sizes = random_numbers(seed);
+            while !rest.is_empty() {
+                let size = 1 + sizes.next().unwrap() as usize % 64;
+                let (chunk, tail) = rest.split_at(std::cmp::min(size, rest.len()));
+                chunks.push(chunk);
+                rest = tail;
+            }
+            assert_eq!(decode_chunks(chunks), items());
+        }
+    }
+
+    #[test]
+    fn incremental_writes_match_the_batch_encoding() {
+        let mut bytes = Vec::new();
+        FramedBatch::<Item>::write_header(items().len() as u32, &mut bytes).unwrap();
+        for item in items() {
+            FramedBatch::write_item(&item, &mut bytes).unwrap();
+        }
+        assert_eq!(bytes, encoded(items()));
+        assert_eq!(decode_chunks(vec![&encoded(vec![])[..]]), vec![]);
+    }
+
+    #[test]
+    fn oversized_and_malformed_frames_are_rejected() {
+        // a frame announcing an item of 4 GiB
+        let mut decoder = FramedBatchDecoder::<Item>::new();
+        decoder.feed(&[0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]);
+        let error = decoder.next_item().unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+
+        let mut decoder = FramedBatchDecoder::<Item>::with_max_item_bytes(10);
+        decoder.feed(&encoded(vec![Item(vec![1; 9])]));
+        assert_eq!(
+            decoder.next_item().unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        // the frame is longer than the item in it
+        let mut decoder = FramedBatchDecoder::<Item>::new();
+        decoder.feed(&[0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 7]);
+        assert_eq!(
+            decoder.next_item().unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        // the frame is shorter than the item in it
+        let mut decoder = FramedBatchDecoder::<Item>::new();
+        decoder.feed(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 1]);
+        assert_eq!(
+            decoder.next_item().unwrap_err().code(),
+            Code::InvalidArgument
+        );
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Id(u32);
+
+    impl Serialize for Id {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            Codec::new(writer).put_u32(self.0)
+        }
+    }
+
+    impl Deserialize for Id {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            Codec::new(reader).get_u32().map(Id)
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let mut response = ProposeFragmentsResponse::new();
+        response.push(Id(1), ProposeFragmentStatus::Accepted);
+        response.push(Id(2), ProposeFragmentStatus::Unknown);
+        response.push(
+            Id(3),
+            ProposeFragmentStatus::Invalid(Error::new(Code::InvalidArgument, "malformed")),
+        );
+        let mut bytes = Vec::new();
+        encode_response(&response, &mut bytes).unwrap();
+
+        let decoded = decode_response::<Id>(&bytes).unwrap();
+        assert_eq!(decoded.accepted_ids().collect::<Vec<_>>(), vec![&Id(1)]);
+        assert_eq!(decoded.unknown_ids().collect::<Vec<_>>(), vec![&Id(2)]);
+        assert_eq!(decoded.invalid_ids().count(), 1);
+
+        bytes.push(0);
+        let error = decode_response::<Id>(&bytes).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        let error = decode_response::<Id>(&bytes[..5]).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+    }
+}
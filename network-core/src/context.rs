@@ -0,0 +1,144 @@
+//! The context of the requests received by a service.
+//!
+//! A protocol implementation knows more about a request than its
+//! arguments: the address of the peer, the deadline set by the client
+//! and the metadata sent along. It passes them to the `_ctx` methods of
+//! the service traits, see `ContentService::get_fragments_ctx`.
+
+use crate::error::Error;
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// What the protocol implementation knows about a request besides its
+/// arguments.
+///
+/// All of it is optional, a protocol implementation filling in what its
+/// transport provides.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    peer_addr: Option<SocketAddr>,
+    deadline: Option<Instant>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl RequestContext {
+    /// A context with no peer address, no deadline and no metadata.
+    pub fn new() -> Self {
+        RequestContext::default()
+    }
+
+    pub fn with_peer_addr(self, peer_addr: SocketAddr) -> Self {
+        RequestContext {
+            peer_addr: Some(peer_addr),
+            ..self
+        }
+    }
+
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        RequestContext {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// The socket address of the peer that sent the request, if the
+    /// transport has one.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The time by which the client expects the response, if it set one.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Whether the deadline has passed at `now`. A context without a
+    /// deadline never expires.
+    pub fn is_expired_at(&self, now: Instant) -> bool {
+        match self.deadline {
+            Some(deadline) => deadline <= now,
+            None => false,
+        }
+    }
+
+    /// Fails with `Error::deadline_exceeded` if the deadline has passed,
+    /// for a service to stop processing a request nobody waits for.
+    pub fn check_deadline(&self) -> Result<(), Error> {
+        if self.is_expired_at(Instant::now()) {
+            Err(Error::deadline_exceeded())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the metadata entry `key` to `value`, returning the value it
+    /// replaced.
+    pub fn insert_metadata<K, V>(&mut self, key: K, value: V) -> Option<String>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.metadata.insert(key.into(), value.into())
+    }
+
+    /// The value of the metadata entry `key`. The entries are opaque to
+    /// this crate, their meaning is agreed upon by the protocol
+    /// implementation and the service.
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// The metadata entries, ordered by key.
+    pub fn metadata_iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.metadata
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Code;
+    use std::time::Duration;
+
+    #[test]
+    fn deadlines_expire_once_reached() {
+        let now = Instant::now();
+        assert!(!RequestContext::new().is_expired_at(now));
+        assert!(RequestContext::new().check_deadline().is_ok());
+
+        let context = RequestContext::new().with_deadline(now + Duration::from_secs(10));
+        assert!(!context.is_expired_at(now));
+        assert!(context.is_expired_at(now + Duration::from_secs(10)));
+        assert!(context.check_deadline().is_ok());
+
+        let context = RequestContext::new().with_deadline(now);
+        assert_eq!(
+            context.check_deadline().unwrap_err().code(),
+            Code::DeadlineExceeded
+        );
+    }
+
+    #[test]
+    fn metadata_is_kept_by_key() {
+        let peer_addr = "127.0.0.1:3000".parse().unwrap();
+        let mut context = RequestContext::new().with_peer_addr(peer_addr);
+        assert_eq!(context.insert_metadata("trace-id", "42"), None);
+        assert_eq!(context.insert_metadata("agent", "node"), None);
+        assert_eq!(
+            context.insert_metadata("trace-id", "43"),
+            Some("42".to_owned())
+        );
+        assert_eq!(context.metadata("trace-id"), Some("43"));
+        assert_eq!(context.metadata("missing"), None);
+        assert_eq!(
+            context.metadata_iter().collect::<Vec<_>>(),
+            vec![("agent", "node"), ("trace-id", "43")]
+        );
+        assert_eq!(context.peer_addr(), Some(peer_addr));
+        assert_eq!(context.deadline(), None);
+    }
+}
@@ -1,43 +1,219 @@
-use std::{error, fmt};
+use std::{convert::TryFrom, error, fmt, io};
 
 /// Common error codes for network protocol requests.
 ///
 /// These codes mimic the status codes used in gRPC and map one to one to
-/// those in the gRPC protocol implementation.
+/// those in the gRPC protocol implementation, including the numeric values
+/// obtained with `u32::from` and `Code::try_from`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Code {
     Canceled,
     Unknown,
     InvalidArgument,
+    DeadlineExceeded,
     NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    ResourceExhausted,
     FailedPrecondition,
     Aborted,
+    OutOfRange,
     Unimplemented,
     Internal,
     Unavailable,
+    DataLoss,
+    Unauthenticated,
+}
+
+impl From<Code> for u32 {
+    fn from(code: Code) -> u32 {
+        match code {
+            Code::Canceled => 1,
+            Code::Unknown => 2,
+            Code::InvalidArgument => 3,
+            Code::DeadlineExceeded => 4,
+            Code::NotFound => 5,
+            Code::AlreadyExists => 6,
+            Code::PermissionDenied => 7,
+            Code::ResourceExhausted => 8,
+            Code::FailedPrecondition => 9,
+            Code::Aborted => 10,
+            Code::OutOfRange => 11,
+            Code::Unimplemented => 12,
+            Code::Internal => 13,
+            Code::Unavailable => 14,
+            Code::DataLoss => 15,
+            Code::Unauthenticated => 16,
+        }
+    }
+}
+
+impl TryFrom<u32> for Code {
+    type Error = UnknownCode;
+
+    fn try_from(value: u32) -> Result<Self, UnknownCode> {
+        let code = match value {
+            1 => Code::Canceled,
+            2 => Code::Unknown,
+            3 => Code::InvalidArgument,
+            4 => Code::DeadlineExceeded,
+            5 => Code::NotFound,
+            6 => Code::AlreadyExists,
+            7 => Code::PermissionDenied,
+            8 => Code::ResourceExhausted,
+            9 => Code::FailedPrecondition,
+            10 => Code::Aborted,
+            11 => Code::OutOfRange,
+            12 => Code::Unimplemented,
+            13 => Code::Internal,
+            14 => Code::Unavailable,
+            15 => Code::DataLoss,
+            16 => Code::Unauthenticated,
+            _ => return Err(UnknownCode(value)),
+        };
+        Ok(code)
+    }
+}
+
+impl Code {
+    /// Whether an operation failing with this code may succeed if it is
+    /// tried again as is, e.g. once the peer is reachable again. This is
+    /// the default for `Error::is_retriable`.
+    pub fn is_retriable(self) -> bool {
+        match self {
+            Code::Unavailable
+            | Code::Aborted
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted => true,
+            _ => false,
+        }
+    }
+}
+
+/// The error returned when converting a number that is not the value
+/// of any gRPC error status code. Note that 0, the gRPC value for success,
+/// is not an error code.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnknownCode(pub u32);
+
+impl error::Error for UnknownCode {}
+
+impl fmt::Display for UnknownCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown error code {}", self.0)
+    }
 }
 
 /// Represents errors that can be returned by the node protocol implementation.
+///
+/// An error converts into an `io::Error` and back with `try_from_io`,
+/// keeping its code and whether it is retriable.
 #[derive(Debug)]
 pub struct Error {
     code: Code,
+    retriable: bool,
     source: Box<dyn error::Error + Send + Sync>,
 }
 
 impl Error {
+    /// An error which is retriable if the code is, see
+    /// `Code::is_retriable`.
     pub fn new<E>(code: Code, source: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Error::with_retriable(code, source, code.is_retriable())
+    }
+
+    /// Same as `new`, but whether the failed operation can be retried is
+    /// given instead of following from the code.
+    pub fn with_retriable<E>(code: Code, source: E, retriable: bool) -> Self
     where
         E: Into<Box<dyn error::Error + Send + Sync>>,
     {
         Error {
             code,
+            retriable,
             source: source.into(),
         }
     }
 
+    /// The error of a service giving up on a request whose deadline has
+    /// passed, see `RequestContext::check_deadline`.
+    pub fn deadline_exceeded() -> Self {
+        Error::new(Code::DeadlineExceeded, "the request deadline has passed")
+    }
+
     pub fn code(&self) -> Code {
         self.code
     }
+
+    /// Whether the failed operation may succeed if it is tried again.
+    pub fn is_retriable(&self) -> bool {
+        self.retriable
+    }
+
+    /// The error converted into `io_error` with `From`, or `io_error`
+    /// itself if it was not made from an `Error`.
+    ///
+    /// For the other I/O errors, `code_from_io_kind` gives the code that
+    /// best describes them.
+    pub fn try_from_io(io_error: io::Error) -> Result<Self, io::Error> {
+        let is_error = match io_error.get_ref() {
+            Some(inner) => inner.is::<Error>(),
+            None => false,
+        };
+        if !is_error {
+            return Err(io_error);
+        }
+        let inner = io_error.into_inner().unwrap();
+        Ok(*inner.downcast::<Error>().unwrap())
+    }
+
+    /// The I/O error kind that best describes an error of the given code,
+    /// `code_from_io_kind` mapping it back to the code when there is such
+    /// a kind.
+    pub fn io_kind_from_code(code: Code) -> io::ErrorKind {
+        use io::ErrorKind;
+
+        match code {
+            Code::NotFound => ErrorKind::NotFound,
+            Code::PermissionDenied => ErrorKind::PermissionDenied,
+            Code::AlreadyExists => ErrorKind::AlreadyExists,
+            Code::Unavailable => ErrorKind::NotConnected,
+            Code::InvalidArgument => ErrorKind::InvalidInput,
+            Code::DeadlineExceeded => ErrorKind::TimedOut,
+            Code::Canceled => ErrorKind::Interrupted,
+            Code::OutOfRange => ErrorKind::UnexpectedEof,
+            Code::DataLoss => ErrorKind::WriteZero,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// The error code that best describes an I/O error of the given kind.
+    pub fn code_from_io_kind(kind: io::ErrorKind) -> Code {
+        use io::ErrorKind::*;
+
+        match kind {
+            NotFound => Code::NotFound,
+            PermissionDenied => Code::PermissionDenied,
+            AlreadyExists => Code::AlreadyExists,
+            ConnectionRefused | ConnectionReset | ConnectionAborted | NotConnected | AddrInUse
+            | AddrNotAvailable | BrokenPipe | WouldBlock => Code::Unavailable,
+            InvalidInput | InvalidData => Code::InvalidArgument,
+            TimedOut => Code::DeadlineExceeded,
+            Interrupted => Code::Canceled,
+            UnexpectedEof => Code::OutOfRange,
+            WriteZero => Code::DataLoss,
+            _ => Code::Unknown,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        io::Error::new(Error::io_kind_from_code(error.code), error)
+    }
 }
 
 impl error::Error for Error {
@@ -52,13 +228,133 @@ impl fmt::Display for Error {
             Code::Canceled => "processing canceled",
             Code::Unknown => "unknown error",
             Code::InvalidArgument => "invalid request data",
+            Code::DeadlineExceeded => "the deadline expired before the operation completed",
             Code::NotFound => "not found",
+            Code::AlreadyExists => "already exists",
+            Code::PermissionDenied => "permission denied",
+            Code::ResourceExhausted => "resource exhausted",
             Code::FailedPrecondition => "system state does not permit the operation",
             Code::Aborted => "the operation was aborted",
+            Code::OutOfRange => "the operation was attempted past the valid range",
             Code::Unimplemented => "not implemented",
             Code::Internal => "internal processing error",
             Code::Unavailable => "the service is unavailable",
+            Code::DataLoss => "unrecoverable data loss or corruption",
+            Code::Unauthenticated => "the request is not authenticated",
         };
         f.write_str(msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_CODES: [Code; 16] = [
+        Code::Canceled,
+        Code::Unknown,
+        Code::InvalidArgument,
+        Code::DeadlineExceeded,
+        Code::NotFound,
+        Code::AlreadyExists,
+        Code::PermissionDenied,
+        Code::ResourceExhausted,
+        Code::FailedPrecondition,
+        Code::Aborted,
+        Code::OutOfRange,
+        Code::Unimplemented,
+        Code::Internal,
+        Code::Unavailable,
+        Code::DataLoss,
+        Code::Unauthenticated,
+    ];
+
+    #[test]
+    fn codes_round_trip_through_grpc_numbers() {
+        for (i, code) in ALL_CODES.iter().enumerate() {
+            let value = u32::from(*code);
+            assert_eq!(value, i as u32 + 1);
+            assert_eq!(Code::try_from(value), Ok(*code));
+        }
+    }
+
+    #[test]
+    fn numbers_outside_grpc_errors_are_rejected() {
+        assert_eq!(Code::try_from(0), Err(UnknownCode(0)));
+        assert_eq!(Code::try_from(17), Err(UnknownCode(17)));
+        assert_eq!(
+            Code::try_from(u32::max_value()),
+            Err(UnknownCode(u32::max_value()))
+        );
+    }
+
+    #[test]
+    fn io_kinds_map_to_codes() {
+        assert_eq!(
+            Error::code_from_io_kind(io::ErrorKind::NotFound),
+            Code::NotFound
+        );
+        assert_eq!(
+            Error::code_from_io_kind(io::ErrorKind::TimedOut),
+            Code::DeadlineExceeded
+        );
+        assert_eq!(
+            Error::code_from_io_kind(io::ErrorKind::ConnectionReset),
+            Code::Unavailable
+        );
+        assert_eq!(
+            Error::code_from_io_kind(io::ErrorKind::Other),
+            Code::Unknown
+        );
+    }
+
+    #[test]
+    fn codes_map_to_io_kinds_and_back() {
+        for code in ALL_CODES.iter() {
+            let kind = Error::io_kind_from_code(*code);
+            if kind != io::ErrorKind::Other {
+                assert_eq!(Error::code_from_io_kind(kind), *code);
+            }
+        }
+    }
+
+    #[test]
+    fn retriable_follows_the_code_by_default() {
+        assert!(Error::new(Code::Unavailable, "down").is_retriable());
+        assert!(Error::new(Code::Aborted, "conflict").is_retriable());
+        assert!(!Error::new(Code::InvalidArgument, "garbage").is_retriable());
+        assert!(!Error::new(Code::NotFound, "nothing here").is_retriable());
+        assert!(!Error::with_retriable(Code::Unavailable, "gone", false).is_retriable());
+        assert!(Error::with_retriable(Code::Internal, "glitch", true).is_retriable());
+    }
+
+    #[test]
+    fn errors_round_trip_through_io_errors() {
+        for code in ALL_CODES.iter() {
+            for retriable in &[false, true] {
+                let error = Error::with_retriable(*code, "some failure", *retriable);
+                let io_error = io::Error::from(error);
+                assert_eq!(io_error.kind(), Error::io_kind_from_code(*code));
+
+                let recovered = Error::try_from_io(io_error).unwrap();
+                assert_eq!(recovered.code(), *code);
+                assert_eq!(recovered.is_retriable(), *retriable);
+                assert_eq!(
+                    error::Error::source(&recovered).unwrap().to_string(),
+                    "some failure"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn other_io_errors_are_given_back() {
+        let io_error = io::Error::new(io::ErrorKind::TimedOut, "too slow");
+        let io_error = Error::try_from_io(io_error).unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(io_error.to_string(), "too slow");
+
+        let io_error = io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(Error::try_from_io(io_error).is_err());
+    }
+}
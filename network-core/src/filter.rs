@@ -0,0 +1,254 @@
+//! Compact filters over fragment identifiers.
+//!
+//! A node sends a filter of the fragments it has so that its peer only
+//! sends back the fragments that are missing, see
+//! `ContentService::exchange_filter`.
+
+use chain_core::{
+    packer::Codec,
+    property::{Deserialize, Serialize},
+};
+
+use std::io::{self, Read, Write};
+
+/// The largest filter accepted from the network, in bytes.
+pub const MAX_FILTER_BYTES: usize = 1 << 20;
+
+/// The largest number of hash functions of a filter.
+pub const MAX_FILTER_HASHES: u8 = 32;
+
+/// A bloom filter over serialized fragment identifiers.
+///
+/// A filter never reports an inserted identifier as absent, but it can
+/// report as present an identifier that was not inserted. The rate of
+/// these false positives depends on the size of the filter, its number
+/// of hash functions and the number of inserted identifiers, see
+/// `with_false_positive_rate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentFilter {
+    bits: Vec<u8>,
+    num_hashes: u8,
+}
+
+impl FragmentFilter {
+    /// Creates an empty filter of at least `num_bits` bits, rounded up to
+    /// whole bytes, using `num_hashes` hash functions.
+    ///
+    /// # Panics
+    ///
+    /// If the filter would be larger than `MAX_FILTER_BYTES`, or if
+    /// `num_hashes` is 0 or larger than `MAX_FILTER_HASHES`.
+    pub fn new(num_bits: usize, num_hashes: u8) -> Self {
+        let num_bytes = std::cmp::max((num_bits + 7) / 8, 1);
+        assert!(num_bytes <= MAX_FILTER_BYTES, "fragment filter too large");
+        assert!(
+            num_hashes > 0 && num_hashes <= MAX_FILTER_HASHES,
+            "invalid number of hashes for a fragment filter"
+        );
+        FragmentFilter {
+            bits: vec![0; num_bytes],
+            num_hashes,
+        }
+    }
+
+    /// Creates an empty filter sized for `expected_ids` identifiers to be
+    /// reported present by mistake with a probability of about
+    /// `false_positive_rate`.
+    ///
+    /// The filter is capped to `MAX_FILTER_BYTES`, with a higher rate of
+    /// false positives for very large numbers of identifiers.
+    pub fn with_false_positive_rate(expected_ids: usize, false_positive_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let expected_ids = std::cmp::max(expected_ids, 1) as f64;
+        let rate = false_positive_rate.max(std::f64::MIN_POSITIVE).min(1.0);
+        let num_bits = (-expected_ids * rate.ln() / (ln2 * ln2)).ceil();
+        let num_bits = num_bits.max(8.0).min((MAX_FILTER_BYTES * 8) as f64);
+        let num_hashes = (num_bits / expected_ids * ln2).round();
+        let num_hashes = num_hashes.max(1.0).min(f64::from(MAX_FILTER_HASHES));
+        FragmentFilter::new(num_bits as usize, num_hashes as u8)
+    }
+
+    /// The number of bits of the filter.
+    pub fn num_bits(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// The number of hash functions of the filter.
+    pub fn num_hashes(&self) -> u8 {
+        self.num_hashes
+    }
+
+    /// Adds the serialized identifier `bytes` to the filter.
+    pub fn insert_bytes(&mut self, bytes: &[u8]) {
+        for bit in self.bit_indices(bytes) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Checks whether the serialized identifier `bytes` is (probably) in
+    /// the filter.
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        self.bit_indices(bytes)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Adds the identifier `id` to the filter.
+    pub fn insert<Id: Serialize>(&mut self, id: &Id) -> Result<(), Id::Error> {
+        let bytes = id.serialize_as_vec()?;
+        self.insert_bytes(&bytes);
+        Ok(())
+    }
+
+    /// Checks whether the identifier `id` is (probably) in the filter.
+    pub fn contains<Id: Serialize>(&self, id: &Id) -> Result<bool, Id::Error> {
+        let bytes = id.serialize_as_vec()?;
+        Ok(self.contains_bytes(&bytes))
+    }
+
+    // The bits are derived from two hashes of the bytes, the i-th one
+    // being h1 + i * h2, so the bytes are only hashed once per lookup.
+    // The hash is fixed here rather than taken from the standard library,
+    // as peers built with different compilers must agree on it.
+    fn bit_indices(&self, bytes: &[u8]) -> impl Iterator<Item = usize> {
+        let h1 = mix(fnv1a(bytes));
+        let h2 = mix(h1 ^ 0x9e37_79b9_7f4a_7c15) | 1;
+        let num_bits = self.num_bits() as u64;
+        (0..u64::from(self.num_hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// the finalizer of MurmurHash3, spreading every input bit over the output
+fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+impl Serialize for FragmentFilter {
+    type Error = io::Error;
+
+    fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+        let mut codec = Codec::new(writer);
+        codec.put_u8(self.num_hashes)?;
+        codec.put_u32(self.bits.len() as u32)?;
+        codec.write_all(&self.bits)
+    }
+}
+
+impl Deserialize for FragmentFilter {
+    type Error = io::Error;
+
+    fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+        let mut codec = Codec::new(reader);
+        let num_hashes = codec.get_u8()?;
+        if num_hashes == 0 || num_hashes > MAX_FILTER_HASHES {
+            return Err(invalid_data(
+                "invalid number of hashes for a fragment filter",
+            ));
+        }
+        let len = codec.get_u32()? as usize;
+        if len == 0 || len > MAX_FILTER_BYTES {
+            return Err(invalid_data("invalid fragment filter size"));
+        }
+        let mut bits = vec![0; len];
+        codec.read_exact(&mut bits)?;
+        Ok(FragmentFilter { bits, num_hashes })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    // xorshift64*, enough to get ids spread over the whole range
+    fn random_ids(seed: u64, count: usize) -> Vec<u64> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state ^= state >> 12;
+                state ^= state << 25;
+                state ^= state >> 27;
+                state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+            })
+            .collect()
+    }
+
+    fn check_filter(seed: u64, num_ids: usize, rate: f64) {
+        let ids = random_ids(seed, num_ids);
+        let mut filter = FragmentFilter::with_false_positive_rate(num_ids, rate);
+        for id in &ids {
+            filter.insert_bytes(&id.to_be_bytes());
+        }
+
+        // no false negatives
+        for id in &ids {
+            assert!(filter.contains_bytes(&id.to_be_bytes()));
+        }
+
+        let inserted: HashSet<_> = ids.into_iter().collect();
+        let others: Vec<_> = random_ids(!seed, 20_000)
+            .into_iter()
+            .filter(|id| !inserted.contains(id))
+            .collect();
+        let false_positives = others
+            .iter()
+            .filter(|id| filter.contains_bytes(&id.to_be_bytes()))
+            .count();
+        let measured = false_positives as f64 / others.len() as f64;
+        assert!(
+            measured <= rate * 1.5,
+            "false positive rate {} above {}",
+            measured,
+            rate
+        );
+    }
+
+    #[test]
+    fn inserted_ids_are_never_missed() {
+        check_filter(1, 1_000, 0.01);
+        check_filter(2, 5_000, 0.05);
+        check_filter(3, 200, 0.02);
+    }
+
+    #[test]
+    fn empty_filter_contains_nothing() {
+        let filter = FragmentFilter::new(64, 3);
+        assert_eq!(filter.num_bits(), 64);
+        assert!(random_ids(4, 100)
+            .iter()
+            .all(|id| !filter.contains_bytes(&id.to_be_bytes())));
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let mut filter = FragmentFilter::with_false_positive_rate(100, 0.01);
+        for id in random_ids(5, 100) {
+            filter.insert_bytes(&id.to_be_bytes());
+        }
+        let bytes = filter.serialize_as_vec().unwrap();
+        let decoded = FragmentFilter::deserialize(&bytes[..]).unwrap();
+        assert_eq!(decoded, filter);
+
+        // neither an empty filter nor too many hashes are accepted
+        assert!(FragmentFilter::deserialize(&[3, 0, 0, 0, 0][..]).is_err());
+        assert!(FragmentFilter::deserialize(&[0, 0, 0, 0, 1, 0][..]).is_err());
+        assert!(FragmentFilter::deserialize(&[33, 0, 0, 0, 1, 0][..]).is_err());
+    }
+}
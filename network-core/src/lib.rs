@@ -2,7 +2,10 @@
 
 #![warn(clippy::all)]
 
+pub mod codec;
+pub mod context;
 pub mod error;
+pub mod filter;
 
 pub mod client;
 pub mod server;
@@ -3,6 +3,8 @@
 pub mod block;
 pub mod content;
 pub mod gossip;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 use crate::gossip::NodeId;
 
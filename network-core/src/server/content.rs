@@ -1,15 +1,30 @@
 //! Blockchain content service abstraction.
 
 use super::P2pService;
-use crate::error::Error;
+use crate::context::RequestContext;
+use crate::error::{Code, Error};
+use crate::filter::FragmentFilter;
+use crate::subscription::SubscriptionHandle;
 
-use chain_core::property::{Fragment, FragmentId};
+use chain_core::{
+    packer::Codec,
+    property::{Deserialize, Fragment, FragmentId, Serialize},
+};
 
 use futures::prelude::*;
 
+use std::collections::{hash_map::Entry, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::{error, fmt};
+
 /// Interface for the blockchain node service implementation responsible for
 /// validating and accepting transactions and other block contents, known
 /// together as fragments.
+///
+/// The requests are sent by peers through their implementation of
+/// `client::transaction::TransactionService`, which has a method for
+/// each of the methods of this trait.
 pub trait ContentService: P2pService {
     /// The data type to represent fragments constituting a block.
     type Fragment: Fragment;
@@ -17,9 +32,18 @@ pub trait ContentService: P2pService {
     /// The fragment identifier type for the blockchain.
     type FragmentId: FragmentId;
 
+    /// The maximum number of identifiers in a `get_fragments` request.
+    ///
+    /// Protocol implementations reject larger requests with
+    /// `Code::InvalidArgument` without calling the service,
+    /// see `check_get_fragments_limit`.
+    const MAX_GET_FRAGMENTS: usize = 1024;
+
     /// The type of an asynchronous stream that provides fragments in
     /// response to `get_fragments`.
-    type GetFragmentsStream: Stream<Item = Self::Fragment, Error = Error> + Send + 'static;
+    type GetFragmentsStream: Stream<Item = GetFragmentsItem<Self::Fragment, Self::FragmentId>, Error = Error>
+        + Send
+        + 'static;
 
     /// The type of asynchronous futures returned by `get_fragments`.
     ///
@@ -29,27 +53,82 @@ pub trait ContentService: P2pService {
 
     /// The type of an asynchronous stream that provides fragments announced
     /// by the peer via the bidirectional subscription.
+    ///
+    /// A `subscription::Subscription` can be used to bound the number of
+    /// announcements buffered for a slow peer.
     type ContentSubscription: Stream<Item = Self::Fragment, Error = Error> + Send + 'static;
 
     /// The type of asynchronous futures returned by method `content_subscription`.
     ///
     /// The future resolves to a stream that will be used by the protocol
-    /// implementation to produce a server-streamed response.
-    type ContentSubscriptionFuture: Future<Item = Self::ContentSubscription, Error = Error>
+    /// implementation to produce a server-streamed response, and to
+    /// the handle of the subscription.
+    type ContentSubscriptionFuture: Future<Item = (Self::ContentSubscription, SubscriptionHandle), Error = Error>
+        + Send
+        + 'static;
+
+    /// The type of asynchronous futures returned by `propose_fragments`.
+    type ProposeFragmentsFuture: Future<Item = ProposeFragmentsResponse<Self::FragmentId>, Error = Error>
         + Send
         + 'static;
 
-    /// Get all transactions by their id.
+    /// The type of asynchronous futures returned by `exchange_filter`.
+    type ExchangeFilterFuture: Future<Item = Vec<Self::FragmentId>, Error = Error> + Send + 'static;
+
+    /// Get fragments by their id.
+    ///
+    /// The stream yields one item per requested identifier, in the order
+    /// of the request: the fragment if the node knows it, or
+    /// `GetFragmentsItem::Unknown` with the identifier otherwise.
+    /// Unknown identifiers do not fail the stream.
+    ///
+    /// The protocol implementation only calls this with at most
+    /// `MAX_GET_FRAGMENTS` identifiers.
     fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture;
 
+    /// Checks the fragment identifiers proposed by a peer.
+    ///
+    /// The response gives a status for each identifier of the batch, in
+    /// the order of the batch, so that `TransactionIdBatch::check_response`
+    /// accepts it. A problem with an individual identifier is reported
+    /// with `ProposeFragmentStatus::Invalid` for that identifier; the
+    /// future only fails on transport or internal errors.
+    ///
+    /// The batch is made with `TransactionIdBatch::new`, so a service is
+    /// never called without identifiers or with duplicates. A protocol
+    /// implementation carrying this request should reject the requests
+    /// that do not make a valid batch with the error converted from
+    /// `BatchError`; the gRPC protocol has no method for it yet.
+    fn propose_fragments(
+        &mut self,
+        ids: &TransactionIdBatch<Self::FragmentId>,
+    ) -> Self::ProposeFragmentsFuture;
+
+    /// Compares the fragments of the node with those of a peer, sent as
+    /// a filter of their identifiers.
+    ///
+    /// Resolves to the identifiers of the fragments the node has and
+    /// which are not in `remote`. The filter may report fragments the
+    /// peer does not have as present, so some missing fragments can be
+    /// left out of the response, but a fragment the peer has is never
+    /// in it.
+    fn exchange_filter(&mut self, remote: FragmentFilter) -> Self::ExchangeFilterFuture;
+
     /// Establishes a bidirectional subscription for announcing new fragments.
     ///
     /// The network protocol implementation passes the node identifier of
     /// the sender and an asynchronous stream that will provide the inbound
     /// announcements.
+    /// The stream of inbound announcements is not bounded: a service
+    /// that cannot keep up with the peer can forward it to a
+    /// `subscription::Subscription` with the `OverflowPolicy` of its
+    /// choice, see `SubscriptionSender::forward`.
     ///
     /// Returns a future resolving to an asynchronous stream
-    /// that will be used by this node to send fragment announcements.
+    /// that will be used by this node to send fragment announcements,
+    /// along with a handle that the protocol implementation closes when
+    /// the subscriber disconnects. The service can keep a clone of the
+    /// handle to clean up its state for the subscriber.
     fn content_subscription<In>(
         &mut self,
         subscriber: Self::NodeId,
@@ -57,4 +136,725 @@ pub trait ContentService: P2pService {
     ) -> Self::ContentSubscriptionFuture
     where
         In: Stream<Item = Self::Fragment, Error = Error> + Send + 'static;
+
+    /// Same as `get_fragments`, with the context of the request.
+    ///
+    /// The protocol implementation calls the `_ctx` methods. By default
+    /// they ignore the context and call the method without it, a service
+    /// that makes use of the context overriding them instead. A service
+    /// honoring the deadline fails with `Error::deadline_exceeded` once
+    /// it has passed, see `RequestContext::check_deadline`.
+    fn get_fragments_ctx(
+        &mut self,
+        _ctx: &RequestContext,
+        ids: &[Self::FragmentId],
+    ) -> Self::GetFragmentsFuture {
+        self.get_fragments(ids)
+    }
+
+    /// Same as `propose_fragments`, with the context of the request.
+    fn propose_fragments_ctx(
+        &mut self,
+        _ctx: &RequestContext,
+        ids: &TransactionIdBatch<Self::FragmentId>,
+    ) -> Self::ProposeFragmentsFuture {
+        self.propose_fragments(ids)
+    }
+
+    /// Same as `exchange_filter`, with the context of the request.
+    fn exchange_filter_ctx(
+        &mut self,
+        _ctx: &RequestContext,
+        remote: FragmentFilter,
+    ) -> Self::ExchangeFilterFuture {
+        self.exchange_filter(remote)
+    }
+
+    /// Same as `content_subscription`, with the context of the request
+    /// establishing the subscription.
+    fn content_subscription_ctx<In>(
+        &mut self,
+        _ctx: &RequestContext,
+        subscriber: Self::NodeId,
+        inbound: In,
+    ) -> Self::ContentSubscriptionFuture
+    where
+        In: Stream<Item = Self::Fragment, Error = Error> + Send + 'static,
+    {
+        self.content_subscription(subscriber, inbound)
+    }
+}
+
+/// An item of the response to `get_fragments`.
+#[derive(Debug)]
+pub enum GetFragmentsItem<F, Id> {
+    /// The requested fragment.
+    Found(F),
+    /// The node does not know the fragment with this identifier.
+    Unknown(Id),
+}
+
+impl<F, Id> GetFragmentsItem<F, Id> {
+    /// The fragment, if it was found.
+    pub fn found(self) -> Option<F> {
+        match self {
+            GetFragmentsItem::Found(fragment) => Some(fragment),
+            GetFragmentsItem::Unknown(_) => None,
+        }
+    }
+}
+
+/// Checks that a `get_fragments` request for `ids` is within the limit
+/// of the service `S`.
+pub fn check_get_fragments_limit<S: ContentService>(ids: &[S::FragmentId]) -> Result<(), Error> {
+    if ids.len() > S::MAX_GET_FRAGMENTS {
+        Err(Error::new(
+            Code::InvalidArgument,
+            format!(
+                "{} fragments requested, at most {} are allowed",
+                ids.len(),
+                S::MAX_GET_FRAGMENTS
+            ),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// What `TransactionIdBatch::with_duplicates` does with an identifier
+/// given more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicates {
+    /// Keep the first occurrence, recording the positions of the others.
+    Merge,
+    /// Fail with `BatchError::Duplicate`.
+    Reject,
+}
+
+/// The ways the identifiers of a `propose_fragments` request can fail
+/// to make a `TransactionIdBatch`.
+///
+/// These are errors of the peer, converted to `Code::InvalidArgument`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchError {
+    /// No identifier was proposed.
+    Empty,
+    /// The identifier at this position of the request was given before.
+    Duplicate { position: usize },
+}
+
+impl error::Error for BatchError {}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchError::Empty => f.write_str("no fragment identifier proposed"),
+            BatchError::Duplicate { position } => write!(
+                f,
+                "the fragment identifier at position {} is proposed twice",
+                position
+            ),
+        }
+    }
+}
+
+impl From<BatchError> for Error {
+    fn from(error: BatchError) -> Self {
+        Error::new(Code::InvalidArgument, error)
+    }
+}
+
+/// The identifiers proposed in a `propose_fragments` request.
+///
+/// A batch is never empty and holds each identifier once, in the order
+/// of their first occurrence in the request. The positions of the
+/// identifiers in the request are kept, so that a response to the batch
+/// can be related to the request as it was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionIdBatch<Id> {
+    ids: Vec<Id>,
+    /// the index in `ids` of each identifier of the request
+    positions: Vec<usize>,
+}
+
+impl<Id: FragmentId> TransactionIdBatch<Id> {
+    /// Makes the batch of `ids`, merging the duplicates.
+    pub fn new(ids: Vec<Id>) -> Result<Self, BatchError> {
+        Self::with_duplicates(ids, Duplicates::Merge)
+    }
+
+    /// Makes the batch of `ids`, handling the duplicates as `duplicates`
+    /// says.
+    pub fn with_duplicates(ids: Vec<Id>, duplicates: Duplicates) -> Result<Self, BatchError> {
+        if ids.is_empty() {
+            return Err(BatchError::Empty);
+        }
+        let mut indices = HashMap::with_capacity(ids.len());
+        let mut unique = Vec::with_capacity(ids.len());
+        let mut positions = Vec::with_capacity(ids.len());
+        for (position, id) in ids.into_iter().enumerate() {
+            let index = match indices.entry(id) {
+                Entry::Occupied(entry) => {
+                    if duplicates == Duplicates::Reject {
+                        return Err(BatchError::Duplicate { position });
+                    }
+                    *entry.get()
+                }
+                Entry::Vacant(entry) => {
+                    let index = unique.len();
+                    unique.push(entry.key().clone());
+                    entry.insert(index);
+                    index
+                }
+            };
+            positions.push(index);
+        }
+        Ok(TransactionIdBatch {
+            ids: unique,
+            positions,
+        })
+    }
+
+    /// The distinct identifiers, in the order of their first occurrence
+    /// in the request.
+    pub fn ids(&self) -> &[Id] {
+        &self.ids
+    }
+
+    /// For each identifier of the request, in order, its index in `ids`.
+    pub fn positions(&self) -> &[usize] {
+        &self.positions
+    }
+
+    /// The identifiers of the request, duplicates included.
+    pub fn request_ids(&self) -> impl Iterator<Item = &Id> {
+        self.positions.iter().map(move |index| &self.ids[*index])
+    }
+
+    /// Checks that `response` has an item for each identifier of the
+    /// batch, in the order of the batch, as services must respond.
+    pub fn check_response(&self, response: &ProposeFragmentsResponse<Id>) -> Result<(), Error> {
+        let items = response.items();
+        let matching = items.len() == self.ids.len()
+            && items
+                .iter()
+                .zip(&self.ids)
+                .all(|((id, _), expected)| id == expected);
+        if matching {
+            Ok(())
+        } else {
+            Err(Error::new(
+                Code::InvalidArgument,
+                "the response does not follow the proposed fragment identifiers",
+            ))
+        }
+    }
+}
+
+/// Outcome of the proposal of a single fragment identifier.
+#[derive(Debug)]
+pub enum ProposeFragmentStatus {
+    /// The fragment is known to the node.
+    Accepted,
+    /// The fragment is not known to the node, which wants to receive it.
+    Unknown,
+    /// The identifier has been rejected by the node. Serialized, the
+    /// message of the error is cut to its first 65535 bytes.
+    Invalid(Error),
+}
+
+const STATUS_ACCEPTED: u8 = 0;
+const STATUS_UNKNOWN: u8 = 1;
+const STATUS_INVALID: u8 = 2;
+
+/// Response to `propose_fragments`, with the status of every proposed
+/// identifier in the order of the request.
+#[derive(Debug)]
+pub struct ProposeFragmentsResponse<Id> {
+    items: Vec<(Id, ProposeFragmentStatus)>,
+}
+
+impl<Id> ProposeFragmentsResponse<Id> {
+    pub fn new() -> Self {
+        ProposeFragmentsResponse { items: Vec::new() }
+    }
+
+    /// Records the status of the next proposed identifier.
+    pub fn push(&mut self, id: Id, status: ProposeFragmentStatus) {
+        self.items.push((id, status))
+    }
+
+    /// All the identifiers with their status.
+    pub fn items(&self) -> &[(Id, ProposeFragmentStatus)] {
+        &self.items
+    }
+
+    /// The status of `id`, if it is in the response.
+    pub fn get(&self, id: &Id) -> Option<&ProposeFragmentStatus>
+    where
+        Id: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|(item_id, _)| item_id == id)
+            .map(|(_, status)| status)
+    }
+
+    /// The identifiers of the fragments known to the node.
+    pub fn accepted_ids(&self) -> impl Iterator<Item = &Id> {
+        self.items.iter().filter_map(|(id, status)| match status {
+            ProposeFragmentStatus::Accepted => Some(id),
+            _ => None,
+        })
+    }
+
+    /// The identifiers of the fragments the node wants to receive.
+    pub fn unknown_ids(&self) -> impl Iterator<Item = &Id> {
+        self.items.iter().filter_map(|(id, status)| match status {
+            ProposeFragmentStatus::Unknown => Some(id),
+            _ => None,
+        })
+    }
+
+    /// The rejected identifiers, with the reason of the rejection.
+    pub fn invalid_ids(&self) -> impl Iterator<Item = (&Id, &Error)> {
+        self.items.iter().filter_map(|(id, status)| match status {
+            ProposeFragmentStatus::Invalid(error) => Some((id, error)),
+            _ => None,
+        })
+    }
+}
+
+impl<Id> Default for ProposeFragmentsResponse<Id> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Id> std::iter::FromIterator<(Id, ProposeFragmentStatus)> for ProposeFragmentsResponse<Id> {
+    fn from_iter<I: IntoIterator<Item = (Id, ProposeFragmentStatus)>>(iter: I) -> Self {
+        ProposeFragmentsResponse {
+            items: iter.into_iter().collect(),
+        }
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// the longest prefix of `message` whose length fits in the u16 it is
+// serialized with, cut on a character boundary
+fn truncate_message(message: &str) -> &str {
+    let mut end = std::cmp::min(message.len(), u16::max_value() as usize);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    &message[..end]
+}
+
+impl<Id: Serialize> Serialize for ProposeFragmentsResponse<Id>
+where
+    Id::Error: Send + Sync + 'static,
+{
+    type Error = io::Error;
+
+    fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+        let mut codec = Codec::new(writer);
+        codec.put_u32(self.items.len() as u32)?;
+        for (id, status) in &self.items {
+            id.serialize(&mut codec).map_err(invalid_data)?;
+            match status {
+                ProposeFragmentStatus::Accepted => codec.put_u8(STATUS_ACCEPTED)?,
+                ProposeFragmentStatus::Unknown => codec.put_u8(STATUS_UNKNOWN)?,
+                ProposeFragmentStatus::Invalid(error) => {
+                    let message = match std::error::Error::source(error) {
+                        Some(source) => source.to_string(),
+                        None => error.to_string(),
+                    };
+                    codec.put_u8(STATUS_INVALID)?;
+                    // the values of `Code` are the gRPC status codes
+                    codec.put_u8(u32::from(error.code()) as u8)?;
+                    let message = truncate_message(&message);
+                    codec.put_u16(message.len() as u16)?;
+                    codec.write_all(message.as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<Id: Deserialize> Deserialize for ProposeFragmentsResponse<Id> {
+    type Error = io::Error;
+
+    fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+        let mut codec = Codec::new(reader);
+        let len = codec.get_u32()?;
+        let mut response = ProposeFragmentsResponse::new();
+        for _ in 0..len {
+            let id = Id::deserialize(&mut codec).map_err(invalid_data)?;
+            let status = match codec.get_u8()? {
+                STATUS_ACCEPTED => ProposeFragmentStatus::Accepted,
+                STATUS_UNKNOWN => ProposeFragmentStatus::Unknown,
+                STATUS_INVALID => {
+                    let code = Code::try_from(u32::from(codec.get_u8()?)).map_err(invalid_data)?;
+                    let len = codec.get_u16()? as usize;
+                    let mut message = vec![0; len];
+                    codec.read_exact(&mut message)?;
+                    let message = String::from_utf8(message).map_err(invalid_data)?;
+                    ProposeFragmentStatus::Invalid(Error::new(code, message))
+                }
+                _ => return Err(invalid_data("unknown fragment status")),
+            };
+            response.push(id, status);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gossip::NodeId;
+    use crate::server::P2pService;
+    use futures::{future, stream};
+    use std::collections::{HashMap, HashSet};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct MockId(u32);
+
+    impl Serialize for MockId {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            Codec::new(writer).put_u32(self.0)
+        }
+    }
+
+    impl Deserialize for MockId {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            Codec::new(reader).get_u32().map(MockId)
+        }
+    }
+
+    impl FragmentId for MockId {}
+
+    impl NodeId for MockId {}
+
+    struct MockFragment(MockId);
+
+    impl Serialize for MockFragment {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            self.0.serialize(writer)
+        }
+    }
+
+    impl Deserialize for MockFragment {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            MockId::deserialize(reader).map(MockFragment)
+        }
+    }
+
+    impl Fragment for MockFragment {
+        type Id = MockId;
+
+        fn id(&self) -> Self::Id {
+            self.0.clone()
+        }
+    }
+
+    /// Knows a fixed set of fragments and considers the zero identifier
+    /// to be malformed.
+    struct MockContentService {
+        known: HashSet<MockId>,
+        subscribers: HashMap<MockId, SubscriptionHandle>,
+    }
+
+    impl MockContentService {
+        fn new(known: Vec<MockId>) -> Self {
+            MockContentService {
+                known: known.into_iter().collect(),
+                subscribers: HashMap::new(),
+            }
+        }
+
+        /// Forgets the subscribers that have disconnected.
+        fn prune_subscribers(&mut self) {
+            self.subscribers.retain(|_, handle| !handle.is_closed())
+        }
+    }
+
+    impl P2pService for MockContentService {
+        type NodeId = MockId;
+
+        fn node_id(&self) -> Self::NodeId {
+            MockId(0)
+        }
+    }
+
+    impl ContentService for MockContentService {
+        type Fragment = MockFragment;
+        type FragmentId = MockId;
+        const MAX_GET_FRAGMENTS: usize = 4;
+        type GetFragmentsStream =
+            stream::IterOk<std::vec::IntoIter<GetFragmentsItem<MockFragment, MockId>>, Error>;
+        type GetFragmentsFuture = future::FutureResult<Self::GetFragmentsStream, Error>;
+        type ContentSubscription = stream::Empty<MockFragment, Error>;
+        type ContentSubscriptionFuture =
+            future::FutureResult<(Self::ContentSubscription, SubscriptionHandle), Error>;
+        type ProposeFragmentsFuture = future::FutureResult<ProposeFragmentsResponse<MockId>, Error>;
+        type ExchangeFilterFuture = future::FutureResult<Vec<MockId>, Error>;
+
+        fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
+            if let Err(error) = check_get_fragments_limit::<Self>(ids) {
+                return future::err(error);
+            }
+            let items: Vec<_> = ids
+                .iter()
+                .map(|id| {
+                    if self.known.contains(id) {
+                        GetFragmentsItem::Found(MockFragment(id.clone()))
+                    } else {
+                        GetFragmentsItem::Unknown(id.clone())
+                    }
+                })
+                .collect();
+            future::ok(stream::iter_ok(items))
+        }
+
+        fn exchange_filter(&mut self, remote: FragmentFilter) -> Self::ExchangeFilterFuture {
+            let mut missing: Vec<_> = self
+                .known
+                .iter()
+                .filter(|id| !remote.contains(*id).unwrap())
+                .cloned()
+                .collect();
+            missing.sort_by_key(|id| id.0);
+            future::ok(missing)
+        }
+
+        fn content_subscription<In>(
+            &mut self,
+            subscriber: Self::NodeId,
+            _inbound: In,
+        ) -> Self::ContentSubscriptionFuture
+        where
+            In: Stream<Item = Self::Fragment, Error = Error> + Send + 'static,
+        {
+            let handle = SubscriptionHandle::new();
+            self.subscribers.insert(subscriber, handle.clone());
+            future::ok((stream::empty(), handle))
+        }
+
+        fn propose_fragments(
+            &mut self,
+            ids: &TransactionIdBatch<Self::FragmentId>,
+        ) -> Self::ProposeFragmentsFuture {
+            let response = ids
+                .ids()
+                .iter()
+                .map(|id| {
+                    let status = if id.0 == 0 {
+                        ProposeFragmentStatus::Invalid(Error::new(
+                            Code::InvalidArgument,
+                            "malformed fragment id",
+                        ))
+                    } else if self.known.contains(id) {
+                        ProposeFragmentStatus::Accepted
+                    } else {
+                        ProposeFragmentStatus::Unknown
+                    };
+                    (id.clone(), status)
+                })
+                .collect();
+            future::ok(response)
+        }
+    }
+
+    fn check_response(response: &ProposeFragmentsResponse<MockId>) {
+        assert_eq!(response.items().len(), 5);
+        assert_eq!(
+            response.accepted_ids().collect::<Vec<_>>(),
+            vec![&MockId(1), &MockId(3)]
+        );
+        assert_eq!(
+            response.unknown_ids().collect::<Vec<_>>(),
+            vec![&MockId(2), &MockId(4)]
+        );
+        let invalid: Vec<_> = response.invalid_ids().collect();
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].0, &MockId(0));
+        assert_eq!(invalid[0].1.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn propose_fragments_reports_each_id() {
+        let mut service = MockContentService::new(vec![MockId(1), MockId(3)]);
+        let ids = vec![MockId(1), MockId(2), MockId(0), MockId(3), MockId(4)];
+        let batch = TransactionIdBatch::new(ids).unwrap();
+        let response = service.propose_fragments(&batch).wait().unwrap();
+        check_response(&response);
+        batch.check_response(&response).unwrap();
+        assert!(match response.get(&MockId(3)) {
+            Some(ProposeFragmentStatus::Accepted) => true,
+            _ => false,
+        });
+        assert!(response.get(&MockId(5)).is_none());
+
+        let bytes = response.serialize_as_vec().unwrap();
+        let decoded = ProposeFragmentsResponse::<MockId>::deserialize(&bytes[..]).unwrap();
+        check_response(&decoded);
+        let (_, error) = decoded.invalid_ids().next().unwrap();
+        assert_eq!(
+            std::error::Error::source(error).unwrap().to_string(),
+            "malformed fragment id"
+        );
+    }
+
+    #[test]
+    fn long_messages_are_truncated() {
+        let message = "é".repeat(40000);
+        let mut response = ProposeFragmentsResponse::new();
+        response.push(
+            MockId(0),
+            ProposeFragmentStatus::Invalid(Error::new(Code::InvalidArgument, message.clone())),
+        );
+
+        let bytes = response.serialize_as_vec().unwrap();
+        let decoded = ProposeFragmentsResponse::<MockId>::deserialize(&bytes[..]).unwrap();
+        let (_, error) = decoded.invalid_ids().next().unwrap();
+        let decoded_message = std::error::Error::source(error).unwrap().to_string();
+        assert_eq!(decoded_message.len(), u16::max_value() as usize - 1);
+        assert!(message.starts_with(&decoded_message));
+    }
+
+    #[test]
+    fn duplicate_ids_are_merged_or_rejected() {
+        let ids = vec![MockId(1), MockId(2), MockId(1), MockId(3), MockId(2)];
+        let batch = TransactionIdBatch::new(ids.clone()).unwrap();
+        assert_eq!(batch.ids(), &[MockId(1), MockId(2), MockId(3)][..]);
+        assert_eq!(batch.positions(), &[0, 1, 0, 2, 1][..]);
+        assert_eq!(batch.request_ids().cloned().collect::<Vec<_>>(), ids);
+
+        // the service responds once for each distinct identifier
+        let mut service = MockContentService::new(vec![MockId(2)]);
+        let response = service.propose_fragments(&batch).wait().unwrap();
+        assert_eq!(response.items().len(), 3);
+        batch.check_response(&response).unwrap();
+        let mut swapped = ProposeFragmentsResponse::new();
+        swapped.push(MockId(2), ProposeFragmentStatus::Accepted);
+        swapped.push(MockId(1), ProposeFragmentStatus::Unknown);
+        swapped.push(MockId(3), ProposeFragmentStatus::Unknown);
+        let error = batch.check_response(&swapped).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+
+        let error =
+            TransactionIdBatch::with_duplicates(ids.clone(), Duplicates::Reject).unwrap_err();
+        assert_eq!(error, BatchError::Duplicate { position: 2 });
+        let unique = TransactionIdBatch::with_duplicates(ids[..2].to_vec(), Duplicates::Reject);
+        assert_eq!(unique.unwrap().ids(), &ids[..2]);
+    }
+
+    #[test]
+    fn empty_batch_is_an_invalid_argument() {
+        for duplicates in &[Duplicates::Merge, Duplicates::Reject] {
+            let error =
+                TransactionIdBatch::<MockId>::with_duplicates(vec![], *duplicates).unwrap_err();
+            assert_eq!(error, BatchError::Empty);
+            assert_eq!(Error::from(error).code(), Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn subscriptions_are_torn_down_independently() {
+        let mut service = MockContentService::new(vec![]);
+        let (_, handle1) = service
+            .content_subscription(MockId(1), stream::empty())
+            .wait()
+            .unwrap();
+        let (_, handle2) = service
+            .content_subscription(MockId(2), stream::empty())
+            .wait()
+            .unwrap();
+        assert_eq!(service.subscribers.len(), 2);
+
+        let disconnected1 = handle1.disconnected();
+        handle1.close();
+        disconnected1.wait().unwrap();
+        service.prune_subscribers();
+        assert!(!service.subscribers.contains_key(&MockId(1)));
+        assert!(service.subscribers.contains_key(&MockId(2)));
+        assert!(!handle2.is_closed());
+
+        handle2.close();
+        handle2.close();
+        service.prune_subscribers();
+        assert!(service.subscribers.is_empty());
+        handle2.disconnected().wait().unwrap();
+    }
+
+    #[test]
+    fn get_fragments_reports_unknown_ids() {
+        let mut service = MockContentService::new(vec![MockId(1), MockId(3)]);
+        let ids = [MockId(1), MockId(2), MockId(3), MockId(4)];
+        let items = service
+            .get_fragments(&ids)
+            .wait()
+            .unwrap()
+            .collect()
+            .wait()
+            .unwrap();
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|item| match item {
+                GetFragmentsItem::Found(fragment) => Ok(fragment.id()),
+                GetFragmentsItem::Unknown(id) => Err(id),
+            })
+            .collect();
+        assert_eq!(
+            items,
+            vec![Ok(MockId(1)), Err(MockId(2)), Ok(MockId(3)), Err(MockId(4))]
+        );
+    }
+
+    #[test]
+    fn get_fragments_over_the_limit_is_rejected() {
+        let mut service = MockContentService::new(vec![MockId(1)]);
+        let ids: Vec<_> = (1..6).map(MockId).collect();
+        assert!(check_get_fragments_limit::<MockContentService>(&ids[..4]).is_ok());
+        let error = check_get_fragments_limit::<MockContentService>(&ids).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+
+        match service.get_fragments(&ids).wait() {
+            Err(error) => assert_eq!(error.code(), Code::InvalidArgument),
+            Ok(_) => panic!("request over the limit accepted"),
+        }
+    }
+
+    #[test]
+    fn exchange_filter_never_returns_what_the_peer_has() {
+        let mut service = MockContentService::new((1..=1000).map(MockId).collect());
+        let peer: Vec<_> = (1..=1000).filter(|i| i % 3 == 0).map(MockId).collect();
+        let mut filter = FragmentFilter::with_false_positive_rate(peer.len(), 0.01);
+        for id in &peer {
+            filter.insert(id).unwrap();
+        }
+
+        let missing = service.exchange_filter(filter).wait().unwrap();
+        assert!(missing.iter().all(|id| id.0 % 3 != 0));
+        // the filter hides at most a few of the missing fragments
+        let expected = (1..=1000).filter(|i| i % 3 != 0).count();
+        assert!(missing.len() <= expected);
+        assert!(missing.len() >= expected * 95 / 100);
+    }
 }
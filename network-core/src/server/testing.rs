@@ -0,0 +1,544 @@
+//! A mock of the content service, for testing code that consumes
+//! the service traits.
+//!
+//! This module is only available with the `testing` feature.
+
+use super::content::{
+    check_get_fragments_limit, ContentService, GetFragmentsItem, ProposeFragmentStatus,
+    ProposeFragmentsResponse, TransactionIdBatch,
+};
+use super::P2pService;
+use crate::context::RequestContext;
+use crate::error::{Code, Error};
+use crate::filter::FragmentFilter;
+use crate::gossip::NodeId;
+use crate::subscription::SubscriptionHandle;
+
+use chain_core::property::Fragment;
+
+use futures::prelude::*;
+use futures::{future, stream};
+
+use std::collections::{HashMap, VecDeque};
+
+/// A future that is already resolved, to use for the future types
+/// of a service implementation.
+pub type Ready<T> = future::FutureResult<T, Error>;
+
+/// A stream of items that are all available at once, to use for the
+/// stream types of a service implementation.
+pub type ReadyStream<T> = stream::IterOk<std::vec::IntoIter<T>, Error>;
+
+/// Makes a resolved future with the outcome of a call.
+pub fn ready<T>(result: Result<T, Error>) -> Ready<T> {
+    future::result(result)
+}
+
+/// Makes a stream of `items`.
+pub fn ready_stream<T>(items: Vec<T>) -> ReadyStream<T> {
+    stream::iter_ok(items)
+}
+
+/// A call received by `MockContentService`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedCall<Id, N> {
+    GetFragments(Vec<Id>),
+    ProposeFragments(Vec<Id>),
+    ExchangeFilter,
+    ContentSubscription(N),
+}
+
+type GetFragmentsHandler<F, Id> =
+    Box<dyn FnMut(&[Id]) -> Result<Vec<GetFragmentsItem<F, Id>>, Error> + Send>;
+
+type ProposeFragmentsHandler<Id> =
+    Box<dyn FnMut(&[Id]) -> Result<ProposeFragmentsResponse<Id>, Error> + Send>;
+
+/// A content service holding its fragments in memory.
+///
+/// By default, the service answers from its fragments: it serves those
+/// it has, accepts the proposal of those it has and asks for the others.
+/// The responses of `get_fragments` and `propose_fragments` can be
+/// programmed instead, and failures can be injected with `fail_next_with`.
+/// The `_ctx` methods honor the deadline of the context, failing with
+/// `Error::deadline_exceeded` once it has passed, and otherwise ignore
+/// the context. Every call is recorded, see `calls`.
+pub struct MockContentService<F: Fragment, N> {
+    node_id: N,
+    fragments: HashMap<F::Id, F>,
+    announcements: Vec<F>,
+    calls: Vec<RecordedCall<F::Id, N>>,
+    failures: VecDeque<Code>,
+    subscribers: HashMap<Vec<u8>, SubscriptionHandle>,
+    get_fragments_handler: Option<GetFragmentsHandler<F, F::Id>>,
+    propose_fragments_handler: Option<ProposeFragmentsHandler<F::Id>>,
+}
+
+impl<F, N> MockContentService<F, N>
+where
+    F: Fragment + Clone,
+    N: NodeId,
+{
+    /// Makes a service with the identifier `node_id` and no fragments.
+    pub fn new(node_id: N) -> Self {
+        MockContentService {
+            node_id,
+            fragments: HashMap::new(),
+            announcements: Vec::new(),
+            calls: Vec::new(),
+            failures: VecDeque::new(),
+            subscribers: HashMap::new(),
+            get_fragments_handler: None,
+            propose_fragments_handler: None,
+        }
+    }
+
+    /// Adds `fragment` to those the service has.
+    pub fn insert(&mut self, fragment: F) {
+        self.fragments.insert(fragment.id(), fragment);
+    }
+
+    /// Sends `fragment` on the outbound stream of every subscription
+    /// established from now on.
+    pub fn announce(&mut self, fragment: F) {
+        self.announcements.push(fragment);
+    }
+
+    /// Makes the next call fail with `code`, whatever the method. The
+    /// failures are used up in the order they were injected.
+    pub fn fail_next_with(&mut self, code: Code) {
+        self.failures.push_back(code);
+    }
+
+    /// Answers `get_fragments` with `handler` instead of the fragments
+    /// of the service.
+    pub fn on_get_fragments<H>(&mut self, handler: H)
+    where
+        H: FnMut(&[F::Id]) -> Result<Vec<GetFragmentsItem<F, F::Id>>, Error> + Send + 'static,
+    {
+        self.get_fragments_handler = Some(Box::new(handler));
+    }
+
+    /// Answers `propose_fragments` with `handler` instead of the
+    /// fragments of the service.
+    pub fn on_propose_fragments<H>(&mut self, handler: H)
+    where
+        H: FnMut(&[F::Id]) -> Result<ProposeFragmentsResponse<F::Id>, Error> + Send + 'static,
+    {
+        self.propose_fragments_handler = Some(Box::new(handler));
+    }
+
+    /// The calls received so far, in order.
+    pub fn calls(&self) -> Vec<RecordedCall<F::Id, N>> {
+        self.calls.clone()
+    }
+
+    /// The handles of the subscriptions that are still open.
+    pub fn open_subscriptions(&self) -> Vec<SubscriptionHandle> {
+        self.subscribers
+            .values()
+            .filter(|handle| !handle.is_closed())
+            .cloned()
+            .collect()
+    }
+
+    fn record(&mut self, call: RecordedCall<F::Id, N>) -> Result<(), Error> {
+        self.calls.push(call);
+        match self.failures.pop_front() {
+            Some(code) => Err(Error::new(code, "injected failure")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F, N> P2pService for MockContentService<F, N>
+where
+    F: Fragment,
+    N: NodeId + Send + 'static,
+{
+    type NodeId = N;
+
+    fn node_id(&self) -> Self::NodeId {
+        self.node_id.clone()
+    }
+}
+
+impl<F, N> ContentService for MockContentService<F, N>
+where
+    F: Fragment + Clone + Send + 'static,
+    F::Id: Send + 'static,
+    N: NodeId + Send + 'static,
+{
+    type Fragment = F;
+    type FragmentId = F::Id;
+    type GetFragmentsStream = ReadyStream<GetFragmentsItem<F, F::Id>>;
+    type GetFragmentsFuture = Ready<Self::GetFragmentsStream>;
+    type ContentSubscription = ReadyStream<F>;
+    type ContentSubscriptionFuture = Ready<(Self::ContentSubscription, SubscriptionHandle)>;
+    type ProposeFragmentsFuture = Ready<ProposeFragmentsResponse<F::Id>>;
+    type ExchangeFilterFuture = Ready<Vec<F::Id>>;
+
+    fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
+        if let Err(error) = self.record(RecordedCall::GetFragments(ids.to_vec())) {
+            return ready(Err(error));
+        }
+        if let Err(error) = check_get_fragments_limit::<Self>(ids) {
+            return ready(Err(error));
+        }
+        let fragments = &self.fragments;
+        let items = match &mut self.get_fragments_handler {
+            Some(handler) => handler(ids),
+            None => Ok(ids
+                .iter()
+                .map(|id| match fragments.get(id) {
+                    Some(fragment) => GetFragmentsItem::Found(fragment.clone()),
+                    None => GetFragmentsItem::Unknown(id.clone()),
+                })
+                .collect()),
+        };
+        ready(items.map(ready_stream))
+    }
+
+    fn propose_fragments(
+        &mut self,
+        ids: &TransactionIdBatch<Self::FragmentId>,
+    ) -> Self::ProposeFragmentsFuture {
+        let ids = ids.ids();
+        if let Err(error) = self.record(RecordedCall::ProposeFragments(ids.to_vec())) {
+            return ready(Err(error));
+        }
+        let fragments = &self.fragments;
+        match &mut self.propose_fragments_handler {
+            Some(handler) => ready(handler(ids)),
+            None => ready(Ok(ids
+                .iter()
+                .map(|id| {
+                    let status = if fragments.contains_key(id) {
+                        ProposeFragmentStatus::Accepted
+                    } else {
+                        ProposeFragmentStatus::Unknown
+                    };
+                    (id.clone(), status)
+                })
+                .collect())),
+        }
+    }
+
+    fn exchange_filter(&mut self, remote: FragmentFilter) -> Self::ExchangeFilterFuture {
+        if let Err(error) = self.record(RecordedCall::ExchangeFilter) {
+            return ready(Err(error));
+        }
+        let mut missing = Vec::new();
+        for id in self.fragments.keys() {
+            match remote.contains(id) {
+                Ok(true) => {}
+                Ok(false) => missing.push(id.clone()),
+                Err(_) => {
+                    return ready(Err(Error::new(
+                        Code::Internal,
+                        "cannot serialize a fragment id",
+                    )))
+                }
+            }
+        }
+        ready(Ok(missing))
+    }
+
+    fn content_subscription<In>(
+        &mut self,
+        subscriber: Self::NodeId,
+        _inbound: In,
+    ) -> Self::ContentSubscriptionFuture
+    where
+        In: Stream<Item = Self::Fragment, Error = Error> + Send + 'static,
+    {
+        if let Err(error) = self.record(RecordedCall::ContentSubscription(subscriber.clone())) {
+            return ready(Err(error));
+        }
+        let key = match subscriber.serialize_as_vec() {
+            Ok(key) => key,
+            Err(_) => {
+                return ready(Err(Error::new(
+                    Code::InvalidArgument,
+                    "cannot serialize the subscriber id",
+                )))
+            }
+        };
+        let handle = SubscriptionHandle::new();
+        self.subscribers.insert(key, handle.clone());
+        let outbound = ready_stream(self.announcements.clone());
+        ready(Ok((outbound, handle)))
+    }
+
+    fn get_fragments_ctx(
+        &mut self,
+        ctx: &RequestContext,
+        ids: &[Self::FragmentId],
+    ) -> Self::GetFragmentsFuture {
+        if let Err(error) = ctx.check_deadline() {
+            self.calls.push(RecordedCall::GetFragments(ids.to_vec()));
+            return ready(Err(error));
+        }
+        self.get_fragments(ids)
+    }
+
+    fn propose_fragments_ctx(
+        &mut self,
+        ctx: &RequestContext,
+        ids: &TransactionIdBatch<Self::FragmentId>,
+    ) -> Self::ProposeFragmentsFuture {
+        if let Err(error) = ctx.check_deadline() {
+            self.calls
+                .push(RecordedCall::ProposeFragments(ids.ids().to_vec()));
+            return ready(Err(error));
+        }
+        self.propose_fragments(ids)
+    }
+
+    fn exchange_filter_ctx(
+        &mut self,
+        ctx: &RequestContext,
+        remote: FragmentFilter,
+    ) -> Self::ExchangeFilterFuture {
+        if let Err(error) = ctx.check_deadline() {
+            self.calls.push(RecordedCall::ExchangeFilter);
+            return ready(Err(error));
+        }
+        self.exchange_filter(remote)
+    }
+
+    fn content_subscription_ctx<In>(
+        &mut self,
+        ctx: &RequestContext,
+        subscriber: Self::NodeId,
+        inbound: In,
+    ) -> Self::ContentSubscriptionFuture
+    where
+        In: Stream<Item = Self::Fragment, Error = Error> + Send + 'static,
+    {
+        if let Err(error) = ctx.check_deadline() {
+            self.calls
+                .push(RecordedCall::ContentSubscription(subscriber));
+            return ready(Err(error));
+        }
+        self.content_subscription(subscriber, inbound)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chain_core::packer::Codec;
+    use chain_core::property::{Deserialize, FragmentId, Serialize};
+    use std::io::{self, Write};
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct TestId(u32);
+
+    impl Serialize for TestId {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            Codec::new(writer).put_u32(self.0)
+        }
+    }
+
+    impl Deserialize for TestId {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            Codec::new(reader).get_u32().map(TestId)
+        }
+    }
+
+    impl FragmentId for TestId {}
+
+    impl NodeId for TestId {}
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TestFragment(TestId);
+
+    impl Serialize for TestFragment {
+        type Error = io::Error;
+
+        fn serialize<W: Write>(&self, writer: W) -> Result<(), Self::Error> {
+            self.0.serialize(writer)
+        }
+    }
+
+    impl Deserialize for TestFragment {
+        type Error = io::Error;
+
+        fn deserialize<R: io::BufRead>(reader: R) -> Result<Self, Self::Error> {
+            TestId::deserialize(reader).map(TestFragment)
+        }
+    }
+
+    impl Fragment for TestFragment {
+        type Id = TestId;
+
+        fn id(&self) -> Self::Id {
+            self.0.clone()
+        }
+    }
+
+    type Service = MockContentService<TestFragment, TestId>;
+
+    fn service_with(ids: &[u32]) -> Service {
+        let mut service = MockContentService::new(TestId(0));
+        for id in ids {
+            service.insert(TestFragment(TestId(*id)));
+        }
+        service
+    }
+
+    fn batch(ids: &[u32]) -> TransactionIdBatch<TestId> {
+        TransactionIdBatch::new(ids.iter().cloned().map(TestId).collect()).unwrap()
+    }
+
+    // a consumer of the service trait, as a protocol implementation
+    // would drive it
+    fn fetch<S: ContentService>(
+        service: &mut S,
+        ids: &[S::FragmentId],
+    ) -> Result<Vec<S::Fragment>, Error> {
+        let items = service.get_fragments(ids).wait()?.collect().wait()?;
+        Ok(items.into_iter().filter_map(|item| item.found()).collect())
+    }
+
+    #[test]
+    fn get_fragments_serves_the_stored_fragments() {
+        let mut service = service_with(&[1, 3]);
+        let fragments = fetch(&mut service, &[TestId(1), TestId(2), TestId(3)]).unwrap();
+        assert_eq!(
+            fragments,
+            vec![TestFragment(TestId(1)), TestFragment(TestId(3))]
+        );
+        assert_eq!(
+            service.calls(),
+            vec![RecordedCall::GetFragments(vec![
+                TestId(1),
+                TestId(2),
+                TestId(3)
+            ])]
+        );
+    }
+
+    #[test]
+    fn propose_fragments_can_be_programmed() {
+        let mut service = service_with(&[1]);
+        let response = service.propose_fragments(&batch(&[1, 2])).wait().unwrap();
+        assert_eq!(
+            response.accepted_ids().collect::<Vec<_>>(),
+            vec![&TestId(1)]
+        );
+        assert_eq!(response.unknown_ids().collect::<Vec<_>>(), vec![&TestId(2)]);
+
+        service.on_propose_fragments(|ids| {
+            Ok(ids
+                .iter()
+                .map(|id| {
+                    let error = Error::new(Code::InvalidArgument, "rejected");
+                    (id.clone(), ProposeFragmentStatus::Invalid(error))
+                })
+                .collect())
+        });
+        let response = service.propose_fragments(&batch(&[1])).wait().unwrap();
+        assert_eq!(response.invalid_ids().count(), 1);
+        assert_eq!(
+            service.calls(),
+            vec![
+                RecordedCall::ProposeFragments(vec![TestId(1), TestId(2)]),
+                RecordedCall::ProposeFragments(vec![TestId(1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn subscription_sends_the_announcements() {
+        let mut service = service_with(&[]);
+        service.announce(TestFragment(TestId(7)));
+        let (outbound, handle) = service
+            .content_subscription(TestId(5), stream::empty())
+            .wait()
+            .unwrap();
+        assert_eq!(
+            outbound.collect().wait().unwrap(),
+            vec![TestFragment(TestId(7))]
+        );
+        assert_eq!(service.open_subscriptions().len(), 1);
+        handle.close();
+        assert!(service.open_subscriptions().is_empty());
+        assert_eq!(
+            service.calls(),
+            vec![RecordedCall::ContentSubscription(TestId(5))]
+        );
+    }
+
+    #[test]
+    fn injected_failures_propagate_in_order() {
+        let mut service = service_with(&[1]);
+        service.fail_next_with(Code::Unavailable);
+        service.fail_next_with(Code::ResourceExhausted);
+
+        let error = fetch(&mut service, &[TestId(1)]).unwrap_err();
+        assert_eq!(error.code(), Code::Unavailable);
+        match service.propose_fragments(&batch(&[1])).wait() {
+            Err(error) => assert_eq!(error.code(), Code::ResourceExhausted),
+            Ok(_) => panic!("injected failure not reported"),
+        }
+        match service
+            .content_subscription(TestId(5), stream::empty())
+            .wait()
+        {
+            Ok((_, handle)) => assert!(!handle.is_closed()),
+            Err(error) => panic!("unexpected failure: {}", error),
+        }
+        assert_eq!(fetch(&mut service, &[TestId(1)]).unwrap().len(), 1);
+        // failed calls are recorded too
+        assert_eq!(service.calls().len(), 4);
+    }
+
+    #[test]
+    fn expired_deadlines_are_honored() {
+        let mut service = service_with(&[1]);
+        let expired = RequestContext::new().with_deadline(Instant::now());
+
+        match service.get_fragments_ctx(&expired, &[TestId(1)]).wait() {
+            Err(error) => assert_eq!(error.code(), Code::DeadlineExceeded),
+            Ok(_) => panic!("expired request served"),
+        }
+        match service.propose_fragments_ctx(&expired, &batch(&[1])).wait() {
+            Err(error) => assert_eq!(error.code(), Code::DeadlineExceeded),
+            Ok(_) => panic!("expired request served"),
+        }
+        match service
+            .content_subscription_ctx(&expired, TestId(5), stream::empty())
+            .wait()
+        {
+            Err(error) => assert_eq!(error.code(), Code::DeadlineExceeded),
+            Ok(_) => panic!("expired subscription established"),
+        }
+        assert!(service.open_subscriptions().is_empty());
+
+        let pending = RequestContext::new().with_deadline(Instant::now() + Duration::from_secs(60));
+        let response = service
+            .propose_fragments_ctx(&pending, &batch(&[1]))
+            .wait()
+            .unwrap();
+        assert_eq!(
+            response.accepted_ids().collect::<Vec<_>>(),
+            vec![&TestId(1)]
+        );
+        assert_eq!(
+            service.calls(),
+            vec![
+                RecordedCall::GetFragments(vec![TestId(1)]),
+                RecordedCall::ProposeFragments(vec![TestId(1)]),
+                RecordedCall::ContentSubscription(TestId(5)),
+                RecordedCall::ProposeFragments(vec![TestId(1)]),
+            ]
+        );
+    }
+}
@@ -1,6 +1,16 @@
+use crate::error::{Code, Error};
+
 use chain_core::property::{Block, HasHeader};
 
+use futures::future::Shared;
+use futures::prelude::*;
+use futures::sync::oneshot;
+use futures::task::{self, Task};
+use futures::try_ready;
+
+use std::collections::VecDeque;
 use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 pub enum BlockEvent<B>
 where
@@ -35,3 +45,405 @@ where
         }
     }
 }
+
+/// Handle to the lifetime of a subscription established by a peer.
+///
+/// A service implementation returns the handle along with its outbound
+/// stream, and keeps a clone to learn when the peer goes away, so that
+/// it can clean up the state it holds for that peer. The protocol
+/// implementation closes the handle once the peer has disconnected.
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+    closer: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    disconnected: Shared<oneshot::Receiver<()>>,
+}
+
+impl SubscriptionHandle {
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+        SubscriptionHandle {
+            closer: Arc::new(Mutex::new(Some(sender))),
+            disconnected: receiver.shared(),
+        }
+    }
+
+    /// Signals that the peer has disconnected.
+    ///
+    /// Closing an already closed subscription has no effect.
+    pub fn close(&self) {
+        if let Some(sender) = self.closer.lock().unwrap().take() {
+            // the receiving end is kept alive by this handle
+            let _ = sender.send(());
+        }
+    }
+
+    /// Returns true if the subscription has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.closer.lock().unwrap().is_none()
+    }
+
+    /// Returns a future that completes when the subscription is closed.
+    pub fn disconnected(&self) -> Disconnected {
+        Disconnected(self.disconnected.clone())
+    }
+}
+
+impl Default for SubscriptionHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for SubscriptionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SubscriptionHandle")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// Future returned by `SubscriptionHandle::disconnected`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Disconnected(Shared<oneshot::Receiver<()>>);
+
+impl Future for Disconnected {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        match self.0.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            // all the handles being dropped also means the peer is gone
+            Ok(Async::Ready(_)) | Err(_) => Ok(Async::Ready(())),
+        }
+    }
+}
+
+/// What a bounded subscription does with an item pushed while its
+/// buffer is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered item to make room for the new one.
+    DropOldest,
+    /// Fail the subscription: the stream yields an error with
+    /// `Code::ResourceExhausted`, then ends.
+    Fail,
+}
+
+struct Buffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: u64,
+    failed: bool,
+    failure_reported: bool,
+    sender_gone: bool,
+    receiver_gone: bool,
+    consumer: Option<Task>,
+}
+
+impl<T> Buffer<T> {
+    fn notify(&mut self) {
+        if let Some(task) = self.consumer.take() {
+            task.notify();
+        }
+    }
+}
+
+fn lock<T>(buffer: &Mutex<Buffer<T>>) -> MutexGuard<Buffer<T>> {
+    buffer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn overflow_error() -> Error {
+    Error::new(
+        Code::ResourceExhausted,
+        "the subscription buffer overflowed",
+    )
+}
+
+/// A stream of subscription items buffered up to a fixed capacity.
+///
+/// The producing side, typically the protocol implementation receiving
+/// the announcements of a peer, pushes the items with a
+/// `SubscriptionSender` without waiting for the consumer. When the
+/// consumer falls behind and the buffer is full, the `OverflowPolicy`
+/// of the subscription decides what happens to the pushed item, so that
+/// a peer flooding the node cannot make it buffer without bounds.
+///
+/// The stream has the item and error types expected of the subscription
+/// streams of the service traits, e.g.
+/// `server::content::ContentService::ContentSubscription`, so it can be
+/// used as is for them.
+pub struct Subscription<T> {
+    buffer: Arc<Mutex<Buffer<T>>>,
+}
+
+/// The producing side of a `Subscription`.
+pub struct SubscriptionSender<T> {
+    buffer: Arc<Mutex<Buffer<T>>>,
+}
+
+impl<T> Subscription<T> {
+    /// Creates a subscription buffering at most `capacity` items.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn bounded(capacity: usize, policy: OverflowPolicy) -> (SubscriptionSender<T>, Self) {
+        assert!(capacity > 0, "a subscription needs room for an item");
+        let buffer = Arc::new(Mutex::new(Buffer {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+            dropped: 0,
+            failed: false,
+            failure_reported: false,
+            sender_gone: false,
+            receiver_gone: false,
+            consumer: None,
+        }));
+        let sender = SubscriptionSender {
+            buffer: buffer.clone(),
+        };
+        (sender, Subscription { buffer })
+    }
+
+    /// The number of items dropped because the buffer was full.
+    pub fn dropped_count(&self) -> u64 {
+        lock(&self.buffer).dropped
+    }
+
+    /// The number of items waiting to be polled.
+    pub fn len(&self) -> usize {
+        lock(&self.buffer).items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        lock(&self.buffer).capacity
+    }
+}
+
+impl<T> SubscriptionSender<T> {
+    /// Pushes an item to the subscription, without waiting for the
+    /// consumer.
+    ///
+    /// When the buffer is full, the `DropOldest` policy drops the oldest
+    /// item and succeeds, while the `Fail` policy drops `item` and fails
+    /// the subscription. Fails with `Code::ResourceExhausted` once the
+    /// subscription has failed, and with `Code::Canceled` when the
+    /// subscription is dropped.
+    pub fn push(&self, item: T) -> Result<(), Error> {
+        let mut buffer = lock(&self.buffer);
+        if buffer.receiver_gone {
+            return Err(Error::new(Code::Canceled, "the subscription is dropped"));
+        }
+        if buffer.failed {
+            buffer.dropped += 1;
+            return Err(overflow_error());
+        }
+        if buffer.items.len() == buffer.capacity {
+            buffer.dropped += 1;
+            match buffer.policy {
+                OverflowPolicy::DropOldest => {
+                    buffer.items.pop_front();
+                }
+                OverflowPolicy::Fail => {
+                    buffer.failed = true;
+                    buffer.notify();
+                    return Err(overflow_error());
+                }
+            }
+        }
+        buffer.items.push_back(item);
+        buffer.notify();
+        Ok(())
+    }
+
+    /// Same as `Subscription::dropped_count`.
+    pub fn dropped_count(&self) -> u64 {
+        lock(&self.buffer).dropped
+    }
+
+    /// Returns a future pushing every item of `inbound` to the
+    /// subscription, e.g. to bound the inbound stream of a subscription
+    /// given to a service. The future fails with the error of the stream
+    /// or of `push`.
+    pub fn forward<S>(self, inbound: S) -> Forward<S>
+    where
+        S: Stream<Item = T, Error = Error>,
+    {
+        Forward {
+            inbound,
+            sender: Some(self),
+        }
+    }
+}
+
+/// Future returned by `SubscriptionSender::forward`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Forward<S: Stream> {
+    inbound: S,
+    sender: Option<SubscriptionSender<S::Item>>,
+}
+
+impl<S> Future for Forward<S>
+where
+    S: Stream<Error = Error>,
+{
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        loop {
+            match try_ready!(self.inbound.poll()) {
+                Some(item) => self
+                    .sender
+                    .as_ref()
+                    .expect("polled a finished future")
+                    .push(item)?,
+                None => {
+                    // ends the subscription
+                    self.sender = None;
+                    return Ok(Async::Ready(()));
+                }
+            }
+        }
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<T>, Error> {
+        let mut buffer = lock(&self.buffer);
+        if buffer.failed {
+            if buffer.failure_reported {
+                return Ok(Async::Ready(None));
+            }
+            buffer.failure_reported = true;
+            buffer.items.clear();
+            return Err(overflow_error());
+        }
+        if let Some(item) = buffer.items.pop_front() {
+            return Ok(Async::Ready(Some(item)));
+        }
+        if buffer.sender_gone {
+            return Ok(Async::Ready(None));
+        }
+        buffer.consumer = Some(task::current());
+        Ok(Async::NotReady)
+    }
+}
+
+/// The stream ends after the buffered items once the sender is dropped.
+impl<T> Drop for SubscriptionSender<T> {
+    fn drop(&mut self) {
+        let mut buffer = lock(&self.buffer);
+        buffer.sender_gone = true;
+        buffer.notify();
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        let mut buffer = lock(&self.buffer);
+        buffer.receiver_gone = true;
+        buffer.items.clear();
+    }
+}
+
+impl<T> Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let buffer = lock(&self.buffer);
+        f.debug_struct("Subscription")
+            .field("len", &buffer.items.len())
+            .field("capacity", &buffer.capacity)
+            .field("policy", &buffer.policy)
+            .field("dropped", &buffer.dropped)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn slow_consumer_drops_the_oldest_items() {
+        let (sender, mut subscription) = Subscription::bounded(2, OverflowPolicy::DropOldest);
+        sender.push(1).unwrap();
+        sender.push(2).unwrap();
+        assert_eq!((&mut subscription).wait().next().unwrap().unwrap(), 1);
+        // 2 is dropped to make room for 4
+        sender.push(3).unwrap();
+        sender.push(4).unwrap();
+        assert_eq!(subscription.dropped_count(), 1);
+        assert_eq!(subscription.len(), 2);
+
+        // a fast producer on another thread
+        let producer = thread::spawn(move || {
+            for item in 5..105 {
+                sender.push(item).unwrap();
+            }
+            sender.dropped_count()
+        });
+        assert_eq!(producer.join().unwrap(), 101);
+        let rest: Vec<u32> = (&mut subscription).wait().map(Result::unwrap).collect();
+        assert_eq!(rest, vec![103, 104]);
+        assert_eq!(subscription.dropped_count(), 101);
+    }
+
+    #[test]
+    fn slow_consumer_fails_the_subscription() {
+        let (sender, mut subscription) = Subscription::bounded(2, OverflowPolicy::Fail);
+        sender.push(1).unwrap();
+        sender.push(2).unwrap();
+        assert_eq!(sender.push(3).unwrap_err().code(), Code::ResourceExhausted);
+        assert_eq!(sender.push(4).unwrap_err().code(), Code::ResourceExhausted);
+        assert_eq!(subscription.dropped_count(), 2);
+
+        let mut items = (&mut subscription).wait();
+        assert_eq!(
+            items.next().unwrap().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+        assert!(items.next().is_none());
+    }
+
+    #[test]
+    fn consumer_is_woken_by_the_producer() {
+        let (sender, subscription) = Subscription::bounded(4, OverflowPolicy::Fail);
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.push("late").unwrap();
+        });
+        let mut items = subscription.wait();
+        assert_eq!(items.next().unwrap().unwrap(), "late");
+        producer.join().unwrap();
+        assert!(items.next().is_none());
+    }
+
+    #[test]
+    fn forwarded_stream_is_bounded() {
+        let (sender, subscription) = Subscription::bounded(3, OverflowPolicy::DropOldest);
+        sender
+            .forward(futures::stream::iter_ok(0..10))
+            .wait()
+            .unwrap();
+        assert_eq!(subscription.dropped_count(), 7);
+        assert_eq!(subscription.collect().wait().unwrap(), vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn dropping_the_subscription_cancels_the_sender() {
+        let (sender, subscription) = Subscription::bounded(4, OverflowPolicy::DropOldest);
+        sender.push(1).unwrap();
+        drop(subscription);
+        assert_eq!(sender.push(2).unwrap_err().code(), Code::Canceled);
+    }
+}
@@ -1,21 +1,24 @@
 use crate::gen;
 
-use chain_core::{
-    mempack::{self, ReadBuf},
-    property,
-};
+use chain_core::{mempack, property};
+use network_core::context::RequestContext;
 use network_core::error as core_error;
 use network_core::gossip::{Gossip, Node, NodeId};
 use network_core::subscription::{BlockEvent, ChainPullRequest};
 
 use tower_grpc::{
-    metadata::{BinaryMetadataValue, MetadataMap},
+    metadata::{BinaryMetadataValue, KeyAndValueRef, MetadataMap},
     Code, Status,
 };
 
+use std::time::{Duration, Instant};
+
 // Name of the binary metadata key used to pass the node ID in subscription requests.
 const NODE_ID_HEADER: &'static str = "node-id-bin";
 
+// Name of the metadata key used by gRPC clients to pass the timeout of a request.
+const TIMEOUT_HEADER: &'static str = "grpc-timeout";
+
 pub fn error_into_grpc(err: core_error::Error) -> Status {
     use core_error::Code::*;
 
@@ -23,12 +26,19 @@ pub fn error_into_grpc(err: core_error::Error) -> Status {
         Canceled => Code::Cancelled,
         Unknown => Code::Unknown,
         InvalidArgument => Code::InvalidArgument,
+        DeadlineExceeded => Code::DeadlineExceeded,
         NotFound => Code::NotFound,
+        AlreadyExists => Code::AlreadyExists,
+        PermissionDenied => Code::PermissionDenied,
+        ResourceExhausted => Code::ResourceExhausted,
         FailedPrecondition => Code::FailedPrecondition,
         Aborted => Code::Aborted,
+        OutOfRange => Code::OutOfRange,
         Unimplemented => Code::Unimplemented,
         Internal => Code::Internal,
         Unavailable => Code::Unavailable,
+        DataLoss => Code::DataLoss,
+        Unauthenticated => Code::Unauthenticated,
         // When a new case has to be added here, remember to
         // add the corresponding case in error_from_grpc below.
     };
@@ -43,12 +53,19 @@ pub fn error_from_grpc(e: Status) -> core_error::Error {
         Cancelled => core_error::Code::Canceled,
         Unknown => core_error::Code::Unknown,
         InvalidArgument => core_error::Code::InvalidArgument,
+        DeadlineExceeded => core_error::Code::DeadlineExceeded,
         NotFound => core_error::Code::NotFound,
+        AlreadyExists => core_error::Code::AlreadyExists,
+        PermissionDenied => core_error::Code::PermissionDenied,
+        ResourceExhausted => core_error::Code::ResourceExhausted,
         FailedPrecondition => core_error::Code::FailedPrecondition,
         Aborted => core_error::Code::Aborted,
+        OutOfRange => core_error::Code::OutOfRange,
         Unimplemented => core_error::Code::Unimplemented,
         Internal => core_error::Code::Internal,
         Unavailable => core_error::Code::Unavailable,
+        DataLoss => core_error::Code::DataLoss,
+        Unauthenticated => core_error::Code::Unauthenticated,
         _ => core_error::Code::Unknown,
     };
 
@@ -82,8 +99,8 @@ pub fn parse_bytes<T>(buf: &[u8]) -> Result<T, core_error::Error>
 where
     T: mempack::Readable,
 {
-    let mut buf = ReadBuf::from(buf);
-    T::read(&mut buf).map_err(|e| core_error::Error::new(core_error::Code::InvalidArgument, e))
+    mempack::read_exactly(buf)
+        .map_err(|e| core_error::Error::new(core_error::Code::InvalidArgument, e))
 }
 
 pub fn parse_repeated_bytes<T>(pb: &[Vec<u8>]) -> Result<Vec<T>, core_error::Error>
@@ -350,3 +367,124 @@ where
     metadata.insert_bin(NODE_ID_HEADER, val);
     Ok(())
 }
+
+// Parses a timeout in the format of the grpc-timeout header: up to 8 digits
+// followed by the unit.
+fn parse_timeout(value: &str) -> Option<Duration> {
+    if !value.is_ascii() || value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n: u64 = digits.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(n * 3600),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+/// The context of a request received with the given metadata at `now`.
+///
+/// The deadline is taken from the grpc-timeout header, an invalid timeout
+/// being ignored. The other ASCII metadata entries go into the context,
+/// except those reserved by gRPC. tower-grpc does not give the address of
+/// the peer to the service, so the context has none.
+pub fn request_context(metadata: &MetadataMap, now: Instant) -> RequestContext {
+    let mut ctx = RequestContext::new();
+    let deadline = metadata
+        .get(TIMEOUT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_timeout)
+        .and_then(|timeout| now.checked_add(timeout));
+    if let Some(deadline) = deadline {
+        ctx = ctx.with_deadline(deadline);
+    }
+    for entry in metadata.iter() {
+        if let KeyAndValueRef::Ascii(key, value) = entry {
+            let key = key.as_str();
+            if key.starts_with("grpc-") {
+                continue;
+            }
+            if let Ok(value) = value.to_str() {
+                ctx.insert_metadata(key, value);
+            }
+        }
+    }
+    ctx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_are_parsed_in_every_unit() {
+        assert_eq!(parse_timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(parse_timeout("4S"), Some(Duration::from_secs(4)));
+        assert_eq!(parse_timeout("5m"), Some(Duration::from_millis(5)));
+        assert_eq!(parse_timeout("6u"), Some(Duration::from_micros(6)));
+        assert_eq!(parse_timeout("7n"), Some(Duration::from_nanos(7)));
+        assert_eq!(parse_timeout("0S"), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn malformed_timeouts_are_rejected() {
+        for value in &[
+            "", "S", "10", "10s", "10 S", "-1S", "+1S", "1.5S", "S10", "1\u{e9}",
+        ] {
+            assert_eq!(parse_timeout(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn timeouts_of_more_than_8_digits_are_rejected() {
+        assert_eq!(
+            parse_timeout("99999999H"),
+            Some(Duration::from_secs(99_999_999 * 3600))
+        );
+        assert_eq!(parse_timeout("100000000n"), None);
+        assert_eq!(parse_timeout("18446744073709551616S"), None);
+    }
+
+    #[test]
+    fn request_context_takes_the_deadline_from_the_timeout() {
+        let now = Instant::now();
+        let mut metadata = MetadataMap::new();
+        metadata.insert(TIMEOUT_HEADER, "10S".parse().unwrap());
+        let ctx = request_context(&metadata, now);
+        assert_eq!(ctx.deadline(), Some(now + Duration::from_secs(10)));
+        assert_eq!(ctx.peer_addr(), None);
+
+        metadata.insert(TIMEOUT_HEADER, "10s".parse().unwrap());
+        assert_eq!(request_context(&metadata, now).deadline(), None);
+
+        // too far for an `Instant` or not, the context is made
+        metadata.insert(TIMEOUT_HEADER, "99999999H".parse().unwrap());
+        let ctx = request_context(&metadata, now);
+        assert!(ctx.deadline().map_or(true, |deadline| deadline > now));
+    }
+
+    #[test]
+    fn request_context_leaves_out_the_reserved_metadata() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(TIMEOUT_HEADER, "1S".parse().unwrap());
+        metadata.insert("grpc-encoding", "gzip".parse().unwrap());
+        metadata.insert("x-request-id", "42".parse().unwrap());
+        metadata.insert_bin(NODE_ID_HEADER, BinaryMetadataValue::from_bytes(&[1, 2]));
+        let ctx = request_context(&metadata, Instant::now());
+        assert_eq!(ctx.metadata("x-request-id"), Some("42"));
+        assert_eq!(ctx.metadata(TIMEOUT_HEADER), None);
+        assert_eq!(ctx.metadata("grpc-encoding"), None);
+        assert_eq!(ctx.metadata(NODE_ID_HEADER), None);
+        assert_eq!(ctx.metadata_iter().count(), 1);
+    }
+}
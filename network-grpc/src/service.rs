@@ -1,7 +1,8 @@
 use crate::{
     convert::{
         decode_node_id, deserialize_bytes, deserialize_repeated_bytes, encode_node_id,
-        error_from_grpc, error_into_grpc, serialize_to_bytes, FromProtobuf, IntoProtobuf,
+        error_from_grpc, error_into_grpc, request_context, serialize_to_bytes, FromProtobuf,
+        IntoProtobuf,
     },
     gen, PROTOCOL_VERSION,
 };
@@ -12,8 +13,12 @@ use network_core::{
     error as core_error,
     gossip::NodeId,
     server::{
-        block::BlockService, content::ContentService, gossip::GossipService, Node, P2pService,
+        block::BlockService,
+        content::{check_get_fragments_limit, ContentService, GetFragmentsItem},
+        gossip::GossipService,
+        Node, P2pService,
     },
+    subscription::SubscriptionHandle,
 };
 
 use futures::future::{self, FutureResult};
@@ -22,7 +27,7 @@ use futures::stream::Forward;
 use futures::try_ready;
 use tower_grpc::{self, Code, Request, Response, Status, Streaming};
 
-use std::{marker::PhantomData, mem};
+use std::{marker::PhantomData, mem, time::Instant};
 
 #[derive(Clone, Debug)]
 pub struct NodeService<T> {
@@ -223,6 +228,92 @@ where
     }
 }
 
+/// Outbound subscription stream that closes the subscription handle
+/// when the response is dropped, which happens when the peer disconnects.
+#[must_use = "streams do nothing unless polled"]
+pub struct HandledStream<S> {
+    inner: S,
+    handle: SubscriptionHandle,
+}
+
+impl<S: Stream> Stream for HandledStream<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        self.inner.poll()
+    }
+}
+
+impl<S> Drop for HandledStream<S> {
+    fn drop(&mut self) {
+        self.handle.close();
+    }
+}
+
+/// Future resolving the subscription of a service into a `HandledStream`.
+#[must_use = "futures do nothing unless polled"]
+pub struct HandledSubscriptionFuture<F> {
+    inner: F,
+}
+
+impl<F, S> Future for HandledSubscriptionFuture<F>
+where
+    F: Future<Item = (S, SubscriptionHandle)>,
+{
+    type Item = HandledStream<S>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<HandledStream<S>, F::Error> {
+        let (inner, handle) = try_ready!(self.inner.poll());
+        Ok(Async::Ready(HandledStream { inner, handle }))
+    }
+}
+
+/// Stream of the fragments found by a `get_fragments` request.
+///
+/// The protocol has no way to report unknown identifiers, they are
+/// skipped and the client finds out about them by their absence.
+#[must_use = "streams do nothing unless polled"]
+pub struct FoundFragments<S> {
+    inner: S,
+}
+
+impl<S, F, Id> Stream for FoundFragments<S>
+where
+    S: Stream<Item = GetFragmentsItem<F, Id>>,
+{
+    type Item = F;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<F>, S::Error> {
+        loop {
+            match try_ready!(self.inner.poll()) {
+                Some(GetFragmentsItem::Found(fragment)) => return Ok(Async::Ready(Some(fragment))),
+                Some(GetFragmentsItem::Unknown(_)) => {}
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+/// Future resolving the response of a service to `get_fragments`
+/// into a `FoundFragments` stream.
+#[must_use = "futures do nothing unless polled"]
+pub struct FoundFragmentsFuture<F> {
+    inner: F,
+}
+
+impl<F: Future> Future for FoundFragmentsFuture<F> {
+    type Item = FoundFragments<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, F::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(FoundFragments { inner }))
+    }
+}
+
 #[must_use = "streams do nothing unless polled"]
 pub struct RequestStream<T, S> {
     inner: S,
@@ -398,11 +489,11 @@ where
     >;
     type GetFragmentsStream = ResponseStream<
         gen::node::Fragment,
-        <<T as Node>::ContentService as ContentService>::GetFragmentsStream,
+        FoundFragments<<<T as Node>::ContentService as ContentService>::GetFragmentsStream>,
     >;
     type GetFragmentsFuture = ResponseFuture<
         Self::GetFragmentsStream,
-        <<T as Node>::ContentService as ContentService>::GetFragmentsFuture,
+        FoundFragmentsFuture<<<T as Node>::ContentService as ContentService>::GetFragmentsFuture>,
     >;
     type PushHeadersFuture = ResponseFuture<
         gen::node::PushHeadersResponse,
@@ -429,12 +520,12 @@ where
     >;
     type ContentSubscriptionStream = ResponseStream<
         gen::node::Fragment,
-        <<T as Node>::ContentService as ContentService>::ContentSubscription,
+        HandledStream<<<T as Node>::ContentService as ContentService>::ContentSubscription>,
     >;
     type ContentSubscriptionFuture = SubscriptionFuture<
         Self::ContentSubscriptionStream,
         <T::ContentService as P2pService>::NodeId,
-        <T::ContentService as ContentService>::ContentSubscriptionFuture,
+        HandledSubscriptionFuture<<T::ContentService as ContentService>::ContentSubscriptionFuture>,
     >;
     type GossipSubscriptionStream = ResponseStream<
         gen::node::Gossip,
@@ -528,7 +619,13 @@ where
                 return ResponseFuture::error(error_into_grpc(e));
             }
         };
-        ResponseFuture::new(service.get_fragments(&tx_ids))
+        if let Err(e) = check_get_fragments_limit::<T::ContentService>(&tx_ids) {
+            return ResponseFuture::error(error_into_grpc(e));
+        }
+        let ctx = request_context(req.metadata(), Instant::now());
+        ResponseFuture::new(FoundFragmentsFuture {
+            inner: service.get_fragments_ctx(&ctx, &tx_ids),
+        })
     }
 
     fn push_headers(
@@ -568,10 +665,13 @@ where
     ) -> Self::ContentSubscriptionFuture {
         let service = try_get_service_sub!(self.inner.content_service());
         let subscriber = try_decode_node_id!(&req);
+        let ctx = request_context(req.metadata(), Instant::now());
         let stream = RequestStream::new(req.into_inner());
         SubscriptionFuture::new(
             service.node_id(),
-            service.content_subscription(subscriber, stream),
+            HandledSubscriptionFuture {
+                inner: service.content_subscription_ctx(&ctx, subscriber, stream),
+            },
         )
     }
 